@@ -0,0 +1,9 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+
+    // used to select per-target libraries in package manifests
+    println!(
+        "cargo:rustc-env=XDSIM_TARGET={}",
+        std::env::var("TARGET").expect("cargo sets TARGET for build scripts")
+    );
+}
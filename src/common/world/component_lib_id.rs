@@ -1,10 +1,11 @@
 use std::fmt::Display;
 
 use semver::{Version, VersionReq};
+use serde::{Deserialize, Serialize};
 
 /// Requirement for component, support rangers and wildcards
 /// e.g. >=0.1.0 or 0.1.*
-#[derive(Hash, PartialEq, Eq, Clone, Debug)]
+#[derive(Hash, PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
 pub struct ComponentVersionReq {
     pub package: String,
     pub version_req: VersionReq,
@@ -29,7 +30,7 @@ impl Display for ComponentVersionReq {
 }
 
 /// A concrete component identifier
-#[derive(Hash, PartialEq, Eq, Clone, Debug)]
+#[derive(Hash, PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
 pub struct ComponentVersion {
     pub package: String,
     pub version: Version,
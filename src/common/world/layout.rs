@@ -3,6 +3,8 @@ use std::{
     ops::{Add, AddAssign},
 };
 
+use serde::{Deserialize, Serialize};
+
 #[derive(Clone, Copy, Debug)]
 pub struct Vec2 {
     x: f64,
@@ -98,6 +100,7 @@ impl AddAssign for Rotation {
     }
 }
 
+#[derive(Debug, Serialize, Deserialize)]
 pub struct BoundingBox {
     top: f64,
     bottom: f64,
@@ -1 +1,7 @@
-fn main() {}
+use xdsim::packages::sandbox;
+
+fn main() {
+    if std::env::args().nth(1).as_deref() == Some(sandbox::HELPER_ARG) {
+        sandbox::run_helper();
+    }
+}
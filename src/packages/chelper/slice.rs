@@ -28,6 +28,19 @@ pub fn from_vec_rustonly<T>(vec: Vec<T>) -> Slice {
     }
 }
 
+extern "C" fn borrowed_drop(_vec_ptr: *mut c_void, _item_size: u64, _len: u64) {}
+
+/// Borrows a slice as a Slice, dropping the Slice does nothing
+/// (will become invalid after the original slice is dropped or moved)
+pub fn from_borrowed<T>(original: &[T]) -> Slice {
+    Slice {
+        length: original.len() as u64,
+        item_size: size_of::<T>() as u64,
+        first: original.as_ptr() as *mut c_void,
+        drop: borrowed_drop,
+    }
+}
+
 /// Convert &Str to String (creates a copy)
 pub fn from_str(original: &Str) -> String {
     unsafe { CStr::from_ptr(original.first) }
@@ -0,0 +1,48 @@
+use std::ops::BitOr;
+
+/// optional entry points of a gate library
+///
+/// v1 libraries export `capabilities() -> u64` with a bit set for every optional
+/// entry point they export, entry points whose bit is not set are never looked up.
+/// bits unknown to this version are ignored, so a newer library still loads
+/// with the entry points both sides know
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "devel", derive(Debug))]
+pub struct Capabilities(u64);
+
+impl Capabilities {
+    pub const NONE: Self = Self(0);
+    /// `gate_props`, see DestructedGate::properties
+    pub const PROPS: Self = Self(1 << 0);
+    /// `gate_draw`, see DestructedGate::draw
+    pub const DRAW: Self = Self(1 << 1);
+    /// `gate_tick_batch`, see DestructedGate::tick_batch
+    pub const BATCH_TICK: Self = Self(1 << 2);
+    /// `gate_interact`, see DestructedGate::interact
+    pub const INTERACT: Self = Self(1 << 3);
+
+    /// every capability this version knows
+    pub const ALL: Self = Self(0b1111);
+
+    /// unknown bits are dropped
+    pub fn from_bits(bits: u64) -> Self {
+        Self(bits & Self::ALL.0)
+    }
+
+    pub fn bits(&self) -> u64 {
+        self.0
+    }
+
+    /// if every capability in other is also in self
+    pub fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for Capabilities {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
@@ -7,7 +7,8 @@ use crate::packages::{
 ///
 /// Note: a copy of library is held for the functions to remain valid
 pub struct DestructedConn {
-    _library: LibraryHandle,
+    /// None if the symbols come from a table, see DestructRequest::from_symbols
+    _library: Option<LibraryHandle>,
     handle: DestructedConnHandle,
 }
 
@@ -16,14 +17,21 @@ pub enum DestructedConnHandle {
 }
 
 impl DestructedConn {
+    /// symbols a library of the schema version must export (besides schema_version),
+    /// returns None if the schema version is not supported
+    pub fn required_symbols(schema_version: u32) -> Option<&'static [&'static str]> {
+        match schema_version {
+            // schema version 1 only changes gates
+            0 | 1 => Some(v0::DestructedConn::SYMBOLS),
+            _ => None,
+        }
+    }
+
     pub fn new(request: DestructRequest) -> Result<Self, destructor::Error> {
-        let get_schema_version: fn() -> u32 = *request
-            .get_library()
-            .get_symbol("schema_version")
-            .map_err(destructor::Error::from_get_symbol)?;
+        let get_schema_version: fn() -> u32 = request.get_symbol("schema_version")?;
 
         let handle = match get_schema_version() {
-            0 => DestructedConnHandle::V0(v0::DestructedConn::new(&request)?),
+            0 | 1 => DestructedConnHandle::V0(v0::DestructedConn::new(&request)?),
             unsupported_version => {
                 return Err(destructor::Error::UnsupportedSchemaVersion {
                     version: unsupported_version,
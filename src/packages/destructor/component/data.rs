@@ -1,18 +1,33 @@
+use std::{path::Path, sync::Arc};
+
 use xdsim_cbinds::common::Slice;
 
 use crate::{
     common::world::{ComponentVersion, DataPtr, DataPtrMut},
     packages::{
-        destructor::{self, DestructRequest, component::v0},
+        chelper::slice,
+        destructor::{self, DestructRequest, DestructedNativeData, NativeData, component::v0},
         loader::LibraryHandle,
+        sandbox::{SandboxHost, SandboxedComponent, protocol::ComponentKind},
     },
 };
 
+#[cfg(feature = "wasm")]
+use crate::packages::destructor::component::wasm;
+
 /// Destructs a library into data functions
 ///
 /// Note: a copy of library is held for the functions to remain valid
+///
+/// # Safety
+///
+/// DestructedData is Send and Sync, with the same requirements on component libraries
+/// as DestructedGate: data may move between threads, and different data may be used
+/// from different threads at the same time
 pub struct DestructedData {
-    _library: LibraryHandle,
+    /// None if the data type runs in a sandbox helper, is native, is a wasm module
+    /// or is destructed from a symbol table
+    library: Option<LibraryHandle>,
     id: ComponentVersion,
     handle: DestructedDataHandle,
 }
@@ -21,21 +36,84 @@ impl DestructedData {
     pub fn id(&self) -> &ComponentVersion {
         &self.id
     }
+
+    /// the library the functions are from,
+    /// None if the data type runs in a sandbox helper, is native or is a wasm module
+    pub fn get_library(&self) -> Option<&LibraryHandle> {
+        self.library.as_ref()
+    }
+
+    /// path of the library the functions are from, wherever it is loaded,
+    /// None if the data type is native
+    pub fn get_lib_path(&self) -> Option<&Path> {
+        match (&self.handle, &self.library) {
+            (DestructedDataHandle::Native(_), _) => None,
+            (DestructedDataHandle::Sandboxed(handle), _) => Some(handle.get_lib_path()),
+            #[cfg(feature = "wasm")]
+            (DestructedDataHandle::Wasm(handle), _) => Some(handle.get_lib_path()),
+            (_, Some(library)) => Some(library.get_path()),
+            (_, None) => unreachable!("only sandboxed, native and wasm data types have no library"),
+        }
+    }
+
+    pub fn is_sandboxed(&self) -> bool {
+        self.get_sandboxed().is_some()
+    }
+
+    pub fn is_native(&self) -> bool {
+        matches!(self.handle, DestructedDataHandle::Native(_))
+    }
+
+    pub fn is_wasm(&self) -> bool {
+        #[cfg(feature = "wasm")]
+        return matches!(self.handle, DestructedDataHandle::Wasm(_));
+
+        #[cfg(not(feature = "wasm"))]
+        false
+    }
+
+    /// if data pointers of the type can be read in this process,
+    /// pointers of sandboxed and wasm data types are handles in the helper or module
+    pub fn is_in_process(&self) -> bool {
+        !self.is_sandboxed() && !self.is_wasm()
+    }
+
+    /// the helper side of a sandboxed data type
+    pub fn get_sandboxed(&self) -> Option<&SandboxedComponent> {
+        match &self.handle {
+            DestructedDataHandle::Sandboxed(handle) => Some(handle),
+            _ => None,
+        }
+    }
 }
 
 pub enum DestructedDataHandle {
     V0(v0::DestructedData),
+    /// data pointers are instance ids in the helper
+    Sandboxed(SandboxedComponent),
+    /// implemented in rust, see NativeData
+    Native(Arc<DestructedNativeData>),
+    /// data pointers are handles in the module, only bytes cross its memory
+    #[cfg(feature = "wasm")]
+    Wasm(Box<wasm::DestructedData>),
 }
 
 impl DestructedData {
+    /// symbols a library of the schema version must export (besides schema_version),
+    /// returns None if the schema version is not supported
+    pub fn required_symbols(schema_version: u32) -> Option<&'static [&'static str]> {
+        match schema_version {
+            // schema version 1 only changes gates
+            0 | 1 => Some(v0::DestructedData::SYMBOLS),
+            _ => None,
+        }
+    }
+
     pub fn new(request: DestructRequest) -> Result<Self, destructor::Error> {
-        let get_schema_version: fn() -> u32 = *request
-            .get_library()
-            .get_symbol("schema_version")
-            .map_err(destructor::Error::from_get_symbol)?;
+        let get_schema_version: fn() -> u32 = request.get_symbol("schema_version")?;
 
         let handle = match get_schema_version() {
-            0 => DestructedDataHandle::V0(v0::DestructedData::new(&request)?),
+            0 | 1 => DestructedDataHandle::V0(v0::DestructedData::new(&request)?),
             unsupported_version => {
                 return Err(destructor::Error::UnsupportedSchemaVersion {
                     version: unsupported_version,
@@ -45,16 +123,119 @@ impl DestructedData {
 
         Ok(Self {
             id: request.get_component_id().clone(),
-            _library: request.into_library(),
+            library: request.into_library(),
             handle,
         })
     }
 
+    /// load the data type in a sandbox helper instead of this process
+    pub fn new_sandboxed(
+        sandbox: &Arc<SandboxHost>,
+        lib_path: &Path,
+        sha256: Option<&str>,
+        component_id: ComponentVersion,
+    ) -> Result<Self, destructor::Error> {
+        let handle = sandbox
+            .load(
+                ComponentKind::Data,
+                lib_path.to_path_buf(),
+                component_id.clone(),
+                false,
+                sha256.map(str::to_string),
+            )
+            .map_err(destructor::Error::from_sandbox)?;
+
+        Ok(Self {
+            id: component_id,
+            library: None,
+            handle: DestructedDataHandle::Sandboxed(handle),
+        })
+    }
+
+    /// wrap a data type implemented in rust, no library is involved
+    pub fn new_native(component_id: ComponentVersion, data: impl NativeData) -> Self {
+        Self {
+            id: component_id,
+            library: None,
+            handle: DestructedDataHandle::Native(Arc::new(DestructedNativeData::new(data))),
+        }
+    }
+
+    /// instantiate a wasm module in this process, see the wasm feature
+    pub fn new_wasm(
+        lib_path: &Path,
+        component_id: ComponentVersion,
+    ) -> Result<Self, destructor::Error> {
+        #[cfg(feature = "wasm")]
+        return Ok(Self {
+            id: component_id,
+            library: None,
+            handle: DestructedDataHandle::Wasm(Box::new(wasm::DestructedData::new(lib_path)?)),
+        });
+
+        #[cfg(not(feature = "wasm"))]
+        {
+            let _ = (lib_path, component_id);
+            Err(destructor::Error::UnsupportedLibraryKind {
+                kind: "wasm".to_string(),
+            })
+        }
+    }
+
+    /// load the library of the data type again (in the same place it runs),
+    /// e.g. after it is rebuilt
+    ///
+    /// native data types have no library, the same implementation is used again
+    pub fn reload(&self) -> Result<Self, destructor::Error> {
+        match (&self.handle, &self.library) {
+            (DestructedDataHandle::Native(handle), _) => Ok(Self {
+                id: self.id.clone(),
+                library: None,
+                handle: DestructedDataHandle::Native(handle.clone()),
+            }),
+            (DestructedDataHandle::Sandboxed(handle), _) => Ok(Self {
+                id: self.id.clone(),
+                library: None,
+                handle: DestructedDataHandle::Sandboxed(
+                    handle
+                        .reload(&self.id)
+                        .map_err(destructor::Error::from_sandbox)?,
+                ),
+            }),
+            #[cfg(feature = "wasm")]
+            (DestructedDataHandle::Wasm(handle), _) => {
+                Self::new_wasm(handle.get_lib_path(), self.id.clone())
+            }
+            (_, Some(library)) => Self::new(DestructRequest::new(
+                library
+                    .reload()
+                    .map_err(|e| destructor::Error::ReloadLibrary {
+                        lib_path: library.get_path().to_path_buf(),
+                        reason: e.to_string(),
+                    })?,
+                self.id.clone(),
+            )),
+            (_, None) => unreachable!("only sandboxed, native and wasm data types have no library"),
+        }
+    }
+
+    /// serialize into bytes that outlive the library
+    pub fn to_bytes(&self, data: DataPtr) -> Vec<u8> {
+        let serialized = self.serialize(data);
+        slice::from_slice::<u8>(&serialized).to_vec()
+    }
+
     /// this is guaranteed to succeed
     /// (unless the component file throws an error)
     pub fn serialize(&self, data: DataPtr) -> Slice {
         match &self.handle {
             DestructedDataHandle::V0(handle) => (handle.serialize)(data),
+            DestructedDataHandle::Sandboxed(handle) => {
+                slice::from_vec_rustonly(handle.serialize(data))
+            }
+            DestructedDataHandle::Native(handle) => (handle.serialize)(data),
+            #[cfg(feature = "wasm")]
+            DestructedDataHandle::Wasm(handle) => slice::from_vec_rustonly(handle.serialize(data)),
         }
     }
 
@@ -67,14 +248,24 @@ impl DestructedData {
                 let ptr = (handle.deserialize)(bytes);
                 if ptr.is_null() { None } else { Some(ptr) }
             }
+            DestructedDataHandle::Sandboxed(handle) => {
+                handle.deserialize(slice::from_slice::<u8>(bytes).to_vec())
+            }
+            DestructedDataHandle::Native(handle) => (handle.deserialize)(bytes),
+            #[cfg(feature = "wasm")]
+            DestructedDataHandle::Wasm(handle) => handle.deserialize(slice::from_slice(bytes)),
         }
     }
 
-    /// this is guaranteed to succeed
+    /// fails if the wasm module traps, every other kind of data type succeeds
     /// (unless the component file throws an error)
-    pub fn default_value(&self) -> DataPtrMut {
+    pub fn default_value(&self) -> Result<DataPtrMut, destructor::Error> {
         match &self.handle {
-            DestructedDataHandle::V0(handle) => (handle.default_value)(),
+            DestructedDataHandle::V0(handle) => Ok((handle.default_value)()),
+            DestructedDataHandle::Sandboxed(handle) => Ok(handle.default_value()),
+            DestructedDataHandle::Native(handle) => Ok((handle.default_value)()),
+            #[cfg(feature = "wasm")]
+            DestructedDataHandle::Wasm(handle) => handle.default_value(),
         }
     }
 
@@ -85,6 +276,10 @@ impl DestructedData {
     pub fn drop_mem(&self, data: DataPtrMut) {
         match &self.handle {
             DestructedDataHandle::V0(handle) => (handle.drop_mem)(data),
+            DestructedDataHandle::Sandboxed(handle) => handle.drop_mem(data),
+            DestructedDataHandle::Native(handle) => (handle.drop_mem)(data),
+            #[cfg(feature = "wasm")]
+            DestructedDataHandle::Wasm(handle) => handle.drop_mem(data),
         }
     }
 }
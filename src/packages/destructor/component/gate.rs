@@ -1,21 +1,47 @@
+use std::{path::Path, sync::Arc};
+
 use xdsim_cbinds::{
     common::{Rotation, Slice, Vec2},
     v0::{app_state::PropertiesMut, graphics::Graphic},
 };
 
 use crate::{
-    common::world::{BoundingBox, ComponentVersion, ComponentVersionReq, GatePtr, GatePtrMut},
+    common::world::{
+        BoundingBox, ComponentVersion, ComponentVersionReq, DataPtr, DataPtrMut, GatePtr,
+        GatePtrMut,
+    },
     packages::{
-        destructor::{self, DestructRequest, component::v0},
+        chelper::slice,
+        destructor::{
+            self, Capabilities, DestructRequest, DestructedData, DestructedNativeGate, NativeGate,
+            component::{v0, v1},
+        },
         loader::LibraryHandle,
+        sandbox::{self, SandboxHost, SandboxedComponent, protocol::ComponentKind},
     },
 };
 
+#[cfg(feature = "script")]
+use crate::packages::destructor::component::script;
+#[cfg(feature = "wasm")]
+use crate::packages::destructor::component::wasm;
+
 /// Destructs a library into gate functions
 ///
 /// Note: a copy of library is held for the functions to remain valid
+///
+/// # Safety
+///
+/// DestructedGate is Send and Sync, so handles can be shared by worlds on different threads.
+/// component libraries must therefore
+/// - not keep thread local state: a gate may be created on one thread and ticked on another
+/// - allow different gates to be used from different threads at the same time
+///
+/// a single gate is never used from two threads at the same time
 pub struct DestructedGate {
-    _library: LibraryHandle,
+    /// None if the gate runs in a sandbox helper, is native, a wasm module, a script
+    /// or is destructed from a symbol table
+    library: Option<LibraryHandle>,
     id: ComponentVersion,
     handle: DestructedGateHandle,
 }
@@ -52,18 +78,37 @@ pub struct DestructedGateProducerEntry {
 }
 
 pub enum DestructedGateHandle {
-    V0(v0::DestructedGate),
+    /// libraries of schema version 0 are adapted to v1
+    V1(v1::DestructedGate),
+    /// gate pointers are instance ids in the helper
+    Sandboxed(SandboxedComponent),
+    /// implemented in rust, see NativeGate
+    Native(Arc<DestructedNativeGate>),
+    /// gate pointers are handles in the module, only bytes cross its memory
+    #[cfg(feature = "wasm")]
+    Wasm(Box<wasm::DestructedGate>),
+    /// gate pointers are boxed script values
+    #[cfg(feature = "script")]
+    Script(Box<script::DestructedGate>),
 }
 
 impl DestructedGate {
+    /// symbols a library of the schema version must export (besides schema_version),
+    /// returns None if the schema version is not supported
+    pub fn required_symbols(schema_version: u32) -> Option<&'static [&'static str]> {
+        match schema_version {
+            0 => Some(v0::DestructedGate::SYMBOLS),
+            1 => Some(v1::DestructedGate::SYMBOLS),
+            _ => None,
+        }
+    }
+
     pub fn new(request: DestructRequest) -> Result<Self, destructor::Error> {
-        let get_schema_version: fn() -> u32 = *request
-            .get_library()
-            .get_symbol("schema_version")
-            .map_err(destructor::Error::from_get_symbol)?;
+        let get_schema_version: fn() -> u32 = request.get_symbol("schema_version")?;
 
         let handle = match get_schema_version() {
-            0 => DestructedGateHandle::V0(v0::DestructedGate::new(&request)?),
+            0 => DestructedGateHandle::V1(v0::DestructedGate::new(&request)?.into()),
+            1 => DestructedGateHandle::V1(v1::DestructedGate::new(&request)?),
             unsupported_version => {
                 return Err(destructor::Error::UnsupportedSchemaVersion {
                     version: unsupported_version,
@@ -73,21 +118,289 @@ impl DestructedGate {
 
         Ok(Self {
             id: request.get_component_id().clone(),
-            _library: request.into_library(),
+            library: request.into_library(),
             handle,
         })
     }
 
+    /// load the gate in a sandbox helper instead of this process
+    pub fn new_sandboxed(
+        sandbox: &Arc<SandboxHost>,
+        lib_path: &Path,
+        sha256: Option<&str>,
+        component_id: ComponentVersion,
+    ) -> Result<Self, destructor::Error> {
+        let handle = sandbox
+            .load(
+                ComponentKind::Gate,
+                lib_path.to_path_buf(),
+                component_id.clone(),
+                false,
+                sha256.map(str::to_string),
+            )
+            .map_err(destructor::Error::from_sandbox)?;
+
+        Ok(Self {
+            id: component_id,
+            library: None,
+            handle: DestructedGateHandle::Sandboxed(handle),
+        })
+    }
+
+    /// wrap a gate type implemented in rust, no library is involved
+    pub fn new_native(component_id: ComponentVersion, gate: impl NativeGate) -> Self {
+        Self {
+            id: component_id,
+            library: None,
+            handle: DestructedGateHandle::Native(Arc::new(DestructedNativeGate::new(gate))),
+        }
+    }
+
+    /// instantiate a wasm module in this process, see the wasm feature
+    pub fn new_wasm(
+        lib_path: &Path,
+        component_id: ComponentVersion,
+    ) -> Result<Self, destructor::Error> {
+        #[cfg(feature = "wasm")]
+        return Ok(Self {
+            id: component_id,
+            library: None,
+            handle: DestructedGateHandle::Wasm(Box::new(wasm::DestructedGate::new(lib_path)?)),
+        });
+
+        #[cfg(not(feature = "wasm"))]
+        {
+            let _ = (lib_path, component_id);
+            Err(destructor::Error::UnsupportedLibraryKind {
+                kind: "wasm".to_string(),
+            })
+        }
+    }
+
+    /// compile a rhai script, see the script feature
+    pub fn new_script(
+        lib_path: &Path,
+        component_id: ComponentVersion,
+    ) -> Result<Self, destructor::Error> {
+        #[cfg(feature = "script")]
+        return Ok(Self {
+            id: component_id,
+            library: None,
+            handle: DestructedGateHandle::Script(Box::new(script::DestructedGate::new(lib_path)?)),
+        });
+
+        #[cfg(not(feature = "script"))]
+        {
+            let _ = (lib_path, component_id);
+            Err(destructor::Error::UnsupportedLibraryKind {
+                kind: "rhai".to_string(),
+            })
+        }
+    }
+
+    /// load the library of the gate again (in the same place it runs), e.g. after it is rebuilt
+    ///
+    /// native gates have no library, the same implementation is used again
+    pub fn reload(&self) -> Result<Self, destructor::Error> {
+        match (&self.handle, &self.library) {
+            (DestructedGateHandle::Native(handle), _) => Ok(Self {
+                id: self.id.clone(),
+                library: None,
+                handle: DestructedGateHandle::Native(handle.clone()),
+            }),
+            (DestructedGateHandle::Sandboxed(handle), _) => Ok(Self {
+                id: self.id.clone(),
+                library: None,
+                handle: DestructedGateHandle::Sandboxed(
+                    handle
+                        .reload(&self.id)
+                        .map_err(destructor::Error::from_sandbox)?,
+                ),
+            }),
+            #[cfg(feature = "wasm")]
+            (DestructedGateHandle::Wasm(handle), _) => {
+                Self::new_wasm(handle.get_lib_path(), self.id.clone())
+            }
+            #[cfg(feature = "script")]
+            (DestructedGateHandle::Script(handle), _) => {
+                Self::new_script(handle.get_lib_path(), self.id.clone())
+            }
+            (_, Some(library)) => Self::new(DestructRequest::new(
+                library
+                    .reload()
+                    .map_err(|e| destructor::Error::ReloadLibrary {
+                        lib_path: library.get_path().to_path_buf(),
+                        reason: e.to_string(),
+                    })?,
+                self.id.clone(),
+            )),
+            (_, None) => {
+                unreachable!("only sandboxed, native, wasm and script gates have no library")
+            }
+        }
+    }
+
     /// the slice is an array of *mut Data
+    ///
+    /// sandboxed, wasm and script gates cannot be ticked this way (the data pointers do not cross
+    /// the process or module boundary, scripts only read bytes), they produce an empty slice,
+    /// use tick_serialized instead, see ticks_serialized
     pub fn tick(&self, gate: GatePtrMut, consumer: *const Slice) -> Slice {
         match &self.handle {
-            DestructedGateHandle::V0(handle) => (handle.tick)(gate, consumer),
+            DestructedGateHandle::V1(handle) => (handle.tick)(gate, consumer),
+            DestructedGateHandle::Sandboxed(_) => {
+                slice::from_vec_rustonly::<DataPtrMut>(Vec::new())
+            }
+            #[cfg(feature = "wasm")]
+            DestructedGateHandle::Wasm(_) => slice::from_vec_rustonly::<DataPtrMut>(Vec::new()),
+            #[cfg(feature = "script")]
+            DestructedGateHandle::Script(_) => slice::from_vec_rustonly::<DataPtrMut>(Vec::new()),
+            DestructedGateHandle::Native(handle) => (handle.tick)(gate, consumer),
         }
     }
 
-    pub fn draw(&self, gate: GatePtr, rotation: Rotation, bounding_box: Vec2) -> Graphic {
+    /// tick with consumers and producers exchanged as serialized bytes,
+    /// works for every gate, but is only needed for sandboxed, wasm and script ones
+    ///
+    /// - consumers are the data type and data of each consumer
+    /// - producers are the data types of the producers, in definition order
+    pub fn tick_serialized(
+        &self,
+        gate: GatePtrMut,
+        consumers: &[(&DestructedData, DataPtr)],
+        producers: &[&DestructedData],
+    ) -> Result<Vec<Vec<u8>>, destructor::Error> {
         match &self.handle {
-            DestructedGateHandle::V0(handle) => (handle.draw)(gate, rotation, bounding_box),
+            DestructedGateHandle::V1(_) | DestructedGateHandle::Native(_) => {
+                let consumer_slice = slice::from_vec_rustonly(
+                    consumers
+                        .iter()
+                        .map(|(_, data)| *data)
+                        .collect::<Vec<DataPtr>>(),
+                );
+
+                let producer_slice = self.tick(gate, &consumer_slice);
+
+                Ok(slice::from_slice::<DataPtrMut>(&producer_slice)
+                    .iter()
+                    .zip(producers)
+                    .map(|(&data, data_type)| {
+                        let bytes = data_type.to_bytes(data);
+                        data_type.drop_mem(data);
+                        bytes
+                    })
+                    .collect())
+            }
+            DestructedGateHandle::Sandboxed(handle) => {
+                let sandbox = handle.get_host();
+
+                let consumers = consumers
+                    .iter()
+                    .map(|(data_type, data)| {
+                        Ok((
+                            sandbox.data_component_id(data_type)?,
+                            data_type.to_bytes(*data),
+                        ))
+                    })
+                    .collect::<Result<Vec<_>, sandbox::Error>>()
+                    .map_err(destructor::Error::from_sandbox)?;
+
+                let producers = producers
+                    .iter()
+                    .map(|data_type| sandbox.data_component_id(data_type))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(destructor::Error::from_sandbox)?;
+
+                handle
+                    .tick(gate, consumers, producers)
+                    .map_err(destructor::Error::from_sandbox)
+            }
+            #[cfg(feature = "wasm")]
+            DestructedGateHandle::Wasm(handle) => {
+                let produced = handle.tick(gate, &consumer_bytes(consumers))?;
+                if produced.len() != producers.len() {
+                    return Err(destructor::Error::Wasm {
+                        reason: format!(
+                            "gate_tick returned {} producers, expected {}",
+                            produced.len(),
+                            producers.len()
+                        ),
+                    });
+                }
+
+                Ok(produced)
+            }
+            #[cfg(feature = "script")]
+            DestructedGateHandle::Script(handle) => {
+                let consumers: Vec<_> = consumers
+                    .iter()
+                    .map(|(data_type, data)| (data_type.id(), data_type.to_bytes(*data)))
+                    .collect();
+
+                let produced = handle.tick(gate, &consumers)?;
+                if produced.len() != producers.len() {
+                    return Err(destructor::Error::Script {
+                        reason: format!(
+                            "fn tick returned {} producers, expected {}",
+                            produced.len(),
+                            producers.len()
+                        ),
+                    });
+                }
+
+                Ok(produced)
+            }
+        }
+    }
+
+    /// tick several gates of this type at once, consumers point to the *mut Data slices of each gate,
+    /// returns a slice of the producer slices of each gate, in the order of gates
+    ///
+    /// uses gate_tick_batch if the library exports it, otherwise ticks the gates one by one.
+    /// gates that must be ticked with tick_serialized produce no data, see tick
+    pub fn tick_batch(&self, gates: &[GatePtrMut], consumers: &[*const Slice]) -> Slice {
+        if let DestructedGateHandle::V1(v1::DestructedGate {
+            tick_batch: Some(tick_batch),
+            ..
+        }) = &self.handle
+        {
+            return tick_batch(
+                &slice::from_borrowed(gates),
+                &slice::from_borrowed(consumers),
+            );
+        }
+
+        slice::from_vec_rustonly(
+            gates
+                .iter()
+                .zip(consumers)
+                .map(|(&gate, &consumer)| self.tick(gate, consumer))
+                .collect::<Vec<Slice>>(),
+        )
+    }
+
+    /// forward an interaction event (e.g. a click) to the gate,
+    /// returns if the state of the gate changed
+    ///
+    /// None if the library does not export gate_interact
+    pub fn interact(&self, gate: GatePtrMut, event: &[u8]) -> Option<bool> {
+        match &self.handle {
+            DestructedGateHandle::V1(handle) => handle
+                .interact
+                .map(|interact| interact(gate, &slice::from_vec_rustonly(event.to_vec()))),
+            _ => None,
+        }
+    }
+
+    /// None if the library does not export gate_draw, if the gate runs in a sandbox,
+    /// graphics do not cross the process boundary,
+    /// or if the gate is native, a wasm module or a script, which have nothing to draw with
+    pub fn draw(&self, gate: GatePtr, rotation: Rotation, bounding_box: Vec2) -> Option<Graphic> {
+        match &self.handle {
+            DestructedGateHandle::V1(handle) => {
+                handle.draw.map(|draw| draw(gate, rotation, bounding_box))
+            }
+            _ => None,
         }
     }
 
@@ -97,13 +410,27 @@ impl DestructedGate {
         gate: GatePtr,
     ) -> Result<DestructedGateDefinition, destructor::Error> {
         match &self.handle {
-            DestructedGateHandle::V0(handle) => handle.get_normalised_definition(gate, &self.id),
+            DestructedGateHandle::V1(handle) => handle.get_normalised_definition(gate, &self.id),
+            DestructedGateHandle::Sandboxed(handle) => handle
+                .definition(gate)
+                .map_err(destructor::Error::from_sandbox),
+            DestructedGateHandle::Native(handle) => Ok((handle.definition)(gate)),
+            #[cfg(feature = "wasm")]
+            DestructedGateHandle::Wasm(handle) => handle.definition(gate),
+            #[cfg(feature = "script")]
+            DestructedGateHandle::Script(handle) => handle.definition(gate),
         }
     }
 
-    pub fn properties(&self, gate: GatePtrMut) -> PropertiesMut {
+    /// None if the library does not export gate_props, if the gate runs in a sandbox,
+    /// properties do not cross the process boundary,
+    /// or if the gate is native, a wasm module or a script, which have no properties
+    pub fn properties(&self, gate: GatePtrMut) -> Option<PropertiesMut> {
         match &self.handle {
-            DestructedGateHandle::V0(handle) => (handle.properties)(gate),
+            DestructedGateHandle::V1(handle) => {
+                handle.properties.map(|properties| properties(gate))
+            }
+            _ => None,
         }
     }
 
@@ -111,7 +438,17 @@ impl DestructedGate {
     /// (unless the component file throws an error)
     pub fn serialize(&self, gate: GatePtr) -> Slice {
         match &self.handle {
-            DestructedGateHandle::V0(handle) => (handle.serialize)(gate),
+            DestructedGateHandle::V1(handle) => (handle.serialize)(gate),
+            DestructedGateHandle::Sandboxed(handle) => {
+                slice::from_vec_rustonly(handle.serialize(gate))
+            }
+            DestructedGateHandle::Native(handle) => (handle.serialize)(gate),
+            #[cfg(feature = "wasm")]
+            DestructedGateHandle::Wasm(handle) => slice::from_vec_rustonly(handle.serialize(gate)),
+            #[cfg(feature = "script")]
+            DestructedGateHandle::Script(handle) => {
+                slice::from_vec_rustonly(handle.serialize(gate))
+            }
         }
     }
 
@@ -120,18 +457,32 @@ impl DestructedGate {
     /// (unless the component file throws an error)
     pub fn deserialize(&self, bytes: &Slice) -> Option<GatePtrMut> {
         match &self.handle {
-            DestructedGateHandle::V0(handle) => {
+            DestructedGateHandle::V1(handle) => {
                 let ptr = (handle.deserialize)(bytes);
                 if ptr.is_null() { None } else { Some(ptr) }
             }
+            DestructedGateHandle::Sandboxed(handle) => {
+                handle.deserialize(slice::from_slice::<u8>(bytes).to_vec())
+            }
+            DestructedGateHandle::Native(handle) => (handle.deserialize)(bytes),
+            #[cfg(feature = "wasm")]
+            DestructedGateHandle::Wasm(handle) => handle.deserialize(slice::from_slice(bytes)),
+            #[cfg(feature = "script")]
+            DestructedGateHandle::Script(handle) => handle.deserialize(slice::from_slice(bytes)),
         }
     }
 
-    /// this is guaranteed to succeed
+    /// fails if the wasm module traps, every other kind of gate succeeds
     /// (unless the component file throws an error)
-    pub fn default_value(&self) -> GatePtrMut {
+    pub fn default_value(&self) -> Result<GatePtrMut, destructor::Error> {
         match &self.handle {
-            DestructedGateHandle::V0(handle) => (handle.default_value)(),
+            DestructedGateHandle::V1(handle) => Ok((handle.default_value)()),
+            DestructedGateHandle::Sandboxed(handle) => Ok(handle.default_value()),
+            DestructedGateHandle::Native(handle) => Ok((handle.default_value)()),
+            #[cfg(feature = "wasm")]
+            DestructedGateHandle::Wasm(handle) => handle.default_value(),
+            #[cfg(feature = "script")]
+            DestructedGateHandle::Script(handle) => Ok(handle.default_value()),
         }
     }
 
@@ -141,7 +492,13 @@ impl DestructedGate {
     /// (unless the component file throws an error, or the data is already been dropped)
     pub fn drop_mem(&self, gate: GatePtrMut) {
         match &self.handle {
-            DestructedGateHandle::V0(handle) => (handle.drop_mem)(gate),
+            DestructedGateHandle::V1(handle) => (handle.drop_mem)(gate),
+            DestructedGateHandle::Sandboxed(handle) => handle.drop_mem(gate),
+            DestructedGateHandle::Native(handle) => (handle.drop_mem)(gate),
+            #[cfg(feature = "wasm")]
+            DestructedGateHandle::Wasm(handle) => handle.drop_mem(gate),
+            #[cfg(feature = "script")]
+            DestructedGateHandle::Script(handle) => handle.drop_mem(gate),
         }
     }
 }
@@ -150,4 +507,70 @@ impl DestructedGate {
     pub fn id(&self) -> &ComponentVersion {
         &self.id
     }
+
+    /// the library the functions are from,
+    /// None if the gate runs in a sandbox helper, is native, a wasm module, a script
+    /// or is destructed from a symbol table
+    pub fn get_library(&self) -> Option<&LibraryHandle> {
+        self.library.as_ref()
+    }
+
+    /// optional entry points the library exports,
+    /// none for gates that are not loaded from a library in this process
+    pub fn capabilities(&self) -> Capabilities {
+        match &self.handle {
+            DestructedGateHandle::V1(handle) => handle.capabilities,
+            _ => Capabilities::NONE,
+        }
+    }
+
+    pub fn is_sandboxed(&self) -> bool {
+        matches!(self.handle, DestructedGateHandle::Sandboxed(_))
+    }
+
+    pub fn is_native(&self) -> bool {
+        matches!(self.handle, DestructedGateHandle::Native(_))
+    }
+
+    pub fn is_wasm(&self) -> bool {
+        #[cfg(feature = "wasm")]
+        return matches!(self.handle, DestructedGateHandle::Wasm(_));
+
+        #[cfg(not(feature = "wasm"))]
+        false
+    }
+
+    pub fn is_script(&self) -> bool {
+        #[cfg(feature = "script")]
+        return matches!(self.handle, DestructedGateHandle::Script(_));
+
+        #[cfg(not(feature = "script"))]
+        false
+    }
+
+    /// if the gate must be ticked with tick_serialized,
+    /// it cannot read or write data pointers of this process
+    pub fn ticks_serialized(&self) -> bool {
+        self.is_sandboxed() || self.is_wasm() || self.is_script()
+    }
+
+    /// if the gate can consume or produce data of the type
+    /// - gates ticked with data pointers cannot use data that is not readable in this process
+    /// - sandboxed gates can only use data that the helper can load
+    pub fn can_use_data(&self, data_type: &DestructedData) -> bool {
+        if !self.ticks_serialized() && !data_type.is_in_process() {
+            return false;
+        }
+
+        !(self.is_sandboxed() && (data_type.is_native() || data_type.is_wasm()))
+    }
+}
+
+/// consumers serialized for gates ticked with tick_serialized in this process
+#[cfg(any(feature = "wasm", feature = "script"))]
+fn consumer_bytes(consumers: &[(&DestructedData, DataPtr)]) -> Vec<Vec<u8>> {
+    consumers
+        .iter()
+        .map(|(data_type, data)| data_type.to_bytes(*data))
+        .collect()
 }
@@ -8,6 +8,11 @@
 /// content in this module should only be accessed through the corresponding
 /// struct in ./
 mod v0;
+/// This module is NOT to be made public, see v0
+mod v1;
+
+mod capabilities;
+pub use capabilities::*;
 
 mod conn;
 pub use conn::*;
@@ -15,3 +20,15 @@ mod data;
 pub use data::*;
 mod gate;
 pub use gate::*;
+mod native;
+pub use native::*;
+/// content in this module should only be accessed through DestructedGate
+#[cfg(feature = "script")]
+mod script;
+/// content in this module should only be accessed through DestructedGate and DestructedData
+#[cfg(feature = "wasm")]
+mod wasm;
+
+/// schema versions of the bindings the destructors can handle,
+/// libraries report theirs through the schema_version symbol
+pub const SUPPORTED_SCHEMA_VERSIONS: std::ops::RangeInclusive<u32> = 0..=1;
@@ -0,0 +1,155 @@
+use std::{ffi::c_void, sync::Arc};
+
+use xdsim_cbinds::common::Slice;
+
+use crate::{
+    common::world::{DataPtr, DataPtrMut, GatePtr, GatePtrMut},
+    packages::{chelper::slice, destructor::DestructedGateDefinition},
+};
+
+/// a gate type implemented in rust, wrapped by DestructedGate::new_native
+///
+/// gates are created, ticked and dropped through DestructedGate like any other gate,
+/// but never leave the process: they cannot be drawn, have no properties
+/// and cannot be used by sandboxed gates
+///
+/// # Safety
+///
+/// the producers returned by tick are owned by the world and dropped by the drop_mem of
+/// the data type in the definition, so tick must return exactly one pointer per producer
+/// in definition order, each created by native_into_ptr from the NativeData::Data
+/// of the data type of that producer
+///
+/// consumers passed to tick are only valid during the call,
+/// and are values of the data types the definition requests
+pub unsafe trait NativeGate: Send + Sync + 'static {
+    /// state of a single gate
+    ///
+    /// must not be zero sized: gates are told apart by their pointer,
+    /// and every box of a zero sized type has the same dangling pointer
+    type Gate: Send + 'static;
+
+    fn default_value(&self) -> Self::Gate;
+
+    fn serialize(&self, gate: &Self::Gate) -> Vec<u8>;
+
+    /// None if the bytes are not a gate of this type
+    fn deserialize(&self, bytes: &[u8]) -> Option<Self::Gate>;
+
+    fn definition(&self, gate: &Self::Gate) -> DestructedGateDefinition;
+
+    /// consumers are data pointers in definition order,
+    /// returns newly created producers in definition order, see the safety section
+    fn tick(&self, gate: &mut Self::Gate, consumers: &[DataPtr]) -> Vec<DataPtrMut>;
+}
+
+/// a data type implemented in rust, wrapped by DestructedData::new_native
+pub trait NativeData: Send + Sync + 'static {
+    /// value of a single data
+    type Data: Send + 'static;
+
+    fn default_value(&self) -> Self::Data;
+
+    fn serialize(&self, data: &Self::Data) -> Vec<u8>;
+
+    /// None if the bytes are not data of this type
+    fn deserialize(&self, bytes: &[u8]) -> Option<Self::Data>;
+}
+
+/// pointer to the value of a native component, to be dropped by the drop_mem of its type
+pub fn native_into_ptr<T: Send + 'static>(value: T) -> *mut c_void {
+    Box::into_raw(Box::new(value)) as *mut c_void
+}
+
+/// value behind a pointer from native_into_ptr
+///
+/// # Safety
+///
+/// ptr must come from native_into_ptr::<T> and not be dropped for 'a
+pub unsafe fn native_from_ptr<'a, T>(ptr: *const c_void) -> &'a T {
+    unsafe { &*(ptr as *const T) }
+}
+
+/// None if the bytes are not a value of the type,
+/// gate and data pointers are the same type
+type DeserializeFn = dyn Fn(&Slice) -> Option<*mut c_void> + Send + Sync;
+
+/// gate functions of a NativeGate, with the same signatures as the ones from a library
+pub struct DestructedNativeGate {
+    pub tick: Box<dyn Fn(GatePtrMut, *const Slice) -> Slice + Send + Sync>,
+    pub definition: Box<dyn Fn(GatePtr) -> DestructedGateDefinition + Send + Sync>,
+    pub serialize: Box<dyn Fn(GatePtr) -> Slice + Send + Sync>,
+    pub deserialize: Box<DeserializeFn>,
+    pub default_value: Box<dyn Fn() -> GatePtrMut + Send + Sync>,
+    pub drop_mem: Box<dyn Fn(GatePtrMut) + Send + Sync>,
+}
+
+impl DestructedNativeGate {
+    pub fn new<T: NativeGate>(gate: T) -> Self {
+        const {
+            assert!(
+                size_of::<T::Gate>() != 0,
+                "NativeGate::Gate must not be zero sized"
+            )
+        };
+        let gate = Arc::new(gate);
+
+        Self {
+            tick: Box::new({
+                let gate = gate.clone();
+                move |ptr, consumers| {
+                    let consumers = slice::from_slice::<DataPtr>(unsafe { &*consumers });
+                    let state = unsafe { &mut *(ptr as *mut T::Gate) };
+                    slice::from_vec_rustonly(gate.tick(state, consumers))
+                }
+            }),
+            definition: Box::new({
+                let gate = gate.clone();
+                move |ptr| gate.definition(unsafe { native_from_ptr(ptr) })
+            }),
+            serialize: Box::new({
+                let gate = gate.clone();
+                move |ptr| slice::from_vec_rustonly(gate.serialize(unsafe { native_from_ptr(ptr) }))
+            }),
+            deserialize: Box::new({
+                let gate = gate.clone();
+                move |bytes| {
+                    gate.deserialize(slice::from_slice(bytes))
+                        .map(native_into_ptr)
+                }
+            }),
+            default_value: Box::new(move || native_into_ptr(gate.default_value())),
+            drop_mem: Box::new(|ptr| drop(unsafe { Box::from_raw(ptr as *mut T::Gate) })),
+        }
+    }
+}
+
+/// data functions of a NativeData, with the same signatures as the ones from a library
+pub struct DestructedNativeData {
+    pub serialize: Box<dyn Fn(DataPtr) -> Slice + Send + Sync>,
+    pub deserialize: Box<DeserializeFn>,
+    pub default_value: Box<dyn Fn() -> DataPtrMut + Send + Sync>,
+    pub drop_mem: Box<dyn Fn(DataPtrMut) + Send + Sync>,
+}
+
+impl DestructedNativeData {
+    pub fn new<T: NativeData>(data: T) -> Self {
+        let data = Arc::new(data);
+
+        Self {
+            serialize: Box::new({
+                let data = data.clone();
+                move |ptr| slice::from_vec_rustonly(data.serialize(unsafe { native_from_ptr(ptr) }))
+            }),
+            deserialize: Box::new({
+                let data = data.clone();
+                move |bytes| {
+                    data.deserialize(slice::from_slice(bytes))
+                        .map(native_into_ptr)
+                }
+            }),
+            default_value: Box::new(move || native_into_ptr(data.default_value())),
+            drop_mem: Box::new(|ptr| drop(unsafe { Box::from_raw(ptr as *mut T::Data) })),
+        }
+    }
+}
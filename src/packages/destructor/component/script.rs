@@ -0,0 +1,235 @@
+//! Gates written in rhai scripts, for prototyping before porting them to a library
+//!
+//! the state of a gate is any rhai value, bound to `this` in the functions that use it.
+//! a script must define
+//! - `fn definition()`, a map with the fields of sandbox::protocol::GateDefinition
+//!   (package is a reserved keyword in rhai, it is quoted), e.g.
+//!   ```rhai
+//!   #{
+//!       consumers: [#{ name: "in", data_type_req: #{ "package": "std", version_req: "=0.1.0", component: "bool" }, position: [0, 0.5] }],
+//!       producers: [#{ name: "out", data_type: #{ "package": "std", version: "0.1.0", component: "bool" }, position: [1, 0.5] }],
+//!       bounding_box: #{ top: 0, bottom: 1, left: 0, right: 1 },
+//!   }
+//!   ```
+//! - `fn tick(inputs)`, inputs are the consumers in definition order: std bool as a bool,
+//!   any other data type as a blob of its serialized bytes (only its package knows the layout),
+//!   returns the serialized producers in definition order,
+//!   each as a blob, an array of bytes, or a bool (one byte, 0 or 1)
+//!
+//! and gates with state define (gates without init have no state, which serializes to nothing)
+//! - `fn init()`, the state of a new gate
+//! - `fn serialize()`, the state as a blob
+//! - `fn deserialize(bytes)`, the state from a blob, throws if the bytes are not a gate
+
+use std::{
+    ffi::c_void,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use rhai::{AST, Array, Blob, CallFnOptions, Dynamic, Engine, FuncArgs, Scope};
+
+use crate::{
+    common::world::ComponentVersion,
+    packages::{
+        destructor::{self, DestructedGateDefinition},
+        sandbox::protocol::GateDefinition,
+        stdlib,
+    },
+};
+
+/// operations a single call may take, so a script stuck in a loop cannot stall the world
+pub const MAX_OPERATIONS: u64 = 1_000_000;
+
+pub struct DestructedGate {
+    lib_path: PathBuf,
+    engine: Engine,
+    ast: AST,
+    has_state: bool,
+}
+
+impl DestructedGate {
+    /// functions the script must define
+    pub const FUNCTIONS: &[&str] = &["definition", "tick"];
+
+    pub fn new(lib_path: &Path) -> Result<Self, destructor::Error> {
+        let source = fs::read_to_string(lib_path).map_err(|e| destructor::Error::Script {
+            reason: format!("cannot read {}: {e}", lib_path.display()),
+        })?;
+
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+
+        let ast = engine
+            .compile(source)
+            .map_err(|e| destructor::Error::Script {
+                reason: e.to_string(),
+            })?;
+
+        let defines = |name: &str| ast.iter_functions().any(|function| function.name == name);
+
+        if let Some(missing) = Self::FUNCTIONS.iter().find(|name| !defines(name)) {
+            return Err(destructor::Error::Script {
+                reason: format!("script does not define fn {missing}"),
+            });
+        }
+
+        Ok(Self {
+            lib_path: lib_path.to_path_buf(),
+            has_state: defines("init"),
+            engine,
+            ast,
+        })
+    }
+
+    pub fn get_lib_path(&self) -> &Path {
+        &self.lib_path
+    }
+
+    fn call(
+        &self,
+        state: Option<&mut Dynamic>,
+        name: &str,
+        args: impl FuncArgs,
+    ) -> Result<Dynamic, destructor::Error> {
+        let mut options = CallFnOptions::new().eval_ast(false);
+        if let Some(state) = state {
+            options = options.bind_this_ptr(state);
+        }
+
+        self.engine
+            .call_fn_with_options(options, &mut Scope::new(), &self.ast, name, args)
+            .map_err(|e| destructor::Error::Script {
+                reason: format!("fn {name}: {e}"),
+            })
+    }
+
+    /// serialized consumers in, serialized producers out
+    pub fn tick(
+        &self,
+        gate: *mut c_void,
+        consumers: &[(&ComponentVersion, Vec<u8>)],
+    ) -> Result<Vec<Vec<u8>>, destructor::Error> {
+        let inputs: Array = consumers
+            .iter()
+            .map(|(data_type, bytes)| to_input(data_type, bytes))
+            .collect();
+
+        let produced = self.call(Some(state_mut(gate)), "tick", (inputs,))?;
+
+        produced
+            .into_array()
+            .map_err(|got| destructor::Error::Script {
+                reason: format!("fn tick returned {got}, expected an array"),
+            })?
+            .into_iter()
+            .map(to_bytes)
+            .collect()
+    }
+
+    pub fn definition(
+        &self,
+        gate: *const c_void,
+    ) -> Result<DestructedGateDefinition, destructor::Error> {
+        // the definition may depend on the state, but must not change it
+        let mut state = state_ref(gate).clone();
+        let definition = self.call(Some(&mut state), "definition", ())?;
+
+        serde_json::to_value(&definition)
+            .and_then(serde_json::from_value::<GateDefinition>)
+            .map(DestructedGateDefinition::from)
+            .map_err(|e| destructor::Error::Script {
+                reason: format!("fn definition returned an invalid definition: {e}"),
+            })
+    }
+
+    /// empty if the gate has no state or the script throws
+    pub fn serialize(&self, gate: *const c_void) -> Vec<u8> {
+        if !self.has_state {
+            return Vec::new();
+        }
+
+        let mut state = state_ref(gate).clone();
+        self.call(Some(&mut state), "serialize", ())
+            .and_then(to_bytes)
+            .unwrap_or_default()
+    }
+
+    pub fn deserialize(&self, bytes: &[u8]) -> Option<*mut c_void> {
+        let state = if self.has_state {
+            self.call(None, "deserialize", (Dynamic::from_blob(bytes.to_vec()),))
+                .ok()?
+        } else {
+            Dynamic::UNIT
+        };
+
+        Some(into_ptr(state))
+    }
+
+    /// a unit state if the script throws
+    pub fn default_value(&self) -> *mut c_void {
+        let state = if self.has_state {
+            self.call(None, "init", ()).unwrap_or(Dynamic::UNIT)
+        } else {
+            Dynamic::UNIT
+        };
+
+        into_ptr(state)
+    }
+
+    pub fn drop_mem(&self, gate: *mut c_void) {
+        if !gate.is_null() {
+            drop(unsafe { Box::from_raw(gate as *mut Dynamic) });
+        }
+    }
+}
+
+fn into_ptr(state: Dynamic) -> *mut c_void {
+    Box::into_raw(Box::new(state)) as *mut c_void
+}
+
+fn state_ref<'a>(gate: *const c_void) -> &'a Dynamic {
+    unsafe { &*(gate as *const Dynamic) }
+}
+
+fn state_mut<'a>(gate: *mut c_void) -> &'a mut Dynamic {
+    unsafe { &mut *(gate as *mut Dynamic) }
+}
+
+/// a consumer passed to tick
+fn to_input(data_type: &ComponentVersion, bytes: &[u8]) -> Dynamic {
+    if *data_type == stdlib::component("bool")
+        && let [bit @ (0 | 1)] = bytes
+    {
+        return Dynamic::from_bool(*bit == 1);
+    }
+
+    Dynamic::from_blob(bytes.to_vec())
+}
+
+/// a producer returned by tick or serialize
+fn to_bytes(value: Dynamic) -> Result<Vec<u8>, destructor::Error> {
+    if value.is_blob() {
+        return Ok(value.cast::<Blob>());
+    }
+
+    if let Ok(bit) = value.as_bool() {
+        return Ok(vec![bit as u8]);
+    }
+
+    let type_name = value.type_name();
+    value
+        .into_array()
+        .ok()
+        .and_then(|bytes| {
+            bytes
+                .into_iter()
+                .map(|byte| u8::try_from(byte.as_int().ok()?).ok())
+                .collect()
+        })
+        .ok_or_else(|| destructor::Error::Script {
+            reason: format!(
+                "{type_name} is not bytes, expected a blob, an array of bytes or a bool"
+            ),
+        })
+}
@@ -20,36 +20,26 @@ pub struct DestructedConn {
 }
 
 impl DestructedConn {
+    /// symbols the library must export (besides schema_version)
+    pub const SYMBOLS: &[&str] = &[
+        "conn_draw",
+        "conn_def",
+        "conn_props",
+        "conn_serialize",
+        "conn_deserialize",
+        "conn_default",
+        "conn_drop",
+    ];
+
     pub fn new(request: &DestructRequest) -> Result<Self, destructor::Error> {
         Ok(Self {
-            draw: *request
-                .get_library()
-                .get_symbol("conn_draw")
-                .map_err(destructor::Error::from_get_symbol)?,
-            definition: *request
-                .get_library()
-                .get_symbol("conn_def")
-                .map_err(destructor::Error::from_get_symbol)?,
-            properties: *request
-                .get_library()
-                .get_symbol("conn_props")
-                .map_err(destructor::Error::from_get_symbol)?,
-            serialize: *request
-                .get_library()
-                .get_symbol("conn_serialize")
-                .map_err(destructor::Error::from_get_symbol)?,
-            deserialize: *request
-                .get_library()
-                .get_symbol("conn_deserialize")
-                .map_err(destructor::Error::from_get_symbol)?,
-            default_value: *request
-                .get_library()
-                .get_symbol("conn_default")
-                .map_err(destructor::Error::from_get_symbol)?,
-            drop_mem: *request
-                .get_library()
-                .get_symbol("conn_drop")
-                .map_err(destructor::Error::from_get_symbol)?,
+            draw: request.get_symbol("conn_draw")?,
+            definition: request.get_symbol("conn_def")?,
+            properties: request.get_symbol("conn_props")?,
+            serialize: request.get_symbol("conn_serialize")?,
+            deserialize: request.get_symbol("conn_deserialize")?,
+            default_value: request.get_symbol("conn_default")?,
+            drop_mem: request.get_symbol("conn_drop")?,
         })
     }
 }
@@ -13,24 +13,20 @@ pub struct DestructedData {
 }
 
 impl DestructedData {
+    /// symbols the library must export (besides schema_version)
+    pub const SYMBOLS: &[&str] = &[
+        "data_serialize",
+        "data_deserialize",
+        "data_default",
+        "data_drop",
+    ];
+
     pub fn new(request: &DestructRequest) -> Result<Self, destructor::Error> {
         Ok(Self {
-            serialize: *request
-                .get_library()
-                .get_symbol("data_serialize")
-                .map_err(destructor::Error::from_get_symbol)?,
-            deserialize: *request
-                .get_library()
-                .get_symbol("data_deserialize")
-                .map_err(destructor::Error::from_get_symbol)?,
-            default_value: *request
-                .get_library()
-                .get_symbol("data_default")
-                .map_err(destructor::Error::from_get_symbol)?,
-            drop_mem: *request
-                .get_library()
-                .get_symbol("data_drop")
-                .map_err(destructor::Error::from_get_symbol)?,
+            serialize: request.get_symbol("data_serialize")?,
+            deserialize: request.get_symbol("data_deserialize")?,
+            default_value: request.get_symbol("data_default")?,
+            drop_mem: request.get_symbol("data_drop")?,
         })
     }
 }
@@ -5,7 +5,7 @@ use xdsim_cbinds::{
 };
 
 use crate::{
-    common::world::{ComponentVersion, ComponentVersionReq, GatePtr},
+    common::world::{ComponentVersion, ComponentVersionReq},
     packages::{
         chelper::slice,
         destructor::{
@@ -27,132 +27,120 @@ pub struct DestructedGate {
 }
 
 impl DestructedGate {
+    /// symbols the library must export (besides schema_version)
+    pub const SYMBOLS: &[&str] = &[
+        "gate_tick",
+        "gate_draw",
+        "gate_def",
+        "gate_props",
+        "gate_serialize",
+        "gate_deserialize",
+        "gate_default",
+        "gate_drop",
+    ];
+
     pub fn new(request: &DestructRequest) -> Result<Self, destructor::Error> {
         Ok(Self {
-            tick: *request
-                .get_library()
-                .get_symbol("gate_tick")
-                .map_err(destructor::Error::from_get_symbol)?,
-            draw: *request
-                .get_library()
-                .get_symbol("gate_draw")
-                .map_err(destructor::Error::from_get_symbol)?,
-            definition: *request
-                .get_library()
-                .get_symbol("gate_def")
-                .map_err(destructor::Error::from_get_symbol)?,
-            properties: *request
-                .get_library()
-                .get_symbol("gate_props")
-                .map_err(destructor::Error::from_get_symbol)?,
-            serialize: *request
-                .get_library()
-                .get_symbol("gate_serialize")
-                .map_err(destructor::Error::from_get_symbol)?,
-            deserialize: *request
-                .get_library()
-                .get_symbol("gate_deserialize")
-                .map_err(destructor::Error::from_get_symbol)?,
-            default_value: *request
-                .get_library()
-                .get_symbol("gate_default")
-                .map_err(destructor::Error::from_get_symbol)?,
-            drop_mem: *request
-                .get_library()
-                .get_symbol("gate_drop")
-                .map_err(destructor::Error::from_get_symbol)?,
+            tick: request.get_symbol("gate_tick")?,
+            draw: request.get_symbol("gate_draw")?,
+            definition: request.get_symbol("gate_def")?,
+            properties: request.get_symbol("gate_props")?,
+            serialize: request.get_symbol("gate_serialize")?,
+            deserialize: request.get_symbol("gate_deserialize")?,
+            default_value: request.get_symbol("gate_default")?,
+            drop_mem: request.get_symbol("gate_drop")?,
         })
     }
+}
 
-    pub fn get_normalised_definition(
-        &self,
-        gate: GatePtr,
-        gate_id: &ComponentVersion,
-    ) -> Result<DestructedGateDefinition, destructor::Error> {
-        let definition = (self.definition)(gate);
-        let consumers: &[GateConsumerEntry] = slice::from_slice(&definition.consumers);
-        let producers: &[GateProducerEntry] = slice::from_slice(&definition.producers);
-
-        pub fn to_consumer_entries(
-            entries: &[GateConsumerEntry],
-            gate_id: &ComponentVersion,
-        ) -> Result<Vec<DestructedGateConsumerEntry>, destructor::Error> {
-            let mut out = Vec::with_capacity(entries.len());
+/// a gate definition that is the same for all versions of gates,
+/// v1 libraries use the same definition layout
+pub fn normalise_definition(
+    definition: GateDefinition,
+    gate_id: &ComponentVersion,
+) -> Result<DestructedGateDefinition, destructor::Error> {
+    let consumers: &[GateConsumerEntry] = slice::from_slice(&definition.consumers);
+    let producers: &[GateProducerEntry] = slice::from_slice(&definition.producers);
 
-            for entry in entries {
-                let GateConsumerEntry {
-                    name,
-                    data_type_req:
-                        ComponentIdent {
-                            package,
-                            version,
-                            component,
-                        },
-                    position,
-                } = entry;
+    pub fn to_consumer_entries(
+        entries: &[GateConsumerEntry],
+        gate_id: &ComponentVersion,
+    ) -> Result<Vec<DestructedGateConsumerEntry>, destructor::Error> {
+        let mut out = Vec::with_capacity(entries.len());
 
-                out.push(DestructedGateConsumerEntry {
-                    name: slice::from_str(name),
-                    data_type_req: ComponentVersionReq {
-                        package: slice::from_str(package),
-                        component: slice::from_str(component),
-                        version_req: VersionReq::parse(&slice::from_str(version)).map_err(|e| {
-                            destructor::Error::InvalidVersionReq {
-                                component: Box::new(gate_id.clone()),
-                                version: slice::from_str(version),
-                                reason: e.to_string(),
-                            }
-                        })?,
+        for entry in entries {
+            let GateConsumerEntry {
+                name,
+                data_type_req:
+                    ComponentIdent {
+                        package,
+                        version,
+                        component,
                     },
-                    position: *position,
-                })
-            }
+                position,
+            } = entry;
 
-            Ok(out)
+            out.push(DestructedGateConsumerEntry {
+                name: slice::from_str(name),
+                data_type_req: ComponentVersionReq {
+                    package: slice::from_str(package),
+                    component: slice::from_str(component),
+                    version_req: VersionReq::parse(&slice::from_str(version)).map_err(|e| {
+                        destructor::Error::InvalidVersionReq {
+                            component: Box::new(gate_id.clone()),
+                            version: slice::from_str(version),
+                            reason: e.to_string(),
+                        }
+                    })?,
+                },
+                position: *position,
+            })
         }
 
-        pub fn to_producer_entries(
-            entries: &[GateProducerEntry],
-            gate_id: &ComponentVersion,
-        ) -> Result<Vec<DestructedGateProducerEntry>, destructor::Error> {
-            let mut out = Vec::with_capacity(entries.len());
+        Ok(out)
+    }
 
-            for entry in entries {
-                let GateProducerEntry {
-                    name,
-                    data_type:
-                        ComponentIdent {
-                            package,
-                            version,
-                            component,
-                        },
-                    position,
-                } = entry;
+    pub fn to_producer_entries(
+        entries: &[GateProducerEntry],
+        gate_id: &ComponentVersion,
+    ) -> Result<Vec<DestructedGateProducerEntry>, destructor::Error> {
+        let mut out = Vec::with_capacity(entries.len());
 
-                out.push(DestructedGateProducerEntry {
-                    name: slice::from_str(name),
-                    data_type: ComponentVersion {
-                        package: slice::from_str(package),
-                        component: slice::from_str(component),
-                        version: Version::parse(&slice::from_str(version)).map_err(|e| {
-                            destructor::Error::InvalidVersionReq {
-                                component: Box::new(gate_id.clone()),
-                                version: slice::from_str(version),
-                                reason: e.to_string(),
-                            }
-                        })?,
+        for entry in entries {
+            let GateProducerEntry {
+                name,
+                data_type:
+                    ComponentIdent {
+                        package,
+                        version,
+                        component,
                     },
-                    position: *position,
-                })
-            }
+                position,
+            } = entry;
 
-            Ok(out)
+            out.push(DestructedGateProducerEntry {
+                name: slice::from_str(name),
+                data_type: ComponentVersion {
+                    package: slice::from_str(package),
+                    component: slice::from_str(component),
+                    version: Version::parse(&slice::from_str(version)).map_err(|e| {
+                        destructor::Error::InvalidVersionReq {
+                            component: Box::new(gate_id.clone()),
+                            version: slice::from_str(version),
+                            reason: e.to_string(),
+                        }
+                    })?,
+                },
+                position: *position,
+            })
         }
 
-        Ok(DestructedGateDefinition {
-            consumers: to_consumer_entries(consumers, gate_id)?,
-            producers: to_producer_entries(producers, gate_id)?,
-            bounding_box: definition.bounding_box.into(),
-        })
+        Ok(out)
     }
+
+    Ok(DestructedGateDefinition {
+        consumers: to_consumer_entries(consumers, gate_id)?,
+        producers: to_producer_entries(producers, gate_id)?,
+        bounding_box: definition.bounding_box.into(),
+    })
 }
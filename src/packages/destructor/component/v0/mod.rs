@@ -3,6 +3,6 @@
 mod data;
 pub use data::DestructedData;
 mod gate;
-pub use gate::DestructedGate;
+pub use gate::{DestructedGate, normalise_definition};
 mod conn;
 pub use conn::DestructedConn;
@@ -0,0 +1,124 @@
+use xdsim_cbinds::{
+    common::*,
+    v0::{app_state::PropertiesMut, component::*, graphics::Graphic},
+};
+
+use crate::{
+    common::world::{ComponentVersion, GatePtr},
+    packages::destructor::{
+        self, Capabilities, DestructRequest, DestructedGateDefinition, component::v0,
+    },
+};
+
+pub struct DestructedGate {
+    pub capabilities: Capabilities,
+    pub tick: extern "C" fn(GateMut, *const Slice) -> Slice,
+    pub definition: extern "C" fn(Gate) -> GateDefinition,
+    pub serialize: extern "C" fn(Gate) -> Slice,
+    pub deserialize: extern "C" fn(*const Slice) -> GateMut,
+    pub default_value: extern "C" fn() -> GateMut,
+    pub drop_mem: extern "C" fn(GateMut),
+    /// Capabilities::PROPS
+    pub properties: Option<extern "C" fn(GateMut) -> PropertiesMut>,
+    /// Capabilities::DRAW
+    pub draw: Option<extern "C" fn(Gate, Rotation, Vec2) -> Graphic>,
+    /// Capabilities::BATCH_TICK, takes a slice of gates and a slice of *const Slice consumers
+    /// (one per gate), returns a slice of producer slices (one per gate),
+    /// dropping the returned slice drops the producer slices in it
+    pub tick_batch: Option<extern "C" fn(*const Slice, *const Slice) -> Slice>,
+    /// Capabilities::INTERACT, takes the event as a slice of bytes,
+    /// returns if the state of the gate changed
+    pub interact: Option<extern "C" fn(GateMut, *const Slice) -> bool>,
+}
+
+impl DestructedGate {
+    /// symbols the library must export (besides schema_version)
+    pub const SYMBOLS: &[&str] = &[
+        "capabilities",
+        "gate_tick",
+        "gate_def",
+        "gate_serialize",
+        "gate_deserialize",
+        "gate_default",
+        "gate_drop",
+    ];
+
+    /// symbols the library must export if it reports the capability
+    pub const OPTIONAL_SYMBOLS: &[(Capabilities, &str)] = &[
+        (Capabilities::PROPS, "gate_props"),
+        (Capabilities::DRAW, "gate_draw"),
+        (Capabilities::BATCH_TICK, "gate_tick_batch"),
+        (Capabilities::INTERACT, "gate_interact"),
+    ];
+
+    pub fn new(request: &DestructRequest) -> Result<Self, destructor::Error> {
+        let get_capabilities: extern "C" fn() -> u64 = request.get_symbol("capabilities")?;
+        let capabilities = Capabilities::from_bits(get_capabilities());
+
+        Ok(Self {
+            capabilities,
+            tick: request.get_symbol("gate_tick")?,
+            definition: request.get_symbol("gate_def")?,
+            serialize: request.get_symbol("gate_serialize")?,
+            deserialize: request.get_symbol("gate_deserialize")?,
+            default_value: request.get_symbol("gate_default")?,
+            drop_mem: request.get_symbol("gate_drop")?,
+            properties: optional_symbol(request, capabilities, Capabilities::PROPS, "gate_props")?,
+            draw: optional_symbol(request, capabilities, Capabilities::DRAW, "gate_draw")?,
+            tick_batch: optional_symbol(
+                request,
+                capabilities,
+                Capabilities::BATCH_TICK,
+                "gate_tick_batch",
+            )?,
+            interact: optional_symbol(
+                request,
+                capabilities,
+                Capabilities::INTERACT,
+                "gate_interact",
+            )?,
+        })
+    }
+
+    pub fn get_normalised_definition(
+        &self,
+        gate: GatePtr,
+        gate_id: &ComponentVersion,
+    ) -> Result<DestructedGateDefinition, destructor::Error> {
+        v0::normalise_definition((self.definition)(gate), gate_id)
+    }
+}
+
+/// a v0 gate exports every entry point v0 has, which are props and draw
+impl From<v0::DestructedGate> for DestructedGate {
+    fn from(value: v0::DestructedGate) -> Self {
+        Self {
+            capabilities: Capabilities::PROPS | Capabilities::DRAW,
+            tick: value.tick,
+            definition: value.definition,
+            serialize: value.serialize,
+            deserialize: value.deserialize,
+            default_value: value.default_value,
+            drop_mem: value.drop_mem,
+            properties: Some(value.properties),
+            draw: Some(value.draw),
+            tick_batch: None,
+            interact: None,
+        }
+    }
+}
+
+/// None if the library does not report the capability,
+/// a library that reports it but does not export the symbol is an error
+fn optional_symbol<T: Copy>(
+    request: &DestructRequest,
+    capabilities: Capabilities,
+    capability: Capabilities,
+    symbol_name: &str,
+) -> Result<Option<T>, destructor::Error> {
+    if !capabilities.contains(capability) {
+        return Ok(None);
+    }
+
+    request.get_symbol(symbol_name).map(Some)
+}
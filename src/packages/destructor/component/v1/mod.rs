@@ -0,0 +1,11 @@
+//! Destructors for schema version 1
+//!
+//! v1 gate libraries export `capabilities() -> u64` (see Capabilities),
+//! the entry points every v0 gate exports are split into required and optional ones,
+//! and v0 gates are adapted to v1 so both are used through the same struct
+//!
+//! data types and connections export the same functions as in v0,
+//! they are destructed with the v0 destructors
+
+mod gate;
+pub use gate::DestructedGate;
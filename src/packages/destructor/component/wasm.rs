@@ -0,0 +1,425 @@
+//! Gates and data types in WebAssembly modules, run by wasmi
+//!
+//! a module exposes the same operations as a v0 library, but only bytes cross
+//! the linear memory boundary. it must import nothing and export:
+//! - `memory`
+//! - `schema_version() -> i32`, which is 0
+//! - `alloc(len: i32) -> i32` and `dealloc(ptr: i32, len: i32)`, used by the host for buffers
+//!
+//! and for gates
+//! - `gate_default() -> i32`
+//! - `gate_deserialize(ptr: i32, len: i32) -> i32`, 0 if the bytes are not a gate
+//! - `gate_serialize(gate: i32) -> i64`
+//! - `gate_def(gate: i32) -> i64`, the definition as json (sandbox::protocol::GateDefinition)
+//! - `gate_tick(gate: i32, ptr: i32, len: i32) -> i64`, takes the serialized consumers
+//!   and returns the serialized producers, both in definition order
+//! - `gate_drop(gate: i32)`
+//!
+//! or for data types
+//! - `data_default() -> i32`
+//! - `data_deserialize(ptr: i32, len: i32) -> i32`, 0 if the bytes are not data
+//! - `data_serialize(data: i32) -> i64`
+//! - `data_drop(data: i32)`
+//!
+//! gates and data are handles chosen by the module (never 0), which the host
+//! passes around as gate and data pointers, they are never dereferenced.
+//!
+//! each operation may use up to MAX_FUEL fuel and the memory may grow up to MAX_MEMORY,
+//! so a module stuck in a loop cannot stall the world or take the memory of the host
+//!
+//! buffers passed to the module are allocated with alloc and deallocated by the host after the call,
+//! bytes returned by the module are packed as `ptr << 32 | len`
+//! and deallocated by the host once they are read.
+//! lists of byte strings (consumers and producers of gate_tick)
+//! are each prefixed by their length as a little endian u32
+
+use std::{
+    ffi::c_void,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard, PoisonError},
+};
+
+use wasmi::{
+    Config, Engine, Linker, Memory, Module, Store, StoreLimits, StoreLimitsBuilder, TypedFunc,
+    WasmParams, WasmResults,
+};
+
+use crate::packages::{
+    destructor::{self, DestructedGateDefinition},
+    sandbox::protocol::GateDefinition,
+};
+
+/// the only schema version of wasm modules
+pub const SCHEMA_VERSION: i32 = 0;
+
+/// fuel a single operation may take (about one per instruction), see MAX_OPERATIONS of scripts
+pub const MAX_FUEL: u64 = 10_000_000;
+
+/// bytes the linear memory of a module may grow to
+pub const MAX_MEMORY: usize = 64 * 1024 * 1024;
+
+/// an instance of a module, with the functions every module exports
+struct Runtime {
+    store: Store<StoreLimits>,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    dealloc: TypedFunc<(i32, i32), ()>,
+}
+
+impl Runtime {
+    fn new(lib_path: &Path) -> Result<(Self, wasmi::Instance), destructor::Error> {
+        let bytes = fs::read(lib_path).map_err(|e| destructor::Error::Wasm {
+            reason: format!("cannot read {}: {e}", lib_path.display()),
+        })?;
+
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+        let module = Module::new(&engine, &bytes).map_err(destructor::Error::from_wasm)?;
+
+        let limits = StoreLimitsBuilder::new().memory_size(MAX_MEMORY).build();
+        let mut store = Store::new(&engine, limits);
+        store.limiter(|limits| limits);
+        // the start function and schema_version run on the fuel of loading the module
+        store.set_fuel(MAX_FUEL).expect("fuel metering is enabled");
+
+        let instance = Linker::<StoreLimits>::new(&engine)
+            .instantiate(&mut store, &module)
+            .and_then(|instance| instance.start(&mut store))
+            .map_err(destructor::Error::from_wasm)?;
+
+        let schema_version = instance
+            .get_typed_func::<(), i32>(&store, "schema_version")
+            .and_then(|get_schema_version| get_schema_version.call(&mut store, ()))
+            .map_err(destructor::Error::from_wasm)?;
+        if schema_version != SCHEMA_VERSION {
+            return Err(destructor::Error::UnsupportedSchemaVersion {
+                version: schema_version as u32,
+            });
+        }
+
+        let memory =
+            instance
+                .get_memory(&store, "memory")
+                .ok_or_else(|| destructor::Error::Wasm {
+                    reason: "module does not export memory".to_string(),
+                })?;
+
+        let runtime = Self {
+            alloc: get_func(&instance, &store, "alloc")?,
+            dealloc: get_func(&instance, &store, "dealloc")?,
+            memory,
+            store,
+        };
+
+        Ok((runtime, instance))
+    }
+
+    /// copy bytes into a buffer in the module, which the caller deallocates
+    fn write(&mut self, bytes: &[u8]) -> Result<(i32, i32), wasmi::Error> {
+        let len = bytes.len() as i32;
+        let ptr = self.alloc.call(&mut self.store, len)?;
+        self.memory
+            .write(&mut self.store, ptr as u32 as usize, bytes)
+            .map_err(|e| wasmi::Error::new(e.to_string()))?;
+        Ok((ptr, len))
+    }
+
+    /// copy out and deallocate bytes returned by the module
+    fn take(&mut self, packed: i64) -> Result<Vec<u8>, wasmi::Error> {
+        let ptr = (packed >> 32) as i32;
+        let len = packed as i32;
+
+        let (start, len_bytes) = (ptr as u32 as usize, len as u32 as usize);
+        // checked before allocating, a bogus length must not make the host allocate it
+        if start
+            .checked_add(len_bytes)
+            .is_none_or(|end| end > self.memory.data(&self.store).len())
+        {
+            return Err(wasmi::Error::new(format!(
+                "{len_bytes} bytes at {start} are outside the memory of the module"
+            )));
+        }
+
+        let mut bytes = vec![0; len_bytes];
+        self.memory
+            .read(&self.store, start, &mut bytes)
+            .map_err(|e| wasmi::Error::new(e.to_string()))?;
+        self.dealloc.call(&mut self.store, (ptr, len))?;
+        Ok(bytes)
+    }
+
+    /// call a function taking a buffer of bytes
+    fn call_with_bytes<R: WasmResults>(
+        &mut self,
+        func: &TypedFunc<(i32, i32), R>,
+        bytes: &[u8],
+    ) -> Result<R, wasmi::Error> {
+        let (ptr, len) = self.write(bytes)?;
+        let result = func.call(&mut self.store, (ptr, len));
+        self.dealloc.call(&mut self.store, (ptr, len))?;
+        result
+    }
+}
+
+fn get_func<P: WasmParams, R: WasmResults>(
+    instance: &wasmi::Instance,
+    store: &Store<StoreLimits>,
+    name: &str,
+) -> Result<TypedFunc<P, R>, destructor::Error> {
+    instance
+        .get_typed_func::<P, R>(store, name)
+        .map_err(|e| destructor::Error::Wasm {
+            reason: format!("{name}: {e}"),
+        })
+}
+
+/// the runtime for one operation, with its fuel refilled
+fn lock(runtime: &Mutex<Runtime>) -> MutexGuard<'_, Runtime> {
+    // a trap does not leave the runtime half updated on the host side
+    let mut runtime = runtime.lock().unwrap_or_else(PoisonError::into_inner);
+    runtime
+        .store
+        .set_fuel(MAX_FUEL)
+        .expect("fuel metering is enabled");
+    runtime
+}
+
+/// handle 0 is never a gate or data, see the module documentation
+fn to_new_ptr(handle: i32, function: &str) -> Result<*mut c_void, destructor::Error> {
+    match handle {
+        0 => Err(destructor::Error::Wasm {
+            reason: format!("{function} returned handle 0"),
+        }),
+        handle => Ok(to_ptr(handle)),
+    }
+}
+
+fn to_ptr(handle: i32) -> *mut c_void {
+    handle as u32 as usize as *mut c_void
+}
+
+fn to_handle(ptr: *const c_void) -> i32 {
+    ptr as usize as u32 as i32
+}
+
+/// byte strings, each prefixed by its length as a little endian u32
+pub fn encode_list(items: &[Vec<u8>]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for item in items {
+        out.extend_from_slice(&(item.len() as u32).to_le_bytes());
+        out.extend_from_slice(item);
+    }
+    out
+}
+
+/// reverse of encode_list, None if the bytes are cut short
+pub fn decode_list(mut bytes: &[u8]) -> Option<Vec<Vec<u8>>> {
+    let mut items = Vec::new();
+    while !bytes.is_empty() {
+        let (len, rest) = bytes.split_first_chunk::<4>()?;
+        let len = u32::from_le_bytes(*len) as usize;
+        if rest.len() < len {
+            return None;
+        }
+        items.push(rest[..len].to_vec());
+        bytes = &rest[len..];
+    }
+    Some(items)
+}
+
+pub struct DestructedGate {
+    lib_path: PathBuf,
+    runtime: Mutex<Runtime>,
+    tick: TypedFunc<(i32, i32, i32), i64>,
+    definition: TypedFunc<i32, i64>,
+    serialize: TypedFunc<i32, i64>,
+    deserialize: TypedFunc<(i32, i32), i32>,
+    default_value: TypedFunc<(), i32>,
+    drop_mem: TypedFunc<i32, ()>,
+}
+
+impl DestructedGate {
+    /// functions the module must export for gates (besides the ones every module exports)
+    pub const FUNCTIONS: &[&str] = &[
+        "gate_tick",
+        "gate_def",
+        "gate_serialize",
+        "gate_deserialize",
+        "gate_default",
+        "gate_drop",
+    ];
+
+    pub fn new(lib_path: &Path) -> Result<Self, destructor::Error> {
+        let (runtime, instance) = Runtime::new(lib_path)?;
+        let store = &runtime.store;
+
+        Ok(Self {
+            lib_path: lib_path.to_path_buf(),
+            tick: get_func(&instance, store, "gate_tick")?,
+            definition: get_func(&instance, store, "gate_def")?,
+            serialize: get_func(&instance, store, "gate_serialize")?,
+            deserialize: get_func(&instance, store, "gate_deserialize")?,
+            default_value: get_func(&instance, store, "gate_default")?,
+            drop_mem: get_func(&instance, store, "gate_drop")?,
+            runtime: Mutex::new(runtime),
+        })
+    }
+
+    pub fn get_lib_path(&self) -> &Path {
+        &self.lib_path
+    }
+
+    /// serialized consumers in, serialized producers out
+    pub fn tick(
+        &self,
+        gate: *mut c_void,
+        consumers: &[Vec<u8>],
+    ) -> Result<Vec<Vec<u8>>, destructor::Error> {
+        let runtime = &mut *lock(&self.runtime);
+        let (ptr, len) = runtime
+            .write(&encode_list(consumers))
+            .map_err(destructor::Error::from_wasm)?;
+
+        let packed = self
+            .tick
+            .call(&mut runtime.store, (to_handle(gate), ptr, len));
+        let produced = runtime
+            .dealloc
+            .call(&mut runtime.store, (ptr, len))
+            .and(packed)
+            .and_then(|packed| runtime.take(packed))
+            .map_err(destructor::Error::from_wasm)?;
+
+        decode_list(&produced).ok_or_else(|| destructor::Error::Wasm {
+            reason: "gate_tick returned a malformed list of producers".to_string(),
+        })
+    }
+
+    pub fn definition(
+        &self,
+        gate: *const c_void,
+    ) -> Result<DestructedGateDefinition, destructor::Error> {
+        let json = {
+            let mut runtime = lock(&self.runtime);
+            self.definition
+                .call(&mut runtime.store, to_handle(gate))
+                .and_then(|packed| runtime.take(packed))
+                .map_err(destructor::Error::from_wasm)?
+        };
+
+        serde_json::from_slice::<GateDefinition>(&json)
+            .map(DestructedGateDefinition::from)
+            .map_err(|e| destructor::Error::Wasm {
+                reason: format!("gate_def returned an invalid definition: {e}"),
+            })
+    }
+
+    /// empty if the module traps
+    pub fn serialize(&self, gate: *const c_void) -> Vec<u8> {
+        let mut runtime = lock(&self.runtime);
+        self.serialize
+            .call(&mut runtime.store, to_handle(gate))
+            .and_then(|packed| runtime.take(packed))
+            .unwrap_or_default()
+    }
+
+    pub fn deserialize(&self, bytes: &[u8]) -> Option<*mut c_void> {
+        let mut runtime = lock(&self.runtime);
+        match runtime.call_with_bytes(&self.deserialize, bytes) {
+            Ok(0) | Err(_) => None,
+            Ok(handle) => Some(to_ptr(handle)),
+        }
+    }
+
+    /// fails if the module traps or returns handle 0, so no gate is created
+    pub fn default_value(&self) -> Result<*mut c_void, destructor::Error> {
+        let mut runtime = lock(&self.runtime);
+        self.default_value
+            .call(&mut runtime.store, ())
+            .map_err(destructor::Error::from_wasm)
+            .and_then(|handle| to_new_ptr(handle, "gate_default"))
+    }
+
+    pub fn drop_mem(&self, gate: *mut c_void) {
+        if gate.is_null() {
+            return;
+        }
+
+        let mut runtime = lock(&self.runtime);
+        let _ = self.drop_mem.call(&mut runtime.store, to_handle(gate));
+    }
+}
+
+pub struct DestructedData {
+    lib_path: PathBuf,
+    runtime: Mutex<Runtime>,
+    serialize: TypedFunc<i32, i64>,
+    deserialize: TypedFunc<(i32, i32), i32>,
+    default_value: TypedFunc<(), i32>,
+    drop_mem: TypedFunc<i32, ()>,
+}
+
+impl DestructedData {
+    /// functions the module must export for data types (besides the ones every module exports)
+    pub const FUNCTIONS: &[&str] = &[
+        "data_serialize",
+        "data_deserialize",
+        "data_default",
+        "data_drop",
+    ];
+
+    pub fn new(lib_path: &Path) -> Result<Self, destructor::Error> {
+        let (runtime, instance) = Runtime::new(lib_path)?;
+        let store = &runtime.store;
+
+        Ok(Self {
+            lib_path: lib_path.to_path_buf(),
+            serialize: get_func(&instance, store, "data_serialize")?,
+            deserialize: get_func(&instance, store, "data_deserialize")?,
+            default_value: get_func(&instance, store, "data_default")?,
+            drop_mem: get_func(&instance, store, "data_drop")?,
+            runtime: Mutex::new(runtime),
+        })
+    }
+
+    pub fn get_lib_path(&self) -> &Path {
+        &self.lib_path
+    }
+
+    /// empty if the module traps
+    pub fn serialize(&self, data: *const c_void) -> Vec<u8> {
+        let mut runtime = lock(&self.runtime);
+        self.serialize
+            .call(&mut runtime.store, to_handle(data))
+            .and_then(|packed| runtime.take(packed))
+            .unwrap_or_default()
+    }
+
+    pub fn deserialize(&self, bytes: &[u8]) -> Option<*mut c_void> {
+        let mut runtime = lock(&self.runtime);
+        match runtime.call_with_bytes(&self.deserialize, bytes) {
+            Ok(0) | Err(_) => None,
+            Ok(handle) => Some(to_ptr(handle)),
+        }
+    }
+
+    /// fails if the module traps or returns handle 0, so no data is created
+    pub fn default_value(&self) -> Result<*mut c_void, destructor::Error> {
+        let mut runtime = lock(&self.runtime);
+        self.default_value
+            .call(&mut runtime.store, ())
+            .map_err(destructor::Error::from_wasm)
+            .and_then(|handle| to_new_ptr(handle, "data_default"))
+    }
+
+    pub fn drop_mem(&self, data: *mut c_void) {
+        if data.is_null() {
+            return;
+        }
+
+        let mut runtime = lock(&self.runtime);
+        let _ = self.drop_mem.call(&mut runtime.store, to_handle(data));
+    }
+}
@@ -1,6 +1,9 @@
 use std::{fmt::Display, path::PathBuf};
 
-use crate::{common::world::ComponentVersion, packages::loader};
+use crate::{
+    common::world::ComponentVersion,
+    packages::{loader, sandbox},
+};
 
 #[derive(Debug)]
 pub enum Error {
@@ -20,6 +23,16 @@ pub enum Error {
         version: String,
         reason: String,
     },
+    /// The library cannot be loaded again
+    ReloadLibrary { lib_path: PathBuf, reason: String },
+    /// The sandbox helper running the component failed
+    Sandbox { reason: String },
+    /// The wasm module cannot be instantiated, is missing an export, or trapped
+    Wasm { reason: String },
+    /// The script cannot be compiled, is missing a function, or threw
+    Script { reason: String },
+    /// The library kind is not supported by this build
+    UnsupportedLibraryKind { kind: String },
 }
 
 impl Display for Error {
@@ -29,6 +42,19 @@ impl Display for Error {
 }
 
 impl Error {
+    pub fn from_sandbox(error: sandbox::Error) -> Self {
+        Self::Sandbox {
+            reason: error.to_string(),
+        }
+    }
+
+    #[cfg(feature = "wasm")]
+    pub fn from_wasm(error: wasmi::Error) -> Self {
+        Self::Wasm {
+            reason: error.to_string(),
+        }
+    }
+
     pub fn from_get_symbol(error: loader::Error) -> Self {
         match error {
             loader::Error::GetSymbol {
@@ -1,27 +1,88 @@
-use crate::{common::world::ComponentVersion, packages::loader::LibraryHandle};
+use std::{collections::HashMap, ffi::c_void, path::PathBuf};
+
+use crate::{
+    common::world::ComponentVersion,
+    packages::{destructor, loader::LibraryHandle},
+};
 
 pub struct DestructRequest {
-    library: LibraryHandle,
+    symbols: SymbolSource,
     component_id: ComponentVersion,
 }
 
+/// where the entry points of the component are looked up
+enum SymbolSource {
+    Library(LibraryHandle),
+    /// symbol name -> function address, e.g. functions linked into the host
+    Table(HashMap<String, *const c_void>),
+}
+
 impl DestructRequest {
     pub fn new(library: LibraryHandle, component_id: ComponentVersion) -> Self {
         Self {
-            library,
+            symbols: SymbolSource::Library(library),
             component_id,
         }
     }
 
-    pub fn get_library(&self) -> &LibraryHandle {
-        &self.library
+    /// destruct entry points that are not in a library, e.g. to test a schema version
+    /// without building a library for it
+    ///
+    /// # Safety
+    ///
+    /// every address must be an `extern "C"` function with the signature
+    /// the destructor expects for its name, and stay valid while the destructed component is used
+    pub unsafe fn from_symbols(
+        symbols: HashMap<String, *const c_void>,
+        component_id: ComponentVersion,
+    ) -> Self {
+        Self {
+            symbols: SymbolSource::Table(symbols),
+            component_id,
+        }
+    }
+
+    /// None if the symbols come from a table
+    pub fn get_library(&self) -> Option<&LibraryHandle> {
+        match &self.symbols {
+            SymbolSource::Library(library) => Some(library),
+            SymbolSource::Table(_) => None,
+        }
     }
 
     pub fn get_component_id(&self) -> &ComponentVersion {
         &self.component_id
     }
 
-    pub fn into_library(self) -> LibraryHandle {
-        self.library
+    /// None if the symbols come from a table
+    pub fn into_library(self) -> Option<LibraryHandle> {
+        match self.symbols {
+            SymbolSource::Library(library) => Some(library),
+            SymbolSource::Table(_) => None,
+        }
+    }
+
+    /// look up an entry point, T must be a function pointer type
+    ///
+    /// the returned function is only valid while the library is loaded,
+    /// see LibraryHandle::get_symbol
+    pub fn get_symbol<T: Copy>(&self, symbol_name: &str) -> Result<T, destructor::Error> {
+        match &self.symbols {
+            SymbolSource::Library(library) => library
+                .get_symbol::<T>(symbol_name)
+                .map(|symbol| *symbol)
+                .map_err(destructor::Error::from_get_symbol),
+            SymbolSource::Table(symbols) => match symbols.get(symbol_name) {
+                // from_symbols guarantees the address is a function of type T
+                Some(address) => {
+                    Ok(unsafe { std::mem::transmute_copy::<*const c_void, T>(address) })
+                }
+                None => Err(destructor::Error::GetSymbol {
+                    reason: "not in the symbol table".to_string(),
+                    symbol_name: symbol_name.to_string(),
+                    lib_path: PathBuf::new(),
+                }),
+            },
+        }
     }
 }
@@ -0,0 +1,374 @@
+//! .xdpkg is a single file archive of a package version,
+//! it contains package.toml and the libraries of all supported platforms
+//!
+//! the archive is laid out as follows (all integers are little endian):
+//!
+//! ```text
+//! magic        b"XDPKG\0"
+//! format       u32 (currently 1)
+//! entry count  u32
+//! entries      [path length: u32][path: utf-8][content length: u64][content]
+//! ```
+//!
+//! paths are relative to the version root and use `/` as separator,
+//! e.g. `package.toml`, `not.so`, `not.dll`
+//!
+//! installing unpacks the archive to a hidden staging directory in the root first,
+//! then moves it to `root/name/version` in one rename,
+//! so the indexer never sees a half installed package
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, Read, Write},
+    path::{Component, Path, PathBuf},
+};
+
+use semver::Version;
+
+use crate::packages::indexer::{
+    self,
+    component::{PackageIndex, PackageManifest},
+};
+
+/// file extension of package archives
+pub const ARCHIVE_EXTENSION: &str = "xdpkg";
+
+const MAGIC: &[u8; 6] = b"XDPKG\0";
+const FORMAT_VERSION: u32 = 1;
+const MANIFEST_NAME: &str = "package.toml";
+
+/// dynamic library extensions of all supported platforms,
+/// for components without targets
+const LIBRARY_EXTENSIONS: [&str; 3] = ["so", "dll", "dylib"];
+
+/// a validated package archive held in memory
+pub struct PackageArchive {
+    manifest: PackageManifest,
+    /// relative path -> file content, includes package.toml
+    files: BTreeMap<String, Vec<u8>>,
+}
+
+impl PackageArchive {
+    /// pack all files in a version root (the directory containing package.toml)
+    pub fn from_dir(version_root: &Path) -> Result<Self, indexer::Error> {
+        fn collect(
+            dir: &Path,
+            prefix: &str,
+            files: &mut BTreeMap<String, Vec<u8>>,
+        ) -> Result<(), indexer::Error> {
+            let fs_err = |path: &Path, e: io::Error| indexer::Error::Fs {
+                path: path.to_path_buf(),
+                reason: e.to_string(),
+            };
+
+            for entry in fs::read_dir(dir).map_err(|e| fs_err(dir, e))? {
+                let path = entry.map_err(|e| fs_err(dir, e))?.path();
+                let name = format!(
+                    "{prefix}{}",
+                    path.file_name().unwrap_or_default().to_string_lossy()
+                );
+
+                if path.is_dir() {
+                    collect(&path, &format!("{name}/"), files)?;
+                } else {
+                    files.insert(name, fs::read(&path).map_err(|e| fs_err(&path, e))?);
+                }
+            }
+
+            Ok(())
+        }
+
+        let mut files = BTreeMap::new();
+        collect(version_root, "", &mut files)?;
+        Self::from_files(files, version_root)
+    }
+
+    /// read and validate an archive
+    pub fn read(archive_path: &Path) -> Result<Self, indexer::Error> {
+        let invalid = |reason: String| indexer::Error::ArchiveInvalid {
+            archive_path: archive_path.to_path_buf(),
+            reason,
+        };
+
+        let content = fs::read(archive_path).map_err(|e| indexer::Error::Fs {
+            path: archive_path.to_path_buf(),
+            reason: e.to_string(),
+        })?;
+        let mut reader = content.as_slice();
+
+        fn read_bytes(reader: &mut &[u8], len: u64) -> io::Result<Vec<u8>> {
+            let mut buf = Vec::new();
+            reader.take(len).read_to_end(&mut buf)?;
+            if buf.len() as u64 == len {
+                Ok(buf)
+            } else {
+                Err(io::ErrorKind::UnexpectedEof.into())
+            }
+        }
+
+        fn read_u32(reader: &mut &[u8]) -> io::Result<u32> {
+            let mut buf = [0; 4];
+            reader.read_exact(&mut buf)?;
+            Ok(u32::from_le_bytes(buf))
+        }
+
+        fn read_u64(reader: &mut &[u8]) -> io::Result<u64> {
+            let mut buf = [0; 8];
+            reader.read_exact(&mut buf)?;
+            Ok(u64::from_le_bytes(buf))
+        }
+
+        let mut parse = || -> io::Result<Result<BTreeMap<String, Vec<u8>>, String>> {
+            if read_bytes(&mut reader, MAGIC.len() as u64)? != MAGIC {
+                return Ok(Err("not a package archive".to_string()));
+            }
+
+            let format = read_u32(&mut reader)?;
+            if format != FORMAT_VERSION {
+                return Ok(Err(format!("unsupported archive format {format}")));
+            }
+
+            let mut files = BTreeMap::new();
+            for _ in 0..read_u32(&mut reader)? {
+                let path_len = read_u32(&mut reader)?;
+                let path = match String::from_utf8(read_bytes(&mut reader, path_len as u64)?) {
+                    Ok(path) => path,
+                    Err(e) => return Ok(Err(format!("entry path is not utf-8: {e}"))),
+                };
+                let content_len = read_u64(&mut reader)?;
+                let content = read_bytes(&mut reader, content_len)?;
+
+                if files.insert(path.clone(), content).is_some() {
+                    return Ok(Err(format!("duplicated entry {path}")));
+                }
+            }
+
+            if !reader.is_empty() {
+                return Ok(Err("trailing bytes after last entry".to_string()));
+            }
+
+            Ok(Ok(files))
+        };
+
+        let files = match parse() {
+            Ok(Ok(files)) => files,
+            Ok(Err(reason)) => return Err(invalid(reason)),
+            Err(e) => return Err(invalid(format!("truncated archive: {e}"))),
+        };
+
+        Self::from_files(files, archive_path)
+    }
+
+    /// write the archive to disk
+    pub fn write(&self, archive_path: &Path) -> Result<(), indexer::Error> {
+        let mut content = Vec::new();
+        content.extend_from_slice(MAGIC);
+        content.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        content.extend_from_slice(&(self.files.len() as u32).to_le_bytes());
+
+        for (path, file) in self.files.iter() {
+            content.extend_from_slice(&(path.len() as u32).to_le_bytes());
+            content.extend_from_slice(path.as_bytes());
+            content.extend_from_slice(&(file.len() as u64).to_le_bytes());
+            content.extend_from_slice(file);
+        }
+
+        fs::write(archive_path, content).map_err(|e| indexer::Error::Fs {
+            path: archive_path.to_path_buf(),
+            reason: e.to_string(),
+        })
+    }
+
+    pub fn get_manifest(&self) -> &PackageManifest {
+        &self.manifest
+    }
+
+    /// relative paths of all files in the archive
+    pub fn list_files(&self) -> Vec<&str> {
+        self.files.keys().map(String::as_str).collect()
+    }
+
+    /// validate the files of an archive
+    ///
+    /// origin is the path shown in error messages
+    fn from_files(files: BTreeMap<String, Vec<u8>>, origin: &Path) -> Result<Self, indexer::Error> {
+        let invalid = |reason: String| indexer::Error::ArchiveInvalid {
+            archive_path: origin.to_path_buf(),
+            reason,
+        };
+
+        for path in files.keys() {
+            let is_safe = !path.is_empty()
+                && !path.contains('\\')
+                && Path::new(path)
+                    .components()
+                    .all(|component| matches!(component, Component::Normal(_)));
+            if !is_safe {
+                return Err(invalid(format!("unsafe entry path {path:?}")));
+            }
+        }
+
+        let manifest_content = files
+            .get(MANIFEST_NAME)
+            .ok_or_else(|| invalid(format!("missing {MANIFEST_NAME}")))?;
+        let manifest: PackageManifest =
+            toml::from_slice(manifest_content).map_err(|e| indexer::Error::ManifestParse {
+                manifest_path: origin.join(MANIFEST_NAME),
+                reason: e.to_string(),
+            })?;
+
+        let name = manifest.get_name();
+        if !is_valid_package_name(name) {
+            return Err(invalid(format!("invalid package name {name:?}")));
+        }
+
+        for (component, provide) in manifest.get_provides() {
+            match provide.get_targets() {
+                Some(targets) => {
+                    for (target, library) in targets {
+                        let path = library.get_path().to_string_lossy();
+                        if !files.contains_key(path.as_ref()) {
+                            return Err(invalid(format!(
+                                "missing library {path} of component {component} for target {target}"
+                            )));
+                        }
+                    }
+                }
+                None => {
+                    let has_library = LIBRARY_EXTENSIONS
+                        .iter()
+                        .any(|extension| files.contains_key(&format!("{component}.{extension}")));
+                    if !has_library {
+                        return Err(invalid(format!("no library for component {component}")));
+                    }
+                }
+            }
+        }
+
+        Ok(Self { manifest, files })
+    }
+
+    /// write all files into a directory
+    fn unpack(&self, dir: &Path) -> io::Result<()> {
+        for (path, content) in self.files.iter() {
+            let path = dir.join(path);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+
+            let mut file = fs::File::create(&path)?;
+            file.write_all(content)?;
+            file.sync_all()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// a package name that is a single visible directory in a root,
+/// anything else could escape the root or collide with staging paths
+fn is_valid_package_name(name: &str) -> bool {
+    !name.is_empty() && !name.starts_with('.') && !name.contains(['/', '\\'])
+}
+
+/// a hidden path in root that is used for staging, the indexer ignores it
+fn staging_path(root: &Path, action: &str, name: &str, version: &Version) -> PathBuf {
+    root.join(format!(".{action}-{name}-{version}-{}", std::process::id()))
+}
+
+fn assert_indexed_root(index: &PackageIndex, root: &Path) -> Result<(), indexer::Error> {
+    if index.get_roots().iter().any(|indexed| indexed == root) {
+        Ok(())
+    } else {
+        Err(indexer::Error::InstallRootNotIndexed {
+            root: root.to_path_buf(),
+        })
+    }
+}
+
+/// validate an archive and install it into root, then refresh the index
+///
+/// root must be one of the roots of the index,
+/// returns the name and version of the installed package
+pub fn install(
+    index: &mut PackageIndex,
+    archive_path: &Path,
+    root: &Path,
+) -> Result<(String, Version), indexer::Error> {
+    assert_indexed_root(index, root)?;
+
+    let archive = PackageArchive::read(archive_path)?;
+    let name = archive.get_manifest().get_name().to_string();
+    let version = archive.get_manifest().get_version().clone();
+
+    let package_root = root.join(&name);
+    let version_root = package_root.join(version.to_string());
+    if version_root.exists() {
+        return Err(indexer::Error::AlreadyInstalled {
+            name,
+            version,
+            version_root,
+        });
+    }
+
+    let staging = staging_path(root, "installing", &name, &version);
+    let _ = fs::remove_dir_all(&staging);
+
+    archive
+        .unpack(&staging)
+        .and_then(|_| fs::create_dir_all(&package_root))
+        .and_then(|_| fs::rename(&staging, &version_root))
+        .map_err(|e| {
+            let _ = fs::remove_dir_all(&staging);
+            indexer::Error::Fs {
+                path: version_root.clone(),
+                reason: e.to_string(),
+            }
+        })?;
+
+    index.refresh_package(&name)?;
+    Ok((name, version))
+}
+
+/// remove a package version from root, then refresh the index
+///
+/// if the same version exists in another root, the index falls back to that one
+pub fn uninstall(
+    index: &mut PackageIndex,
+    root: &Path,
+    name: &str,
+    version: &Version,
+) -> Result<(), indexer::Error> {
+    assert_indexed_root(index, root)?;
+
+    if !is_valid_package_name(name) {
+        return Err(indexer::Error::InvalidPackageName {
+            name: name.to_string(),
+        });
+    }
+
+    let package_root = root.join(name);
+    let version_root = package_root.join(version.to_string());
+    if !version_root.join(MANIFEST_NAME).is_file() {
+        return Err(indexer::Error::NotInstalled {
+            name: name.to_string(),
+            version: version.clone(),
+            root: root.to_path_buf(),
+        });
+    }
+
+    // move out of the package first, so the version disappears in one step
+    let staging = staging_path(root, "removing", name, version);
+    fs::rename(&version_root, &staging)
+        .and_then(|_| fs::remove_dir_all(&staging))
+        .map_err(|e| indexer::Error::Fs {
+            path: version_root.clone(),
+            reason: e.to_string(),
+        })?;
+
+    // only succeeds if no other versions are left
+    let _ = fs::remove_dir(&package_root);
+
+    index.refresh_package(name)
+}
@@ -1,6 +1,13 @@
+mod package_host;
+pub use package_host::*;
 mod package_index;
 pub use package_index::*;
 mod package_index_builder;
 pub use package_index_builder::*;
 mod package_manifest;
 pub use package_manifest::*;
+mod package_search;
+pub use package_search::*;
+mod package_status;
+pub use package_status::*;
+mod package_index_cache;
@@ -0,0 +1,108 @@
+use std::fmt::Display;
+
+use semver::{Version, VersionReq};
+use serde::{Deserialize, Serialize};
+
+use crate::packages::destructor::SUPPORTED_SCHEMA_VERSIONS;
+
+/// [host] section of package.toml, what the package needs from the running xdsim
+///
+/// ```toml
+/// [host]
+/// xdsim = "^0.1"
+/// schema = { min = 0, max = 0 }
+/// ```
+///
+/// both fields are optional, a missing field is compatible with any host
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "devel", derive(Debug))]
+pub struct PackageHost {
+    /// required version of xdsim
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub xdsim: Option<VersionReq>,
+    /// bindings schema versions the libraries of the package are built against
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema: Option<SchemaRange>,
+}
+
+/// an inclusive range of bindings schema versions
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "devel", derive(Debug))]
+pub struct SchemaRange {
+    pub min: u32,
+    pub max: u32,
+}
+
+/// why a package version cannot run on this host
+#[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "devel", derive(Debug))]
+pub enum HostIncompatibility {
+    /// the running xdsim does not match the required version
+    XdsimVersion { required: VersionReq, host: Version },
+    /// none of the schema versions of the package are supported by the destructors
+    SchemaVersion {
+        package: SchemaRange,
+        supported: SchemaRange,
+    },
+}
+
+/// version of the running xdsim
+pub fn host_xdsim_version() -> Version {
+    Version::parse(env!("CARGO_PKG_VERSION")).expect("cargo package versions are semver")
+}
+
+impl PackageHost {
+    /// everything the package requires that the running host does not provide,
+    /// empty if the package is compatible
+    pub fn check(&self) -> Vec<HostIncompatibility> {
+        let mut incompatibilities = Vec::new();
+
+        if let Some(required) = &self.xdsim {
+            let host = host_xdsim_version();
+            if !required.matches(&host) {
+                incompatibilities.push(HostIncompatibility::XdsimVersion {
+                    required: required.clone(),
+                    host,
+                });
+            }
+        }
+
+        if let Some(package) = self.schema {
+            let supported = SchemaRange {
+                min: *SUPPORTED_SCHEMA_VERSIONS.start(),
+                max: *SUPPORTED_SCHEMA_VERSIONS.end(),
+            };
+
+            if package.min > package.max
+                || package.max < supported.min
+                || package.min > supported.max
+            {
+                incompatibilities.push(HostIncompatibility::SchemaVersion { package, supported });
+            }
+        }
+
+        incompatibilities
+    }
+}
+
+impl Display for SchemaRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}..={}", self.min, self.max)
+    }
+}
+
+impl Display for HostIncompatibility {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::XdsimVersion { required, host } => {
+                write!(f, "requires xdsim {required}, running {host}")
+            }
+            Self::SchemaVersion { package, supported } => {
+                write!(
+                    f,
+                    "built for bindings schema {package}, host supports {supported}"
+                )
+            }
+        }
+    }
+}
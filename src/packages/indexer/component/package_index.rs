@@ -1,73 +1,279 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    env::consts::DLL_EXTENSION,
     path::{Path, PathBuf},
 };
 
 use semver::Version;
 
-use crate::packages::indexer::{component::PackageManifest, deps_resolvable::DepsResolvable};
+use crate::packages::indexer::{
+    component::{HostIncompatibility, PackageManifest, VersionStatus},
+    deps_resolvable::DepsResolvable,
+};
 
 /// an index of all packages
 #[cfg_attr(feature = "devel", derive(Debug))]
 pub struct PackageIndex {
+    /// roots the index is read from, from lowest to highest priority
+    roots: Vec<PathBuf>,
     packages: HashMap<String, Package>,
 }
 
 impl PackageIndex {
     /// internal: construct the struct
-    pub fn from_packages(packages: HashMap<String, Package>) -> Self {
-        Self { packages }
+    pub fn from_packages(roots: Vec<PathBuf>, packages: HashMap<String, Package>) -> Self {
+        Self { roots, packages }
     }
 
     pub fn get_package(&self, name: &str) -> Option<&Package> {
         self.packages.get(name)
     }
+
+    /// all packages, sorted by name
+    pub fn list_packages(&self) -> Vec<&Package> {
+        let mut packages: Vec<_> = self.packages.values().collect();
+        packages.sort_by_key(|package| package.get_name());
+        packages
+    }
+
+    /// versions that cannot run on this host, sorted by name then version
+    pub fn list_incompatible(&self) -> Vec<(&str, &Version, &[HostIncompatibility])> {
+        self.list_packages()
+            .into_iter()
+            .flat_map(|package| {
+                package
+                    .versions
+                    .iter()
+                    .filter(|(_, entry)| !entry.host_incompatibilities.is_empty())
+                    .map(|(version, entry)| {
+                        (
+                            package.get_name(),
+                            version,
+                            entry.get_host_incompatibilities(),
+                        )
+                    })
+            })
+            .collect()
+    }
+
+    /// roots the index is read from, from lowest to highest priority
+    pub fn get_roots(&self) -> &[PathBuf] {
+        &self.roots
+    }
+
+    /// remove a package from the index and return it
+    pub fn take_package(&mut self, name: &str) -> Option<Package> {
+        self.packages.remove(name)
+    }
+
+    /// the changes from self to newer, sorted by package name then version
+    pub fn diff(&self, newer: &PackageIndex) -> Vec<IndexEvent> {
+        let mut names: Vec<&String> = self.packages.keys().chain(newer.packages.keys()).collect();
+        names.sort();
+        names.dedup();
+
+        let mut events = Vec::new();
+
+        for name in names {
+            let old = self.packages.get(name.as_str());
+            let new = newer.packages.get(name.as_str());
+
+            let mut versions: Vec<&Version> = old
+                .into_iter()
+                .chain(new)
+                .flat_map(|package| package.versions.keys())
+                .collect();
+            versions.sort();
+            versions.dedup();
+
+            for version in versions {
+                let old_entry = old.and_then(|package| package.versions.get(version));
+                let new_entry = new.and_then(|package| package.versions.get(version));
+                let (name, version) = (name.clone(), version.clone());
+
+                match (old_entry, new_entry) {
+                    (None, Some(_)) => events.push(IndexEvent::Added { name, version }),
+                    (Some(_), None) => events.push(IndexEvent::Removed { name, version }),
+                    (Some(old_entry), Some(new_entry))
+                        if old_entry.version_root != new_entry.version_root
+                            || old_entry.manifest != new_entry.manifest
+                            || old_entry.status != new_entry.status =>
+                    {
+                        events.push(IndexEvent::Changed { name, version })
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        events
+    }
+
+    /// replace (or remove if None) the package of a name
+    pub fn replace_package(&mut self, name: &str, package: Option<Package>) {
+        match package {
+            Some(package) => {
+                self.packages.insert(name.to_string(), package);
+            }
+            None => {
+                self.packages.remove(name);
+            }
+        }
+    }
 }
 
+/// a difference between two scans of the index, see PackageIndex::refresh
+#[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "devel", derive(Debug))]
+pub enum IndexEvent {
+    /// a version that was not in the index before
+    Added { name: String, version: Version },
+    /// a version that is no longer in the index
+    Removed { name: String, version: Version },
+    /// a version that is still in the index, but its manifest, location or status changed
+    Changed { name: String, version: Version },
+}
+
+impl IndexEvent {
+    pub fn get_name(&self) -> &str {
+        match self {
+            Self::Added { name, .. } | Self::Removed { name, .. } | Self::Changed { name, .. } => {
+                name
+            }
+        }
+    }
+
+    pub fn get_version(&self) -> &Version {
+        match self {
+            Self::Added { version, .. }
+            | Self::Removed { version, .. }
+            | Self::Changed { version, .. } => version,
+        }
+    }
+}
+
+/// a package is the merge of the package directory in all roots
 #[cfg_attr(feature = "devel", derive(Debug))]
 pub struct Package {
     name: String,
-    package_root: PathBuf,
-    versions: HashMap<Version, PackageManifest>,
+    versions: BTreeMap<Version, PackageVersion>,
 }
 
-impl Package {
-    pub fn new(package_root: PathBuf) -> Self {
+/// a single version of a package
+#[cfg_attr(feature = "devel", derive(Debug))]
+pub struct PackageVersion {
+    manifest: PackageManifest,
+    /// the root this version is read from
+    source_root: PathBuf,
+    /// directory containing package.toml
+    version_root: PathBuf,
+    /// lower priority roots that also contains this version, from lowest to highest priority
+    shadowed: Vec<PathBuf>,
+    /// why the version cannot run on this host, checked when the version is indexed
+    host_incompatibilities: Vec<HostIncompatibility>,
+    /// status from the manifest, with overrides applied
+    status: VersionStatus,
+}
+
+impl PackageVersion {
+    pub fn new(manifest: PackageManifest, source_root: PathBuf, version_root: PathBuf) -> Self {
         Self {
-            name: package_root
-                .file_name()
-                .unwrap_or_default()
-                .to_string_lossy()
-                .to_string(),
-            package_root,
-            versions: HashMap::new(),
+            host_incompatibilities: manifest.host_incompatibilities(),
+            status: manifest.get_status(),
+            manifest,
+            source_root,
+            version_root,
+            shadowed: Vec::new(),
         }
     }
 
-    /// add a version to a package
-    pub fn insert(&mut self, version: Version, manifest: PackageManifest) {
-        self.versions.insert(version, manifest);
+    /// whether the version is yanked or deprecated
+    pub fn get_status(&self) -> &VersionStatus {
+        &self.status
     }
 
-    pub fn get_name(&self) -> &str {
-        &self.name
+    pub fn set_status(&mut self, status: VersionStatus) {
+        self.status = status;
     }
 
-    pub fn get_root(&self) -> &Path {
-        &self.package_root
+    /// why the version cannot run on this host, empty if it can
+    ///
+    /// incompatible versions stay in the index, but are skipped by deps_resolver
+    pub fn get_host_incompatibilities(&self) -> &[HostIncompatibility] {
+        &self.host_incompatibilities
     }
 
-    /// destruct into name, root
-    pub fn destruct(self) -> (String, PathBuf) {
-        (self.name, self.package_root)
+    pub fn get_manifest(&self) -> &PackageManifest {
+        &self.manifest
     }
 
-    pub fn into_name(self) -> String {
-        self.name
+    /// the root this version is read from
+    pub fn get_source_root(&self) -> &Path {
+        &self.source_root
+    }
+
+    /// directory containing package.toml
+    pub fn get_version_root(&self) -> &Path {
+        &self.version_root
+    }
+
+    /// lower priority roots that also contains this version, but are overridden
+    pub fn get_shadowed(&self) -> &[PathBuf] {
+        &self.shadowed
+    }
+
+    /// path to the dynamic library of a component for the current platform
+    pub fn get_library_path(&self, component: &str) -> PathBuf {
+        self.version_root.join(self.get_library_file(component))
+    }
+
+    /// path to the dynamic library of a component for the current platform,
+    /// relative to the version root
+    pub fn get_library_file(&self, component: &str) -> PathBuf {
+        // versions with missing targets are not indexed, so this only falls back
+        // for components that are not in provides
+        self.manifest
+            .get_provides()
+            .get(component)
+            .and_then(|provide| provide.get_library_file(component))
+            .unwrap_or_else(|| PathBuf::from(component).with_extension(DLL_EXTENSION))
+    }
+}
+
+impl Package {
+    pub fn new(name: String) -> Self {
+        Self {
+            name,
+            versions: BTreeMap::new(),
+        }
     }
 
-    pub fn into_root(self) -> PathBuf {
-        self.package_root
+    /// add a version to a package,
+    /// replaces the existing definition of the same version
+    /// (the replaced definition is recorded as shadowed)
+    pub fn insert(&mut self, version: Version, mut entry: PackageVersion) {
+        if let Some(shadowed) = self.versions.remove(&version) {
+            entry.shadowed = shadowed.shadowed;
+            entry.shadowed.push(shadowed.source_root);
+        }
+
+        self.versions.insert(version, entry);
+    }
+
+    /// merge all versions of another package into self,
+    /// versions in other takes precedence
+    pub fn merge(&mut self, other: Package) {
+        for (version, entry) in other.versions {
+            self.insert(version, entry);
+        }
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn into_name(self) -> String {
+        self.name
     }
 
     /// returns true if the package has no versions (how?)
@@ -76,9 +282,33 @@ impl Package {
     }
 
     pub fn get_version(&self, version: &Version) -> Option<&PackageManifest> {
+        Some(self.versions.get(version)?.get_manifest())
+    }
+
+    pub fn get_version_entry(&self, version: &Version) -> Option<&PackageVersion> {
         self.versions.get(version)
     }
 
+    pub fn get_version_entry_mut(&mut self, version: &Version) -> Option<&mut PackageVersion> {
+        self.versions.get_mut(version)
+    }
+
+    /// the root a version is read from
+    pub fn get_version_source(&self, version: &Version) -> Option<&Path> {
+        Some(self.versions.get(version)?.get_source_root())
+    }
+
+    /// directory containing package.toml of a version
+    pub fn get_version_root(&self, version: &Version) -> Option<&Path> {
+        Some(self.versions.get(version)?.get_version_root())
+    }
+
+    /// path to the dynamic library of a component in a version
+    pub fn get_library_path(&self, version: &Version, component: &str) -> Option<PathBuf> {
+        Some(self.versions.get(version)?.get_library_path(component))
+    }
+
+    /// list all versions, from oldest to newest
     pub fn list_versions(&self) -> Vec<&Version> {
         self.versions.keys().collect()
     }
@@ -96,7 +326,8 @@ impl DepsResolvable for PackageIndex {
                 .get_version(version)?
                 .get_dependencies()
                 .iter()
-                .map(|(name, version)| (name.as_str(), version))
+                .filter(|(_, dependency)| !dependency.is_optional())
+                .map(|(name, dependency)| (name.as_str(), dependency.get_version()))
                 .collect(),
         )
     }
@@ -104,4 +335,39 @@ impl DepsResolvable for PackageIndex {
     fn get_versions(&self, name: &str) -> Option<Vec<&Version>> {
         Some(self.packages.get(name)?.list_versions())
     }
+
+    fn is_yanked(&self, name: &str, version: &Version) -> bool {
+        self.packages
+            .get(name)
+            .and_then(|package| package.get_version_entry(version))
+            .is_some_and(|entry| entry.get_status().yanked)
+    }
+
+    fn get_host_incompatibilities(&self, name: &str, version: &Version) -> &[HostIncompatibility] {
+        self.packages
+            .get(name)
+            .and_then(|package| package.get_version_entry(version))
+            .map(PackageVersion::get_host_incompatibilities)
+            .unwrap_or_default()
+    }
+
+    fn get_dependencies_with_features(
+        &self,
+        name: &str,
+        version: &Version,
+        features: &BTreeSet<String>,
+    ) -> Option<Vec<(&str, &semver::VersionReq, &[String])>> {
+        let manifest = self.packages.get(name)?.get_version(version)?;
+        let enabled = manifest.enabled_features(features.iter().map(String::as_str))?;
+
+        Some(
+            manifest
+                .enabled_dependencies(&enabled)
+                .into_iter()
+                .map(|(name, dependency)| {
+                    (name, dependency.get_version(), dependency.get_features())
+                })
+                .collect(),
+        )
+    }
 }
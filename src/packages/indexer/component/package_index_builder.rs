@@ -6,21 +6,36 @@ use std::{
 
 use semver::Version;
 
-use crate::packages::indexer::{
-    self,
-    component::{
-        PackageManifest,
-        package_index::{Package, PackageIndex},
+use crate::packages::{
+    indexer::{
+        self,
+        component::{
+            PackageManifest, PackageOverrides, current_targets,
+            package_index::{IndexEvent, Package, PackageIndex, PackageVersion},
+            package_index_cache::{CachedVersion, IndexCacheState, Stamp},
+        },
     },
+    stdlib,
 };
 
 /// a package index builder is an incomplete package index
 /// it contains errors information which will be removed on build
 /// and can be modified
+///
+/// roots are layered: a package may be defined in multiple roots,
+/// its versions from all roots are merged into a single package.
+/// if the same version exists in multiple roots, the root added last takes precedence
 pub struct PackageIndexBuilder {
-    packages: HashMap<String, Vec<Package>>,
+    /// roots that are added, from lowest to highest priority
+    roots: Vec<PathBuf>,
+    packages: HashMap<String, Package>,
     /// errors are returned on build()
     errors: Vec<indexer::Error>,
+    /// parsed manifests from previous builds, see with_cache
+    cache: Option<IndexCacheState>,
+    /// overrides files of the roots, from lowest to highest priority, applied on build()
+    /// to the versions indexed from the same root
+    overrides: Vec<(PathBuf, PackageOverrides)>,
 }
 
 impl Default for PackageIndexBuilder {
@@ -33,38 +48,58 @@ impl PackageIndexBuilder {
     /// create new instance
     pub fn new() -> Self {
         Self {
+            roots: Vec::new(),
             packages: HashMap::new(),
             errors: Vec::new(),
+            cache: None,
+            overrides: Vec::new(),
         }
     }
 
+    /// reuse directory listings and manifests of previous builds
+    /// - a root or package directory is only listed again if its modification time or size changed
+    /// - a manifest is only parsed again if the modification time or size of package.toml changed
+    ///
+    /// package.toml of every cached version is still looked at on each build,
+    /// so a manifest edited in place (e.g. a new sha256) is not served from the cache
+    ///
+    /// the cache is rewritten on build() with the manifests of this build,
+    /// must be called before add_roots
+    pub fn with_cache(mut self, cache_path: &Path) -> Self {
+        self.cache = Some(IndexCacheState::read(cache_path));
+        self
+    }
+
     /// build self to a PackageIndex
     /// returns PackageIndex, and the errors
     /// since the indexer can build with errors present
     /// PackageIndex and Result are both returned
     pub fn build(mut self) -> (PackageIndex, Result<(), indexer::Error>) {
-        let mut packages_cleaned = HashMap::new();
-
-        for (name, mut package) in self.packages.into_iter() {
-            match package.len() {
-                0 => unreachable!(
-                    "this should not happen, a package is added to index only when a definition is found, please report this error"
-                ),
-                1 => {
-                    let _ = packages_cleaned.insert(name, package.remove(0));
+        if let Some(cache) = self.cache {
+            cache.write();
+        }
+
+        for (root_path, overrides) in self.overrides.iter() {
+            for (name, versions) in overrides.iter() {
+                let Some(package) = self.packages.get_mut(name) else {
+                    continue;
+                };
+
+                for (version, version_override) in versions {
+                    // a version shadowing the one of this root is not patched by it
+                    if let Some(entry) = package.get_version_entry_mut(version)
+                        && entry.get_source_root() == root_path
+                    {
+                        let mut status = entry.get_status().clone();
+                        status.apply(version_override);
+                        entry.set_status(status);
+                    }
                 }
-                _ => self.errors.push(indexer::Error::MultipleDefinitions {
-                    name: package[0].get_name().to_string(),
-                    paths: package
-                        .into_iter()
-                        .map(|package_def| package_def.into_root())
-                        .collect(),
-                }),
             }
         }
 
         (
-            PackageIndex::from_packages(packages_cleaned),
+            PackageIndex::from_packages(self.roots, self.packages),
             if self.errors.is_empty() {
                 Ok(())
             } else {
@@ -76,6 +111,9 @@ impl PackageIndexBuilder {
     }
 
     /// read packages using the specified folders as package root
+    ///
+    /// roots are ordered from lowest to highest priority,
+    /// and have higher priority than roots added by previous calls
     pub fn add_roots(mut self, paths: &[PathBuf]) -> Self {
         macro_rules! wrap_fs_op {
             ($ex: expr, $p: expr) => {
@@ -95,6 +133,8 @@ impl PackageIndexBuilder {
         // read root path where immediate childrens are directories
         // where the name of each dir is a package
         for root_path in paths {
+            self.roots.push(root_path.clone());
+
             if !wrap_fs_op!(fs::exists(root_path), root_path) {
                 self.errors.push(indexer::Error::IndexMissingDir {
                     index_path: root_path.clone(),
@@ -102,58 +142,235 @@ impl PackageIndexBuilder {
                 continue;
             }
 
-            // read the dir of a package
-            // the immediate childrens are versions of the package
-            // the name of the dir is the full version name of the package version
-            for package in wrap_fs_op!(fs::read_dir(root_path), root_path) {
-                let package = wrap_fs_op!(package, root_path);
-                let package_path = package.path();
+            self.add_overrides(root_path);
 
-                let mut package_builder = Package::new(package_path.clone());
+            // each immediate child of a root is a package,
+            // the listing is taken from the cache if the root did not change
+            let stamp = fs::metadata(root_path)
+                .ok()
+                .and_then(|metadata| Stamp::from_metadata(&metadata));
+            let cached = self
+                .cache
+                .as_ref()
+                .zip(stamp)
+                .and_then(|(cache, stamp)| cache.get_root(root_path, stamp))
+                .map(<[String]>::to_vec);
 
-                if !wrap_fs_op!(fs::metadata(&package_path), package_path).is_dir() {
-                    continue;
+            let entries = match cached {
+                Some(entries) => entries,
+                None => {
+                    let mut entries = Vec::new();
+                    for package in wrap_fs_op!(fs::read_dir(root_path), root_path) {
+                        let package = wrap_fs_op!(package, root_path);
+
+                        // hidden entries are reserved for staging installs
+                        if !is_hidden(&package.path()) {
+                            entries.push(package.file_name().to_string_lossy().to_string());
+                        }
+                    }
+                    entries
                 }
+            };
 
-                // read a single version in a package
-                // package.toml is inside the dir
-                for version in wrap_fs_op!(fs::read_dir(&package_path), package_path) {
-                    let version = wrap_fs_op!(version, &package_path);
-                    let version_path = version.path();
-                    let manifest_path = version_path.join("package.toml");
+            for entry in entries.iter() {
+                self.add_package_dir(root_path, &root_path.join(entry));
+            }
 
-                    if !wrap_fs_op!(fs::metadata(&manifest_path), &manifest_path).is_file() {
-                        continue;
+            if let Some(cache) = self.cache.as_mut() {
+                cache.insert_root(root_path, stamp, entries);
+            }
+        }
+
+        self
+    }
+}
+
+impl PackageIndexBuilder {
+    /// read the overrides file of a root, if there is one
+    fn add_overrides(&mut self, root_path: &Path) {
+        match PackageOverrides::read(root_path) {
+            Ok(overrides) => self.overrides.push((root_path.to_path_buf(), overrides)),
+            Err(e) => self.errors.push(e),
+        }
+    }
+
+    /// read the dir of a package
+    /// the immediate childrens are versions of the package
+    /// the name of the dir is the full version name of the package version
+    fn add_package_dir(&mut self, root_path: &Path, package_path: &Path) {
+        macro_rules! wrap_fs_op {
+            ($ex: expr, $p: expr, $otherwise: expr) => {
+                match $ex {
+                    Ok(res) => res,
+                    Err(e) => {
+                        self.errors.push(indexer::Error::Fs {
+                            path: PathBuf::from($p),
+                            reason: e.to_string(),
+                        });
+                        $otherwise
+                    }
+                }
+            };
+        }
+
+        let mut package_builder = Package::new(
+            package_path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string(),
+        );
+
+        let metadata = wrap_fs_op!(fs::metadata(package_path), package_path, return);
+        if !metadata.is_dir() {
+            return;
+        }
+
+        // its handles would replace the stdlib ones when registered in a world
+        if package_builder.get_name() == stdlib::PACKAGE {
+            self.errors.push(indexer::Error::ReservedPackageName {
+                name: stdlib::PACKAGE.to_string(),
+                package_root: package_path.to_path_buf(),
+            });
+            return;
+        }
+
+        // the package did not change: its versions are taken from the cache
+        // without listing the package directory, if none of their manifests changed
+        let stamp = Stamp::from_metadata(&metadata);
+        let cached = self
+            .cache
+            .as_ref()
+            .zip(stamp)
+            .and_then(|(cache, stamp)| cache.get_package(package_path, stamp))
+            .filter(|versions| versions.iter().all(CachedVersion::is_fresh))
+            .map(<[CachedVersion]>::to_vec);
+
+        let versions = match cached {
+            Some(versions) => {
+                if let Some(cache) = self.cache.as_mut() {
+                    cache.insert_package(root_path, package_path, stamp, versions.clone());
+                }
+                versions
+            }
+            None => {
+                let (versions, complete) = self.scan_package_dir(package_path);
+                if let Some(cache) = self.cache.as_mut() {
+                    // incomplete packages are listed again, so their errors are reported again
+                    cache.insert_package(
+                        root_path,
+                        package_path,
+                        stamp.filter(|_| complete),
+                        versions.clone(),
+                    );
+                }
+                versions
+            }
+        };
+
+        for version in versions {
+            if let Err(e) =
+                package_builder.add_version(root_path, &version.version_root, version.manifest)
+            {
+                self.errors.push(e);
+            }
+        }
+
+        if let Err(e) = self.add_package(package_builder, package_path) {
+            self.errors.push(e);
+        }
+    }
+
+    /// list the versions of a package and parse their manifests,
+    /// manifests are taken from the cache if package.toml did not change
+    ///
+    /// returns the versions that parsed, and if every version directory has a valid manifest
+    fn scan_package_dir(&mut self, package_path: &Path) -> (Vec<CachedVersion>, bool) {
+        macro_rules! wrap_fs_op {
+            ($ex: expr, $p: expr, $otherwise: expr) => {
+                match $ex {
+                    Ok(res) => res,
+                    Err(e) => {
+                        self.errors.push(indexer::Error::Fs {
+                            path: PathBuf::from($p),
+                            reason: e.to_string(),
+                        });
+                        $otherwise
                     }
+                }
+            };
+        }
+
+        let mut versions = Vec::new();
+        let mut complete = true;
+
+        // read a single version in a package
+        // package.toml is inside the dir
+        for version in wrap_fs_op!(
+            fs::read_dir(package_path),
+            package_path,
+            return (versions, false)
+        ) {
+            let version = wrap_fs_op!(version, package_path, {
+                complete = false;
+                continue;
+            });
+            let version_path = version.path();
+            let manifest_path = version_path.join("package.toml");
+
+            if is_hidden(&version_path) {
+                continue;
+            }
+
+            // a version directory without package.toml may still be being written
+            let metadata = wrap_fs_op!(fs::metadata(&manifest_path), &manifest_path, {
+                complete = false;
+                continue;
+            });
+            if !metadata.is_file() {
+                complete = false;
+                continue;
+            }
 
-                    let manifest_content = wrap_fs_op!(fs::read(&manifest_path), &manifest_path);
-                    let manifest: PackageManifest = match toml::from_slice(&manifest_content) {
+            let stamp = Stamp::from_metadata(&metadata);
+            let cached = self
+                .cache
+                .as_ref()
+                .zip(stamp)
+                .and_then(|(cache, stamp)| cache.get_manifest(package_path, &version_path, stamp))
+                .cloned();
+
+            let manifest: PackageManifest = match cached {
+                Some(manifest) => manifest,
+                None => {
+                    let manifest_content = wrap_fs_op!(fs::read(&manifest_path), &manifest_path, {
+                        complete = false;
+                        continue;
+                    });
+                    match toml::from_slice(&manifest_content) {
                         Ok(res) => res,
                         Err(e) => {
                             self.errors.push(indexer::Error::ManifestParse {
                                 manifest_path: manifest_path.clone(),
                                 reason: e.to_string(),
                             });
+                            complete = false;
                             continue;
                         }
-                    };
-
-                    if let Err(e) = package_builder.add_version(&version_path, manifest) {
-                        self.errors.push(e);
                     }
                 }
+            };
 
-                if let Err(e) = self.add_package(package_builder, &package_path) {
-                    self.errors.push(e);
-                }
-            }
+            versions.push(CachedVersion {
+                version_root: version_path,
+                stamp,
+                manifest,
+            });
         }
 
-        self
+        (versions, complete)
     }
-}
 
-impl PackageIndexBuilder {
     fn add_package(&mut self, package: Package, package_root: &Path) -> Result<(), indexer::Error> {
         let expected_name = package_root
             .file_name()
@@ -168,22 +385,29 @@ impl PackageIndexBuilder {
         }
 
         if package.is_empty() {
-            let (name, package_root) = package.destruct();
-            return Err(indexer::Error::NoVersions { name, package_root });
+            return Err(indexer::Error::NoVersions {
+                name: package.into_name(),
+                package_root: package_root.to_path_buf(),
+            });
         }
 
-        self.packages
-            .entry(package.get_name().to_string())
-            .or_default()
-            .push(package);
+        match self.packages.get_mut(package.get_name()) {
+            Some(existing) => existing.merge(package),
+            None => {
+                self.packages
+                    .insert(package.get_name().to_string(), package);
+            }
+        }
 
         Ok(())
     }
 }
 
 impl Package {
+    /// add a version read from version_path in source_root
     pub fn add_version(
         &mut self,
+        source_root: &Path,
         version_path: &Path,
         manifest: PackageManifest,
     ) -> Result<(), indexer::Error> {
@@ -211,7 +435,99 @@ impl Package {
             });
         }
 
-        self.insert(expected_version, manifest);
+        for (component, provide) in manifest.get_provides() {
+            // checked for every target, not only the selected one
+            if let Some(path) = provide.find_unsafe_target() {
+                return Err(indexer::Error::UnsafeLibraryPath {
+                    component: component.clone(),
+                    path: path.to_path_buf(),
+                    version_root: version_path.to_path_buf(),
+                });
+            }
+
+            if provide.get_library_file(component).is_none() {
+                return Err(indexer::Error::MissingTarget {
+                    component: component.clone(),
+                    targets: current_targets().to_vec(),
+                    version_root: version_path.to_path_buf(),
+                });
+            }
+
+            if !provide.get_library_kind().supports(provide.get_type()) {
+                return Err(indexer::Error::UnsupportedLibraryKind {
+                    component: component.clone(),
+                    kind: provide.get_library_kind().get_name().to_string(),
+                    version_root: version_path.to_path_buf(),
+                });
+            }
+        }
+
+        if let Some((feature, item)) = manifest.find_invalid_feature() {
+            return Err(indexer::Error::InvalidFeature {
+                feature: feature.to_string(),
+                item: item.to_string(),
+                version_root: version_path.to_path_buf(),
+            });
+        }
+
+        self.insert(
+            expected_version,
+            PackageVersion::new(
+                manifest,
+                source_root.to_path_buf(),
+                version_path.to_path_buf(),
+            ),
+        );
         Ok(())
     }
 }
+
+/// files and directories starting with a dot are ignored by the indexer
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+}
+
+impl PackageIndex {
+    /// rescan a single package in all roots of the index,
+    /// the package is removed from the index if it no longer has any versions
+    ///
+    /// errors in the package are returned, the rest of the package is still indexed
+    pub fn refresh_package(&mut self, name: &str) -> Result<(), indexer::Error> {
+        let mut builder = PackageIndexBuilder::new();
+
+        for root in self.get_roots() {
+            builder.add_overrides(root);
+
+            let package_path = root.join(name);
+            if package_path.exists() {
+                builder.add_package_dir(root, &package_path);
+            }
+        }
+
+        let (mut scanned, res) = builder.build();
+        self.replace_package(name, scanned.take_package(name));
+        res
+    }
+
+    /// scan all roots of the index again, without changing self
+    ///
+    /// use diff to find what changed, and replace_package to take changes in one package at a time
+    pub fn rescan(&self) -> (PackageIndex, Result<(), indexer::Error>) {
+        PackageIndexBuilder::new()
+            .add_roots(self.get_roots())
+            .build()
+    }
+
+    /// rescan all roots of the index and replace self with the result
+    ///
+    /// returns what changed since the last scan, and the errors of the new scan.
+    /// this can be polled, handles loaded from the old index are not affected
+    pub fn refresh(&mut self) -> (Vec<IndexEvent>, Result<(), indexer::Error>) {
+        let (scanned, res) = self.rescan();
+        let events = self.diff(&scanned);
+
+        *self = scanned;
+        (events, res)
+    }
+}
@@ -0,0 +1,233 @@
+use std::{
+    collections::HashMap,
+    fs::{self, Metadata},
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::packages::indexer::component::PackageManifest;
+
+/// the cache is discarded if it is written in a different format,
+///
+/// bump this whenever PackageManifest (or anything it contains) or the layout of the cache changes,
+/// otherwise manifests cached before the change are read back with new fields defaulted
+const CACHE_FORMAT_VERSION: u32 = 2;
+
+/// directory listings and parsed manifests from a previous build, keyed by root
+///
+/// - a root whose directory did not change is not listed again
+/// - a package whose directory did not change is not listed again
+/// - manifests are reused if package.toml did not change
+///
+/// only manifests that parsed successfully are cached, and a package with a broken
+/// (or missing) manifest is always listed again, so broken manifests are parsed (and reported)
+/// on every build
+#[derive(Serialize, Deserialize, Default)]
+struct IndexCache {
+    format: u32,
+    #[serde(rename = "root", default)]
+    roots: Vec<CachedRoot>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedRoot {
+    path: PathBuf,
+    /// None if the root could not be listed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    stamp: Option<Stamp>,
+    /// names of the (non hidden) entries of the root
+    #[serde(default)]
+    entries: Vec<String>,
+    #[serde(rename = "package", default)]
+    packages: Vec<CachedPackage>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedPackage {
+    /// the package directory
+    path: PathBuf,
+    /// None if the package must be listed again on the next build,
+    /// e.g. one of its manifests is broken
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    stamp: Option<Stamp>,
+    #[serde(rename = "version", default)]
+    versions: Vec<CachedVersion>,
+}
+
+/// a package.toml as it was when it was parsed
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CachedVersion {
+    /// directory containing package.toml
+    pub version_root: PathBuf,
+    /// stamp of package.toml, None if the platform does not report modification time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stamp: Option<Stamp>,
+    pub manifest: PackageManifest,
+}
+
+impl CachedVersion {
+    /// if package.toml is still as it was when it was parsed
+    pub fn is_fresh(&self) -> bool {
+        let stamp = fs::metadata(self.version_root.join("package.toml"))
+            .ok()
+            .and_then(|metadata| Stamp::from_metadata(&metadata));
+        stamp.is_some() && stamp == self.stamp
+    }
+}
+
+/// modification time and size of a file or directory,
+/// it is scanned again if either changed
+///
+/// the modification time of a directory changes when entries are added, removed or renamed in it
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct Stamp {
+    /// nanoseconds since unix epoch
+    modified: u64,
+    size: u64,
+}
+
+impl Stamp {
+    /// returns None if the platform does not report modification time
+    pub fn from_metadata(metadata: &Metadata) -> Option<Self> {
+        Some(Self {
+            modified: metadata
+                .modified()
+                .ok()?
+                .duration_since(UNIX_EPOCH)
+                .ok()?
+                .as_nanos()
+                .try_into()
+                .ok()?,
+            size: metadata.len(),
+        })
+    }
+}
+
+/// the cache while the index is being built
+pub struct IndexCacheState {
+    path: PathBuf,
+    /// root -> listing read from the cache file
+    previous_roots: HashMap<PathBuf, (Stamp, Vec<String>)>,
+    /// package directory -> versions read from the cache file
+    previous_packages: HashMap<PathBuf, (Option<Stamp>, Vec<CachedVersion>)>,
+    /// entries seen in this build, written back on build
+    current: Vec<CachedRoot>,
+}
+
+impl IndexCacheState {
+    /// read the cache file, an unreadable or outdated cache is treated as empty
+    pub fn read(path: &Path) -> Self {
+        let cache = fs::read(path)
+            .ok()
+            .and_then(|content| toml::from_slice::<IndexCache>(&content).ok())
+            .filter(|cache| cache.format == CACHE_FORMAT_VERSION)
+            .unwrap_or_default();
+
+        let mut previous_roots = HashMap::new();
+        let mut previous_packages = HashMap::new();
+
+        for root in cache.roots {
+            if let Some(stamp) = root.stamp {
+                previous_roots.insert(root.path, (stamp, root.entries));
+            }
+
+            for package in root.packages {
+                previous_packages.insert(package.path, (package.stamp, package.versions));
+            }
+        }
+
+        Self {
+            path: path.to_path_buf(),
+            previous_roots,
+            previous_packages,
+            current: Vec::new(),
+        }
+    }
+
+    /// the cached entries of a root, if its directory did not change
+    pub fn get_root(&self, root: &Path, stamp: Stamp) -> Option<&[String]> {
+        let (cached_stamp, entries) = self.previous_roots.get(root)?;
+        (*cached_stamp == stamp).then_some(entries.as_slice())
+    }
+
+    /// the cached versions of a package, if its directory did not change
+    /// and all its versions were cached
+    pub fn get_package(&self, package_path: &Path, stamp: Stamp) -> Option<&[CachedVersion]> {
+        let (cached_stamp, versions) = self.previous_packages.get(package_path)?;
+        (*cached_stamp == Some(stamp)).then_some(versions.as_slice())
+    }
+
+    /// the cached manifest of a version, if package.toml did not change
+    pub fn get_manifest(
+        &self,
+        package_path: &Path,
+        version_root: &Path,
+        stamp: Stamp,
+    ) -> Option<&PackageManifest> {
+        self.previous_packages
+            .get(package_path)?
+            .1
+            .iter()
+            .find(|cached| cached.version_root == version_root && cached.stamp == Some(stamp))
+            .map(|cached| &cached.manifest)
+    }
+
+    /// record the listing of a root
+    pub fn insert_root(&mut self, root: &Path, stamp: Option<Stamp>, entries: Vec<String>) {
+        let cached = self.current_root(root);
+        cached.stamp = stamp;
+        cached.entries = entries;
+    }
+
+    /// record the successfully parsed manifests of a package,
+    /// stamp is None if the package must be listed again on the next build
+    pub fn insert_package(
+        &mut self,
+        root: &Path,
+        package_path: &Path,
+        stamp: Option<Stamp>,
+        versions: Vec<CachedVersion>,
+    ) {
+        self.current_root(root).packages.push(CachedPackage {
+            path: package_path.to_path_buf(),
+            stamp,
+            versions,
+        });
+    }
+
+    fn current_root(&mut self, root: &Path) -> &mut CachedRoot {
+        let index = match self.current.iter().position(|cached| cached.path == root) {
+            Some(index) => index,
+            None => {
+                self.current.push(CachedRoot {
+                    path: root.to_path_buf(),
+                    stamp: None,
+                    entries: Vec::new(),
+                    packages: Vec::new(),
+                });
+                self.current.len() - 1
+            }
+        };
+
+        &mut self.current[index]
+    }
+
+    /// write the entries seen in this build to the cache file
+    ///
+    /// the cache is an optimisation, failing to write it is not an error
+    pub fn write(self) {
+        let cache = IndexCache {
+            format: CACHE_FORMAT_VERSION,
+            roots: self.current,
+        };
+
+        if let Ok(content) = toml::to_string(&cache) {
+            let temp_path = self.path.with_extension("tmp");
+            if fs::write(&temp_path, content).is_ok() {
+                let _ = fs::rename(&temp_path, &self.path);
+            }
+        }
+    }
+}
@@ -1,15 +1,27 @@
-use std::collections::HashMap;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    env::consts::{ARCH, DLL_EXTENSION, OS},
+    path::{Component, Path, PathBuf},
+};
 
 use semver::{Version, VersionReq};
 use serde::{Deserialize, Serialize};
 
+use crate::packages::indexer::component::{HostIncompatibility, PackageHost, VersionStatus};
+
 /// package.toml manifest
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
 #[cfg_attr(feature = "devel", derive(Debug))]
 pub struct PackageManifest {
     package: PackageInfo,
-    dependencies: HashMap<String, VersionReq>,
-    provides: HashMap<String, PackageComponentType>,
+    /// see PackageHost
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    host: Option<PackageHost>,
+    dependencies: HashMap<String, PackageDependency>,
+    provides: HashMap<String, PackageProvide>,
+    /// feature name -> what the feature enables, see PackageManifest::enabled_features
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    features: BTreeMap<String, Vec<String>>,
 }
 
 impl PackageManifest {
@@ -21,24 +33,87 @@ impl PackageManifest {
         &self.package.version
     }
 
-    pub fn get_provides(&self) -> &HashMap<String, PackageComponentType> {
+    pub fn get_provides(&self) -> &HashMap<String, PackageProvide> {
         &self.provides
     }
 
-    pub fn get_dependencies(&self) -> &HashMap<String, VersionReq> {
+    /// all dependencies, including optional ones
+    pub fn get_dependencies(&self) -> &HashMap<String, PackageDependency> {
         &self.dependencies
     }
+
+    pub fn get_features(&self) -> &BTreeMap<String, Vec<String>> {
+        &self.features
+    }
+
+    pub fn get_description(&self) -> Option<&str> {
+        self.package.description.as_deref()
+    }
+
+    pub fn get_authors(&self) -> &[String] {
+        &self.package.authors
+    }
+
+    pub fn get_license(&self) -> Option<&str> {
+        self.package.license.as_deref()
+    }
+
+    pub fn get_homepage(&self) -> Option<&str> {
+        self.package.homepage.as_deref()
+    }
+
+    pub fn get_keywords(&self) -> &[String] {
+        &self.package.keywords
+    }
+
+    /// status set by the manifest, overrides files may change it (see PackageVersion::get_status)
+    pub fn get_status(&self) -> VersionStatus {
+        VersionStatus {
+            yanked: self.package.yanked,
+            deprecated: self.package.deprecated.clone(),
+            isolated: false,
+        }
+    }
+
+    pub fn get_host(&self) -> Option<&PackageHost> {
+        self.host.as_ref()
+    }
+
+    /// why the package cannot run on this host, empty if it can
+    pub fn host_incompatibilities(&self) -> Vec<HostIncompatibility> {
+        self.host
+            .as_ref()
+            .map(PackageHost::check)
+            .unwrap_or_default()
+    }
 }
 
 /// has public fields,
 /// but the struct is private
 /// so it is fine.
 /// the struct exists solely to add structure to the toml file
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
 #[cfg_attr(feature = "devel", derive(Debug))]
 struct PackageInfo {
     pub name: String,
     pub version: Version,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub authors: Vec<String>,
+    /// SPDX license expression
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub homepage: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub keywords: Vec<String>,
+    /// see VersionStatus
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub yanked: bool,
+    /// deprecation message, see VersionStatus
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deprecated: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
@@ -52,4 +127,435 @@ pub enum PackageComponentType {
     Conn,
 }
 
-impl PackageManifest {}
+/// how the library of a component is run
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "devel", derive(Debug))]
+pub enum PackageLibraryKind {
+    /// a dynamic library loaded into the process
+    #[default]
+    #[serde(rename = "native")]
+    Native,
+    /// a WebAssembly module run by an embedded runtime (needs the wasm feature),
+    /// only gates and data types can be wasm modules
+    #[serde(rename = "wasm")]
+    Wasm,
+    /// a rhai script (needs the script feature), only gates can be scripts
+    #[serde(rename = "rhai")]
+    Script,
+}
+
+impl PackageLibraryKind {
+    /// name of the kind in the manifest
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            Self::Native => "native",
+            Self::Wasm => "wasm",
+            Self::Script => "rhai",
+        }
+    }
+
+    /// extension of the default library file
+    pub fn get_extension(&self) -> &'static str {
+        match self {
+            Self::Native => DLL_EXTENSION,
+            Self::Wasm => "wasm",
+            Self::Script => "rhai",
+        }
+    }
+
+    /// if a component of the type can have a library of this kind
+    pub fn supports(&self, variant: PackageComponentType) -> bool {
+        match self {
+            Self::Native => true,
+            Self::Wasm => variant != PackageComponentType::Conn,
+            Self::Script => variant == PackageComponentType::Gate,
+        }
+    }
+}
+
+/// a component in provides, either just the component type
+///
+/// ```toml
+/// not = "gate"
+/// ```
+///
+/// or a table that also pins the sha256 digest of the library, or describes the component
+///
+/// ```toml
+/// not = { type = "gate", sha256 = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08" }
+/// and = { type = "gate", description = "2 input and gate" }
+/// ```
+///
+/// optional components are only loaded when a feature enables them (see PackageDependency)
+///
+/// the library is a dynamic library unless it is a wasm module or a rhai script
+///
+/// ```toml
+/// not = { type = "gate", library = "wasm" }
+/// and = { type = "gate", library = "rhai" }
+/// ```
+///
+/// by default the library is `not.[dll/dylib/so]` (or `not.wasm`, `and.rhai`) in the version root,
+/// targets maps platforms to library files instead (see current_targets for the keys)
+///
+/// ```toml
+/// [provides.not]
+/// type = "gate"
+///
+/// [provides.not.targets]
+/// x86_64-linux = "x86_64-linux/not.so"
+/// aarch64-unknown-linux-gnu = { path = "aarch64-linux/not.so", sha256 = "..." }
+/// ```
+///
+/// unknown keys are a parse error, so a misspelled sha256 does not turn off the check
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "devel", derive(Debug))]
+#[serde(untagged, deny_unknown_fields)]
+pub enum PackageProvide {
+    Type(PackageComponentType),
+    Detailed {
+        #[serde(rename = "type")]
+        variant: PackageComponentType,
+        /// lowercase hex sha256 digest of the library
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sha256: Option<String>,
+        #[serde(default, skip_serializing_if = "is_native_library")]
+        library: PackageLibraryKind,
+        /// target -> library, relative to the version root
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        targets: BTreeMap<String, PackageTargetLibrary>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        description: Option<String>,
+        /// only loaded when a feature enables it
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        optional: bool,
+    },
+}
+
+/// the library of a component for a single target,
+/// either just the path or a table with the sha256 digest
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "devel", derive(Debug))]
+#[serde(untagged, deny_unknown_fields)]
+pub enum PackageTargetLibrary {
+    Path(PathBuf),
+    Detailed {
+        path: PathBuf,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sha256: Option<String>,
+    },
+}
+
+impl PackageTargetLibrary {
+    pub fn get_path(&self) -> &Path {
+        match self {
+            Self::Path(path) | Self::Detailed { path, .. } => path,
+        }
+    }
+
+    pub fn get_sha256(&self) -> Option<&str> {
+        match self {
+            Self::Path(_) => None,
+            Self::Detailed { sha256, .. } => sha256.as_deref(),
+        }
+    }
+}
+
+impl PackageProvide {
+    pub fn get_type(&self) -> PackageComponentType {
+        match self {
+            Self::Type(variant) | Self::Detailed { variant, .. } => *variant,
+        }
+    }
+
+    pub fn get_library_kind(&self) -> PackageLibraryKind {
+        match self {
+            Self::Type(_) => PackageLibraryKind::Native,
+            Self::Detailed { library, .. } => *library,
+        }
+    }
+
+    /// expected sha256 digest of the default library, if the manifest specifies one
+    pub fn get_sha256(&self) -> Option<&str> {
+        match self {
+            Self::Type(_) => None,
+            Self::Detailed { sha256, .. } => sha256.as_deref(),
+        }
+    }
+
+    pub fn get_description(&self) -> Option<&str> {
+        match self {
+            Self::Type(_) => None,
+            Self::Detailed { description, .. } => description.as_deref(),
+        }
+    }
+
+    pub fn is_optional(&self) -> bool {
+        match self {
+            Self::Type(_) => false,
+            Self::Detailed { optional, .. } => *optional,
+        }
+    }
+
+    /// all targets listed for the component, None if it only has the default library
+    pub fn get_targets(&self) -> Option<&BTreeMap<String, PackageTargetLibrary>> {
+        match self {
+            Self::Detailed { targets, .. } if !targets.is_empty() => Some(targets),
+            _ => None,
+        }
+    }
+
+    /// the first target library whose path is absolute or leaves the version root
+    pub fn find_unsafe_target(&self) -> Option<&Path> {
+        self.get_targets()?
+            .values()
+            .map(PackageTargetLibrary::get_path)
+            .find(|path| {
+                !path
+                    .components()
+                    .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+            })
+    }
+
+    /// the library of the current platform,
+    /// None if the component only has the default library
+    pub fn select_target(&self) -> Option<&PackageTargetLibrary> {
+        let targets = self.get_targets()?;
+        current_targets()
+            .iter()
+            .find_map(|target| targets.get(target))
+    }
+
+    /// path to the library of the current platform, relative to the version root
+    ///
+    /// returns None if targets are listed, but none matches the current platform
+    pub fn get_library_file(&self, component: &str) -> Option<PathBuf> {
+        match self.get_targets() {
+            Some(_) => Some(self.select_target()?.get_path().to_path_buf()),
+            None => Some(
+                PathBuf::from(component).with_extension(self.get_library_kind().get_extension()),
+            ),
+        }
+    }
+
+    /// expected sha256 digest of the library of the current platform
+    pub fn get_library_sha256(&self) -> Option<&str> {
+        match self.get_targets() {
+            Some(_) => self.select_target()?.get_sha256(),
+            None => self.get_sha256(),
+        }
+    }
+}
+
+/// keys in provides.<component>.targets that match the current platform,
+/// from most to least specific:
+/// - the full target triple, e.g. `x86_64-unknown-linux-gnu`
+/// - arch-os, e.g. `x86_64-linux`
+pub fn current_targets() -> [String; 2] {
+    [env!("XDSIM_TARGET").to_string(), format!("{ARCH}-{OS}")]
+}
+
+fn is_native_library(kind: &PackageLibraryKind) -> bool {
+    *kind == PackageLibraryKind::Native
+}
+
+/// an item in the list of a feature
+enum FeatureItem<'a> {
+    /// another feature, which is enabled as well
+    Feature(&'a str),
+    /// `dep:<name>`, an optional dependency
+    Dependency(&'a str),
+    /// an optional component in provides
+    Component(&'a str),
+}
+
+impl PackageManifest {
+    /// what an item in the list of a feature refers to, None if it refers to nothing
+    fn feature_item<'a>(&self, item: &'a str) -> Option<FeatureItem<'a>> {
+        if let Some(dependency) = item.strip_prefix("dep:") {
+            return self
+                .dependencies
+                .get(dependency)
+                .is_some_and(PackageDependency::is_optional)
+                .then_some(FeatureItem::Dependency(dependency));
+        }
+
+        if self.features.contains_key(item) {
+            Some(FeatureItem::Feature(item))
+        } else if self
+            .provides
+            .get(item)
+            .is_some_and(PackageProvide::is_optional)
+        {
+            Some(FeatureItem::Component(item))
+        } else {
+            None
+        }
+    }
+
+    /// the first (feature, item) where the item does not refer to
+    /// a feature, an optional dependency or an optional component
+    pub fn find_invalid_feature(&self) -> Option<(&str, &str)> {
+        self.features.iter().find_map(|(feature, items)| {
+            items
+                .iter()
+                .find(|item| self.feature_item(item).is_none())
+                .map(|item| (feature.as_str(), item.as_str()))
+        })
+    }
+
+    /// the features, and the features they enable (transitively)
+    ///
+    /// returns None if one of the features does not exist
+    pub fn enabled_features<'a>(
+        &'a self,
+        features: impl IntoIterator<Item = &'a str>,
+    ) -> Option<BTreeSet<&'a str>> {
+        let mut enabled = BTreeSet::new();
+        let mut pending: Vec<&str> = features.into_iter().collect();
+
+        while let Some(feature) = pending.pop() {
+            let (feature, items) = self.features.get_key_value(feature)?;
+
+            if enabled.insert(feature.as_str()) {
+                pending.extend(
+                    items
+                        .iter()
+                        .filter_map(|item| match self.feature_item(item) {
+                            Some(FeatureItem::Feature(feature)) => Some(feature),
+                            _ => None,
+                        }),
+                );
+            }
+        }
+
+        Some(enabled)
+    }
+
+    /// items of the enabled features
+    fn enabled_items<'a>(
+        &'a self,
+        enabled: &'a BTreeSet<&str>,
+    ) -> impl Iterator<Item = FeatureItem<'a>> + 'a {
+        enabled
+            .iter()
+            .filter_map(|feature| self.features.get(*feature))
+            .flatten()
+            .filter_map(|item| self.feature_item(item))
+    }
+
+    /// non optional dependencies, and optional dependencies enabled by the features,
+    /// sorted by name
+    ///
+    /// enabled should be the output of enabled_features
+    pub fn enabled_dependencies(
+        &self,
+        enabled: &BTreeSet<&str>,
+    ) -> Vec<(&str, &PackageDependency)> {
+        let enabled_optional: BTreeSet<&str> = self
+            .enabled_items(enabled)
+            .filter_map(|item| match item {
+                FeatureItem::Dependency(name) => Some(name),
+                _ => None,
+            })
+            .collect();
+
+        let mut dependencies: Vec<_> = self
+            .dependencies
+            .iter()
+            .filter(|(name, dependency)| {
+                !dependency.is_optional() || enabled_optional.contains(name.as_str())
+            })
+            .map(|(name, dependency)| (name.as_str(), dependency))
+            .collect();
+
+        dependencies.sort_by_key(|(name, _)| *name);
+        dependencies
+    }
+
+    /// non optional components, and optional components enabled by the features,
+    /// sorted by name
+    ///
+    /// enabled should be the output of enabled_features
+    pub fn enabled_provides(&self, enabled: &BTreeSet<&str>) -> Vec<(&str, &PackageProvide)> {
+        let enabled_optional: BTreeSet<&str> = self
+            .enabled_items(enabled)
+            .filter_map(|item| match item {
+                FeatureItem::Component(name) => Some(name),
+                _ => None,
+            })
+            .collect();
+
+        let mut provides: Vec<_> = self
+            .provides
+            .iter()
+            .filter(|(name, provide)| {
+                !provide.is_optional() || enabled_optional.contains(name.as_str())
+            })
+            .map(|(name, provide)| (name.as_str(), provide))
+            .collect();
+
+        provides.sort_by_key(|(name, _)| *name);
+        provides
+    }
+}
+
+/// a dependency, either just the version requirement
+///
+/// ```toml
+/// logic = "^0.3"
+/// ```
+///
+/// or a table, for optional dependencies and enabling features of the dependency
+///
+/// ```toml
+/// rom = { version = "^1", optional = true, features = ["large"] }
+/// ```
+///
+/// optional dependencies are only required when a feature enables them:
+///
+/// ```toml
+/// [features]
+/// # another feature, an optional dependency (dep:<name>), or an optional component
+/// probes = ["trace", "dep:rom", "probe"]
+/// trace = []
+///
+/// [provides]
+/// probe = { type = "gate", optional = true }
+/// ```
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "devel", derive(Debug))]
+#[serde(untagged)]
+pub enum PackageDependency {
+    Version(VersionReq),
+    Detailed {
+        version: VersionReq,
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        optional: bool,
+        /// features of the dependency to enable
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        features: Vec<String>,
+    },
+}
+
+impl PackageDependency {
+    pub fn get_version(&self) -> &VersionReq {
+        match self {
+            Self::Version(version) | Self::Detailed { version, .. } => version,
+        }
+    }
+
+    pub fn is_optional(&self) -> bool {
+        match self {
+            Self::Version(_) => false,
+            Self::Detailed { optional, .. } => *optional,
+        }
+    }
+
+    pub fn get_features(&self) -> &[String] {
+        match self {
+            Self::Version(_) => &[],
+            Self::Detailed { features, .. } => features,
+        }
+    }
+}
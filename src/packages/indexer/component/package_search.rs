@@ -0,0 +1,128 @@
+use semver::Version;
+
+use crate::packages::indexer::component::{PackageComponentType, PackageIndex, PackageManifest};
+
+/// filters for PackageIndex::search, all filters must match
+///
+/// ```ignore
+/// let query = PackageQuery::new()
+///     .keyword("adder")
+///     .component_type(PackageComponentType::Gate);
+/// ```
+#[derive(Default, Clone)]
+#[cfg_attr(feature = "devel", derive(Debug))]
+pub struct PackageQuery {
+    keyword: Option<String>,
+    component: Option<String>,
+    component_type: Option<PackageComponentType>,
+}
+
+/// a package version matched by a query
+pub struct PackageMatch<'a> {
+    pub name: &'a str,
+    pub version: &'a Version,
+    pub manifest: &'a PackageManifest,
+    /// components that match the component filters, sorted by name
+    /// (all components if there are no component filters)
+    pub components: Vec<&'a str>,
+}
+
+impl PackageQuery {
+    /// a query that matches everything
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// case insensitive match against the package name, description and keywords,
+    /// and the names and descriptions of its components
+    pub fn keyword(mut self, keyword: &str) -> Self {
+        self.keyword = Some(keyword.to_lowercase());
+        self
+    }
+
+    /// case insensitive match against component names
+    pub fn component(mut self, component: &str) -> Self {
+        self.component = Some(component.to_lowercase());
+        self
+    }
+
+    /// only packages providing a component of the type
+    pub fn component_type(mut self, component_type: PackageComponentType) -> Self {
+        self.component_type = Some(component_type);
+        self
+    }
+
+    fn has_component_filters(&self) -> bool {
+        self.component.is_some() || self.component_type.is_some()
+    }
+
+    /// components of the manifest that match the component filters
+    fn matching_components<'a>(&self, manifest: &'a PackageManifest) -> Vec<&'a str> {
+        let mut components: Vec<_> = manifest
+            .get_provides()
+            .iter()
+            .filter(|(name, provide)| {
+                self.component
+                    .as_ref()
+                    .is_none_or(|component| name.to_lowercase().contains(component))
+                    && self
+                        .component_type
+                        .is_none_or(|component_type| provide.get_type() == component_type)
+            })
+            .map(|(name, _)| name.as_str())
+            .collect();
+
+        components.sort();
+        components
+    }
+
+    fn matches_keyword(&self, manifest: &PackageManifest) -> bool {
+        let keyword = match &self.keyword {
+            Some(keyword) => keyword,
+            None => return true,
+        };
+        let contains = |text: &str| text.to_lowercase().contains(keyword);
+
+        contains(manifest.get_name())
+            || manifest.get_description().is_some_and(contains)
+            || manifest.get_keywords().iter().any(|word| contains(word))
+            || manifest.get_provides().iter().any(|(name, provide)| {
+                contains(name) || provide.get_description().is_some_and(contains)
+            })
+    }
+}
+
+impl PackageIndex {
+    /// find packages matching a query
+    ///
+    /// for each package, only the newest version that matches is returned,
+    /// results are sorted by package name
+    pub fn search(&self, query: &PackageQuery) -> Vec<PackageMatch<'_>> {
+        self.list_packages()
+            .into_iter()
+            .filter_map(|package| {
+                package
+                    .list_versions()
+                    .into_iter()
+                    .rev()
+                    .find_map(|version| {
+                        let manifest = package.get_version(version)?;
+                        let components = query.matching_components(manifest);
+
+                        if !query.matches_keyword(manifest)
+                            || (query.has_component_filters() && components.is_empty())
+                        {
+                            return None;
+                        }
+
+                        Some(PackageMatch {
+                            name: package.get_name(),
+                            version,
+                            manifest,
+                            components,
+                        })
+                    })
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,113 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::Path,
+};
+
+use semver::Version;
+use serde::{Deserialize, Serialize};
+
+use crate::packages::indexer;
+
+/// file in a root that overrides the status of package versions
+///
+/// ```toml
+/// [logic."0.3.2"]
+/// yanked = true
+///
+/// [logic."0.3.1"]
+/// deprecated = "miscomputes carries, use 0.3.3"
+///
+/// [untrusted."1.0.0"]
+/// isolated = true
+/// ```
+///
+/// overrides only apply to versions indexed from the same root,
+/// a version of a higher priority root that shadows one of this root is not changed
+pub const OVERRIDES_FILE_NAME: &str = "overrides.toml";
+
+/// whether a version should still be used, and how,
+/// set in the [package] table of the manifest, or in an overrides file
+#[derive(Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "devel", derive(Debug))]
+pub struct VersionStatus {
+    /// skipped by deps_resolver, but still loadable when pinned (e.g. by a lockfile)
+    pub yanked: bool,
+    /// the version can be used, but loading it produces a loader::Warning with the message
+    pub deprecated: Option<String>,
+    /// gates and data types of the version run in a sandbox helper process,
+    /// see loader::indexed::component::Isolation
+    ///
+    /// only an overrides file can set it, a package does not choose to be trusted
+    pub isolated: bool,
+}
+
+/// a single entry of an overrides file, unset fields keep the status of the manifest
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "devel", derive(Debug))]
+pub struct VersionOverride {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub yanked: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deprecated: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub isolated: Option<bool>,
+}
+
+/// content of an overrides file, package name -> version -> override
+#[derive(Serialize, Deserialize, Default)]
+#[cfg_attr(feature = "devel", derive(Debug))]
+#[serde(transparent)]
+pub struct PackageOverrides {
+    packages: HashMap<String, BTreeMap<Version, VersionOverride>>,
+}
+
+impl VersionStatus {
+    pub fn apply(&mut self, version_override: &VersionOverride) {
+        if let Some(yanked) = version_override.yanked {
+            self.yanked = yanked;
+        }
+
+        if let Some(deprecated) = &version_override.deprecated {
+            self.deprecated = Some(deprecated.clone());
+        }
+
+        if let Some(isolated) = version_override.isolated {
+            self.isolated = isolated;
+        }
+    }
+}
+
+impl PackageOverrides {
+    /// read the overrides file of a root, a root without one has no overrides
+    pub fn read(root: &Path) -> Result<Self, indexer::Error> {
+        let path = root.join(OVERRIDES_FILE_NAME);
+
+        let content = match fs::read(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => {
+                return Err(indexer::Error::Fs {
+                    path,
+                    reason: e.to_string(),
+                });
+            }
+        };
+
+        toml::from_slice(&content).map_err(|e| indexer::Error::OverridesParse {
+            overrides_path: path,
+            reason: e.to_string(),
+        })
+    }
+
+    /// overrides of a package, version -> override
+    pub fn get_package(&self, name: &str) -> Option<&BTreeMap<Version, VersionOverride>> {
+        self.packages.get(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &BTreeMap<Version, VersionOverride>)> {
+        self.packages
+            .iter()
+            .map(|(name, versions)| (name.as_str(), versions))
+    }
+}
@@ -1,9 +1,56 @@
+use std::collections::BTreeSet;
+
 use semver::{Version, VersionReq};
 
+use crate::packages::indexer::component::HostIncompatibility;
+
 /// a package index that holds package in a name-version-dependencies format
 pub trait DepsResolvable {
     /// returns the dependencies of a package, returned in [(package name, required version)]
+    ///
+    /// optional dependencies are not included
     fn get_dependencies(&self, name: &str, version: &Version) -> Option<Vec<(&str, &VersionReq)>>;
     /// returns all versions of the specified package
     fn get_versions(&self, name: &str) -> Option<Vec<&Version>>;
+
+    /// returns the dependencies of a package with features enabled,
+    /// returned in [(package name, required version, features of the dependency to enable)]
+    ///
+    /// returns None if the package does not have one of the features,
+    /// by default packages have no features
+    fn get_dependencies_with_features(
+        &self,
+        name: &str,
+        version: &Version,
+        features: &BTreeSet<String>,
+    ) -> Option<Vec<(&str, &VersionReq, &[String])>> {
+        if !features.is_empty() {
+            return None;
+        }
+
+        Some(
+            self.get_dependencies(name, version)?
+                .into_iter()
+                .map(|(name, req)| (name, req, [].as_slice()))
+                .collect(),
+        )
+    }
+
+    /// returns true if a package version is yanked, yanked versions are never chosen
+    ///
+    /// by default no versions are yanked
+    fn is_yanked(&self, _name: &str, _version: &Version) -> bool {
+        false
+    }
+
+    /// why a package version cannot run on this host, empty if it can
+    ///
+    /// incompatible versions are never chosen, by default all versions are compatible
+    fn get_host_incompatibilities(
+        &self,
+        _name: &str,
+        _version: &Version,
+    ) -> &[HostIncompatibility] {
+        &[]
+    }
 }
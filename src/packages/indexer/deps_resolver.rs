@@ -1,119 +1,660 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
 use semver::{Version, VersionReq};
 
-use crate::packages::indexer::{self, deps_resolvable::DepsResolvable};
+use crate::packages::indexer::{
+    self,
+    deps_resolvable::DepsResolvable,
+    graph::DependencyGraph,
+    resolution_trace::{RejectReason, RejectedCandidate, ResolutionTrace},
+};
 
 #[derive(Clone)]
 pub struct DepsResolveRequest {
     name: String,
     version: VersionReq,
+    features: Vec<String>,
 }
 
 impl DepsResolveRequest {
     pub fn new(name: String, version: VersionReq) -> Self {
-        Self { name, version }
+        Self {
+            name,
+            version,
+            features: Vec::new(),
+        }
+    }
+
+    /// features of the package to enable, see PackageDependency for the manifest format
+    pub fn with_features(mut self, features: Vec<String>) -> Self {
+        self.features = features;
+        self
+    }
+
+    pub fn get_features(&self) -> &[String] {
+        &self.features
     }
 
-    fn get_name(&self) -> &str {
+    pub fn get_name(&self) -> &str {
         &self.name
     }
 
-    fn get_version(&self) -> &VersionReq {
+    pub fn get_version(&self) -> &VersionReq {
         &self.version
     }
 }
 
+/// what to do when the resolved packages contain a dependency cycle
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "devel", derive(Debug))]
+pub enum CyclePolicy {
+    /// accept cycles, they are still reported in Resolved::cycles
+    #[default]
+    Allow,
+    /// fail with indexer::Error::DependencyCycles
+    Reject,
+}
+
+/// options for deps_resolver_with_options
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "devel", derive(Debug))]
+pub struct ResolveOptions {
+    cycle_policy: CyclePolicy,
+}
+
+impl ResolveOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cycle_policy(mut self, cycle_policy: CyclePolicy) -> Self {
+        self.cycle_policy = cycle_policy;
+        self
+    }
+
+    pub fn get_cycle_policy(&self) -> CyclePolicy {
+        self.cycle_policy
+    }
+}
+
+/// output of deps_resolver_with_options
+#[cfg_attr(feature = "devel", derive(Debug))]
+pub struct Resolved {
+    /// same as the output of deps_resolver
+    pub packages: HashMap<String, Vec<Version>>,
+    /// dependency cycles between the resolved versions,
+    /// each cycle starts and ends with the same package version
+    pub cycles: Vec<Vec<(String, Version)>>,
+    /// features enabled on each resolved version (empty if none are),
+    /// pass to IndexComponentLoader::load_all with LoadOptions::features
+    pub features: EnabledFeatures,
+}
+
+/// package name -> version -> enabled features
+pub type EnabledFeatures = HashMap<String, BTreeMap<Version, BTreeSet<String>>>;
+
+/// represents a dependency chain
+/// the first item in the dependency chain is the actual item that is missing
+type MissingDependency = Vec<(String, VersionReq)>;
+
+/// versions that are semver compatible with each other share the same slot,
+/// at most one version is selected per slot
+///
+/// - 1.2.3 -> (1, 0, 0)
+/// - 0.2.3 -> (0, 2, 0)
+/// - 0.0.3 -> (0, 0, 3)
+type CompatSlot = (u64, u64, u64);
+
+fn compat_slot(version: &Version) -> CompatSlot {
+    match (version.major, version.minor) {
+        (0, 0) => (0, 0, version.patch),
+        (0, minor) => (0, minor, 0),
+        (major, _) => (major, 0, 0),
+    }
+}
+
+/// a requirement that is waiting to be resolved
+#[derive(Clone)]
+struct PendingRequirement {
+    name: String,
+    version: VersionReq,
+    features: BTreeSet<String>,
+    /// requirements that led to this one, the immediate parent comes first
+    chain: Vec<(String, VersionReq)>,
+}
+
+impl PendingRequirement {
+    fn from_request(request: &DepsResolveRequest) -> Self {
+        Self {
+            name: request.get_name().to_string(),
+            version: request.get_version().clone(),
+            features: request.get_features().iter().cloned().collect(),
+            chain: Vec::new(),
+        }
+    }
+    /// dependency chain with this requirement as the missing item
+    fn as_missing(&self) -> MissingDependency {
+        let mut chain = Vec::with_capacity(self.chain.len() + 1);
+        chain.push((self.name.clone(), self.version.clone()));
+        chain.extend(self.chain.iter().cloned());
+        chain
+    }
+
+    /// a candidate for this requirement that is not chosen
+    fn reject(&self, version: Version, reason: RejectReason) -> RejectedCandidate {
+        RejectedCandidate {
+            name: self.name.clone(),
+            version,
+            req: self.version.clone(),
+            reason,
+        }
+    }
+}
+
+/// a version selected so far
+#[derive(Clone)]
+struct SelectedVersion {
+    version: Version,
+    /// union of the features of every requirement the version satisfies
+    features: BTreeSet<String>,
+}
+
+/// versions selected so far, one per compat slot of a package
+type Selected = BTreeMap<String, BTreeMap<CompatSlot, SelectedVersion>>;
+
+/// a pending requirement being resolved, see internal_resolver in resolve
+struct ResolveFrame {
+    /// candidates that are not tried yet, oldest first
+    candidates: Vec<Version>,
+    /// the candidate being tried, None before the first one is
+    tried: Option<TriedCandidate>,
+    /// the failure of the newest candidate is the one reported,
+    /// it is the version the user most likely expected to be picked
+    first_failure: Option<Vec<MissingDependency>>,
+    /// length of pending before the candidate being tried appends its dependencies
+    pending_len: usize,
+    /// length of rejected when the candidate being tried is chosen,
+    /// rejections under a candidate that fails do not explain the final selection
+    rejected_len: usize,
+}
+
+/// how a candidate changed selected, undone if the candidate fails
+enum TriedCandidate {
+    /// the version is already selected with every feature of the requirement
+    Unchanged,
+    /// features of the requirement are enabled on the selected version
+    Features {
+        version: Version,
+        previous: BTreeSet<String>,
+    },
+    /// the version is selected
+    Inserted { version: Version },
+}
+
+/// the requirements a version adds with features enabled,
+/// None if the version does not have one of the features
+fn pending_dependencies<I: DepsResolvable>(
+    resolvable: &I,
+    requirement: &PendingRequirement,
+    version: &Version,
+    features: &BTreeSet<String>,
+) -> Option<Vec<PendingRequirement>> {
+    // the chain of the dependencies, with this requirement as immediate parent
+    let chain = requirement.as_missing();
+
+    // dependencies are sorted so the search order is deterministic
+    let mut deps = resolvable
+        .get_dependencies_with_features(&requirement.name, version, features)?
+        .into_iter()
+        .map(|(dep_name, dep_version, dep_features)| PendingRequirement {
+            name: dep_name.to_string(),
+            version: dep_version.clone(),
+            features: dep_features.iter().cloned().collect(),
+            chain: chain.clone(),
+        })
+        .collect::<Vec<_>>();
+    deps.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Some(deps)
+}
+
 /// given a list of packages that are required,
 /// return a full list of packages and their dependencies that will need to be loaded
 ///
-/// may return an indexer::Error::MissingDependencies if there are any missing dependencies
+/// - for each requirement, the newest version that keeps the whole set satisfiable is chosen
+/// - semver compatible versions of a package are unified into a single version,
+///   incompatible versions (e.g. 0.1.x and 0.2.x) may be loaded side by side
+/// - if a choice leads to a requirement that cannot be met, the resolver backtracks
+///   and tries the next older candidate
+/// - features of the requests and dependencies are unified per version,
+///   a version without one of the requested features is not a candidate
+/// - versions that cannot run on this host (see PackageHost) are not candidates
+/// - yanked versions (see VersionStatus) are not candidates,
+///   use a lockfile to keep using a yanked version
+///
+/// the versions of each package are sorted from oldest to newest,
+/// the result only depends on the content of the index and the requests
+///
+/// may return an indexer::Error::MissingDependencies if there are any missing dependencies,
+/// or indexer::Error::IncompatibleCycle if the missing dependency is caused by a cycle,
+/// if there are several of them they are returned together in indexer::Error::Resolve
+///
+/// dependency cycles are allowed, use deps_resolver_with_options to find or reject them
 pub fn deps_resolver<I: DepsResolvable>(
     resolvable: &I,
     requests: &[DepsResolveRequest],
 ) -> Result<HashMap<String, Vec<Version>>, indexer::Error> {
-    let mut resolved: HashMap<String, Vec<Version>> = HashMap::new();
+    deps_resolver_with_options(resolvable, requests, &ResolveOptions::default())
+        .map(|resolved| resolved.packages)
+}
+
+/// deps_resolver, but also finds dependency cycles between the resolved versions,
+/// and returns the features enabled on each version
+///
+/// returns indexer::Error::DependencyCycles if there are cycles and the policy rejects them
+pub fn deps_resolver_with_options<I: DepsResolvable>(
+    resolvable: &I,
+    requests: &[DepsResolveRequest],
+    options: &ResolveOptions,
+) -> Result<Resolved, indexer::Error> {
+    deps_resolver_traced(resolvable, requests, options).1
+}
+
+/// deps_resolver_with_options, but also returns a trace of why each version is chosen
+/// and which candidates are rejected
+///
+/// since the trace is useful when resolving fails,
+/// ResolutionTrace and Result are both returned
+pub fn deps_resolver_traced<I: DepsResolvable>(
+    resolvable: &I,
+    requests: &[DepsResolveRequest],
+    options: &ResolveOptions,
+) -> (ResolutionTrace, Result<Resolved, indexer::Error>) {
+    let mut rejected = Vec::new();
+    let res = resolve(resolvable, requests, options, &mut rejected);
+
+    let trace = match &res {
+        Ok(resolved) => ResolutionTrace::new(resolvable, requests, resolved, rejected),
+        Err(_) => ResolutionTrace::failed(rejected),
+    };
 
-    // represents a dependency chain
-    // the first item in the dependency chain is the actual item that is missing
-    // todo: get the newest version of all packages possible
-    type MissingDependency = Vec<(String, VersionReq)>;
+    (trace, res)
+}
 
+fn resolve<I: DepsResolvable>(
+    resolvable: &I,
+    requests: &[DepsResolveRequest],
+    options: &ResolveOptions,
+    rejected: &mut Vec<RejectedCandidate>,
+) -> Result<Resolved, indexer::Error> {
+    /// resolve the pending requirements in order, choosing a candidate for a requirement
+    /// appends its dependencies to pending
+    ///
+    /// the search is a loop over a stack of frames instead of recursion,
+    /// the frame at depth d resolves pending[d], and pending is shared by every frame:
+    /// a candidate that fails truncates pending back to before its dependencies
+    ///
+    /// on failure, selected is left as it was when the function is called,
+    /// rejected only keeps the candidates rejected under choices that are still made
+    /// (the candidates of the first requirement if every candidate fails)
     fn internal_resolver<I: DepsResolvable>(
         resolvable: &I,
-        request_name: &str,
-        request_version: &VersionReq,
-        resolved: &mut HashMap<String, Vec<Version>>,
+        mut pending: Vec<PendingRequirement>,
+        selected: &mut Selected,
+        rejected: &mut Vec<RejectedCandidate>,
     ) -> Result<(), Vec<MissingDependency>> {
-        if let Some(package_versions) = resolved.get(request_name)
-            && package_versions
-                .iter()
-                .any(|version| request_version.matches(version))
-        {
-            return Ok(());
-        }
+        let mut frames: Vec<ResolveFrame> = Vec::new();
+        // failure of the frame above the top one, the candidate of the top frame is undone
+        let mut failure = None;
+
+        loop {
+            if failure.is_none() {
+                // the top frame has chosen a candidate (or there is none yet),
+                // every requirement is met once there is nothing left to resolve
+                let Some(requirement) = pending.get(frames.len()) else {
+                    return Ok(());
+                };
+
+                // see available versions, oldest first so the newest is popped first
+                let mut candidates = resolvable
+                    .get_versions(&requirement.name)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter(|version| requirement.version.matches(version))
+                    .cloned()
+                    .collect::<Vec<_>>();
+                candidates.sort_unstable();
 
-        // see available versions
-        let versions = resolvable
-            .get_versions(request_name)
-            .ok_or_else(|| vec![vec![(request_name.to_string(), request_version.clone())]])?;
-
-        // pick the version to load
-        let accepted_version = versions
-            .iter()
-            .find(|version| request_version.matches(version))
-            .ok_or_else(|| vec![vec![(request_name.to_string(), request_version.clone())]])?;
-
-        // add version to resolved list of packages
-        resolved
-            .entry(request_name.to_string())
-            .or_default()
-            .push((*accepted_version).clone());
-
-        let mut missings = Vec::new();
-
-        let deps = resolvable.get_dependencies(request_name, accepted_version)
-            .expect("the package with this name and version should guarantee to exist (because of previous step)");
-
-        // look for dependencies of this package
-        for (dep_name, dep_version) in deps {
-            if let Err(mut dep_missings) =
-                internal_resolver(resolvable, dep_name, dep_version, resolved)
-            {
-                missings.append(&mut dep_missings);
+                frames.push(ResolveFrame {
+                    candidates,
+                    tried: None,
+                    first_failure: None,
+                    pending_len: pending.len(),
+                    rejected_len: rejected.len(),
+                });
             }
-        }
 
-        if missings.is_empty() {
-            Ok(())
-        } else {
-            // adds the current package to the end of the dependency chain
-            let request = (request_name.to_string(), request_version.clone());
-            missings
-                .iter_mut()
-                .for_each(|chain| chain.push(request.clone()));
-            Err(missings)
+            let depth = frames.len() - 1;
+            let frame = frames
+                .last_mut()
+                .expect("a frame is pushed or failed above");
+
+            if let Some(missings) = failure.take() {
+                // backtrack
+                pending.truncate(frame.pending_len);
+                rejected.truncate(frame.rejected_len);
+                let requirement = &pending[depth];
+
+                match frame.tried.take() {
+                    Some(TriedCandidate::Features { version, previous }) => {
+                        selected
+                            .get_mut(&requirement.name)
+                            .and_then(|slots| slots.get_mut(&compat_slot(&version)))
+                            .expect("selected when it is tried")
+                            .features = previous;
+                        rejected.push(requirement.reject(
+                            version,
+                            RejectReason::Unsatisfiable {
+                                dependency_chains: missings.clone(),
+                            },
+                        ));
+                    }
+                    Some(TriedCandidate::Inserted { version }) => {
+                        let slots = selected
+                            .get_mut(&requirement.name)
+                            .expect("inserted when it is tried");
+                        slots.remove(&compat_slot(&version));
+                        if slots.is_empty() {
+                            selected.remove(&requirement.name);
+                        }
+                        rejected.push(requirement.reject(
+                            version,
+                            RejectReason::Unsatisfiable {
+                                dependency_chains: missings.clone(),
+                            },
+                        ));
+                    }
+                    Some(TriedCandidate::Unchanged) | None => {}
+                }
+
+                frame.first_failure.get_or_insert(missings);
+            }
+
+            let requirement = &pending[depth];
+            let mut deps = None;
+
+            while let Some(candidate) = frame.candidates.pop() {
+                let slot = compat_slot(&candidate);
+
+                if resolvable.is_yanked(&requirement.name, &candidate) {
+                    rejected.push(requirement.reject(candidate, RejectReason::Yanked));
+                    continue;
+                }
+
+                let incompatibilities =
+                    resolvable.get_host_incompatibilities(&requirement.name, &candidate);
+                if !incompatibilities.is_empty() {
+                    let incompatibilities = incompatibilities.to_vec();
+                    rejected.push(requirement.reject(
+                        candidate,
+                        RejectReason::HostIncompatible { incompatibilities },
+                    ));
+                    continue;
+                }
+
+                match selected
+                    .get(&requirement.name)
+                    .and_then(|slots| slots.get(&slot))
+                    .cloned()
+                {
+                    // already selected, only the features not enabled yet can pull in anything new
+                    Some(existing) if existing.version == candidate => {
+                        let features = existing
+                            .features
+                            .union(&requirement.features)
+                            .cloned()
+                            .collect::<BTreeSet<_>>();
+
+                        if features == existing.features {
+                            frame.tried = Some(TriedCandidate::Unchanged);
+                            deps = Some(Vec::new());
+                            break;
+                        }
+
+                        let Some(new_deps) =
+                            pending_dependencies(resolvable, requirement, &candidate, &features)
+                        else {
+                            rejected.push(requirement.reject(
+                                candidate,
+                                RejectReason::MissingFeatures {
+                                    features: requirement.features.iter().cloned().collect(),
+                                },
+                            ));
+                            continue;
+                        };
+
+                        // requirements of the features already enabled are already resolved
+                        let previous = pending_dependencies(
+                            resolvable,
+                            requirement,
+                            &candidate,
+                            &existing.features,
+                        )
+                        .unwrap_or_default();
+                        let new_deps = new_deps
+                            .into_iter()
+                            .filter(|dep| {
+                                !previous.iter().any(|prev| {
+                                    prev.name == dep.name
+                                        && prev.version == dep.version
+                                        && prev.features == dep.features
+                                })
+                            })
+                            .collect::<Vec<_>>();
+
+                        selected
+                            .get_mut(&requirement.name)
+                            .and_then(|slots| slots.get_mut(&slot))
+                            .expect("selected above")
+                            .features = features;
+
+                        frame.tried = Some(TriedCandidate::Features {
+                            version: candidate,
+                            previous: existing.features,
+                        });
+                        deps = Some(new_deps);
+                        break;
+                    }
+                    // a different compatible version is already selected
+                    Some(existing) => rejected.push(requirement.reject(
+                        candidate,
+                        RejectReason::ConflictsWithSelected {
+                            selected: existing.version,
+                        },
+                    )),
+                    None => {
+                        let Some(new_deps) = pending_dependencies(
+                            resolvable,
+                            requirement,
+                            &candidate,
+                            &requirement.features,
+                        ) else {
+                            rejected.push(requirement.reject(
+                                candidate,
+                                RejectReason::MissingFeatures {
+                                    features: requirement.features.iter().cloned().collect(),
+                                },
+                            ));
+                            continue;
+                        };
+
+                        selected
+                            .entry(requirement.name.clone())
+                            .or_default()
+                            .insert(
+                                slot,
+                                SelectedVersion {
+                                    version: candidate.clone(),
+                                    features: requirement.features.clone(),
+                                },
+                            );
+
+                        frame.tried = Some(TriedCandidate::Inserted { version: candidate });
+                        deps = Some(new_deps);
+                        break;
+                    }
+                }
+            }
+
+            match deps {
+                // resolve the rest with the dependencies of the candidate
+                Some(deps) => {
+                    frame.rejected_len = rejected.len();
+                    pending.extend(deps);
+                }
+                // every candidate failed
+                None => {
+                    let frame = frames.pop().expect("the top frame");
+                    let missings = frame
+                        .first_failure
+                        .unwrap_or_else(|| vec![pending[depth].as_missing()]);
+
+                    if frames.is_empty() {
+                        return Err(missings);
+                    }
+                    failure = Some(missings);
+                }
+            }
         }
     }
 
-    let mut missings = Vec::new();
+    let mut selected = Selected::new();
+
+    // every request is resolved on its own first, so all unsatisfiable requests are reported
+    // instead of only the first one
+    let mut precheck_rejected = Vec::new();
+    let missings = requests
+        .iter()
+        .filter_map(|request| {
+            internal_resolver(
+                resolvable,
+                vec![PendingRequirement::from_request(request)],
+                &mut Selected::new(),
+                &mut precheck_rejected,
+            )
+            .err()
+        })
+        .flatten()
+        .collect::<Vec<_>>();
+
+    if !missings.is_empty() {
+        rejected.append(&mut precheck_rejected);
+        return Err(missing_error(missings));
+    }
+
+    let pending = requests
+        .iter()
+        .map(PendingRequirement::from_request)
+        .collect::<Vec<_>>();
+
+    internal_resolver(resolvable, pending, &mut selected, rejected).map_err(missing_error)?;
+
+    let features: EnabledFeatures = selected
+        .iter()
+        .map(|(name, slots)| {
+            (
+                name.clone(),
+                slots
+                    .values()
+                    .map(|selected| (selected.version.clone(), selected.features.clone()))
+                    .collect(),
+            )
+        })
+        .collect();
+
+    let packages: HashMap<String, Vec<Version>> = selected
+        .into_iter()
+        .map(|(name, slots)| {
+            (
+                name,
+                slots
+                    .into_values()
+                    .map(|selected| selected.version)
+                    .collect(),
+            )
+        })
+        .collect();
+
+    let cycles = find_resolved_cycles(resolvable, &packages);
+
+    if options.cycle_policy == CyclePolicy::Reject && !cycles.is_empty() {
+        return Err(indexer::Error::DependencyCycles { cycles });
+    }
+
+    Ok(Resolved {
+        packages,
+        cycles,
+        features,
+    })
+}
+
+/// dependency cycles between resolved versions, in the format of Resolved::cycles
+pub fn find_resolved_cycles<I: DepsResolvable>(
+    resolvable: &I,
+    packages: &HashMap<String, Vec<Version>>,
+) -> Vec<Vec<(String, Version)>> {
+    DependencyGraph::from_resolved(resolvable, packages)
+        .find_cycles()
+        .into_iter()
+        .map(|cycle| {
+            cycle
+                .into_iter()
+                .map(|node| (node.name, node.version))
+                .collect()
+        })
+        .collect()
+}
 
-    for request in requests {
-        if let Err(mut dep_missings) = internal_resolver(
-            resolvable,
-            request.get_name(),
-            request.get_version(),
-            &mut resolved,
-        ) {
-            missings.append(&mut dep_missings);
+/// a missing dependency is caused by a cycle if the missing package also appears
+/// further up its own chain: the cycle came back to the package, but requires a version
+/// that conflicts with the one already selected
+///
+/// every cycle is reported as an IncompatibleCycle, the other chains as MissingDependencies,
+/// if there is more than one error they are returned in indexer::Error::Resolve
+fn missing_error(missings: Vec<MissingDependency>) -> indexer::Error {
+    let mut cycles = Vec::new();
+    let mut dependency_chains = Vec::new();
+
+    for chain in missings {
+        let cycle_end = chain
+            .split_first()
+            .and_then(|((missing_name, _), parents)| {
+                parents.iter().position(|(name, _)| name == missing_name)
+            });
+
+        match cycle_end {
+            Some(position) => {
+                let cycle = chain[..=position + 1].to_vec();
+                if !cycles.contains(&cycle) {
+                    cycles.push(cycle);
+                }
+            }
+            None => dependency_chains.push(chain),
         }
     }
 
-    if missings.is_empty() {
-        Ok(resolved)
+    let mut errors = cycles
+        .into_iter()
+        .map(|cycle| indexer::Error::IncompatibleCycle { cycle })
+        .collect::<Vec<_>>();
+
+    if !dependency_chains.is_empty() {
+        errors.push(indexer::Error::MissingDependencies { dependency_chains });
+    }
+
+    if errors.len() == 1 {
+        errors.pop().expect("one error")
     } else {
-        Err(indexer::Error::MissingDependencies {
-            dependency_chains: missings,
-        })
+        indexer::Error::Resolve { errors }
     }
 }
@@ -1,6 +1,6 @@
 use std::path::PathBuf;
 
-use semver::VersionReq;
+use semver::{Version, VersionReq};
 
 #[derive(Debug)]
 pub enum Error {
@@ -17,6 +17,8 @@ pub enum Error {
     },
     /// a package exists but includes no versions (a package must have at least one version)
     NoVersions { name: String, package_root: PathBuf },
+    /// the package name is reserved for the built-in stdlib (see stdlib::PACKAGE)
+    ReservedPackageName { name: String, package_root: PathBuf },
     /// a package manifest has different name than its directory name
     NameMismatch {
         expected: String,
@@ -35,8 +37,38 @@ pub enum Error {
         reason: String,
         version_root: PathBuf,
     },
-    /// the same package is defined in multiple locations
-    MultipleDefinitions { name: String, paths: Vec<PathBuf> },
+    /// a component lists libraries for specific targets, but none for the current platform
+    MissingTarget {
+        component: String,
+        /// target keys that would have matched the current platform
+        targets: Vec<String>,
+        version_root: PathBuf,
+    },
+    /// a target library path is absolute or leaves the version root (contains `..`)
+    UnsafeLibraryPath {
+        component: String,
+        path: PathBuf,
+        version_root: PathBuf,
+    },
+    /// the component type cannot have a library of the kind,
+    /// e.g. a connection that is a wasm module, see PackageLibraryKind::supports
+    UnsupportedLibraryKind {
+        component: String,
+        kind: String,
+        version_root: PathBuf,
+    },
+    /// an item in a feature is not a feature, `dep:<name>` of an optional dependency,
+    /// or an optional component
+    InvalidFeature {
+        feature: String,
+        item: String,
+        version_root: PathBuf,
+    },
+    /// the overrides file of a root cannot be parsed
+    OverridesParse {
+        overrides_path: PathBuf,
+        reason: String,
+    },
 
     // resolver errors
     /// Missing dependencies when resolving
@@ -44,4 +76,53 @@ pub enum Error {
     MissingDependencies {
         dependency_chains: Vec<Vec<(String, VersionReq)>>,
     },
+    /// dependency cycles between resolved versions, when cycles are rejected
+    /// (see deps_resolver::CyclePolicy), each cycle starts and ends with the same version
+    DependencyCycles { cycles: Vec<Vec<(String, Version)>> },
+    /// a dependency cycle requires a version of a package that conflicts with the version
+    /// selected when the cycle was entered.
+    /// the first item is the conflicting requirement, followed by the requirements that led to it,
+    /// up to the requirement that entered the cycle (the first and last items are the same package)
+    IncompatibleCycle { cycle: Vec<(String, VersionReq)> },
+    /// multiple errors occured when resolving,
+    /// every IncompatibleCycle followed by the MissingDependencies not caused by a cycle
+    Resolve { errors: Vec<Self> },
+
+    // lockfile errors
+    /// the lockfile cannot be parsed
+    LockfileParse {
+        lockfile_path: PathBuf,
+        reason: String,
+    },
+    /// the lockfile cannot be serialized or written
+    LockfileWrite { path: PathBuf, reason: String },
+    /// a package version pinned by the lockfile is no longer in the index
+    LockedVersionMissing {
+        name: String,
+        version: Version,
+        lockfile_path: Option<PathBuf>,
+    },
+
+    // archive errors
+    /// a package archive is malformed or does not contain a valid package
+    ArchiveInvalid {
+        archive_path: PathBuf,
+        reason: String,
+    },
+    /// packages can only be installed to (or uninstalled from) a root of the index
+    InstallRootNotIndexed { root: PathBuf },
+    /// the package version already exists in the install root
+    AlreadyInstalled {
+        name: String,
+        version: Version,
+        version_root: PathBuf,
+    },
+    /// the name is not a package name, e.g. it contains a path separator
+    InvalidPackageName { name: String },
+    /// the package version does not exist in the root
+    NotInstalled {
+        name: String,
+        version: Version,
+        root: PathBuf,
+    },
 }
@@ -0,0 +1,352 @@
+//! dependency graph of package versions
+//!
+//! a graph is built from either the whole index, or a resolved set from deps_resolver.
+//! each node is a package version, and each of its requirements points to the versions
+//! in the graph that satisfy it (none if the requirement is unsatisfied)
+//!
+//! the graph can be exported to Graphviz DOT and JSON
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::Write,
+};
+
+use semver::{Version, VersionReq};
+use serde::Serialize;
+
+use crate::packages::indexer::{component::PackageIndex, deps_resolvable::DepsResolvable};
+
+/// a package version in the graph
+#[derive(Serialize, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "devel", derive(Debug))]
+pub struct PackageNode {
+    pub name: String,
+    pub version: Version,
+}
+
+/// a single dependency of a node
+#[derive(Serialize, Clone)]
+#[cfg_attr(feature = "devel", derive(Debug))]
+pub struct GraphRequirement {
+    pub name: String,
+    pub req: VersionReq,
+    /// versions in the graph that satisfy the requirement, from oldest to newest
+    pub matches: Vec<Version>,
+}
+
+/// package versions and their dependencies
+#[cfg_attr(feature = "devel", derive(Debug))]
+pub struct DependencyGraph {
+    /// node -> its requirements, sorted by name
+    nodes: BTreeMap<PackageNode, Vec<GraphRequirement>>,
+}
+
+impl DependencyGraph {
+    /// graph of a set of package versions,
+    /// requirements are only matched against versions in the set
+    ///
+    /// versions that are not in resolvable are skipped
+    pub fn from_resolved<I: DepsResolvable>(
+        resolvable: &I,
+        resolved: &HashMap<String, Vec<Version>>,
+    ) -> Self {
+        let mut nodes = BTreeMap::new();
+
+        for (name, versions) in resolved {
+            for version in versions {
+                let mut requirements: Vec<_> = match resolvable.get_dependencies(name, version) {
+                    Some(deps) => deps
+                        .into_iter()
+                        .map(|(dep_name, req)| {
+                            let mut matches: Vec<Version> = resolved
+                                .get(dep_name)
+                                .into_iter()
+                                .flatten()
+                                .filter(|dep_version| req.matches(dep_version))
+                                .cloned()
+                                .collect();
+                            matches.sort();
+
+                            GraphRequirement {
+                                name: dep_name.to_string(),
+                                req: req.clone(),
+                                matches,
+                            }
+                        })
+                        .collect(),
+                    None => continue,
+                };
+                requirements.sort_by(|a, b| a.name.cmp(&b.name));
+
+                nodes.insert(
+                    PackageNode {
+                        name: name.clone(),
+                        version: version.clone(),
+                    },
+                    requirements,
+                );
+            }
+        }
+
+        Self { nodes }
+    }
+
+    /// all nodes, sorted by name then version
+    pub fn get_nodes(&self) -> Vec<&PackageNode> {
+        self.nodes.keys().collect()
+    }
+
+    /// requirements of a node, None if the node is not in the graph
+    pub fn get_requirements(&self, name: &str, version: &Version) -> Option<&[GraphRequirement]> {
+        self.nodes
+            .get(&PackageNode {
+                name: name.to_string(),
+                version: version.clone(),
+            })
+            .map(Vec::as_slice)
+    }
+
+    /// nodes that directly depend on a package version
+    pub fn dependents(&self, name: &str, version: &Version) -> Vec<&PackageNode> {
+        self.nodes
+            .iter()
+            .filter(|(_, requirements)| {
+                requirements.iter().any(|requirement| {
+                    requirement.name == name && requirement.matches.contains(version)
+                })
+            })
+            .map(|(node, _)| node)
+            .collect()
+    }
+
+    /// nodes that can no longer be satisfied if a package version is removed,
+    /// directly or transitively (does not include the removed version)
+    ///
+    /// a node breaks when all versions matching one of its requirements are removed or broken
+    pub fn broken_by_removal(&self, name: &str, version: &Version) -> Vec<&PackageNode> {
+        let mut gone: BTreeSet<(&str, &Version)> = BTreeSet::from([(name, version)]);
+
+        loop {
+            let newly_broken: Vec<&PackageNode> =
+                self.nodes
+                    .iter()
+                    .filter(|(node, _)| !gone.contains(&(node.name.as_str(), &node.version)))
+                    .filter(|(_, requirements)| {
+                        requirements.iter().any(|requirement| {
+                            !requirement.matches.is_empty()
+                                && requirement.matches.iter().all(|matched| {
+                                    gone.contains(&(requirement.name.as_str(), matched))
+                                })
+                        })
+                    })
+                    .map(|(node, _)| node)
+                    .collect();
+
+            if newly_broken.is_empty() {
+                break;
+            }
+
+            gone.extend(
+                newly_broken
+                    .into_iter()
+                    .map(|node| (node.name.as_str(), &node.version)),
+            );
+        }
+
+        self.nodes
+            .keys()
+            .filter(|node| {
+                gone.contains(&(node.name.as_str(), &node.version))
+                    && !(node.name == name && node.version == *version)
+            })
+            .collect()
+    }
+
+    /// dependency cycles in the graph,
+    /// each cycle starts and ends with the same node, e.g. [a, b, a]
+    ///
+    /// every cycle found by a depth first search from each node (in order) is returned once,
+    /// cycles sharing edges with an already reported cycle may be omitted
+    pub fn find_cycles(&self) -> Vec<Vec<PackageNode>> {
+        #[derive(Clone, Copy, PartialEq, Eq)]
+        enum Visit {
+            InProgress,
+            Done,
+        }
+
+        fn visit<'a>(
+            graph: &'a DependencyGraph,
+            node: &'a PackageNode,
+            visits: &mut HashMap<&'a PackageNode, Visit>,
+            stack: &mut Vec<&'a PackageNode>,
+            cycles: &mut Vec<Vec<PackageNode>>,
+        ) {
+            visits.insert(node, Visit::InProgress);
+            stack.push(node);
+
+            for requirement in graph.nodes.get(node).into_iter().flatten() {
+                for matched in requirement.matches.iter() {
+                    let Some((next, _)) = graph.nodes.get_key_value(&PackageNode {
+                        name: requirement.name.clone(),
+                        version: matched.clone(),
+                    }) else {
+                        continue;
+                    };
+
+                    match visits.get(next) {
+                        Some(Visit::InProgress) => {
+                            let start = stack
+                                .iter()
+                                .position(|on_stack| *on_stack == next)
+                                .expect("nodes in progress are on the stack");
+                            let mut cycle: Vec<PackageNode> =
+                                stack[start..].iter().map(|node| (*node).clone()).collect();
+                            cycle.push(next.clone());
+                            cycles.push(cycle);
+                        }
+                        Some(Visit::Done) => {}
+                        None => visit(graph, next, visits, stack, cycles),
+                    }
+                }
+            }
+
+            stack.pop();
+            visits.insert(node, Visit::Done);
+        }
+
+        let mut visits = HashMap::new();
+        let mut cycles = Vec::new();
+
+        for node in self.nodes.keys() {
+            if !visits.contains_key(node) {
+                visit(self, node, &mut visits, &mut Vec::new(), &mut cycles);
+            }
+        }
+
+        cycles
+    }
+
+    /// Graphviz DOT, unsatisfied requirements are drawn as dashed edges to a placeholder node
+    pub fn to_dot(&self) -> String {
+        fn quote(text: &str) -> String {
+            format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+        }
+
+        let mut dot = String::from("digraph dependencies {\n");
+
+        for (node, requirements) in self.nodes.iter() {
+            let from = quote(&format!("{} {}", node.name, node.version));
+            let _ = writeln!(dot, "    {from};");
+
+            for requirement in requirements {
+                let label = quote(&requirement.req.to_string());
+
+                if requirement.matches.is_empty() {
+                    let missing = quote(&format!("{} {}", requirement.name, requirement.req));
+                    let _ = writeln!(
+                        dot,
+                        "    {from} -> {missing} [label={label}, style=dashed, color=red];"
+                    );
+                }
+
+                for matched in requirement.matches.iter() {
+                    let to = quote(&format!("{} {matched}", requirement.name));
+                    let _ = writeln!(dot, "    {from} -> {to} [label={label}];");
+                }
+            }
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// JSON in the form
+    ///
+    /// ```json
+    /// {
+    ///   "nodes": [{ "name": "arith", "version": "0.1.0" }],
+    ///   "edges": [{ "from": { .. }, "to": { .. }, "req": "^0.1" }],
+    ///   "unsatisfied": [{ "from": { .. }, "name": "logic", "req": "^2" }]
+    /// }
+    /// ```
+    pub fn to_json(&self) -> String {
+        #[derive(Serialize)]
+        struct JsonGraph<'a> {
+            nodes: Vec<&'a PackageNode>,
+            edges: Vec<JsonEdge<'a>>,
+            unsatisfied: Vec<JsonUnsatisfied<'a>>,
+        }
+
+        #[derive(Serialize)]
+        struct JsonEdge<'a> {
+            from: &'a PackageNode,
+            to: PackageNode,
+            req: &'a VersionReq,
+        }
+
+        #[derive(Serialize)]
+        struct JsonUnsatisfied<'a> {
+            from: &'a PackageNode,
+            name: &'a str,
+            req: &'a VersionReq,
+        }
+
+        let mut graph = JsonGraph {
+            nodes: self.get_nodes(),
+            edges: Vec::new(),
+            unsatisfied: Vec::new(),
+        };
+
+        for (node, requirements) in self.nodes.iter() {
+            for requirement in requirements {
+                if requirement.matches.is_empty() {
+                    graph.unsatisfied.push(JsonUnsatisfied {
+                        from: node,
+                        name: &requirement.name,
+                        req: &requirement.req,
+                    });
+                }
+
+                graph
+                    .edges
+                    .extend(requirement.matches.iter().map(|matched| JsonEdge {
+                        from: node,
+                        to: PackageNode {
+                            name: requirement.name.clone(),
+                            version: matched.clone(),
+                        },
+                        req: &requirement.req,
+                    }));
+            }
+        }
+
+        serde_json::to_string_pretty(&graph).expect("graph only contains strings")
+    }
+}
+
+impl PackageIndex {
+    /// graph of every version of every package in the index
+    pub fn dependency_graph(&self) -> DependencyGraph {
+        let all_versions: HashMap<String, Vec<Version>> = self
+            .list_packages()
+            .into_iter()
+            .map(|package| {
+                (
+                    package.get_name().to_string(),
+                    package.list_versions().into_iter().cloned().collect(),
+                )
+            })
+            .collect();
+
+        DependencyGraph::from_resolved(self, &all_versions)
+    }
+
+    /// package versions in the index that directly depend on a package version
+    pub fn reverse_dependencies(&self, name: &str, version: &Version) -> Vec<PackageNode> {
+        self.dependency_graph()
+            .dependents(name, version)
+            .into_iter()
+            .cloned()
+            .collect()
+    }
+}
@@ -0,0 +1,300 @@
+//! xdsim.lock pins the exact package versions a world is built with
+//!
+//! ```toml
+//! [[package]]
+//! name = "testlib"
+//! version = "0.1.0"
+//! features = ["debug"]
+//!
+//! [package.libraries]
+//! not = "not.so"
+//! ```
+//!
+//! library paths are relative to the version directory of the package, so the lockfile
+//! can be shared between machines. they are informational (they differ between platforms),
+//! only the name, version and features of each package are used when reading the lockfile back
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use semver::Version;
+use serde::{Deserialize, Serialize};
+
+use crate::packages::indexer::{
+    self,
+    component::PackageIndex,
+    deps_resolvable::DepsResolvable,
+    deps_resolver::{
+        CyclePolicy, DepsResolveRequest, EnabledFeatures, ResolveOptions, Resolved,
+        deps_resolver_with_options, find_resolved_cycles,
+    },
+};
+
+/// file name of the lockfile
+pub const LOCKFILE_NAME: &str = "xdsim.lock";
+
+/// content of xdsim.lock
+#[derive(Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "devel", derive(Debug))]
+pub struct Lockfile {
+    #[serde(rename = "package", default)]
+    packages: Vec<LockedPackage>,
+}
+
+/// a single resolved package version
+#[derive(Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "devel", derive(Debug))]
+pub struct LockedPackage {
+    name: String,
+    version: Version,
+    /// features enabled on the version, see Resolved::features
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    features: BTreeSet<String>,
+    /// component name -> path of the library it was resolved to, relative to the version root
+    #[serde(default)]
+    libraries: BTreeMap<String, PathBuf>,
+}
+
+impl LockedPackage {
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_version(&self) -> &Version {
+        &self.version
+    }
+
+    pub fn get_features(&self) -> &BTreeSet<String> {
+        &self.features
+    }
+
+    pub fn get_libraries(&self) -> &BTreeMap<String, PathBuf> {
+        &self.libraries
+    }
+}
+
+impl Lockfile {
+    /// create a lockfile from the output of deps_resolver_with_options
+    ///
+    /// packages are sorted by name then version so the file is stable
+    pub fn from_resolved(
+        index: &PackageIndex,
+        resolved: &Resolved,
+    ) -> Result<Self, indexer::Error> {
+        let mut packages = Vec::new();
+
+        for (name, versions) in resolved.packages.iter() {
+            for version in versions {
+                let entry = match index
+                    .get_package(name)
+                    .and_then(|package| package.get_version_entry(version))
+                {
+                    Some(entry) => entry,
+                    None => {
+                        return Err(indexer::Error::LockedVersionMissing {
+                            name: name.clone(),
+                            version: version.clone(),
+                            lockfile_path: None,
+                        });
+                    }
+                };
+
+                packages.push(LockedPackage {
+                    name: name.clone(),
+                    version: version.clone(),
+                    features: resolved
+                        .features
+                        .get(name)
+                        .and_then(|versions| versions.get(version))
+                        .cloned()
+                        .unwrap_or_default(),
+                    libraries: entry
+                        .get_manifest()
+                        .get_provides()
+                        .keys()
+                        .map(|component| (component.clone(), entry.get_library_file(component)))
+                        .collect(),
+                });
+            }
+        }
+
+        packages.sort_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)));
+        Ok(Self { packages })
+    }
+
+    /// read a lockfile from disk
+    pub fn read(path: &Path) -> Result<Self, indexer::Error> {
+        let content = fs::read(path).map_err(|e| indexer::Error::Fs {
+            path: path.to_path_buf(),
+            reason: e.to_string(),
+        })?;
+
+        toml::from_slice(&content).map_err(|e| indexer::Error::LockfileParse {
+            lockfile_path: path.to_path_buf(),
+            reason: e.to_string(),
+        })
+    }
+
+    /// write the lockfile to disk
+    ///
+    /// the content is written to a temporary file first then moved in place,
+    /// so a crash does not leave a half written lockfile
+    pub fn write(&self, path: &Path) -> Result<(), indexer::Error> {
+        let content = toml::to_string(self).map_err(|e| indexer::Error::LockfileWrite {
+            path: path.to_path_buf(),
+            reason: e.to_string(),
+        })?;
+
+        let temp_path = path.with_extension("lock.tmp");
+        fs::write(&temp_path, content)
+            .and_then(|_| fs::rename(&temp_path, path))
+            .map_err(|e| indexer::Error::LockfileWrite {
+                path: path.to_path_buf(),
+                reason: e.to_string(),
+            })
+    }
+
+    pub fn get_packages(&self) -> &[LockedPackage] {
+        &self.packages
+    }
+
+    /// the locked versions in the same format as deps_resolver
+    pub fn to_resolved(&self) -> HashMap<String, Vec<Version>> {
+        let mut resolved: HashMap<String, Vec<Version>> = HashMap::new();
+
+        for package in self.packages.iter() {
+            resolved
+                .entry(package.name.clone())
+                .or_default()
+                .push(package.version.clone());
+        }
+
+        resolved.values_mut().for_each(|versions| versions.sort());
+        resolved
+    }
+
+    /// the features of the locked versions in the same format as Resolved::features
+    pub fn to_features(&self) -> EnabledFeatures {
+        let mut features: EnabledFeatures = HashMap::new();
+
+        for package in self.packages.iter() {
+            features
+                .entry(package.name.clone())
+                .or_default()
+                .insert(package.version.clone(), package.features.clone());
+        }
+
+        features
+    }
+
+    /// check the lockfile against an index
+    ///
+    /// - returns indexer::Error::LockedVersionMissing if a locked version is not in the index
+    /// - returns false if the lockfile is stale: it does not satisfy all requests,
+    ///   a locked version can no longer run on this host (see PackageHost),
+    ///   a locked version no longer has one of its locked features,
+    ///   or a dependency of a locked package (including optional dependencies
+    ///   enabled by its features) is not locked with the features it requires
+    pub fn is_valid<I: DepsResolvable>(
+        &self,
+        resolvable: &I,
+        requests: &[DepsResolveRequest],
+        lockfile_path: &Path,
+    ) -> Result<bool, indexer::Error> {
+        let satisfied = |name: &str, req: &semver::VersionReq, features: &[String]| {
+            self.packages.iter().any(|package| {
+                package.name == name
+                    && req.matches(&package.version)
+                    && features
+                        .iter()
+                        .all(|feature| package.features.contains(feature))
+            })
+        };
+
+        for package in self.packages.iter() {
+            if resolvable
+                .get_versions(&package.name)
+                .is_none_or(|versions| !versions.contains(&&package.version))
+            {
+                return Err(indexer::Error::LockedVersionMissing {
+                    name: package.name.clone(),
+                    version: package.version.clone(),
+                    lockfile_path: Some(lockfile_path.to_path_buf()),
+                });
+            }
+
+            if !resolvable
+                .get_host_incompatibilities(&package.name, &package.version)
+                .is_empty()
+            {
+                return Ok(false);
+            }
+
+            let Some(deps) = resolvable.get_dependencies_with_features(
+                &package.name,
+                &package.version,
+                &package.features,
+            ) else {
+                return Ok(false);
+            };
+
+            if !deps
+                .into_iter()
+                .all(|(name, req, features)| satisfied(name, req, features))
+            {
+                return Ok(false);
+            }
+        }
+
+        Ok(requests.iter().all(|request| {
+            satisfied(
+                request.get_name(),
+                request.get_version(),
+                request.get_features(),
+            )
+        }))
+    }
+}
+
+/// deps_resolver_with_options, but pinned by a lockfile
+///
+/// - if the lockfile exists and is valid, the locked versions and features are returned
+///   without resolving
+/// - if the lockfile does not exist or is stale, packages are resolved
+///   and the lockfile is (re)written
+/// - if a locked version no longer exists in the index, indexer::Error::LockedVersionMissing
+///   is returned, the lockfile is left untouched
+///
+/// the cycle policy of options applies to locked versions as well
+pub fn deps_resolver_locked(
+    index: &PackageIndex,
+    requests: &[DepsResolveRequest],
+    options: &ResolveOptions,
+    lockfile_path: &Path,
+) -> Result<Resolved, indexer::Error> {
+    if lockfile_path.exists() {
+        let lockfile = Lockfile::read(lockfile_path)?;
+
+        if lockfile.is_valid(index, requests, lockfile_path)? {
+            let packages = lockfile.to_resolved();
+            let cycles = find_resolved_cycles(index, &packages);
+
+            if options.get_cycle_policy() == CyclePolicy::Reject && !cycles.is_empty() {
+                return Err(indexer::Error::DependencyCycles { cycles });
+            }
+
+            return Ok(Resolved {
+                packages,
+                cycles,
+                features: lockfile.to_features(),
+            });
+        }
+    }
+
+    let resolved = deps_resolver_with_options(index, requests, options)?;
+    Lockfile::from_resolved(index, &resolved)?.write(lockfile_path)?;
+    Ok(resolved)
+}
@@ -7,6 +7,7 @@
 //! in manifest.toml, provides specifies what components are included in package
 //! in the same directory, it must contain the componentname.[dll/dylib/so]
 //! which is the dynamic library file supported by the operating system
+//! (or the files listed for the current platform, if the component lists targets)
 //!
 //! repo-root/
 //! ├── package1/
@@ -24,10 +25,27 @@
 //!     └── 0.1.0/
 //!         └── package.toml
 //!
+//! a package may appear in multiple roots, its versions from all roots are merged.
+//! if a version is defined in more than one root, the root with the highest priority
+//! (the one added last) is used
+//!
 //! package.toml specs is found in component/package_manifest
+//!
+//! resolved package versions can be pinned with an xdsim.lock, see lockfile
+//!
+//! packages can be distributed as a single .xdpkg file and installed into a root, see archive
+//!
+//! dependencies between package versions can be queried and exported, see graph
+//!
+//! versions can be yanked or deprecated in package.toml, or in an overrides.toml of a root,
+//! see component/package_status
 
+pub mod archive;
 pub mod component;
-mod deps_resolvable;
+pub mod deps_resolvable;
 pub mod deps_resolver;
 mod error;
 pub use error::Error;
+pub mod graph;
+pub mod lockfile;
+pub mod resolution_trace;
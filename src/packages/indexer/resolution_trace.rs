@@ -0,0 +1,263 @@
+//! explains the output of deps_resolver: why each version is chosen,
+//! and which newer candidates are rejected on the way
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+};
+
+use semver::{Version, VersionReq};
+
+use crate::packages::indexer::{
+    component::HostIncompatibility,
+    deps_resolvable::DepsResolvable,
+    deps_resolver::{DepsResolveRequest, Resolved},
+    graph::PackageNode,
+};
+
+/// returned by deps_resolver_traced
+#[cfg_attr(feature = "devel", derive(Debug))]
+pub struct ResolutionTrace {
+    /// chosen version -> why it is chosen (empty if resolving failed)
+    chosen: BTreeMap<PackageNode, ChosenVersion>,
+    /// candidates rejected under the final selection, in the order they are rejected,
+    /// rejections under choices the search backtracked from are left out
+    rejected: Vec<RejectedCandidate>,
+}
+
+/// why a version is chosen
+#[cfg_attr(feature = "devel", derive(Debug))]
+pub struct ChosenVersion {
+    /// requirements the version satisfies
+    pub required_by: Vec<RequiredBy>,
+    /// newer compatible versions that are tried first but rejected, newest first
+    pub rejected: Vec<RejectedCandidate>,
+}
+
+/// a requirement edge that led to a version
+#[derive(Clone)]
+#[cfg_attr(feature = "devel", derive(Debug))]
+pub struct RequiredBy {
+    /// the package version with the dependency, None if it is a request
+    pub parent: Option<PackageNode>,
+    pub req: VersionReq,
+}
+
+/// a version that matched a requirement, but is not chosen
+#[derive(Clone)]
+#[cfg_attr(feature = "devel", derive(Debug))]
+pub struct RejectedCandidate {
+    pub name: String,
+    pub version: Version,
+    /// the requirement the version is a candidate for
+    pub req: VersionReq,
+    pub reason: RejectReason,
+}
+
+#[derive(Clone)]
+#[cfg_attr(feature = "devel", derive(Debug))]
+pub enum RejectReason {
+    /// a different semver compatible version of the package is already selected
+    ConflictsWithSelected { selected: Version },
+    /// choosing the version leads to requirements that cannot be met,
+    /// the chains are in the same format as indexer::Error::MissingDependencies
+    Unsatisfiable {
+        dependency_chains: Vec<Vec<(String, VersionReq)>>,
+    },
+    /// the version does not have all the requested features
+    MissingFeatures { features: Vec<String> },
+    /// the version is yanked
+    Yanked,
+    /// the version cannot run on this host
+    HostIncompatible {
+        incompatibilities: Vec<HostIncompatibility>,
+    },
+}
+
+impl ResolutionTrace {
+    /// trace of a successful resolution
+    pub fn new<I: DepsResolvable>(
+        resolvable: &I,
+        requests: &[DepsResolveRequest],
+        resolved: &Resolved,
+        rejected: Vec<RejectedCandidate>,
+    ) -> Self {
+        let mut chosen: BTreeMap<PackageNode, ChosenVersion> = resolved
+            .packages
+            .iter()
+            .flat_map(|(name, versions)| {
+                versions.iter().map(|version| {
+                    (
+                        PackageNode {
+                            name: name.clone(),
+                            version: version.clone(),
+                        },
+                        ChosenVersion {
+                            required_by: Vec::new(),
+                            rejected: Vec::new(),
+                        },
+                    )
+                })
+            })
+            .collect();
+
+        let parents: Vec<PackageNode> = chosen.keys().cloned().collect();
+
+        let mut add_edge = |name: &str, req: &VersionReq, parent: Option<&PackageNode>| {
+            for (node, entry) in chosen.iter_mut() {
+                if node.name == name && req.matches(&node.version) {
+                    entry.required_by.push(RequiredBy {
+                        parent: parent.cloned(),
+                        req: req.clone(),
+                    });
+                }
+            }
+        };
+
+        for request in requests {
+            add_edge(request.get_name(), request.get_version(), None);
+        }
+
+        let no_features = BTreeSet::new();
+        for parent in parents.iter() {
+            let features = resolved
+                .features
+                .get(&parent.name)
+                .and_then(|versions| versions.get(&parent.version))
+                .unwrap_or(&no_features);
+
+            for (name, req, _) in resolvable
+                .get_dependencies_with_features(&parent.name, &parent.version, features)
+                .unwrap_or_default()
+            {
+                add_edge(name, req, Some(parent));
+            }
+        }
+
+        for (node, entry) in chosen.iter_mut() {
+            for candidate in rejected.iter() {
+                let is_newer_compatible = candidate.name == node.name
+                    && candidate.version > node.version
+                    && candidate.req.matches(&node.version);
+                let is_listed = entry
+                    .rejected
+                    .iter()
+                    .any(|listed| listed.version == candidate.version);
+
+                if is_newer_compatible && !is_listed {
+                    entry.rejected.push(candidate.clone());
+                }
+            }
+
+            entry.rejected.sort_by(|a, b| b.version.cmp(&a.version));
+        }
+
+        Self { chosen, rejected }
+    }
+
+    /// trace of a failed resolution, only contains the rejected candidates
+    pub fn failed(rejected: Vec<RejectedCandidate>) -> Self {
+        Self {
+            chosen: BTreeMap::new(),
+            rejected,
+        }
+    }
+
+    /// why a version is chosen, None if it is not chosen
+    pub fn get_chosen(&self, name: &str, version: &Version) -> Option<&ChosenVersion> {
+        self.chosen.get(&PackageNode {
+            name: name.to_string(),
+            version: version.clone(),
+        })
+    }
+
+    /// all chosen versions, sorted by name then version
+    pub fn list_chosen(&self) -> Vec<(&PackageNode, &ChosenVersion)> {
+        self.chosen.iter().collect()
+    }
+
+    /// candidates rejected under the final selection
+    pub fn get_rejected(&self) -> &[RejectedCandidate] {
+        &self.rejected
+    }
+}
+
+impl Display for RejectReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ConflictsWithSelected { selected } => {
+                write!(f, "conflicts with already selected {selected}")
+            }
+            Self::Unsatisfiable { dependency_chains } => {
+                let chains = dependency_chains
+                    .iter()
+                    .map(|chain| {
+                        chain
+                            .iter()
+                            .map(|(name, req)| format!("{name} {req}"))
+                            .collect::<Vec<_>>()
+                            .join(" <- ")
+                    })
+                    .collect::<Vec<_>>()
+                    .join("; ");
+                write!(f, "cannot satisfy {chains}")
+            }
+            Self::MissingFeatures { features } => {
+                write!(f, "missing features {}", features.join(", "))
+            }
+            Self::Yanked => write!(f, "yanked"),
+            Self::HostIncompatible { incompatibilities } => {
+                let reasons = incompatibilities
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join("; ");
+                write!(f, "incompatible with host: {reasons}")
+            }
+        }
+    }
+}
+
+/// human readable form, e.g.
+///
+/// ```text
+/// logic 0.3.1
+///   required by request (^0.3)
+///   required by gates 0.1.4 (^0.3)
+///   rejected 0.3.2: cannot satisfy power ^1 <- logic ^0.3
+/// ```
+impl Display for ResolutionTrace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (node, entry) in self.chosen.iter() {
+            writeln!(f, "{} {}", node.name, node.version)?;
+
+            for required_by in entry.required_by.iter() {
+                match &required_by.parent {
+                    Some(parent) => writeln!(
+                        f,
+                        "  required by {} {} ({})",
+                        parent.name, parent.version, required_by.req
+                    )?,
+                    None => writeln!(f, "  required by request ({})", required_by.req)?,
+                }
+            }
+
+            for candidate in entry.rejected.iter() {
+                writeln!(f, "  rejected {}: {}", candidate.version, candidate.reason)?;
+            }
+        }
+
+        if self.chosen.is_empty() && !self.rejected.is_empty() {
+            writeln!(f, "resolving failed, rejected candidates:")?;
+            for candidate in self.rejected.iter() {
+                writeln!(
+                    f,
+                    "  {} {} (for {}): {}",
+                    candidate.name, candidate.version, candidate.req, candidate.reason
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
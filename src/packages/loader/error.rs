@@ -20,10 +20,28 @@ pub enum Error {
         /// Path to library that failed
         lib_path: PathBuf,
     },
+    /// Library content does not match the sha256 digest in the manifest
+    ChecksumMismatch {
+        /// Path to library that failed
+        lib_path: PathBuf,
+        /// Digest specified in the manifest
+        expected: String,
+        /// Digest of the library on disk
+        got: String,
+    },
     /// Missing package from index
     MissingPackage { name: String },
+    /// The package name is reserved for the built-in stdlib (see stdlib::PACKAGE)
+    ReservedPackage { name: String },
     /// Missing package version from index
     MissingPackageVersion { name: String, version: Version },
+    /// The package version does not have one of the features to enable
+    MissingFeatures { name: String, version: Version },
+    /// The sandbox helper for isolated versions cannot be set up
+    Sandbox { reason: String },
+    /// The version is isolated (see VersionStatus::isolated),
+    /// but the loader cannot run components in a sandbox helper
+    IsolatedVersion { name: String, version: Version },
     /// Error when loading all requested component packages
     LoadAllComponentPackages { errors: Vec<Self> },
     /// An error caused by the destructor
@@ -1,8 +1,7 @@
 use std::{
-    collections::{BTreeMap, HashMap},
-    env::consts::DLL_EXTENSION,
-    path::PathBuf,
-    rc::Rc,
+    collections::{BTreeMap, HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Arc,
 };
 
 use semver::Version;
@@ -11,8 +10,14 @@ use crate::{
     common::world::ComponentVersion,
     packages::{
         destructor::{self, DestructRequest, DestructedConn, DestructedData, DestructedGate},
-        indexer::{self, component::PackageComponentType},
+        indexer::{
+            self,
+            component::{Package, PackageComponentType, PackageLibraryKind},
+            deps_resolver::EnabledFeatures,
+        },
         loader::{self, LibraryHandle, manager::LoadManager},
+        sandbox::SandboxHost,
+        stdlib,
     },
 };
 
@@ -21,16 +26,169 @@ type PackageVersion = Version;
 type LibName = String;
 
 type DestructedGateHandles =
-    HashMap<PackageName, BTreeMap<PackageVersion, HashMap<LibName, Rc<DestructedGate>>>>;
+    HashMap<PackageName, BTreeMap<PackageVersion, HashMap<LibName, Arc<DestructedGate>>>>;
 type DestructedDataHandles =
-    HashMap<PackageName, BTreeMap<PackageVersion, HashMap<LibName, Rc<DestructedData>>>>;
+    HashMap<PackageName, BTreeMap<PackageVersion, HashMap<LibName, Arc<DestructedData>>>>;
 type DestructedConnHandles =
-    HashMap<PackageName, BTreeMap<PackageVersion, HashMap<LibName, Rc<DestructedConn>>>>;
+    HashMap<PackageName, BTreeMap<PackageVersion, HashMap<LibName, Arc<DestructedConn>>>>;
 
 struct LoadedEntry {
     pub variant: PackageComponentType,
-    pub handle: LibraryHandle,
+    pub kind: PackageLibraryKind,
+    /// None if the component is loaded in the sandbox or is a wasm module instead
+    pub handle: Option<LibraryHandle>,
     pub path: PathBuf,
+    /// checked again when a sandboxed library is reloaded
+    pub sha256: Option<String>,
+}
+
+/// packages whose gates and data types run in a sandbox helper process instead of this one,
+/// so a crashing component does not bring down the server
+///
+/// versions isolated by an overrides file (see VersionStatus::isolated) run in the helper too,
+/// without an Isolation in LoadOptions they fail to load with loader::Error::Sandbox
+///
+/// connection libraries only draw, they are still loaded in this process,
+/// and wasm modules and scripts cannot reach outside their runtime,
+/// they are run in this process as well
+pub struct Isolation {
+    pub sandbox: Arc<SandboxHost>,
+    pub packages: HashSet<String>,
+}
+
+/// options for IndexComponentLoader::load_all
+#[derive(Default)]
+pub struct LoadOptions {
+    features: EnabledFeatures,
+    isolation: Option<Isolation>,
+}
+
+impl LoadOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// features enabled on each version,
+    /// usually Resolved::features from deps_resolver_with_options
+    pub fn features(mut self, features: EnabledFeatures) -> Self {
+        self.features = features;
+        self
+    }
+
+    /// load the packages listed in isolation, and isolated versions, in its sandbox helper
+    pub fn isolation(mut self, isolation: Isolation) -> Self {
+        self.isolation = Some(isolation);
+        self
+    }
+
+    pub fn get_features(&self) -> &EnabledFeatures {
+        &self.features
+    }
+
+    pub fn get_isolation(&self) -> Option<&Isolation> {
+        self.isolation.as_ref()
+    }
+}
+
+/// library of a component enabled in a package version
+pub struct EnabledLibrary {
+    pub name: String,
+    pub variant: PackageComponentType,
+    pub kind: PackageLibraryKind,
+    pub path: PathBuf,
+    pub sha256: Option<String>,
+}
+
+/// libraries of the components a package version provides with the features enabled,
+/// (features is usually Resolved::features from deps_resolver_with_options)
+///
+/// warnings of the version (e.g. it is deprecated) are added to warnings
+pub fn enabled_libraries(
+    package: &Package,
+    package_name: &str,
+    version: &Version,
+    features: &EnabledFeatures,
+    warnings: &mut Vec<loader::Warning>,
+) -> Result<Vec<EnabledLibrary>, loader::Error> {
+    let entry =
+        package
+            .get_version_entry(version)
+            .ok_or_else(|| loader::Error::MissingPackageVersion {
+                name: package_name.to_string(),
+                version: version.clone(),
+            })?;
+
+    let manifest = entry.get_manifest();
+
+    if let Some(message) = &entry.get_status().deprecated {
+        warnings.push(loader::Warning::Deprecated {
+            name: package_name.to_string(),
+            version: version.clone(),
+            message: message.clone(),
+        });
+    }
+
+    if entry.get_status().yanked {
+        warnings.push(loader::Warning::Yanked {
+            name: package_name.to_string(),
+            version: version.clone(),
+        });
+    }
+
+    let requested_features = features
+        .get(package_name)
+        .and_then(|versions| versions.get(version))
+        .into_iter()
+        .flatten()
+        .map(String::as_str);
+
+    let enabled = manifest
+        .enabled_features(requested_features)
+        .ok_or_else(|| loader::Error::MissingFeatures {
+            name: package_name.to_string(),
+            version: version.clone(),
+        })?;
+
+    Ok(manifest
+        .enabled_provides(&enabled)
+        .into_iter()
+        .map(|(name, provide)| EnabledLibrary {
+            name: name.to_string(),
+            variant: provide.get_type(),
+            kind: provide.get_library_kind(),
+            path: entry.get_library_path(name),
+            sha256: provide.get_library_sha256().map(str::to_string),
+        })
+        .collect())
+}
+
+/// gate type from a library that is not loaded into the process (a wasm module or a script)
+pub fn destruct_gate_file(
+    kind: PackageLibraryKind,
+    path: &Path,
+    component_id: ComponentVersion,
+) -> Result<DestructedGate, destructor::Error> {
+    match kind {
+        PackageLibraryKind::Wasm => DestructedGate::new_wasm(path, component_id),
+        PackageLibraryKind::Script => DestructedGate::new_script(path, component_id),
+        PackageLibraryKind::Native => unreachable!("native libraries are loaded"),
+    }
+}
+
+/// data type from a library that is not loaded into the process (a wasm module)
+pub fn destruct_data_file(
+    kind: PackageLibraryKind,
+    path: &Path,
+    component_id: ComponentVersion,
+) -> Result<DestructedData, destructor::Error> {
+    match kind {
+        PackageLibraryKind::Wasm => DestructedData::new_wasm(path, component_id),
+        // rejected when the package is indexed
+        PackageLibraryKind::Script => Err(destructor::Error::UnsupportedLibraryKind {
+            kind: kind.get_name().to_string(),
+        }),
+        PackageLibraryKind::Native => unreachable!("native libraries are loaded"),
+    }
 }
 
 /// library loading utility for situations where:
@@ -43,24 +201,41 @@ pub struct IndexComponentLoader {
     pub gates: DestructedGateHandles,
     pub data: DestructedDataHandles,
     pub conns: DestructedConnHandles,
+    /// deprecated or yanked versions that are loaded
+    pub warnings: Vec<loader::Warning>,
 }
 
 impl IndexComponentLoader {
     /// given an index and a list of packages to load,
-    /// load all libraries that the packages contains into memory
+    /// load all libraries that the packages contains into memory,
+    /// optional components are only loaded if a feature in options enables them,
+    /// the stdlib package cannot be loaded from the index
+    ///
+    /// the index is borrowed so it can be used again,
+    /// e.g. for loading versions added by PackageIndex::refresh
     ///
     /// this does not destruct the libraries
     pub fn load_all(
-        index: indexer::component::PackageIndex,
+        index: &indexer::component::PackageIndex,
         packages_to_load: HashMap<String, Vec<Version>>,
+        options: &LoadOptions,
     ) -> Result<Self, loader::Error> {
+        let features = options.get_features();
+        let isolation = options.get_isolation();
+
         let mut errors = Vec::new();
+        let mut warnings = Vec::new();
 
         let mut loaded_index = HashMap::new();
 
         for (package_name, versions_to_load) in packages_to_load {
             let mut package_map = HashMap::new();
 
+            if package_name == stdlib::PACKAGE {
+                errors.push(loader::Error::ReservedPackage { name: package_name });
+                continue;
+            }
+
             let package = match index.get_package(&package_name) {
                 Some(pkg) => pkg,
                 None => {
@@ -72,24 +247,51 @@ impl IndexComponentLoader {
             for version in versions_to_load {
                 let mut version_map = HashMap::new();
 
-                let manifest = match package.get_version(&version) {
-                    Some(manifest) => manifest,
-                    None => {
-                        errors.push(loader::Error::MissingPackageVersion {
-                            name: package_name.clone(),
-                            version: version.clone(),
-                        });
+                let libs_to_load = match enabled_libraries(
+                    package,
+                    &package_name,
+                    &version,
+                    features,
+                    &mut warnings,
+                ) {
+                    Ok(libs) => libs,
+                    Err(e) => {
+                        errors.push(e);
                         continue;
                     }
                 };
 
-                let libs_to_load = manifest.get_provides();
-                let version_root = package.get_root().join(version.to_string());
+                let isolated = isolation
+                    .is_some_and(|isolation| isolation.packages.contains(&package_name))
+                    || package
+                        .get_version_entry(&version)
+                        .is_some_and(|entry| entry.get_status().isolated);
 
-                for (name, variant) in libs_to_load {
-                    let lib_path = version_root.join(name).with_extension(DLL_EXTENSION);
+                // there is no helper command to run the isolated components with
+                if isolated && isolation.is_none() {
+                    errors.push(loader::Error::Sandbox {
+                        reason: format!(
+                            "{package_name} {version} is isolated, but no sandbox helper is configured (see LoadOptions::isolation)"
+                        ),
+                    });
+                    continue;
+                }
 
-                    let lib = match LoadManager::load_with_path(lib_path.clone()) {
+                for library in libs_to_load {
+                    let in_sandbox = isolated && library.variant != PackageComponentType::Conn;
+
+                    let lib = if in_sandbox || library.kind != PackageLibraryKind::Native {
+                        LoadManager::verify_checksum(&library.path, library.sha256.as_deref())
+                            .map(|_| None)
+                    } else {
+                        LoadManager::load_with_checksum(
+                            library.path.clone(),
+                            library.sha256.as_deref(),
+                        )
+                        .map(Some)
+                    };
+
+                    let lib = match lib {
                         Ok(loaded) => loaded,
                         Err(e) => {
                             errors.push(e);
@@ -98,11 +300,13 @@ impl IndexComponentLoader {
                     };
 
                     version_map.insert(
-                        name.clone(),
+                        library.name,
                         LoadedEntry {
-                            variant: *variant,
+                            variant: library.variant,
+                            kind: library.kind,
                             handle: lib,
-                            path: lib_path,
+                            path: library.path,
+                            sha256: library.sha256,
                         },
                     );
                 }
@@ -116,11 +320,11 @@ impl IndexComponentLoader {
         /// maintain the structure of the hashmap (includes empty entries)
         /// extract a map for a component from the loaded index
         fn destruct_component<T>(
-            destruct: fn(DestructRequest) -> Result<T, destructor::Error>,
+            destruct: impl Fn(&LoadedEntry, ComponentVersion) -> Result<T, destructor::Error>,
             variant: PackageComponentType,
             index: &HashMap<PackageName, HashMap<PackageVersion, HashMap<LibName, LoadedEntry>>>,
             errors: &mut Vec<loader::Error>,
-        ) -> HashMap<PackageName, BTreeMap<PackageVersion, HashMap<LibName, Rc<T>>>> {
+        ) -> HashMap<PackageName, BTreeMap<PackageVersion, HashMap<LibName, Arc<T>>>> {
             index
                 .iter()
                 .map(|(package_name, versions)| {
@@ -135,19 +339,16 @@ impl IndexComponentLoader {
                                         .iter()
                                         .filter_map(|(lib_name, lib_content)| {
                                             if lib_content.variant == variant {
-                                                let destruct_request = DestructRequest::new(
-                                                    lib_content.handle.clone(),
-                                                    ComponentVersion {
-                                                        package: package_name.clone(),
-                                                        version: version_name.clone(),
-                                                        component: lib_name.clone(),
-                                                    },
-                                                );
-
-                                                match destruct(destruct_request) {
+                                                let component_id = ComponentVersion {
+                                                    package: package_name.clone(),
+                                                    version: version_name.clone(),
+                                                    component: lib_name.clone(),
+                                                };
+
+                                                match destruct(lib_content, component_id) {
                                                     Ok(destructed) => Some((
                                                         lib_name.clone(),
-                                                        Rc::new(destructed),
+                                                        Arc::new(destructed),
                                                     )),
                                                     Err(e) => {
                                                         errors.push(
@@ -172,20 +373,59 @@ impl IndexComponentLoader {
                 .collect::<HashMap<_, _>>()
         }
 
+        let sandbox = isolation.map(|isolation| &isolation.sandbox);
+
         let gates = destruct_component(
-            DestructedGate::new,
+            |entry, component_id| match (&entry.handle, sandbox) {
+                _ if entry.kind != PackageLibraryKind::Native => {
+                    destruct_gate_file(entry.kind, &entry.path, component_id)
+                }
+                (Some(library), _) => {
+                    DestructedGate::new(DestructRequest::new(library.clone(), component_id))
+                }
+                (None, Some(sandbox)) => DestructedGate::new_sandboxed(
+                    sandbox,
+                    &entry.path,
+                    entry.sha256.as_deref(),
+                    component_id,
+                ),
+                (None, None) => {
+                    unreachable!("only isolated packages, wasm modules and scripts are not loaded")
+                }
+            },
             PackageComponentType::Gate,
             &loaded_index,
             &mut errors,
         );
         let data = destruct_component(
-            DestructedData::new,
+            |entry, component_id| match (&entry.handle, sandbox) {
+                _ if entry.kind != PackageLibraryKind::Native => {
+                    destruct_data_file(entry.kind, &entry.path, component_id)
+                }
+                (Some(library), _) => {
+                    DestructedData::new(DestructRequest::new(library.clone(), component_id))
+                }
+                (None, Some(sandbox)) => DestructedData::new_sandboxed(
+                    sandbox,
+                    &entry.path,
+                    entry.sha256.as_deref(),
+                    component_id,
+                ),
+                (None, None) => {
+                    unreachable!("only isolated packages, wasm modules and scripts are not loaded")
+                }
+            },
             PackageComponentType::Data,
             &loaded_index,
             &mut errors,
         );
         let conns = destruct_component(
-            DestructedConn::new,
+            |entry, component_id| {
+                DestructedConn::new(DestructRequest::new(
+                    entry.handle.clone().expect("conns are never isolated"),
+                    component_id,
+                ))
+            },
             PackageComponentType::Conn,
             &loaded_index,
             &mut errors,
@@ -193,7 +433,12 @@ impl IndexComponentLoader {
         // TODO: destruct connections
 
         if errors.is_empty() {
-            Ok(Self { gates, data, conns })
+            Ok(Self {
+                gates,
+                data,
+                conns,
+                warnings,
+            })
         } else {
             Err(loader::Error::LoadAllComponentPackages { errors })
         }
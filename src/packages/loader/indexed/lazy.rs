@@ -0,0 +1,447 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard, PoisonError, Weak},
+};
+
+use semver::Version;
+
+use crate::{
+    common::world::{ComponentVersion, ComponentVersionReq},
+    packages::{
+        destructor::{self, DestructRequest, DestructedData, DestructedGate},
+        indexer::{
+            self,
+            component::{Package, PackageComponentType, PackageLibraryKind},
+            deps_resolver::EnabledFeatures,
+        },
+        loader::{
+            self, LoadManager,
+            indexed::component::{
+                EnabledLibrary, destruct_data_file, destruct_gate_file, enabled_libraries,
+            },
+        },
+        stdlib,
+    },
+};
+
+type PackageName = String;
+type PackageVersion = Version;
+type LibName = String;
+
+type LazyHandles<T> =
+    HashMap<PackageName, BTreeMap<PackageVersion, HashMap<LibName, LazyEntry<T>>>>;
+
+/// a component that can be loaded, and the handle if it is currently loaded
+struct LazyEntry<T> {
+    kind: PackageLibraryKind,
+    path: PathBuf,
+    sha256: Option<String>,
+    /// the handle is owned by the gates and data using it,
+    /// the library is unloaded when the last of them is dropped
+    loaded: Weak<T>,
+}
+
+/// library loading utility like IndexComponentLoader,
+/// but only registers the gates and data types the packages provide
+///
+/// a library is loaded and destructed when its component is first asked for,
+/// and unloaded again once every handle to it is dropped,
+/// asking for the component after that loads the library again
+///
+/// connection libraries are not registered, load them with IndexComponentLoader,
+/// isolated versions (see VersionStatus::isolated) are rejected with loader::Error::IsolatedVersion
+pub struct LazyComponentLoader {
+    gates: Mutex<LazyHandles<DestructedGate>>,
+    data: Mutex<LazyHandles<DestructedData>>,
+    /// features each registered version is registered with
+    features: Mutex<EnabledFeatures>,
+    /// deprecated or yanked versions that are registered by new
+    warnings: Vec<loader::Warning>,
+}
+
+/// libraries of a version to register
+struct VersionLibraries {
+    package: String,
+    version: Version,
+    features: BTreeSet<String>,
+    libraries: Vec<EnabledLibrary>,
+}
+
+impl LazyComponentLoader {
+    /// given an index and a list of packages to load,
+    /// register the components the packages provide with the features enabled
+    ///
+    /// nothing is loaded, libraries that do not exist are only reported when they are first used
+    pub fn new(
+        index: &indexer::component::PackageIndex,
+        packages_to_load: HashMap<String, Vec<Version>>,
+        features: &EnabledFeatures,
+    ) -> Result<Self, loader::Error> {
+        let mut errors = Vec::new();
+        let mut warnings = Vec::new();
+        let mut to_register = Vec::new();
+
+        for (package_name, versions_to_load) in packages_to_load {
+            let package = match index.get_package(&package_name) {
+                Some(pkg) => pkg,
+                None => {
+                    errors.push(loader::Error::MissingPackage { name: package_name });
+                    continue;
+                }
+            };
+
+            to_register.extend(version_libraries(
+                package,
+                &versions_to_load,
+                features,
+                &mut warnings,
+                &mut errors,
+            ));
+        }
+
+        if !errors.is_empty() {
+            return Err(loader::Error::LoadAllComponentPackages { errors });
+        }
+
+        let loader = Self {
+            gates: Mutex::new(HashMap::new()),
+            data: Mutex::new(HashMap::new()),
+            features: Mutex::new(HashMap::new()),
+            warnings,
+        };
+        to_register
+            .into_iter()
+            .for_each(|version| loader.insert_version(version));
+
+        Ok(loader)
+    }
+
+    /// deprecated or yanked versions that are registered by new
+    pub fn get_warnings(&self) -> &[loader::Warning] {
+        &self.warnings
+    }
+
+    /// register versions of a package, replacing the components of versions already registered
+    ///
+    /// either every version is registered or, if one of them fails, none are.
+    /// handles of replaced components stay loaded as long as something uses them,
+    /// the next time the component is asked for, the new library is loaded
+    ///
+    /// returns the warnings of the versions, e.g. if they are deprecated
+    pub fn register_package(
+        &self,
+        package: &Package,
+        versions: &[Version],
+        features: &EnabledFeatures,
+    ) -> Result<Vec<loader::Warning>, loader::Error> {
+        let mut errors = Vec::new();
+        let mut warnings = Vec::new();
+
+        let to_register =
+            version_libraries(package, versions, features, &mut warnings, &mut errors);
+
+        if !errors.is_empty() {
+            return Err(loader::Error::LoadAllComponentPackages { errors });
+        }
+
+        to_register
+            .into_iter()
+            .for_each(|version| self.insert_version(version));
+
+        Ok(warnings)
+    }
+
+    /// remove all components of a package version,
+    /// handles that are loaded stay loaded as long as something uses them
+    pub fn unregister_version(&self, package: &str, version: &Version) {
+        remove_version(&mut lock(&self.gates), package, version);
+        remove_version(&mut lock(&self.data), package, version);
+        remove_version(&mut lock(&self.features), package, version);
+    }
+
+    /// features a version is registered with, None if the version is not registered
+    pub fn get_features(&self, package: &str, version: &Version) -> Option<BTreeSet<String>> {
+        lock(&self.features).get(package)?.get(version).cloned()
+    }
+
+    fn insert_version(&self, version: VersionLibraries) {
+        let mut gates = lock(&self.gates);
+        let mut data = lock(&self.data);
+
+        remove_version(&mut gates, &version.package, &version.version);
+        remove_version(&mut data, &version.package, &version.version);
+
+        for library in version.libraries {
+            match library.variant {
+                PackageComponentType::Gate => {
+                    register(&mut gates, &version.package, &version.version, library)
+                }
+                PackageComponentType::Data => {
+                    register(&mut data, &version.package, &version.version, library)
+                }
+                PackageComponentType::Conn => {}
+            }
+        }
+
+        lock(&self.features)
+            .entry(version.package)
+            .or_default()
+            .insert(version.version, version.features);
+    }
+
+    /// handle of a gate type, its library is loaded if it is not already,
+    /// None if the gate type is not registered
+    pub fn load_gate(
+        &self,
+        component: &ComponentVersion,
+    ) -> Result<Option<Arc<DestructedGate>>, loader::Error> {
+        load(
+            &self.gates,
+            component,
+            DestructedGate::new,
+            destruct_gate_file,
+        )
+    }
+
+    /// handle of a data type, its library is loaded if it is not already,
+    /// None if the data type is not registered
+    pub fn load_data(
+        &self,
+        component: &ComponentVersion,
+    ) -> Result<Option<Arc<DestructedData>>, loader::Error> {
+        load(
+            &self.data,
+            component,
+            DestructedData::new,
+            destruct_data_file,
+        )
+    }
+
+    /// handle of a gate type only if its library is currently loaded
+    pub fn get_loaded_gate(&self, component: &ComponentVersion) -> Option<Arc<DestructedGate>> {
+        get_entry(&mut lock(&self.gates), component)?
+            .loaded
+            .upgrade()
+    }
+
+    /// handle of a data type only if its library is currently loaded
+    pub fn get_loaded_data(&self, component: &ComponentVersion) -> Option<Arc<DestructedData>> {
+        get_entry(&mut lock(&self.data), component)?
+            .loaded
+            .upgrade()
+    }
+
+    /// highest registered version of the gate type that matches the request
+    pub fn find_gate_version(&self, request: &ComponentVersionReq) -> Option<Version> {
+        find_version(&lock(&self.gates), request)
+    }
+
+    /// highest registered version of the data type that matches the request
+    pub fn find_data_version(&self, request: &ComponentVersionReq) -> Option<Version> {
+        find_version(&lock(&self.data), request)
+    }
+
+    /// if a gate or data type is registered, whether or not it is loaded
+    pub fn contains(&self, component: &ComponentVersion) -> bool {
+        get_entry(&mut lock(&self.gates), component).is_some()
+            || get_entry(&mut lock(&self.data), component).is_some()
+    }
+
+    /// if the library of a gate or data type is currently loaded
+    pub fn is_loaded(&self, component: &ComponentVersion) -> bool {
+        self.get_loaded_gate(component).is_some() || self.get_loaded_data(component).is_some()
+    }
+
+    /// all gate and data types whose library is currently loaded
+    pub fn list_loaded(&self) -> Vec<ComponentVersion> {
+        let mut loaded = loaded_components(&lock(&self.gates));
+        loaded.extend(loaded_components(&lock(&self.data)));
+        loaded
+    }
+
+    /// use a new handle for a registered gate type, e.g. after its library is reloaded
+    pub fn replace_gate(&self, handle: &Arc<DestructedGate>) {
+        if let Some(entry) = get_entry(&mut lock(&self.gates), handle.id()) {
+            entry.loaded = Arc::downgrade(handle);
+        }
+    }
+
+    /// use a new handle for a registered data type, e.g. after its library is reloaded
+    pub fn replace_data(&self, handle: &Arc<DestructedData>) {
+        if let Some(entry) = get_entry(&mut lock(&self.data), handle.id()) {
+            entry.loaded = Arc::downgrade(handle);
+        }
+    }
+}
+
+fn lock<T>(handles: &Mutex<T>) -> MutexGuard<'_, T> {
+    // entries are only replaced as a whole, a panic cannot leave one half written
+    handles.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// the libraries of the versions of a package with the features enabled,
+/// the errors of versions that cannot be registered are added to errors
+fn version_libraries(
+    package: &Package,
+    versions: &[Version],
+    features: &EnabledFeatures,
+    warnings: &mut Vec<loader::Warning>,
+    errors: &mut Vec<loader::Error>,
+) -> Vec<VersionLibraries> {
+    let package_name = package.get_name();
+
+    // its components would be used instead of the stdlib ones
+    if package_name == stdlib::PACKAGE {
+        errors.push(loader::Error::ReservedPackage {
+            name: package_name.to_string(),
+        });
+        return Vec::new();
+    }
+
+    versions
+        .iter()
+        .filter_map(|version| {
+            // loaded in this process, the components would not be isolated
+            if package
+                .get_version_entry(version)
+                .is_some_and(|entry| entry.get_status().isolated)
+            {
+                errors.push(loader::Error::IsolatedVersion {
+                    name: package_name.to_string(),
+                    version: version.clone(),
+                });
+                return None;
+            }
+
+            match enabled_libraries(package, package_name, version, features, warnings) {
+                Ok(libraries) => Some(VersionLibraries {
+                    package: package_name.to_string(),
+                    version: version.clone(),
+                    features: features
+                        .get(package_name)
+                        .and_then(|versions| versions.get(version))
+                        .cloned()
+                        .unwrap_or_default(),
+                    libraries,
+                }),
+                Err(e) => {
+                    errors.push(e);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+fn remove_version<T>(
+    versions: &mut HashMap<PackageName, BTreeMap<PackageVersion, T>>,
+    package: &str,
+    version: &Version,
+) {
+    if let Some(package_versions) = versions.get_mut(package) {
+        package_versions.remove(version);
+        if package_versions.is_empty() {
+            versions.remove(package);
+        }
+    }
+}
+
+fn register<T>(
+    handles: &mut LazyHandles<T>,
+    package: &str,
+    version: &Version,
+    library: EnabledLibrary,
+) {
+    handles
+        .entry(package.to_string())
+        .or_default()
+        .entry(version.clone())
+        .or_default()
+        .insert(
+            library.name,
+            LazyEntry {
+                kind: library.kind,
+                path: library.path,
+                sha256: library.sha256,
+                loaded: Weak::new(),
+            },
+        );
+}
+
+fn get_entry<'a, T>(
+    handles: &'a mut LazyHandles<T>,
+    component: &ComponentVersion,
+) -> Option<&'a mut LazyEntry<T>> {
+    handles
+        .get_mut(&component.package)?
+        .get_mut(&component.version)?
+        .get_mut(&component.component)
+}
+
+fn load<T>(
+    handles: &Mutex<LazyHandles<T>>,
+    component: &ComponentVersion,
+    destruct: impl Fn(DestructRequest) -> Result<T, destructor::Error>,
+    destruct_file: impl Fn(PackageLibraryKind, &Path, ComponentVersion) -> Result<T, destructor::Error>,
+) -> Result<Option<Arc<T>>, loader::Error> {
+    // held while loading, so two threads asking for the same component load it once
+    let mut handles = lock(handles);
+
+    let Some(entry) = get_entry(&mut handles, component) else {
+        return Ok(None);
+    };
+
+    if let Some(handle) = entry.loaded.upgrade() {
+        return Ok(Some(handle));
+    }
+
+    let handle = match entry.kind {
+        PackageLibraryKind::Native => {
+            let library =
+                LoadManager::load_with_checksum(entry.path.clone(), entry.sha256.as_deref())?;
+            destruct(DestructRequest::new(library, component.clone()))
+        }
+        kind => {
+            LoadManager::verify_checksum(&entry.path, entry.sha256.as_deref())?;
+            destruct_file(kind, &entry.path, component.clone())
+        }
+    }
+    .map_err(|e| loader::Error::DestructorError {
+        content: e.to_string(),
+    })?;
+
+    let handle = Arc::new(handle);
+    entry.loaded = Arc::downgrade(&handle);
+    Ok(Some(handle))
+}
+
+fn find_version<T>(handles: &LazyHandles<T>, request: &ComponentVersionReq) -> Option<Version> {
+    handles
+        .get(&request.package)?
+        .iter()
+        .rfind(|(version, components)| {
+            request.version_req.matches(version) && components.contains_key(&request.component)
+        })
+        .map(|(version, _)| version.clone())
+}
+
+fn loaded_components<T>(handles: &LazyHandles<T>) -> Vec<ComponentVersion> {
+    let mut loaded = Vec::new();
+
+    for (package, versions) in handles {
+        for (version, components) in versions {
+            for (component, entry) in components {
+                if entry.loaded.strong_count() > 0 {
+                    loaded.push(ComponentVersion {
+                        package: package.clone(),
+                        version: version.clone(),
+                        component: component.clone(),
+                    });
+                }
+            }
+        }
+    }
+
+    loaded
+}
@@ -1 +1,3 @@
 pub mod component;
+pub mod lazy;
+pub mod validate;
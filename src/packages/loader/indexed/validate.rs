@@ -0,0 +1,269 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use semver::Version;
+
+use crate::{
+    common::world::ComponentVersion,
+    packages::{
+        destructor::{self, DestructRequest, DestructedConn, DestructedData, DestructedGate},
+        indexer::component::{PackageComponentType, PackageIndex, PackageLibraryKind},
+        loader::{
+            self,
+            indexed::component::{destruct_data_file, destruct_gate_file},
+            manager::LoadManager,
+        },
+    },
+};
+
+/// result of validate_package
+#[cfg_attr(feature = "devel", derive(Debug))]
+pub struct PackageReport {
+    pub name: String,
+    pub version: Version,
+    /// component name -> report
+    pub components: BTreeMap<String, ComponentReport>,
+}
+
+impl PackageReport {
+    /// returns true if no component has any problems
+    pub fn is_ok(&self) -> bool {
+        self.components
+            .values()
+            .all(|component| component.problems.is_empty())
+    }
+}
+
+/// result of validating a single component in provides
+#[cfg_attr(feature = "devel", derive(Debug))]
+pub struct ComponentReport {
+    pub variant: PackageComponentType,
+    pub lib_path: PathBuf,
+    /// empty if the component is valid
+    pub problems: Vec<ComponentProblem>,
+}
+
+#[cfg_attr(feature = "devel", derive(Debug))]
+pub enum ComponentProblem {
+    /// there is no library file for the component
+    MissingLibrary,
+    /// library content does not match the sha256 digest in the manifest
+    ChecksumMismatch { expected: String, got: String },
+    /// the library exists but cannot be loaded
+    /// (for wasm modules and scripts, also if an export or function is missing)
+    LoadLib { reason: String },
+    /// the schema version of the library is not supported
+    UnsupportedSchemaVersion { version: u32 },
+    /// a symbol required for the component type is missing
+    MissingSymbol { symbol_name: String },
+    /// the definition of the component cannot be normalised (gates only)
+    InvalidDefinition { reason: String },
+}
+
+/// check a package version without loading it into the world
+///
+/// for every component in provides, checks that:
+/// - the library file exists (and matches its sha256 digest, if specified)
+/// - schema_version is supported
+/// - all symbols needed by the destructor for the component type are present
+///   (wasm modules are instantiated and scripts compiled instead)
+/// - the definition of gates can be normalised
+///
+/// only missing packages/versions are returned as error,
+/// problems with components are collected in the report
+pub fn validate_package(
+    index: &PackageIndex,
+    name: &str,
+    version: &Version,
+) -> Result<PackageReport, loader::Error> {
+    let package = index
+        .get_package(name)
+        .ok_or_else(|| loader::Error::MissingPackage {
+            name: name.to_string(),
+        })?;
+    let manifest =
+        package
+            .get_version(version)
+            .ok_or_else(|| loader::Error::MissingPackageVersion {
+                name: name.to_string(),
+                version: version.clone(),
+            })?;
+
+    let components = manifest
+        .get_provides()
+        .iter()
+        .map(|(component, provide)| {
+            let lib_path = package
+                .get_library_path(version, component)
+                .expect("version exists (because of previous step)");
+            let id = ComponentVersion {
+                package: name.to_string(),
+                version: version.clone(),
+                component: component.clone(),
+            };
+
+            let problems = validate_component(
+                provide.get_type(),
+                provide.get_library_kind(),
+                lib_path.clone(),
+                provide.get_library_sha256(),
+                id,
+            );
+
+            (
+                component.clone(),
+                ComponentReport {
+                    variant: provide.get_type(),
+                    lib_path,
+                    problems,
+                },
+            )
+        })
+        .collect();
+
+    Ok(PackageReport {
+        name: name.to_string(),
+        version: version.clone(),
+        components,
+    })
+}
+
+fn validate_component(
+    variant: PackageComponentType,
+    kind: PackageLibraryKind,
+    lib_path: PathBuf,
+    sha256: Option<&str>,
+    id: ComponentVersion,
+) -> Vec<ComponentProblem> {
+    if !lib_path.is_file() {
+        return vec![ComponentProblem::MissingLibrary];
+    }
+
+    if kind != PackageLibraryKind::Native {
+        return validate_file_library(variant, kind, lib_path, sha256, id);
+    }
+
+    let library = match LoadManager::load_with_checksum(lib_path, sha256) {
+        Ok(library) => library,
+        Err(loader::Error::ChecksumMismatch { expected, got, .. }) => {
+            return vec![ComponentProblem::ChecksumMismatch { expected, got }];
+        }
+        Err(e) => {
+            return vec![ComponentProblem::LoadLib {
+                reason: e.to_string(),
+            }];
+        }
+    };
+
+    let schema_version = match library.get_symbol::<fn() -> u32>("schema_version") {
+        Ok(get_schema_version) => get_schema_version(),
+        Err(_) => {
+            return vec![ComponentProblem::MissingSymbol {
+                symbol_name: "schema_version".to_string(),
+            }];
+        }
+    };
+
+    let required_symbols = match variant {
+        PackageComponentType::Gate => DestructedGate::required_symbols(schema_version),
+        PackageComponentType::Data => DestructedData::required_symbols(schema_version),
+        PackageComponentType::Conn => DestructedConn::required_symbols(schema_version),
+    };
+    let required_symbols = match required_symbols {
+        Some(symbols) => symbols,
+        None => {
+            return vec![ComponentProblem::UnsupportedSchemaVersion {
+                version: schema_version,
+            }];
+        }
+    };
+
+    let problems: Vec<_> = required_symbols
+        .iter()
+        .filter(|symbol| library.get_symbol::<*const ()>(symbol).is_err())
+        .map(|symbol| ComponentProblem::MissingSymbol {
+            symbol_name: symbol.to_string(),
+        })
+        .collect();
+
+    if !problems.is_empty() || variant != PackageComponentType::Gate {
+        return problems;
+    }
+
+    // all symbols are present, only the definition is left to check
+    match DestructedGate::new(DestructRequest::new(library, id)) {
+        Ok(gate) => validate_definition(&gate),
+        // an entry point of a capability the library reports
+        Err(destructor::Error::GetSymbol { symbol_name, .. }) => {
+            vec![ComponentProblem::MissingSymbol { symbol_name }]
+        }
+        Err(e) => vec![ComponentProblem::InvalidDefinition {
+            reason: e.to_string(),
+        }],
+    }
+}
+
+/// wasm modules and scripts are instantiated or compiled instead of loaded
+fn validate_file_library(
+    variant: PackageComponentType,
+    kind: PackageLibraryKind,
+    lib_path: PathBuf,
+    sha256: Option<&str>,
+    id: ComponentVersion,
+) -> Vec<ComponentProblem> {
+    match LoadManager::verify_checksum(&lib_path, sha256) {
+        Ok(()) => {}
+        Err(loader::Error::ChecksumMismatch { expected, got, .. }) => {
+            return vec![ComponentProblem::ChecksumMismatch { expected, got }];
+        }
+        Err(e) => {
+            return vec![ComponentProblem::LoadLib {
+                reason: e.to_string(),
+            }];
+        }
+    }
+
+    let library_problem = |e: destructor::Error| match e {
+        destructor::Error::UnsupportedSchemaVersion { version } => {
+            vec![ComponentProblem::UnsupportedSchemaVersion { version }]
+        }
+        e => vec![ComponentProblem::LoadLib {
+            reason: e.to_string(),
+        }],
+    };
+
+    match variant {
+        PackageComponentType::Gate => match destruct_gate_file(kind, &lib_path, id) {
+            Ok(gate) => validate_definition(&gate),
+            Err(e) => library_problem(e),
+        },
+        PackageComponentType::Data => match destruct_data_file(kind, &lib_path, id) {
+            Ok(_) => Vec::new(),
+            Err(e) => library_problem(e),
+        },
+        // rejected when the package is indexed
+        PackageComponentType::Conn => vec![ComponentProblem::LoadLib {
+            reason: format!("connections cannot be {} libraries", kind.get_name()),
+        }],
+    }
+}
+
+/// the definition of the default gate can be normalised
+fn validate_definition(gate: &DestructedGate) -> Vec<ComponentProblem> {
+    let gate_ptr = match gate.default_value() {
+        Ok(gate_ptr) => gate_ptr,
+        Err(e) => {
+            return vec![ComponentProblem::InvalidDefinition {
+                reason: e.to_string(),
+            }];
+        }
+    };
+    let definition = gate.normalised_definition(gate_ptr);
+    gate.drop_mem(gate_ptr);
+
+    match definition {
+        Ok(_) => Vec::new(),
+        Err(e) => vec![ComponentProblem::InvalidDefinition {
+            reason: e.to_string(),
+        }],
+    }
+}
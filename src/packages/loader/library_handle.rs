@@ -1,25 +1,30 @@
 use std::{
     path::{Path, PathBuf},
-    rc::Rc,
+    sync::Arc,
 };
 
 use libloading::{Library, Symbol};
 
+use crate::packages::loader::manager::LoadManager;
+
 /// Handle to a loaded dynamic library
 ///
 /// Cloning the handle will create new handles referencing the same library
 /// If the last handle to a library is dropped, the library is unloaded from memory
 #[derive(Clone)]
 pub struct LibraryHandle {
-    library: Rc<Library>,
+    library: Arc<Library>,
     path: PathBuf,
+    /// sha256 digest the library is checked against, see LoadManager::load_with_checksum
+    sha256: Option<String>,
 }
 
 impl LibraryHandle {
-    pub fn new(library: Library, path: PathBuf) -> Self {
+    pub fn new(library: Library, path: PathBuf, sha256: Option<String>) -> Self {
         Self {
-            library: Rc::new(library),
+            library: Arc::new(library),
             path,
+            sha256,
         }
     }
 
@@ -27,6 +32,21 @@ impl LibraryHandle {
         &self.path
     }
 
+    pub fn get_sha256(&self) -> Option<&str> {
+        self.sha256.as_deref()
+    }
+
+    /// load the library at the same path again, e.g. after it is rebuilt
+    ///
+    /// returns a new handle to the newly loaded library,
+    /// self (and symbols from it) remains valid
+    ///
+    /// a library loaded with a sha256 digest is checked against the same digest,
+    /// so a library pinned by its manifest cannot be swapped by reloading it
+    pub fn reload(&self) -> Result<Self, super::Error> {
+        LoadManager::load_fresh_copy(self.path.clone(), self.sha256.as_deref())
+    }
+
     /// DANGER!
     /// Symbols are only valid if the library is loaded in memory
     /// Because the library will only unload when all handles are dropped,
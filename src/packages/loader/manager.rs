@@ -1,6 +1,13 @@
-use std::{env::consts::DLL_EXTENSION, path::PathBuf};
+use std::{
+    env::{self, consts::DLL_EXTENSION},
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
 use libloading::Library;
+use sha2::{Digest, Sha256};
 
 use crate::packages::loader::library_handle::LibraryHandle;
 
@@ -20,8 +27,130 @@ impl LoadManager {
 
     pub fn load_with_path(lib_path: PathBuf) -> Result<LibraryHandle, super::Error> {
         match unsafe { Library::new(&lib_path) } {
-            Ok(lib) => Ok(LibraryHandle::new(lib, lib_path)),
+            Ok(lib) => Ok(LibraryHandle::new(lib, lib_path, None)),
             Err(e) => Err(super::Error::from_load_lib(e, lib_path.to_path_buf())),
         }
     }
+
+    /// load the current content of lib_path, even if the library is already loaded
+    ///
+    /// the dynamic loader returns the library already in memory when the same path
+    /// is loaded again, so the library is loaded from a temporary copy instead.
+    /// the copy is checked against expected (no check if expected is None),
+    /// the handle still reports lib_path as its path
+    ///
+    /// the copy is created only by this process and readable only by its user,
+    /// so the bytes that are checked are the ones that are loaded
+    pub fn load_fresh_copy(
+        lib_path: PathBuf,
+        expected: Option<&str>,
+    ) -> Result<LibraryHandle, super::Error> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let copy_path = env::temp_dir().join(format!(
+            "xdsim-lib-{}-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed),
+            lib_path.file_name().unwrap_or_default().to_string_lossy()
+        ));
+
+        // the bytes that are checked are the ones written to the copy
+        let content = fs::read(&lib_path).map_err(|e| super::Error::LoadLib {
+            reason: e.to_string(),
+            lib_path: lib_path.clone(),
+        })?;
+        check_digest(&content, &lib_path, expected)?;
+
+        write_private(&copy_path, &content).map_err(|e| super::Error::LoadLib {
+            reason: e.to_string(),
+            lib_path: lib_path.clone(),
+        })?;
+
+        let loaded = unsafe { Library::new(&copy_path) };
+        // the copy is no longer needed once it is mapped,
+        // on platforms that lock loaded libraries the copy is left in the temp directory
+        let _ = fs::remove_file(&copy_path);
+
+        match loaded {
+            Ok(lib) => Ok(LibraryHandle::new(
+                lib,
+                lib_path,
+                expected.map(str::to_string),
+            )),
+            Err(e) => Err(super::Error::from_load_lib(e, lib_path)),
+        }
+    }
+
+    /// load a library only if its sha256 digest matches expected
+    /// (no check if expected is None)
+    ///
+    /// a library with a digest is loaded from a private copy of the bytes that are checked
+    /// (see load_fresh_copy), so swapping the file after it is checked has no effect.
+    /// each load is then a separate copy of the library, with its own statics
+    ///
+    /// the handle keeps expected, so reloading it checks the same digest
+    pub fn load_with_checksum(
+        lib_path: PathBuf,
+        expected: Option<&str>,
+    ) -> Result<LibraryHandle, super::Error> {
+        match expected {
+            Some(_) => Self::load_fresh_copy(lib_path, expected),
+            None => Self::load_with_path(lib_path),
+        }
+    }
+
+    /// check the sha256 digest of a library without loading it
+    /// (no check if expected is None)
+    ///
+    /// the file is opened again by whatever loads it afterwards (the sandbox helper,
+    /// the wasm runtime or the script engine), so this only guards against
+    /// accidental corruption, not against the file being swapped in between
+    pub fn verify_checksum(lib_path: &Path, expected: Option<&str>) -> Result<(), super::Error> {
+        if expected.is_none() {
+            return Ok(());
+        }
+
+        let content = fs::read(lib_path).map_err(|e| super::Error::LoadLib {
+            reason: e.to_string(),
+            lib_path: lib_path.to_path_buf(),
+        })?;
+        check_digest(&content, lib_path, expected)
+    }
+}
+
+/// write a new file that only the current user can read or write,
+/// fails if the path already exists (e.g. a link planted in the temp directory)
+fn write_private(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    options.open(path)?.write_all(content)
+}
+
+/// check the sha256 digest of the content of a library (no check if expected is None)
+fn check_digest(
+    content: &[u8],
+    lib_path: &Path,
+    expected: Option<&str>,
+) -> Result<(), super::Error> {
+    let Some(expected) = expected else {
+        return Ok(());
+    };
+
+    let got = Sha256::digest(content)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<String>();
+
+    if !got.eq_ignore_ascii_case(expected) {
+        return Err(super::Error::ChecksumMismatch {
+            lib_path: lib_path.to_path_buf(),
+            expected: expected.to_string(),
+            got,
+        });
+    }
+
+    Ok(())
 }
@@ -3,10 +3,14 @@
 //! - Wraps around libloading to provide a stable interface.
 
 mod manager;
+pub use manager::LoadManager;
 
 mod error;
 pub use error::Error;
 
+mod warning;
+pub use warning::Warning;
+
 mod library_handle;
 pub use library_handle::LibraryHandle;
 
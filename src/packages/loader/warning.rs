@@ -0,0 +1,30 @@
+use std::fmt::Display;
+
+use semver::Version;
+
+/// something worth telling the user about, that does not stop loading
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    /// A deprecated package version is loaded
+    Deprecated {
+        name: String,
+        version: Version,
+        /// Deprecation message from the manifest or overrides file
+        message: String,
+    },
+    /// A yanked package version is loaded, e.g. because a lockfile pins it
+    Yanked { name: String, version: Version },
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Deprecated {
+                name,
+                version,
+                message,
+            } => write!(f, "{name} {version} is deprecated: {message}"),
+            Self::Yanked { name, version } => write!(f, "{name} {version} is yanked"),
+        }
+    }
+}
@@ -2,3 +2,5 @@ pub mod chelper;
 pub mod destructor;
 pub mod indexer;
 pub mod loader;
+pub mod sandbox;
+pub mod stdlib;
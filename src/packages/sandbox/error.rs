@@ -0,0 +1,23 @@
+use std::fmt::Display;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The helper process cannot be started
+    Spawn { reason: String },
+    /// The helper process died while handling a request,
+    /// or did not respond within the timeout of its SandboxCommand and is killed
+    Crashed { reason: String },
+    /// The component in the helper returned an error
+    Component { reason: String },
+    /// The helper responded with something other than what the request expects
+    UnexpectedResponse { expected: &'static str },
+    /// The instance is lost when the helper crashed,
+    /// it is recreated with its default value
+    InstanceReset { instance: u64 },
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!("{self:?}"))
+    }
+}
@@ -0,0 +1,296 @@
+use std::{
+    collections::HashMap,
+    ffi::c_void,
+    io::{self, BufRead, Write},
+    sync::Arc,
+};
+
+use crate::{
+    common::world::DataPtrMut,
+    packages::{
+        chelper::slice,
+        destructor::{DestructRequest, DestructedData, DestructedGate},
+        loader::LoadManager,
+        sandbox::protocol::{ComponentKind, GateDefinition, Request, Response},
+    },
+};
+
+/// serve requests from stdin until it is closed,
+/// this is what the helper process runs
+pub fn run_helper() {
+    let stdin = io::stdin();
+
+    let output = match protocol_output() {
+        Ok(output) => output,
+        Err(e) => {
+            eprintln!("sandbox helper cannot take over stdout: {e}");
+            return;
+        }
+    };
+
+    if let Err(e) = serve(stdin.lock(), io::BufWriter::new(output)) {
+        eprintln!("sandbox helper stopped: {e}");
+    }
+}
+
+/// responses are written to a copy of stdout, and stdout is pointed at stderr,
+/// so whatever a component library prints cannot be mistaken for a response
+#[cfg(unix)]
+fn protocol_output() -> io::Result<std::fs::File> {
+    use std::os::fd::AsFd;
+
+    let output = io::stdout().as_fd().try_clone_to_owned()?;
+
+    // nothing is written to stdout before this, so its buffer is empty
+    if unsafe { libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(std::fs::File::from(output))
+}
+
+/// stdout is not redirected on this platform,
+/// a component library printing to it breaks the helper
+#[cfg(not(unix))]
+fn protocol_output() -> io::Result<io::Stdout> {
+    Ok(io::stdout())
+}
+
+/// serve requests line by line until reader is exhausted
+pub fn serve<R: BufRead, W: Write>(reader: R, mut writer: W) -> io::Result<()> {
+    let mut helper = Helper::default();
+
+    for line in reader.lines() {
+        let line = line?;
+
+        let response = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => helper.handle(request),
+            Err(e) => Response::Failed {
+                reason: e.to_string(),
+            },
+        };
+
+        serde_json::to_writer(&mut writer, &response)?;
+        writer.write_all(b"\n")?;
+        writer.flush()?;
+    }
+
+    Ok(())
+}
+
+#[derive(Clone)]
+enum Handle {
+    Gate(Arc<DestructedGate>),
+    Data(Arc<DestructedData>),
+}
+
+/// a gate or data in the helper, dropped with the handle it is created from
+struct Instance {
+    handle: Handle,
+    ptr: *mut c_void,
+}
+
+impl Drop for Instance {
+    fn drop(&mut self) {
+        match &self.handle {
+            Handle::Gate(handle) => handle.drop_mem(self.ptr),
+            Handle::Data(handle) => handle.drop_mem(self.ptr),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Helper {
+    components: HashMap<u64, Handle>,
+    instances: HashMap<u64, Instance>,
+}
+
+impl Helper {
+    fn handle(&mut self, request: Request) -> Response {
+        match self.try_handle(request) {
+            Ok(response) => response,
+            Err(reason) => Response::Failed { reason },
+        }
+    }
+
+    fn try_handle(&mut self, request: Request) -> Result<Response, String> {
+        match request {
+            Request::Load {
+                component_id,
+                kind,
+                lib_path,
+                component,
+                fresh,
+                sha256,
+            } => {
+                // the first load is verified by the host, see IndexComponentLoader::load_all
+                let library = if fresh {
+                    LoadManager::load_fresh_copy(lib_path, sha256.as_deref())
+                } else {
+                    LoadManager::load_with_path(lib_path)
+                }
+                .map_err(|e| e.to_string())?;
+
+                let request = DestructRequest::new(library, component);
+                let handle = match kind {
+                    ComponentKind::Gate => Handle::Gate(Arc::new(
+                        DestructedGate::new(request).map_err(|e| e.to_string())?,
+                    )),
+                    ComponentKind::Data => Handle::Data(Arc::new(
+                        DestructedData::new(request).map_err(|e| e.to_string())?,
+                    )),
+                };
+
+                self.components.insert(component_id, handle);
+                Ok(Response::Done)
+            }
+            Request::Unload { component_id } => {
+                // instances of the component keep their handle alive
+                self.components.remove(&component_id);
+                Ok(Response::Done)
+            }
+            Request::Default {
+                component_id,
+                instance,
+            } => {
+                let handle = self.get_component(component_id)?.clone();
+                let ptr = match &handle {
+                    Handle::Gate(handle) => handle.default_value().map_err(|e| e.to_string())?,
+                    Handle::Data(handle) => handle.default_value().map_err(|e| e.to_string())?,
+                };
+
+                self.instances.insert(instance, Instance { handle, ptr });
+                Ok(Response::Done)
+            }
+            Request::Deserialize {
+                component_id,
+                instance,
+                bytes,
+            } => {
+                let handle = self.get_component(component_id)?.clone();
+                let bytes = slice::from_vec_rustonly(bytes);
+                let ptr = match &handle {
+                    Handle::Gate(handle) => handle.deserialize(&bytes),
+                    Handle::Data(handle) => handle.deserialize(&bytes),
+                }
+                .ok_or_else(|| "component cannot deserialize the bytes".to_string())?;
+
+                self.instances.insert(instance, Instance { handle, ptr });
+                Ok(Response::Done)
+            }
+            Request::Serialize { instance } => {
+                let instance = self.get_instance(instance)?;
+                let serialized = match &instance.handle {
+                    Handle::Gate(handle) => handle.serialize(instance.ptr),
+                    Handle::Data(handle) => handle.serialize(instance.ptr),
+                };
+
+                Ok(Response::Bytes {
+                    bytes: slice::from_slice::<u8>(&serialized).to_vec(),
+                })
+            }
+            Request::Drop { instance } => {
+                self.instances.remove(&instance);
+                Ok(Response::Done)
+            }
+            Request::Definition { instance } => {
+                let instance = self.get_instance(instance)?;
+                let Handle::Gate(handle) = &instance.handle else {
+                    return Err("instance is not a gate".to_string());
+                };
+
+                let definition = handle
+                    .normalised_definition(instance.ptr)
+                    .map_err(|e| e.to_string())?;
+
+                Ok(Response::Definition {
+                    definition: GateDefinition::from(definition),
+                })
+            }
+            Request::Tick {
+                instance,
+                consumers,
+                producers,
+            } => self.tick(instance, consumers, producers),
+        }
+    }
+
+    fn tick(
+        &self,
+        instance: u64,
+        consumers: Vec<(u64, Vec<u8>)>,
+        producers: Vec<u64>,
+    ) -> Result<Response, String> {
+        let gate = self.get_instance(instance)?;
+        let Handle::Gate(gate_handle) = &gate.handle else {
+            return Err("instance is not a gate".to_string());
+        };
+
+        // checked before ticking, the produced data cannot be dropped without its handle
+        let producers = producers
+            .into_iter()
+            .map(|component_id| self.get_data(component_id))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // temporary consumer data, dropped after the tick
+        let mut consumer_data = Vec::with_capacity(consumers.len());
+
+        for (component_id, bytes) in consumers {
+            let handle = self.get_data(component_id)?;
+            let ptr = match handle.deserialize(&slice::from_vec_rustonly(bytes)) {
+                Some(ptr) => ptr,
+                None => handle.default_value().map_err(|e| e.to_string())?,
+            };
+
+            consumer_data.push(Instance {
+                handle: Handle::Data(handle.clone()),
+                ptr,
+            });
+        }
+
+        let consumer_slice = slice::from_vec_rustonly(
+            consumer_data
+                .iter()
+                .map(|data| data.ptr)
+                .collect::<Vec<DataPtrMut>>(),
+        );
+
+        let producer_slice = gate_handle.tick(gate.ptr, &consumer_slice);
+
+        let produced = slice::from_slice::<DataPtrMut>(&producer_slice)
+            .iter()
+            .zip(producers.iter())
+            .map(|(&ptr, handle)| {
+                let data = Instance {
+                    handle: Handle::Data((*handle).clone()),
+                    ptr,
+                };
+                let serialized = handle.serialize(data.ptr);
+                slice::from_slice::<u8>(&serialized).to_vec()
+            })
+            .collect();
+
+        Ok(Response::Producers {
+            producers: produced,
+        })
+    }
+
+    fn get_component(&self, component_id: u64) -> Result<&Handle, String> {
+        self.components
+            .get(&component_id)
+            .ok_or_else(|| format!("component {component_id} is not loaded"))
+    }
+
+    fn get_data(&self, component_id: u64) -> Result<&Arc<DestructedData>, String> {
+        match self.get_component(component_id)? {
+            Handle::Data(handle) => Ok(handle),
+            Handle::Gate(_) => Err(format!("component {component_id} is not a data type")),
+        }
+    }
+
+    fn get_instance(&self, instance: u64) -> Result<&Instance, String> {
+        self.instances
+            .get(&instance)
+            .ok_or_else(|| format!("no instance {instance}"))
+    }
+}
@@ -0,0 +1,575 @@
+use std::{
+    collections::HashMap,
+    ffi::c_void,
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::{
+        Arc, Mutex, MutexGuard, PoisonError,
+        mpsc::{self, Receiver, RecvTimeoutError},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    common::world::ComponentVersion,
+    packages::{
+        destructor::{DestructedData, DestructedGateDefinition},
+        sandbox::{
+            self, HELPER_ARG,
+            protocol::{ComponentKind, Request, Response},
+        },
+    },
+};
+
+/// how the helper process is started
+#[derive(Clone)]
+#[cfg_attr(feature = "devel", derive(Debug))]
+pub struct SandboxCommand {
+    program: PathBuf,
+    args: Vec<String>,
+    timeout: Duration,
+}
+
+impl SandboxCommand {
+    /// how long the helper may take to respond before it is killed, unless set with timeout
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+    pub fn new<T: Into<PathBuf>>(program: T, args: Vec<String>) -> Self {
+        Self {
+            program: program.into(),
+            args,
+            timeout: Self::DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// a helper that does not respond to a request within timeout is killed,
+    /// the request returns Error::Crashed and the helper is restarted on the next request
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// the running xdsim executable with HELPER_ARG
+    pub fn current_exe() -> Result<Self, sandbox::Error> {
+        let program = std::env::current_exe().map_err(|e| sandbox::Error::Spawn {
+            reason: e.to_string(),
+        })?;
+
+        Ok(Self::new(program, vec![HELPER_ARG.to_string()]))
+    }
+}
+
+/// Owner of a sandbox helper process
+///
+/// the helper is started on the first request, and started again after it crashes
+pub struct SandboxHost {
+    state: Mutex<HostState>,
+}
+
+struct HostState {
+    command: SandboxCommand,
+    helper: Option<HelperProcess>,
+    /// ids of components and instances share the same counter
+    next_id: u64,
+    /// components to load when the helper (re)starts
+    components: HashMap<u64, LoadedComponent>,
+    /// data types of other packages loaded so sandboxed gates can use them,
+    /// (library path, component) -> component id
+    borrowed_data: HashMap<(PathBuf, ComponentVersion), u64>,
+    instances: HashMap<u64, InstanceEntry>,
+}
+
+struct LoadedComponent {
+    kind: ComponentKind,
+    lib_path: PathBuf,
+    component: ComponentVersion,
+    sha256: Option<String>,
+}
+
+struct InstanceEntry {
+    component_id: u64,
+    /// false if the instance does not exist in the running helper,
+    /// e.g. the helper crashed since it is created
+    live: bool,
+}
+
+struct HelperProcess {
+    child: Child,
+    stdin: ChildStdin,
+    /// lines of stdout, read on a separate thread so a response can be waited for with a timeout
+    responses: Receiver<std::io::Result<String>>,
+    timeout: Duration,
+}
+
+/// A gate or data type loaded in a sandbox helper
+///
+/// gate and data pointers of the component are instance ids in the helper
+pub struct SandboxedComponent {
+    host: Arc<SandboxHost>,
+    component_id: u64,
+    kind: ComponentKind,
+    lib_path: PathBuf,
+    /// sha256 digest of the manifest, checked when the library is reloaded
+    sha256: Option<String>,
+}
+
+fn to_ptr(instance: u64) -> *mut c_void {
+    instance as usize as *mut c_void
+}
+
+fn from_ptr(ptr: *const c_void) -> u64 {
+    ptr as usize as u64
+}
+
+impl HelperProcess {
+    /// send a request and wait for its response,
+    /// an error means the helper can no longer be used
+    fn exchange(&mut self, request: &Request) -> Result<Response, String> {
+        let mut line = serde_json::to_string(request).map_err(|e| e.to_string())?;
+        line.push('\n');
+
+        self.stdin
+            .write_all(line.as_bytes())
+            .and_then(|_| self.stdin.flush())
+            .map_err(|e| e.to_string())?;
+
+        match self.responses.recv_timeout(self.timeout) {
+            Ok(Ok(response)) => serde_json::from_str(&response).map_err(|e| e.to_string()),
+            Ok(Err(e)) => Err(e.to_string()),
+            Err(RecvTimeoutError::Timeout) => {
+                Err(format!("helper did not respond within {:?}", self.timeout))
+            }
+            Err(RecvTimeoutError::Disconnected) => Err("helper closed its output".to_string()),
+        }
+    }
+}
+
+impl HostState {
+    fn next_id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    fn start(&mut self) -> Result<(), sandbox::Error> {
+        let mut child = Command::new(&self.command.program)
+            .args(&self.command.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| sandbox::Error::Spawn {
+                reason: e.to_string(),
+            })?;
+
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = BufReader::new(child.stdout.take().expect("stdout is piped"));
+
+        // stops when the helper exits (or is killed) and its stdout is closed
+        let (sender, responses) = mpsc::channel();
+        thread::spawn(move || {
+            for line in stdout.lines() {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut helper = HelperProcess {
+            child,
+            stdin,
+            responses,
+            timeout: self.command.timeout,
+        };
+
+        let mut component_ids: Vec<_> = self.components.keys().copied().collect();
+        component_ids.sort();
+
+        for component_id in component_ids {
+            let loaded = &self.components[&component_id];
+            let request = Request::Load {
+                component_id,
+                kind: loaded.kind,
+                lib_path: loaded.lib_path.clone(),
+                component: loaded.component.clone(),
+                fresh: false,
+                sha256: loaded.sha256.clone(),
+            };
+
+            // components that fail to load again report errors when they are used
+            if let Err(reason) = helper.exchange(&request) {
+                self.helper = Some(helper);
+                return Err(self.crashed(reason));
+            }
+        }
+
+        self.helper = Some(helper);
+        Ok(())
+    }
+
+    /// stop the helper after it died or did not respond in time,
+    /// every instance it held is lost
+    fn crashed(&mut self, reason: String) -> sandbox::Error {
+        let mut reason = reason;
+
+        if let Some(mut helper) = self.helper.take() {
+            let _ = helper.child.kill();
+            if let Ok(status) = helper.child.wait() {
+                reason = format!("{reason} ({status})");
+            }
+        }
+
+        for instance in self.instances.values_mut() {
+            instance.live = false;
+        }
+
+        sandbox::Error::Crashed { reason }
+    }
+
+    fn call(&mut self, request: &Request) -> Result<Response, sandbox::Error> {
+        if self.helper.is_none() {
+            self.start()?;
+        }
+
+        let helper = self.helper.as_mut().expect("helper is started");
+
+        match helper.exchange(request) {
+            Ok(Response::Failed { reason }) => Err(sandbox::Error::Component { reason }),
+            Ok(response) => Ok(response),
+            Err(reason) => Err(self.crashed(reason)),
+        }
+    }
+
+    fn call_done(&mut self, request: &Request) -> Result<(), sandbox::Error> {
+        match self.call(request)? {
+            Response::Done => Ok(()),
+            _ => Err(sandbox::Error::UnexpectedResponse { expected: "Done" }),
+        }
+    }
+
+    fn load(
+        &mut self,
+        kind: ComponentKind,
+        lib_path: PathBuf,
+        component: ComponentVersion,
+        fresh: bool,
+        sha256: Option<String>,
+    ) -> Result<u64, sandbox::Error> {
+        let component_id = self.next_id();
+
+        self.call_done(&Request::Load {
+            component_id,
+            kind,
+            lib_path: lib_path.clone(),
+            component: component.clone(),
+            fresh,
+            sha256: sha256.clone(),
+        })?;
+
+        self.components.insert(
+            component_id,
+            LoadedComponent {
+                kind,
+                lib_path,
+                component,
+                sha256,
+            },
+        );
+
+        Ok(component_id)
+    }
+
+    /// recreate the instance with its default value if it is lost,
+    /// returns true if it is recreated
+    fn ensure_live(&mut self, instance: u64) -> Result<bool, sandbox::Error> {
+        let component_id = match self.instances.get(&instance) {
+            Some(entry) if entry.live => return Ok(false),
+            Some(entry) => entry.component_id,
+            None => {
+                return Err(sandbox::Error::Component {
+                    reason: format!("no instance {instance}"),
+                });
+            }
+        };
+
+        self.call_done(&Request::Default {
+            component_id,
+            instance,
+        })?;
+
+        if let Some(entry) = self.instances.get_mut(&instance) {
+            entry.live = true;
+        }
+
+        Ok(true)
+    }
+}
+
+impl SandboxHost {
+    /// requests to the helper are sent one at a time
+    fn lock(&self) -> MutexGuard<'_, HostState> {
+        // a panic while holding the lock leaves the state usable,
+        // at worst the helper is restarted
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// the helper is not started until the first request
+    pub fn new(command: SandboxCommand) -> Self {
+        Self {
+            state: Mutex::new(HostState {
+                command,
+                helper: None,
+                next_id: 0,
+                components: HashMap::new(),
+                borrowed_data: HashMap::new(),
+                instances: HashMap::new(),
+            }),
+        }
+    }
+
+    /// load a gate or data type in the helper
+    ///
+    /// fresh loads the current content of the file even if the library is already loaded
+    /// and checks it against sha256, see LibraryHandle::reload
+    pub fn load(
+        self: &Arc<Self>,
+        kind: ComponentKind,
+        lib_path: PathBuf,
+        component: ComponentVersion,
+        fresh: bool,
+        sha256: Option<String>,
+    ) -> Result<SandboxedComponent, sandbox::Error> {
+        let component_id =
+            self.lock()
+                .load(kind, lib_path.clone(), component, fresh, sha256.clone())?;
+
+        Ok(SandboxedComponent {
+            host: self.clone(),
+            component_id,
+            kind,
+            lib_path,
+            sha256,
+        })
+    }
+
+    /// id of a data type in the helper, so sandboxed gates can consume or produce it
+    ///
+    /// data types running outside this helper are loaded in the helper as well
+    pub fn data_component_id(
+        self: &Arc<Self>,
+        data: &DestructedData,
+    ) -> Result<u64, sandbox::Error> {
+        if let Some(sandboxed) = data.get_sandboxed()
+            && Arc::ptr_eq(&sandboxed.host, self)
+        {
+            return Ok(sandboxed.component_id);
+        }
+
+        if data.is_wasm() {
+            return Err(sandbox::Error::Component {
+                reason: format!(
+                    "{} is a wasm module, it cannot be loaded in the helper",
+                    data.id()
+                ),
+            });
+        }
+
+        let lib_path = data
+            .get_lib_path()
+            .ok_or_else(|| sandbox::Error::Component {
+                reason: format!("{} is native, it cannot be loaded in the helper", data.id()),
+            })?;
+
+        let key = (lib_path.to_path_buf(), data.id().clone());
+        let mut state = self.lock();
+
+        if let Some(component_id) = state.borrowed_data.get(&key) {
+            return Ok(*component_id);
+        }
+
+        let component_id = state.load(
+            ComponentKind::Data,
+            key.0.clone(),
+            key.1.clone(),
+            false,
+            None,
+        )?;
+        state.borrowed_data.insert(key, component_id);
+        Ok(component_id)
+    }
+}
+
+impl SandboxedComponent {
+    pub fn get_host(&self) -> &Arc<SandboxHost> {
+        &self.host
+    }
+
+    pub fn get_lib_path(&self) -> &Path {
+        &self.lib_path
+    }
+
+    /// load the library of the component in the helper again, e.g. after it is rebuilt
+    ///
+    /// the helper checks the reloaded library against the same sha256 digest
+    pub fn reload(&self, component: &ComponentVersion) -> Result<Self, sandbox::Error> {
+        self.host.load(
+            self.kind,
+            self.lib_path.clone(),
+            component.clone(),
+            true,
+            self.sha256.clone(),
+        )
+    }
+
+    /// the returned instance is recreated when it is next used if the helper cannot create it
+    pub fn default_value(&self) -> *mut c_void {
+        let mut state = self.host.lock();
+        let instance = state.next_id();
+
+        let live = state
+            .call_done(&Request::Default {
+                component_id: self.component_id,
+                instance,
+            })
+            .is_ok();
+
+        state.instances.insert(
+            instance,
+            InstanceEntry {
+                component_id: self.component_id,
+                live,
+            },
+        );
+
+        to_ptr(instance)
+    }
+
+    /// None if the component rejects the bytes or the helper crashed
+    pub fn deserialize(&self, bytes: Vec<u8>) -> Option<*mut c_void> {
+        let mut state = self.host.lock();
+        let instance = state.next_id();
+
+        state
+            .call_done(&Request::Deserialize {
+                component_id: self.component_id,
+                instance,
+                bytes,
+            })
+            .ok()?;
+
+        state.instances.insert(
+            instance,
+            InstanceEntry {
+                component_id: self.component_id,
+                live: true,
+            },
+        );
+
+        Some(to_ptr(instance))
+    }
+
+    /// empty if the helper cannot serialize the instance
+    pub fn serialize(&self, ptr: *const c_void) -> Vec<u8> {
+        let instance = from_ptr(ptr);
+        let mut state = self.host.lock();
+
+        if state.ensure_live(instance).is_err() {
+            return Vec::new();
+        }
+
+        match state.call(&Request::Serialize { instance }) {
+            Ok(Response::Bytes { bytes }) => bytes,
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn drop_mem(&self, ptr: *mut c_void) {
+        let instance = from_ptr(ptr);
+        let mut state = self.host.lock();
+
+        if let Some(entry) = state.instances.remove(&instance)
+            && entry.live
+            && state.helper.is_some()
+        {
+            let _ = state.call(&Request::Drop { instance });
+        }
+    }
+
+    pub fn definition(
+        &self,
+        ptr: *const c_void,
+    ) -> Result<DestructedGateDefinition, sandbox::Error> {
+        let instance = from_ptr(ptr);
+        let mut state = self.host.lock();
+        state.ensure_live(instance)?;
+
+        match state.call(&Request::Definition { instance })? {
+            Response::Definition { definition } => Ok(definition.into()),
+            _ => Err(sandbox::Error::UnexpectedResponse {
+                expected: "Definition",
+            }),
+        }
+    }
+
+    /// consumers are (data component id, serialized data),
+    /// see SandboxHost::data_component_id
+    ///
+    /// a gate lost in a crash is reset instead of ticked, and returns InstanceReset
+    pub fn tick(
+        &self,
+        ptr: *mut c_void,
+        consumers: Vec<(u64, Vec<u8>)>,
+        producers: Vec<u64>,
+    ) -> Result<Vec<Vec<u8>>, sandbox::Error> {
+        let instance = from_ptr(ptr);
+        let mut state = self.host.lock();
+
+        if state.ensure_live(instance)? {
+            return Err(sandbox::Error::InstanceReset { instance });
+        }
+
+        match state.call(&Request::Tick {
+            instance,
+            consumers,
+            producers,
+        })? {
+            Response::Producers { producers } => Ok(producers),
+            _ => Err(sandbox::Error::UnexpectedResponse {
+                expected: "Producers",
+            }),
+        }
+    }
+}
+
+impl Drop for SandboxedComponent {
+    fn drop(&mut self) {
+        let mut state = self.host.lock();
+        state.components.remove(&self.component_id);
+
+        if state.helper.is_some() {
+            let _ = state.call(&Request::Unload {
+                component_id: self.component_id,
+            });
+        }
+    }
+}
+
+impl Drop for HostState {
+    fn drop(&mut self) {
+        if let Some(mut helper) = self.helper.take() {
+            // closing stdin stops the helper, unless it is stuck (e.g. in a gate tick),
+            // then it is killed after the response timeout
+            drop(helper.stdin);
+
+            let deadline = Instant::now() + helper.timeout;
+            while let Ok(None) = helper.child.try_wait() {
+                if Instant::now() >= deadline {
+                    let _ = helper.child.kill();
+                    break;
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+            let _ = helper.child.wait();
+        }
+    }
+}
@@ -0,0 +1,33 @@
+//! The sandbox module
+//! - Runs component libraries in a child helper process, so a crashing component
+//!   does not take the server down with it.
+//! - The host talks to the helper over its stdin/stdout, one JSON message per line.
+//!   Components in the helper print to stderr instead, see run_helper.
+//!
+//! the helper is the xdsim executable started with HELPER_ARG,
+//! main() hands the process over to run_helper when it sees it
+//!
+//! gates and data in the helper are referred to by ids chosen by the host,
+//! the host passes the ids around as gate and data pointers, they are never dereferenced.
+//! nothing but serialized bytes crosses the process boundary, so sandboxed gates are ticked
+//! with DestructedGate::tick_serialized instead of tick
+//!
+//! if the helper dies or does not respond in time (see SandboxCommand::timeout),
+//! the call that was running returns Error::Crashed,
+//! the helper is restarted on the next call with all components loaded again.
+//! gates and data that lived in the old helper are recreated with their default value
+//! when they are next used
+
+mod error;
+pub use error::Error;
+
+mod helper;
+pub use helper::{run_helper, serve};
+
+mod host;
+pub use host::{SandboxCommand, SandboxHost, SandboxedComponent};
+
+pub mod protocol;
+
+/// first argument of the xdsim executable that makes it run as a sandbox helper
+pub const HELPER_ARG: &str = "sandbox-helper";
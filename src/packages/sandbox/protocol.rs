@@ -0,0 +1,169 @@
+//! Messages between the sandbox host and the helper,
+//! each message is a single line of JSON
+//!
+//! component and instance ids are chosen by the host
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    common::world::{BoundingBox, ComponentVersion, ComponentVersionReq},
+    packages::destructor::{
+        DestructedGateConsumerEntry, DestructedGateDefinition, DestructedGateProducerEntry,
+    },
+};
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ComponentKind {
+    Gate,
+    Data,
+}
+
+/// host -> helper
+#[derive(Serialize, Deserialize, Debug)]
+pub enum Request {
+    /// load a library and destruct a component from it,
+    /// fresh loads the current content of the file even if the library is already loaded
+    /// and checks it against sha256 (the host checks the first load)
+    Load {
+        component_id: u64,
+        kind: ComponentKind,
+        lib_path: PathBuf,
+        component: ComponentVersion,
+        fresh: bool,
+        sha256: Option<String>,
+    },
+    /// the component will no longer be used
+    Unload {
+        component_id: u64,
+    },
+    /// create an instance with the default value of the component
+    Default {
+        component_id: u64,
+        instance: u64,
+    },
+    /// create an instance from bytes
+    Deserialize {
+        component_id: u64,
+        instance: u64,
+        bytes: Vec<u8>,
+    },
+    Serialize {
+        instance: u64,
+    },
+    Drop {
+        instance: u64,
+    },
+    /// normalised definition of a gate instance
+    Definition {
+        instance: u64,
+    },
+    /// tick a gate instance, consumers are (data component id, serialized data),
+    /// producers are the data component ids of the producers in definition order
+    Tick {
+        instance: u64,
+        consumers: Vec<(u64, Vec<u8>)>,
+        producers: Vec<u64>,
+    },
+}
+
+/// helper -> host
+#[derive(Serialize, Deserialize, Debug)]
+pub enum Response {
+    Done,
+    /// the request cannot be completed, the helper is still usable
+    Failed {
+        reason: String,
+    },
+    Bytes {
+        bytes: Vec<u8>,
+    },
+    Definition {
+        definition: GateDefinition,
+    },
+    /// serialized producers in definition order
+    Producers {
+        producers: Vec<Vec<u8>>,
+    },
+}
+
+/// DestructedGateDefinition in a form that can be sent
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GateDefinition {
+    pub consumers: Vec<GateConsumerEntry>,
+    pub producers: Vec<GateProducerEntry>,
+    pub bounding_box: BoundingBox,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GateConsumerEntry {
+    pub name: String,
+    pub data_type_req: ComponentVersionReq,
+    pub position: (f64, f64),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GateProducerEntry {
+    pub name: String,
+    pub data_type: ComponentVersion,
+    pub position: (f64, f64),
+}
+
+impl From<DestructedGateDefinition> for GateDefinition {
+    fn from(value: DestructedGateDefinition) -> Self {
+        Self {
+            consumers: value
+                .consumers
+                .into_iter()
+                .map(|entry| GateConsumerEntry {
+                    name: entry.name,
+                    data_type_req: entry.data_type_req,
+                    position: (entry.position.x, entry.position.y),
+                })
+                .collect(),
+            producers: value
+                .producers
+                .into_iter()
+                .map(|entry| GateProducerEntry {
+                    name: entry.name,
+                    data_type: entry.data_type,
+                    position: (entry.position.x, entry.position.y),
+                })
+                .collect(),
+            bounding_box: value.bounding_box,
+        }
+    }
+}
+
+impl From<GateDefinition> for DestructedGateDefinition {
+    fn from(value: GateDefinition) -> Self {
+        Self {
+            consumers: value
+                .consumers
+                .into_iter()
+                .map(|entry| DestructedGateConsumerEntry {
+                    name: entry.name,
+                    data_type_req: entry.data_type_req,
+                    position: xdsim_cbinds::common::Vec2 {
+                        x: entry.position.0,
+                        y: entry.position.1,
+                    },
+                })
+                .collect(),
+            producers: value
+                .producers
+                .into_iter()
+                .map(|entry| DestructedGateProducerEntry {
+                    name: entry.name,
+                    data_type: entry.data_type,
+                    position: xdsim_cbinds::common::Vec2 {
+                        x: entry.position.0,
+                        y: entry.position.1,
+                    },
+                })
+                .collect(),
+            bounding_box: value.bounding_box,
+        }
+    }
+}
@@ -0,0 +1,24 @@
+use crate::packages::destructor::NativeData;
+
+/// a single boolean, serialized as one byte that is 0 or 1
+pub struct Bool;
+
+impl NativeData for Bool {
+    type Data = bool;
+
+    fn default_value(&self) -> bool {
+        false
+    }
+
+    fn serialize(&self, data: &bool) -> Vec<u8> {
+        vec![*data as u8]
+    }
+
+    fn deserialize(&self, bytes: &[u8]) -> Option<bool> {
+        match bytes {
+            [0] => Some(false),
+            [1] => Some(true),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,183 @@
+use semver::VersionReq;
+use xdsim_cbinds::common::{BoundingBox, Vec2};
+
+use crate::{
+    common::world::{ComponentVersionReq, DataPtr, DataPtrMut},
+    packages::{
+        destructor::{
+            DestructedGateConsumerEntry, DestructedGateDefinition, DestructedGateProducerEntry,
+            NativeGate, native_from_ptr, native_into_ptr,
+        },
+        stdlib,
+    },
+};
+
+/// a gate without state, producing a single bool "out" from its bool consumers
+pub struct LogicGate {
+    consumers: &'static [&'static str],
+    op: fn(&[bool]) -> bool,
+}
+
+impl LogicGate {
+    pub fn not() -> Self {
+        Self {
+            consumers: &["in"],
+            op: |inputs| !inputs[0],
+        }
+    }
+
+    pub fn and() -> Self {
+        Self {
+            consumers: &["a", "b"],
+            op: |inputs| inputs[0] && inputs[1],
+        }
+    }
+
+    pub fn or() -> Self {
+        Self {
+            consumers: &["a", "b"],
+            op: |inputs| inputs[0] || inputs[1],
+        }
+    }
+
+    pub fn xor() -> Self {
+        Self {
+            consumers: &["a", "b"],
+            op: |inputs| inputs[0] ^ inputs[1],
+        }
+    }
+}
+
+// tick returns a single bool from native_into_ptr, "out" is a stdlib bool
+unsafe impl NativeGate for LogicGate {
+    /// logic gates have no state, the byte is unused,
+    /// it is only there so each gate has its own pointer (see NativeGate::Gate)
+    type Gate = u8;
+
+    fn default_value(&self) -> u8 {
+        0
+    }
+
+    fn serialize(&self, _: &u8) -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn deserialize(&self, bytes: &[u8]) -> Option<u8> {
+        bytes.is_empty().then_some(0)
+    }
+
+    fn definition(&self, _: &u8) -> DestructedGateDefinition {
+        definition(self.consumers, &["out"])
+    }
+
+    fn tick(&self, _: &mut u8, consumers: &[DataPtr]) -> Vec<DataPtrMut> {
+        let inputs = consumers
+            .iter()
+            .map(|&data| read_bool(data))
+            .collect::<Vec<_>>();
+        vec![native_into_ptr((self.op)(&inputs))]
+    }
+}
+
+/// a D flip-flop, "q" takes the value of "d" when "clk" goes from false to true
+pub struct FlipFlop;
+
+/// state of a FlipFlop, serialized as [q, clk]
+pub struct FlipFlopState {
+    q: bool,
+    /// clk in the previous tick, to find the rising edge
+    clk: bool,
+}
+
+// tick returns a single bool from native_into_ptr, "q" is a stdlib bool
+unsafe impl NativeGate for FlipFlop {
+    type Gate = FlipFlopState;
+
+    fn default_value(&self) -> FlipFlopState {
+        FlipFlopState {
+            q: false,
+            clk: false,
+        }
+    }
+
+    fn serialize(&self, gate: &FlipFlopState) -> Vec<u8> {
+        vec![gate.q as u8, gate.clk as u8]
+    }
+
+    fn deserialize(&self, bytes: &[u8]) -> Option<FlipFlopState> {
+        match bytes {
+            &[q @ (0 | 1), clk @ (0 | 1)] => Some(FlipFlopState {
+                q: q == 1,
+                clk: clk == 1,
+            }),
+            _ => None,
+        }
+    }
+
+    fn definition(&self, _: &FlipFlopState) -> DestructedGateDefinition {
+        definition(&["d", "clk"], &["q"])
+    }
+
+    fn tick(&self, gate: &mut FlipFlopState, consumers: &[DataPtr]) -> Vec<DataPtrMut> {
+        let d = read_bool(consumers[0]);
+        let clk = read_bool(consumers[1]);
+
+        if clk && !gate.clk {
+            gate.q = d;
+        }
+        gate.clk = clk;
+
+        vec![native_into_ptr(gate.q)]
+    }
+}
+
+fn read_bool(data: DataPtr) -> bool {
+    // every consumer requests stdlib bool, which only Bool provides
+    unsafe { *native_from_ptr::<bool>(data) }
+}
+
+/// consumers on the left edge and producers on the right edge, all of them bool
+fn definition(consumers: &[&str], producers: &[&str]) -> DestructedGateDefinition {
+    fn y(index: usize, count: usize) -> f64 {
+        (index as f64 + 1.0) / (count as f64 + 1.0) * 2.0 - 1.0
+    }
+
+    DestructedGateDefinition {
+        consumers: consumers
+            .iter()
+            .enumerate()
+            .map(|(index, name)| DestructedGateConsumerEntry {
+                name: name.to_string(),
+                data_type_req: ComponentVersionReq {
+                    package: stdlib::PACKAGE.to_string(),
+                    version_req: VersionReq::parse(&format!("={}", stdlib::VERSION))
+                        .expect("VERSION is valid"),
+                    component: "bool".to_string(),
+                },
+                position: Vec2 {
+                    x: -1.0,
+                    y: y(index, consumers.len()),
+                },
+            })
+            .collect(),
+        producers: producers
+            .iter()
+            .enumerate()
+            .map(|(index, name)| DestructedGateProducerEntry {
+                name: name.to_string(),
+                data_type: stdlib::component("bool"),
+                position: Vec2 {
+                    x: 1.0,
+                    y: y(index, producers.len()),
+                },
+            })
+            .collect(),
+        bounding_box: BoundingBox {
+            top: 1.0,
+            bottom: -1.0,
+            left: -1.0,
+            right: 1.0,
+        }
+        .into(),
+    }
+}
@@ -0,0 +1,90 @@
+//! The stdlib module
+//! - A package of gates and data types implemented in rust, see NativeGate and NativeData.
+//! - Registered without any files on disk, so a world works without installing packages.
+//!
+//! the package is named PACKAGE at VERSION, it provides:
+//! - bool: a single boolean data type
+//! - not, and, or, xor: logic gates producing a bool named "out"
+//! - dff: a D flip-flop, latching "d" to "q" on the rising edge of "clk"
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
+
+use semver::Version;
+
+use crate::{
+    common::world::ComponentVersion,
+    packages::destructor::{DestructedData, DestructedGate},
+};
+
+mod data;
+pub use data::Bool;
+
+mod gates;
+pub use gates::{FlipFlop, LogicGate};
+
+/// name of the package, packages in the index should not use it
+/// (their handles would replace the stdlib ones when registered in the same world)
+pub const PACKAGE: &str = "std";
+
+/// version of the package
+pub const VERSION: &str = "0.1.0";
+
+type DestructedGateHandles =
+    HashMap<String, BTreeMap<Version, HashMap<String, Arc<DestructedGate>>>>;
+type DestructedDataHandles =
+    HashMap<String, BTreeMap<Version, HashMap<String, Arc<DestructedData>>>>;
+
+/// id of a component in the package
+pub fn component(name: &str) -> ComponentVersion {
+    ComponentVersion {
+        package: PACKAGE.to_string(),
+        version: Version::parse(VERSION).expect("VERSION is valid"),
+        component: name.to_string(),
+    }
+}
+
+/// handles of all gate types in the package
+pub fn gate_handles() -> DestructedGateHandles {
+    let gates = [
+        DestructedGate::new_native(component("not"), LogicGate::not()),
+        DestructedGate::new_native(component("and"), LogicGate::and()),
+        DestructedGate::new_native(component("or"), LogicGate::or()),
+        DestructedGate::new_native(component("xor"), LogicGate::xor()),
+        DestructedGate::new_native(component("dff"), FlipFlop),
+    ];
+
+    package_handles(
+        gates
+            .into_iter()
+            .map(|gate| (gate.id().component.clone(), gate)),
+    )
+}
+
+/// handles of all data types in the package
+pub fn data_handles() -> DestructedDataHandles {
+    let data = [DestructedData::new_native(component("bool"), Bool)];
+
+    package_handles(
+        data.into_iter()
+            .map(|data| (data.id().component.clone(), data)),
+    )
+}
+
+fn package_handles<T>(
+    components: impl Iterator<Item = (String, T)>,
+) -> HashMap<String, BTreeMap<Version, HashMap<String, Arc<T>>>> {
+    let components = components
+        .map(|(name, handle)| (name, Arc::new(handle)))
+        .collect();
+
+    HashMap::from([(
+        PACKAGE.to_string(),
+        BTreeMap::from([(
+            Version::parse(VERSION).expect("VERSION is valid"),
+            components,
+        )]),
+    )])
+}
@@ -4,6 +4,7 @@ use tokio::sync::oneshot;
 
 use crate::{
     common::world::ComponentId,
+    packages::indexer::component::PackageIndex,
     world::{MasterWorld, user::ConnectRequest},
 };
 
@@ -11,6 +12,8 @@ use crate::{
 pub struct ServerWorld {
     world: MasterWorld,
     rx: mpsc::Receiver<WorldRequest>,
+    /// index the world's packages are loaded from, used by WorldRequest::RefreshPackages
+    index: Option<PackageIndex>,
 }
 
 /// response when a server world is created
@@ -24,11 +27,20 @@ impl ServerWorld {
     pub fn with_world(world: MasterWorld) -> CreatedServerWorld {
         let (tx, rx) = mpsc::channel();
         CreatedServerWorld {
-            world: Self { world, rx },
+            world: Self {
+                world,
+                rx,
+                index: None,
+            },
             sender: tx,
         }
     }
 
+    /// set the index used to pick up packages installed or removed while the server is running
+    pub fn set_index(&mut self, index: PackageIndex) {
+        self.index = Some(index);
+    }
+
     /// start the world as a server
     pub fn run_blocking(&mut self) {
         while let Ok(request) = self.rx.recv() {
@@ -39,6 +51,14 @@ impl ServerWorld {
                         Err(e) => responses::PlayerConnect::Rejected(e),
                     });
                 }
+                WorldRequest::RefreshPackages { res } => {
+                    let _ = res.send(match &mut self.index {
+                        Some(index) => responses::RefreshPackages::Refreshed(
+                            self.world.refresh_packages(index),
+                        ),
+                        None => responses::RefreshPackages::NoIndex,
+                    });
+                }
             }
         }
     }
@@ -52,15 +72,32 @@ pub enum WorldRequest {
         /// response hook
         res: oneshot::Sender<responses::PlayerConnect>,
     },
+    /// rescan the roots of the server's index and apply the changes to the world,
+    /// can be sent periodically to poll for installed or removed packages
+    RefreshPackages {
+        /// response hook
+        res: oneshot::Sender<responses::RefreshPackages>,
+    },
 }
 
 /// responses from the server
 pub mod responses {
-    use crate::{common::world::ComponentId, world::WorldError};
+    use crate::{
+        common::world::ComponentId,
+        world::{RefreshedPackages, WorldError},
+    };
 
     pub enum PlayerConnect {
         /// connection accepted, and returns the user id of the player
         Accepted(ComponentId),
         Rejected(Box<WorldError>),
     }
+
+    pub enum RefreshPackages {
+        /// the server has no index, see ServerWorld::set_index
+        NoIndex,
+        /// the index and the world are up to date with the roots,
+        /// see MasterWorld::refresh_packages
+        Refreshed(RefreshedPackages),
+    }
 }
@@ -0,0 +1,100 @@
+use std::fs;
+
+use semver::Version;
+
+use crate::{
+    packages::indexer::{
+        self,
+        archive::{PackageArchive, install, uninstall},
+        component::PackageIndexBuilder,
+    },
+    tests::packages::temp_root::TempRoot,
+};
+
+#[test]
+fn archive_install_uninstall() {
+    let source = TempRoot::new("archive-source");
+    let version_root = source.add(
+        "logic",
+        "0.2.0",
+        "[dependencies]\n\n[provides]\nnot = \"gate\"\n",
+    );
+    fs::write(version_root.join("not.so"), b"not a real library").unwrap();
+
+    let archive_path = source.path().join("logic-0.2.0.xdpkg");
+    PackageArchive::from_dir(&version_root)
+        .unwrap()
+        .write(&archive_path)
+        .unwrap();
+
+    let root = TempRoot::new("archive-root");
+    let (mut index, res) = PackageIndexBuilder::new()
+        .add_roots(&[root.path().to_path_buf()])
+        .build();
+    res.unwrap();
+    assert!(index.get_package("logic").is_none());
+
+    let (name, version) = install(&mut index, &archive_path, root.path()).unwrap();
+    assert_eq!(name, "logic");
+    assert_eq!(version, Version::parse("0.2.0").unwrap());
+    assert_eq!(
+        fs::read(root.path().join("logic/0.2.0/not.so")).unwrap(),
+        b"not a real library"
+    );
+    assert!(
+        index
+            .get_package("logic")
+            .unwrap()
+            .get_version(&version)
+            .is_some()
+    );
+
+    match install(&mut index, &archive_path, root.path()) {
+        Err(indexer::Error::AlreadyInstalled { .. }) => {}
+        other => panic!("expected already installed, got {other:?}"),
+    }
+
+    uninstall(&mut index, root.path(), "logic", &version).unwrap();
+    assert!(index.get_package("logic").is_none());
+    assert!(!root.path().join("logic").exists());
+}
+
+#[test]
+fn archive_rejects_missing_library() {
+    let source = TempRoot::new("archive-invalid");
+    let version_root = source.add(
+        "logic",
+        "0.2.0",
+        "[dependencies]\n\n[provides]\nnot = \"gate\"\n",
+    );
+
+    match PackageArchive::from_dir(&version_root) {
+        Err(indexer::Error::ArchiveInvalid { reason, .. }) => {
+            assert!(reason.contains("not"), "{reason}")
+        }
+        Err(e) => panic!("expected invalid archive, got {e:?}"),
+        Ok(_) => panic!("expected invalid archive"),
+    }
+}
+
+#[test]
+fn uninstall_rejects_escaping_name() {
+    let root = TempRoot::new("archive-escape");
+    let outside = TempRoot::new("archive-outside");
+    outside.add("logic", "0.2.0", "[dependencies]\n\n[provides]\n");
+
+    let (mut index, res) = PackageIndexBuilder::new()
+        .add_roots(&[root.path().to_path_buf()])
+        .build();
+    res.unwrap();
+
+    let escaping = format!(
+        "../{}/logic",
+        outside.path().file_name().unwrap().to_string_lossy()
+    );
+    match uninstall(&mut index, root.path(), &escaping, &Version::new(0, 2, 0)) {
+        Err(indexer::Error::InvalidPackageName { name }) => assert_eq!(name, escaping),
+        other => panic!("expected invalid package name, got {other:?}"),
+    }
+    assert!(outside.path().join("logic/0.2.0/package.toml").is_file());
+}
@@ -0,0 +1,143 @@
+use xdsim_cbinds::common::Slice;
+
+use crate::{
+    common::world::{DataPtr, DataPtrMut},
+    packages::{
+        chelper::slice,
+        destructor::{self, Capabilities},
+        stdlib,
+    },
+    tests::packages::fake_library,
+};
+
+fn consumer_ptrs(consumers: &[Slice]) -> Vec<*const Slice> {
+    consumers
+        .iter()
+        .map(|consumer| consumer as *const Slice)
+        .collect()
+}
+
+#[test]
+fn unknown_capabilities_are_dropped() {
+    let capabilities = Capabilities::from_bits(u64::MAX);
+    assert_eq!(capabilities, Capabilities::ALL);
+    assert!(capabilities.contains(Capabilities::BATCH_TICK | Capabilities::INTERACT));
+
+    let capabilities = Capabilities::from_bits(Capabilities::PROPS.bits() | 1 << 40);
+    assert_eq!(capabilities, Capabilities::PROPS);
+    assert!(!capabilities.contains(Capabilities::PROPS | Capabilities::DRAW));
+    assert!(capabilities.contains(Capabilities::NONE));
+}
+
+#[test]
+fn batch_tick_falls_back_to_tick() {
+    let data = stdlib::data_handles();
+    let gates = stdlib::gate_handles();
+    let version = stdlib::component("bool").version;
+    let bool_type = &data[stdlib::PACKAGE][&version]["bool"];
+    let not = &gates[stdlib::PACKAGE][&version]["not"];
+
+    // native gates have no library to report optional entry points
+    assert_eq!(not.capabilities(), Capabilities::NONE);
+
+    let value = |bit: u8| {
+        bool_type
+            .deserialize(&slice::from_vec_rustonly(vec![bit]))
+            .unwrap()
+    };
+    let (low, high) = (value(0), value(1));
+
+    let instances = [not.default_value().unwrap(), not.default_value().unwrap()];
+    assert_eq!(not.interact(instances[0], &[]), None);
+
+    let consumers = [
+        slice::from_vec_rustonly(vec![low as DataPtr]),
+        slice::from_vec_rustonly(vec![high as DataPtr]),
+    ];
+    let produced = not.tick_batch(&instances, &consumer_ptrs(&consumers));
+    let produced = slice::from_slice::<Slice>(&produced);
+    assert_eq!(produced.len(), 2);
+
+    let bytes: Vec<Vec<u8>> = produced
+        .iter()
+        .map(|producers| {
+            let producers = slice::from_slice::<DataPtrMut>(producers);
+            assert_eq!(producers.len(), 1);
+            let bytes = bool_type.to_bytes(producers[0]);
+            bool_type.drop_mem(producers[0]);
+            bytes
+        })
+        .collect();
+    assert_eq!(bytes, vec![vec![1], vec![0]]);
+
+    for gate in instances {
+        not.drop_mem(gate);
+    }
+    bool_type.drop_mem(low);
+    bool_type.drop_mem(high);
+}
+
+#[test]
+fn v1_gate_from_symbols() {
+    let not = fake_library::destruct(fake_library::symbols()).unwrap();
+    assert_eq!(not.capabilities(), Capabilities::BATCH_TICK);
+    assert!(not.get_library().is_none());
+
+    let gate = not.default_value().unwrap();
+    let definition = not.normalised_definition(gate).unwrap();
+    assert_eq!(definition.consumers[0].name, "in");
+    assert_eq!(definition.producers[0].data_type, stdlib::component("bool"));
+    not.drop_mem(gate);
+
+    // the library reports batch ticks but does not export them
+    let mut symbols = fake_library::symbols();
+    symbols.remove("gate_tick_batch");
+    match fake_library::destruct(symbols) {
+        Err(destructor::Error::GetSymbol { symbol_name, .. }) => {
+            assert_eq!(symbol_name, "gate_tick_batch")
+        }
+        Err(e) => panic!("expected a missing symbol, got {e:?}"),
+        Ok(_) => panic!("expected a missing symbol"),
+    }
+}
+
+#[test]
+fn batch_tick_through_library() {
+    let data = stdlib::data_handles();
+    let bool_type = &data[stdlib::PACKAGE][&stdlib::component("bool").version]["bool"];
+    let not = fake_library::destruct(fake_library::symbols()).unwrap();
+
+    let value = |bit: u8| {
+        bool_type
+            .deserialize(&slice::from_vec_rustonly(vec![bit]))
+            .unwrap()
+    };
+    let (low, high) = (value(0), value(1));
+
+    let instances = [not.default_value().unwrap(), not.default_value().unwrap()];
+    let consumers = [
+        slice::from_vec_rustonly(vec![low as DataPtr]),
+        slice::from_vec_rustonly(vec![high as DataPtr]),
+    ];
+
+    let produced = not.tick_batch(&instances, &consumer_ptrs(&consumers));
+    let bytes: Vec<Vec<u8>> = slice::from_slice::<Slice>(&produced)
+        .iter()
+        .map(|producers| {
+            let producers = slice::from_slice::<DataPtrMut>(producers);
+            assert_eq!(producers.len(), 1);
+            let bytes = bool_type.to_bytes(producers[0]);
+            bool_type.drop_mem(producers[0]);
+            bytes
+        })
+        .collect();
+    assert_eq!(bytes, vec![vec![1], vec![0]]);
+
+    for gate in instances {
+        let state = fake_library::NotState::from_bytes(slice::from_slice(&not.serialize(gate)));
+        assert_eq!((state.ticks, state.batched), (0, 1));
+        not.drop_mem(gate);
+    }
+    bool_type.drop_mem(low);
+    bool_type.drop_mem(high);
+}
@@ -0,0 +1,314 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use semver::{Version, VersionReq};
+
+use crate::packages::indexer::{
+    self,
+    deps_resolvable::DepsResolvable,
+    deps_resolver::{
+        CyclePolicy, DepsResolveRequest, ResolveOptions, deps_resolver, deps_resolver_traced,
+        deps_resolver_with_options,
+    },
+    resolution_trace::RejectReason,
+};
+
+/// in memory index: name -> version -> [(dependency name, requirement)]
+#[derive(Default)]
+struct MockIndex {
+    packages: HashMap<String, HashMap<Version, Vec<(String, VersionReq)>>>,
+}
+
+impl MockIndex {
+    fn with(mut self, name: &str, version: &str, deps: &[(&str, &str)]) -> Self {
+        self.packages.entry(name.to_string()).or_default().insert(
+            Version::parse(version).unwrap(),
+            deps.iter()
+                .map(|(name, req)| (name.to_string(), VersionReq::parse(req).unwrap()))
+                .collect(),
+        );
+        self
+    }
+}
+
+impl DepsResolvable for MockIndex {
+    fn get_dependencies(&self, name: &str, version: &Version) -> Option<Vec<(&str, &VersionReq)>> {
+        Some(
+            self.packages
+                .get(name)?
+                .get(version)?
+                .iter()
+                .map(|(name, req)| (name.as_str(), req))
+                .collect(),
+        )
+    }
+
+    fn get_versions(&self, name: &str) -> Option<Vec<&Version>> {
+        Some(self.packages.get(name)?.keys().collect())
+    }
+}
+
+fn request(name: &str, req: &str) -> DepsResolveRequest {
+    DepsResolveRequest::new(name.to_string(), VersionReq::parse(req).unwrap())
+}
+
+fn versions(list: &[&str]) -> Vec<Version> {
+    list.iter().map(|v| Version::parse(v).unwrap()).collect()
+}
+
+#[test]
+fn resolver_picks_newest() {
+    let index = MockIndex::default()
+        .with("gates", "0.1.0", &[("logic", "^0.3")])
+        .with("gates", "0.1.4", &[("logic", "^0.3")])
+        .with("gates", "0.1.2", &[("logic", "^0.3")])
+        .with("logic", "0.3.0", &[])
+        .with("logic", "0.3.7", &[])
+        .with("logic", "0.4.0", &[]);
+
+    let resolved = deps_resolver(&index, &[request("gates", "^0.1")]).unwrap();
+
+    assert_eq!(resolved.get("gates"), Some(&versions(&["0.1.4"])));
+    assert_eq!(resolved.get("logic"), Some(&versions(&["0.3.7"])));
+}
+
+#[test]
+fn resolver_backtracks_on_conflict() {
+    // newest gates requires a logic version that does not exist,
+    // the resolver must fall back to gates 0.1.1
+    // logic is unified with the direct request into 0.3.1
+    let index = MockIndex::default()
+        .with("gates", "0.1.1", &[("logic", ">=0.3.0, <0.3.2")])
+        .with("gates", "0.1.2", &[("logic", "^0.3.5")])
+        .with("logic", "0.3.0", &[])
+        .with("logic", "0.3.1", &[])
+        .with("logic", "0.3.3", &[]);
+
+    let resolved = deps_resolver(
+        &index,
+        &[request("logic", "^0.3"), request("gates", "^0.1")],
+    )
+    .unwrap();
+
+    assert_eq!(resolved.get("gates"), Some(&versions(&["0.1.1"])));
+    assert_eq!(resolved.get("logic"), Some(&versions(&["0.3.1"])));
+}
+
+#[test]
+fn resolver_loads_incompatible_versions_side_by_side() {
+    let index = MockIndex::default()
+        .with("old", "1.0.0", &[("logic", "^0.3")])
+        .with("new", "1.0.0", &[("logic", "^0.4")])
+        .with("logic", "0.3.2", &[])
+        .with("logic", "0.4.1", &[]);
+
+    let resolved = deps_resolver(&index, &[request("new", "1"), request("old", "1")]).unwrap();
+
+    assert_eq!(resolved.get("logic"), Some(&versions(&["0.3.2", "0.4.1"])));
+}
+
+#[test]
+fn resolver_reports_missing() {
+    let index = MockIndex::default().with("gates", "0.1.0", &[("logic", "^0.3")]);
+
+    match deps_resolver(&index, &[request("gates", "^0.1")]) {
+        Err(indexer::Error::MissingDependencies { dependency_chains }) => {
+            assert_eq!(
+                dependency_chains,
+                vec![vec![
+                    ("logic".to_string(), VersionReq::parse("^0.3").unwrap()),
+                    ("gates".to_string(), VersionReq::parse("^0.1").unwrap()),
+                ]]
+            );
+        }
+        other => panic!("expected missing dependencies, got {other:?}"),
+    }
+}
+
+#[test]
+fn resolver_reports_cycles() {
+    let index = MockIndex::default()
+        .with("flipflop", "0.1.0", &[("latch", "^0.1")])
+        .with("latch", "0.1.0", &[("flipflop", "^0.1")]);
+    let requests = [request("flipflop", "^0.1")];
+
+    let resolved = deps_resolver_with_options(&index, &requests, &ResolveOptions::new()).unwrap();
+    assert_eq!(resolved.packages["latch"], versions(&["0.1.0"]));
+    assert_eq!(
+        resolved.cycles,
+        vec![vec![
+            ("flipflop".to_string(), Version::parse("0.1.0").unwrap()),
+            ("latch".to_string(), Version::parse("0.1.0").unwrap()),
+            ("flipflop".to_string(), Version::parse("0.1.0").unwrap()),
+        ]]
+    );
+
+    match deps_resolver_with_options(
+        &index,
+        &requests,
+        &ResolveOptions::new().cycle_policy(CyclePolicy::Reject),
+    ) {
+        Err(indexer::Error::DependencyCycles { cycles }) => assert_eq!(cycles, resolved.cycles),
+        other => panic!("expected dependency cycles, got {other:?}"),
+    }
+}
+
+#[test]
+fn resolver_explains_incompatible_cycle() {
+    let index = MockIndex::default()
+        .with("flipflop", "0.1.1", &[("latch", "^0.1")])
+        .with("flipflop", "0.1.0", &[])
+        .with("latch", "0.1.0", &[("flipflop", "=0.1.0")]);
+
+    match deps_resolver(&index, &[request("flipflop", "=0.1.1")]) {
+        Err(indexer::Error::IncompatibleCycle { cycle }) => assert_eq!(
+            cycle,
+            vec![
+                ("flipflop".to_string(), VersionReq::parse("=0.1.0").unwrap()),
+                ("latch".to_string(), VersionReq::parse("^0.1").unwrap()),
+                ("flipflop".to_string(), VersionReq::parse("=0.1.1").unwrap()),
+            ]
+        ),
+        other => panic!("expected an incompatible cycle, got {other:?}"),
+    }
+}
+
+#[test]
+fn resolver_explains_every_incompatible_cycle() {
+    let index = MockIndex::default()
+        .with("flipflop", "0.1.1", &[("latch", "^0.1")])
+        .with("flipflop", "0.1.0", &[])
+        .with("latch", "0.1.0", &[("flipflop", "=0.1.0")])
+        .with("counter", "0.2.1", &[("reg", "^0.1")])
+        .with("counter", "0.2.0", &[])
+        .with("reg", "0.1.0", &[("counter", "=0.2.0")]);
+
+    let requests = [
+        request("flipflop", "=0.1.1"),
+        request("counter", "=0.2.1"),
+        request("clock", "^1"),
+    ];
+
+    match deps_resolver(&index, &requests) {
+        Err(indexer::Error::Resolve { errors }) => match errors.as_slice() {
+            [
+                indexer::Error::IncompatibleCycle { cycle: first },
+                indexer::Error::IncompatibleCycle { cycle: second },
+                indexer::Error::MissingDependencies { dependency_chains },
+            ] => {
+                assert_eq!(first[0].0, "flipflop");
+                assert_eq!(second[0].0, "counter");
+                assert_eq!(
+                    dependency_chains,
+                    &vec![vec![(
+                        "clock".to_string(),
+                        VersionReq::parse("^1").unwrap()
+                    )]]
+                );
+            }
+            other => panic!("expected two cycles and a missing dependency, got {other:?}"),
+        },
+        other => panic!("expected resolve errors, got {other:?}"),
+    }
+}
+
+#[test]
+fn resolver_traces_choices() {
+    let index = MockIndex::default()
+        .with("gates", "0.1.0", &[("logic", "^0.3")])
+        .with("logic", "0.3.0", &[])
+        .with("logic", "0.3.1", &[("power", "^1")]);
+
+    let (trace, res) =
+        deps_resolver_traced(&index, &[request("gates", "^0.1")], &ResolveOptions::new());
+    res.unwrap();
+
+    let chosen = trace
+        .get_chosen("logic", &Version::parse("0.3.0").unwrap())
+        .unwrap();
+    assert_eq!(chosen.required_by.len(), 1);
+    assert_eq!(
+        chosen.required_by[0].parent.as_ref().unwrap().name,
+        "gates".to_string()
+    );
+    assert_eq!(chosen.rejected.len(), 1);
+    assert_eq!(chosen.rejected[0].version, Version::parse("0.3.1").unwrap());
+    assert!(matches!(
+        chosen.rejected[0].reason,
+        RejectReason::Unsatisfiable { .. }
+    ));
+
+    let rendered = trace.to_string();
+    assert!(rendered.contains("logic 0.3.0\n  required by gates 0.1.0 (^0.3)\n"));
+    assert!(rendered.contains("  rejected 0.3.1: cannot satisfy power ^1 <- logic ^0.3"));
+}
+
+#[test]
+fn resolver_handles_large_graphs() {
+    // root -> chain0 -> chain1 -> ... -> chain999, and root -> leaf0..leaf999,
+    // the newest chain999 requires a package that does not exist
+    const SIZE: usize = 1000;
+
+    let chain = |index: usize| format!("chain{index}");
+    let leaf = |index: usize| format!("leaf{index}");
+
+    let root_deps: Vec<(String, &str)> = std::iter::once((chain(0), "^1"))
+        .chain((0..SIZE).map(|index| (leaf(index), "^1")))
+        .collect();
+    let root_deps: Vec<(&str, &str)> = root_deps
+        .iter()
+        .map(|(name, req)| (name.as_str(), *req))
+        .collect();
+
+    let mut index = MockIndex::default().with("root", "1.0.0", &root_deps);
+    for i in 0..SIZE {
+        index = index.with(&leaf(i), "1.0.0", &[]);
+        if i + 1 < SIZE {
+            index = index.with(&chain(i), "1.0.0", &[(&chain(i + 1), "^1")]);
+        }
+    }
+    index = index.with(&chain(SIZE - 1), "1.0.0", &[]).with(
+        &chain(SIZE - 1),
+        "1.0.1",
+        &[("missing", "^1")],
+    );
+
+    let start = Instant::now();
+    let resolved = deps_resolver(&index, &[request("root", "^1")]).unwrap();
+
+    assert!(start.elapsed() < Duration::from_secs(10));
+    assert_eq!(resolved.len(), 2 * SIZE + 1);
+    assert_eq!(resolved.get(&chain(SIZE - 1)), Some(&versions(&["1.0.0"])));
+}
+
+#[test]
+fn resolver_traces_only_the_final_selection() {
+    // app 1.1.0 selects util 1.0.0 after rejecting util 1.1.0, then fails on zmissing,
+    // so util is not part of the result and its rejection is not explained
+    let index = MockIndex::default()
+        .with("app", "1.0.0", &[])
+        .with("app", "1.1.0", &[("util", "^1"), ("zmissing", "^1")])
+        .with("util", "1.0.0", &[])
+        .with("util", "1.1.0", &[("power", "^1")]);
+
+    let (trace, res) =
+        deps_resolver_traced(&index, &[request("app", "^1")], &ResolveOptions::new());
+    assert_eq!(
+        res.unwrap().packages.get("app"),
+        Some(&versions(&["1.0.0"]))
+    );
+
+    match trace.get_rejected() {
+        [rejected] => {
+            assert_eq!(rejected.name, "app");
+            assert_eq!(rejected.version, Version::new(1, 1, 0));
+            assert!(matches!(
+                rejected.reason,
+                RejectReason::Unsatisfiable { .. }
+            ));
+        }
+        other => panic!("expected only app 1.1.0 to be rejected, got {other:?}"),
+    }
+}
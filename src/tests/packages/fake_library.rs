@@ -0,0 +1,221 @@
+//! a schema version 1 not gate on std bool, linked into the tests instead of built as a library,
+//! destructed with DestructRequest::from_symbols
+//!
+//! the gate counts how it is ticked, see NotState
+
+use std::{collections::HashMap, ffi::c_void};
+
+use semver::Version;
+use xdsim_cbinds::{
+    common::{BoundingBox, Slice, Str, Vec2},
+    v0::component::{
+        ComponentIdent, Gate, GateConsumerEntry, GateDefinition, GateMut, GateProducerEntry,
+    },
+};
+
+use crate::{
+    common::world::{ComponentVersion, DataPtr, DataPtrMut},
+    packages::{
+        chelper::slice,
+        destructor::{
+            self, Capabilities, DestructRequest, DestructedGate, native_from_ptr, native_into_ptr,
+        },
+    },
+};
+
+/// state of a gate, serialized as the two counts in little endian
+pub struct NotState {
+    /// ticks through gate_tick
+    pub ticks: u64,
+    /// ticks through gate_tick_batch
+    pub batched: u64,
+}
+
+impl NotState {
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        Self {
+            ticks: u64::from_le_bytes(bytes[..8].try_into().unwrap()),
+            batched: u64::from_le_bytes(bytes[8..16].try_into().unwrap()),
+        }
+    }
+}
+
+pub fn component() -> ComponentVersion {
+    ComponentVersion {
+        package: "fakelogic".to_string(),
+        version: Version::new(0, 1, 0),
+        component: "not".to_string(),
+    }
+}
+
+/// every symbol of the library, remove some to test a library missing them
+pub fn symbols() -> HashMap<String, *const c_void> {
+    let schema_version: fn() -> u32 = schema_version;
+    let capabilities: extern "C" fn() -> u64 = capabilities;
+    let gate_tick: extern "C" fn(GateMut, *const Slice) -> Slice = gate_tick;
+    let gate_def: extern "C" fn(Gate) -> GateDefinition = gate_def;
+    let gate_serialize: extern "C" fn(Gate) -> Slice = gate_serialize;
+    let gate_deserialize: extern "C" fn(*const Slice) -> GateMut = gate_deserialize;
+    let gate_default: extern "C" fn() -> GateMut = gate_default;
+    let gate_drop: extern "C" fn(GateMut) = gate_drop;
+    let gate_tick_batch: extern "C" fn(*const Slice, *const Slice) -> Slice = gate_tick_batch;
+
+    HashMap::from([
+        (
+            "schema_version".to_string(),
+            schema_version as *const c_void,
+        ),
+        ("capabilities".to_string(), capabilities as *const c_void),
+        ("gate_tick".to_string(), gate_tick as *const c_void),
+        ("gate_def".to_string(), gate_def as *const c_void),
+        (
+            "gate_serialize".to_string(),
+            gate_serialize as *const c_void,
+        ),
+        (
+            "gate_deserialize".to_string(),
+            gate_deserialize as *const c_void,
+        ),
+        ("gate_default".to_string(), gate_default as *const c_void),
+        ("gate_drop".to_string(), gate_drop as *const c_void),
+        (
+            "gate_tick_batch".to_string(),
+            gate_tick_batch as *const c_void,
+        ),
+    ])
+}
+
+/// symbols of a library whose gate_tick_batch returns producers for one more gate than it is given
+pub fn extra_batch_symbols() -> HashMap<String, *const c_void> {
+    let gate_tick_batch: extern "C" fn(*const Slice, *const Slice) -> Slice = extra_tick_batch;
+
+    let mut symbols = symbols();
+    symbols.insert(
+        "gate_tick_batch".to_string(),
+        gate_tick_batch as *const c_void,
+    );
+    symbols
+}
+
+pub fn destruct(
+    symbols: HashMap<String, *const c_void>,
+) -> Result<DestructedGate, destructor::Error> {
+    // every symbol is a function of this module with the signature of its name
+    DestructedGate::new(unsafe { DestructRequest::from_symbols(symbols, component()) })
+}
+
+fn schema_version() -> u32 {
+    1
+}
+
+extern "C" fn capabilities() -> u64 {
+    Capabilities::BATCH_TICK.bits()
+}
+
+fn not(consumers: &Slice) -> Slice {
+    let input = unsafe { *native_from_ptr::<bool>(slice::from_slice::<DataPtr>(consumers)[0]) };
+    slice::from_vec_rustonly(vec![native_into_ptr(!input) as DataPtrMut])
+}
+
+fn state<'a>(gate: GateMut) -> &'a mut NotState {
+    unsafe { &mut *(gate as *mut NotState) }
+}
+
+extern "C" fn gate_tick(gate: GateMut, consumers: *const Slice) -> Slice {
+    state(gate).ticks += 1;
+    not(unsafe { &*consumers })
+}
+
+extern "C" fn gate_tick_batch(gates: *const Slice, consumers: *const Slice) -> Slice {
+    let gates = slice::from_slice::<GateMut>(unsafe { &*gates });
+    let consumers = slice::from_slice::<*const Slice>(unsafe { &*consumers });
+
+    slice::from_vec_rustonly(
+        gates
+            .iter()
+            .zip(consumers)
+            .map(|(&gate, &consumers)| {
+                state(gate).batched += 1;
+                not(unsafe { &*consumers })
+            })
+            .collect::<Vec<Slice>>(),
+    )
+}
+
+/// ticks every gate like gate_tick_batch, then returns producers for one more gate
+extern "C" fn extra_tick_batch(gates: *const Slice, consumers: *const Slice) -> Slice {
+    let gates = slice::from_slice::<GateMut>(unsafe { &*gates });
+    let consumers = slice::from_slice::<*const Slice>(unsafe { &*consumers });
+
+    let mut produced: Vec<Slice> = gates
+        .iter()
+        .zip(consumers)
+        .map(|(&gate, &consumers)| {
+            state(gate).batched += 1;
+            not(unsafe { &*consumers })
+        })
+        .collect();
+    produced.push(not(unsafe { &*consumers[0] }));
+    slice::from_vec_rustonly(produced)
+}
+
+extern "C" fn gate_def(_: Gate) -> GateDefinition {
+    let bool_type = |version: &'static std::ffi::CStr| ComponentIdent {
+        package: Str {
+            first: c"std".as_ptr(),
+        },
+        version: Str {
+            first: version.as_ptr(),
+        },
+        component: Str {
+            first: c"bool".as_ptr(),
+        },
+    };
+
+    GateDefinition {
+        consumers: slice::from_vec_rustonly(vec![GateConsumerEntry {
+            name: Str {
+                first: c"in".as_ptr(),
+            },
+            data_type_req: bool_type(c"=0.1.0"),
+            position: Vec2 { x: -1.0, y: 0.0 },
+        }]),
+        producers: slice::from_vec_rustonly(vec![GateProducerEntry {
+            name: Str {
+                first: c"out".as_ptr(),
+            },
+            data_type: bool_type(c"0.1.0"),
+            position: Vec2 { x: 1.0, y: 0.0 },
+        }]),
+        bounding_box: BoundingBox {
+            top: 1.0,
+            bottom: -1.0,
+            left: -1.0,
+            right: 1.0,
+        },
+    }
+}
+
+extern "C" fn gate_serialize(gate: Gate) -> Slice {
+    let state = state(gate as GateMut);
+    slice::from_vec_rustonly([state.ticks.to_le_bytes(), state.batched.to_le_bytes()].concat())
+}
+
+extern "C" fn gate_deserialize(bytes: *const Slice) -> GateMut {
+    let bytes = slice::from_slice::<u8>(unsafe { &*bytes });
+    if bytes.len() != 16 {
+        return std::ptr::null_mut();
+    }
+    native_into_ptr(NotState::from_bytes(bytes))
+}
+
+extern "C" fn gate_default() -> GateMut {
+    native_into_ptr(NotState {
+        ticks: 0,
+        batched: 0,
+    })
+}
+
+extern "C" fn gate_drop(gate: GateMut) {
+    drop(unsafe { Box::from_raw(gate as *mut NotState) });
+}
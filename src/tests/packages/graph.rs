@@ -0,0 +1,59 @@
+use semver::Version;
+
+use crate::{
+    packages::indexer::{component::PackageIndexBuilder, graph::PackageNode},
+    tests::packages::temp_root::TempRoot,
+};
+
+#[test]
+fn reverse_dependencies() {
+    let root = TempRoot::new("graph");
+    root.add_empty("stdlogic", "0.3.0");
+    root.add_empty("stdlogic", "0.3.1");
+    root.add(
+        "arith",
+        "0.1.0",
+        "[dependencies]\nstdlogic = \"=0.3.0\"\n\n[provides]\n",
+    );
+    root.add(
+        "alu",
+        "0.1.0",
+        "[dependencies]\narith = \"^0.1\"\n\n[provides]\n",
+    );
+    root.add(
+        "mux",
+        "0.1.0",
+        "[dependencies]\nstdlogic = \"^0.3\"\nmissing = \"^2\"\n\n[provides]\n",
+    );
+
+    let (index, res) = PackageIndexBuilder::new()
+        .add_roots(&[root.path().to_path_buf()])
+        .build();
+    res.unwrap();
+
+    let node = |name: &str, version: &str| PackageNode {
+        name: name.to_string(),
+        version: Version::parse(version).unwrap(),
+    };
+    let stdlogic = Version::parse("0.3.0").unwrap();
+
+    assert_eq!(
+        index.reverse_dependencies("stdlogic", &stdlogic),
+        vec![node("arith", "0.1.0"), node("mux", "0.1.0")]
+    );
+
+    // mux can fall back to 0.3.1, arith and everything depending on it breaks
+    let graph = index.dependency_graph();
+    assert_eq!(
+        graph.broken_by_removal("stdlogic", &stdlogic),
+        vec![&node("alu", "0.1.0"), &node("arith", "0.1.0")]
+    );
+
+    let dot = graph.to_dot();
+    assert!(dot.contains("\"arith 0.1.0\" -> \"stdlogic 0.3.0\" [label=\"=0.3.0\"];"));
+    assert!(dot.contains("\"mux 0.1.0\" -> \"missing ^2\""));
+
+    let json: serde_json::Value = serde_json::from_str(&graph.to_json()).unwrap();
+    assert_eq!(json["nodes"].as_array().unwrap().len(), 5);
+    assert_eq!(json["unsatisfied"][0]["name"], "missing");
+}
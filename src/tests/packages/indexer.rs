@@ -1,26 +1,69 @@
-use semver::VersionReq;
+use std::{
+    env::consts::{ARCH, OS},
+    fs,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+use semver::{Version, VersionReq};
 
-use crate::packages::indexer::{
-    component::PackageIndexBuilder,
-    deps_resolver::{DepsResolveRequest, deps_resolver},
+use crate::{
+    packages::indexer::{
+        self,
+        component::{
+            HostIncompatibility, IndexEvent, OVERRIDES_FILE_NAME, PackageComponentType,
+            PackageIndexBuilder, PackageQuery,
+        },
+        deps_resolver::{
+            DepsResolveRequest, ResolveOptions, deps_resolver, deps_resolver_traced,
+            deps_resolver_with_options,
+        },
+    },
+    tests::packages::temp_root::TempRoot,
 };
 
 #[test]
 fn index_home() {
+    let root = TempRoot::new("index-home");
+    root.add_empty("testlib", "0.1.0");
+    root.add_empty("logic", "0.1.0");
+    root.add_empty("logic", "0.2.0");
+
     let (index, res) = PackageIndexBuilder::new()
-        .add_roots(&[dirs::data_dir().unwrap().join("xdsim/packages/components/")])
+        .add_roots(&[root.path().to_path_buf()])
         .build();
-
     res.unwrap();
-    dbg!(index);
+
+    let mut packages = index
+        .list_packages()
+        .into_iter()
+        .map(|package| package.get_name())
+        .collect::<Vec<_>>();
+    packages.sort();
+    assert_eq!(packages, ["logic", "testlib"]);
+    assert!(
+        index
+            .get_package("logic")
+            .unwrap()
+            .get_version(&Version::new(0, 2, 0))
+            .is_some()
+    );
 }
 
 #[test]
 fn resolver_single() {
+    let root = TempRoot::new("resolver-single");
+    root.add(
+        "testlib",
+        "0.1.0",
+        "[dependencies]\nlogic = \"^0.1\"\n\n[provides]\n",
+    );
+    root.add_empty("logic", "0.1.0");
+    root.add_empty("logic", "0.1.1");
+
     let (index, res) = PackageIndexBuilder::new()
-        .add_roots(&[dirs::data_dir().unwrap().join("xdsim/packages/components/")])
+        .add_roots(&[root.path().to_path_buf()])
         .build();
-
     res.unwrap();
 
     let to_load = deps_resolver(
@@ -31,5 +74,538 @@ fn resolver_single() {
         )],
     )
     .unwrap();
-    dbg!(to_load);
+    assert_eq!(to_load.len(), 2);
+    assert_eq!(to_load["testlib"], vec![Version::new(0, 1, 0)]);
+    assert_eq!(to_load["logic"], vec![Version::new(0, 1, 1)]);
+}
+
+#[test]
+fn std_package_name_is_reserved() {
+    let root = TempRoot::new("index-reserved");
+    root.add_empty("std", "0.1.0");
+    root.add_empty("logic", "0.1.0");
+
+    let (index, res) = PackageIndexBuilder::new()
+        .add_roots(&[root.path().to_path_buf()])
+        .build();
+
+    match res {
+        Err(indexer::Error::NewIndex { errors }) => match errors.as_slice() {
+            [indexer::Error::ReservedPackageName { name, .. }] => assert_eq!(name, "std"),
+            other => panic!("expected a reserved package name, got {other:?}"),
+        },
+        other => panic!("expected a reserved package name, got {other:?}"),
+    }
+    assert!(index.get_package("std").is_none());
+    assert!(index.get_package("logic").is_some());
+}
+
+#[test]
+fn layered_roots() {
+    let system = TempRoot::new("layered-system");
+    let user = TempRoot::new("layered-user");
+    system.add_empty("logic", "0.1.0");
+    system.add_empty("logic", "0.2.0");
+    user.add_empty("logic", "0.2.0");
+    user.add_empty("logic", "0.3.0");
+
+    let (index, res) = PackageIndexBuilder::new()
+        .add_roots(&[system.path().to_path_buf(), user.path().to_path_buf()])
+        .build();
+    res.unwrap();
+
+    let package = index.get_package("logic").unwrap();
+    assert_eq!(package.list_versions().len(), 3);
+
+    let version_source = |version: &str| {
+        package
+            .get_version_source(&Version::parse(version).unwrap())
+            .unwrap()
+            .to_path_buf()
+    };
+    assert_eq!(version_source("0.1.0"), system.path());
+    assert_eq!(version_source("0.2.0"), user.path());
+    assert_eq!(version_source("0.3.0"), user.path());
+
+    let overridden = package
+        .get_version_entry(&Version::parse("0.2.0").unwrap())
+        .unwrap();
+    assert_eq!(overridden.get_shadowed(), &[system.path().to_path_buf()]);
+}
+
+#[test]
+fn overrides_stay_in_their_root() {
+    let system = TempRoot::new("overrides-system");
+    let user = TempRoot::new("overrides-user");
+    system.add_empty("logic", "0.1.0");
+    system.add_empty("logic", "0.2.0");
+    user.add_empty("logic", "0.2.0");
+    fs::write(
+        system.path().join(OVERRIDES_FILE_NAME),
+        "[logic.\"0.1.0\"]\nyanked = true\n\n[logic.\"0.2.0\"]\nyanked = true\n",
+    )
+    .unwrap();
+
+    let (index, res) = PackageIndexBuilder::new()
+        .add_roots(&[system.path().to_path_buf(), user.path().to_path_buf()])
+        .build();
+    res.unwrap();
+
+    let package = index.get_package("logic").unwrap();
+    let yanked = |version: &str| {
+        package
+            .get_version_entry(&Version::parse(version).unwrap())
+            .unwrap()
+            .get_status()
+            .yanked
+    };
+    assert!(yanked("0.1.0"));
+    // 0.2.0 is indexed from the user root, the system overrides do not apply to it
+    assert!(!yanked("0.2.0"));
+}
+
+#[test]
+fn per_target_libraries() {
+    let root = TempRoot::new("indexer-targets");
+    let version_root = root.add(
+        "logic",
+        "0.1.0",
+        &format!(
+            "[dependencies]\n\n[provides.not]\ntype = \"gate\"\n\n[provides.not.targets]\n{ARCH}-{OS} = \"native/not.lib\"\nriscv64-plan9 = \"riscv/not.lib\"\n"
+        ),
+    );
+    root.add(
+        "logic",
+        "0.2.0",
+        "[dependencies]\n\n[provides.not]\ntype = \"gate\"\n\n[provides.not.targets]\nriscv64-plan9 = \"riscv/not.lib\"\n",
+    );
+
+    let (index, res) = PackageIndexBuilder::new()
+        .add_roots(&[root.path().to_path_buf()])
+        .build();
+
+    match res {
+        Err(indexer::Error::NewIndex { errors }) => match errors.as_slice() {
+            [indexer::Error::MissingTarget { component, .. }] => assert_eq!(component, "not"),
+            other => panic!("expected a missing target, got {other:?}"),
+        },
+        other => panic!("expected a missing target, got {other:?}"),
+    }
+
+    let package = index.get_package("logic").unwrap();
+    assert_eq!(
+        package.list_versions(),
+        vec![&Version::parse("0.1.0").unwrap()]
+    );
+    assert_eq!(
+        package.get_library_path(&Version::parse("0.1.0").unwrap(), "not"),
+        Some(version_root.join("native/not.lib"))
+    );
+}
+
+#[test]
+fn target_paths_stay_in_version_root() {
+    let root = TempRoot::new("indexer-unsafe-targets");
+    root.add(
+        "logic",
+        "0.1.0",
+        &format!(
+            "[dependencies]\n\n[provides.not]\ntype = \"gate\"\n\n[provides.not.targets]\n{ARCH}-{OS} = \"../../other/not.lib\"\n"
+        ),
+    );
+    root.add(
+        "logic",
+        "0.2.0",
+        "[dependencies]\n\n[provides.not]\ntype = \"gate\"\n\n[provides.not.targets]\nriscv64-plan9 = \"/usr/lib/not.lib\"\n",
+    );
+    root.add_empty("logic", "0.3.0");
+
+    let (index, res) = PackageIndexBuilder::new()
+        .add_roots(&[root.path().to_path_buf()])
+        .build();
+
+    match res {
+        Err(indexer::Error::NewIndex { errors }) => {
+            let paths: Vec<_> = errors
+                .iter()
+                .map(|e| match e {
+                    indexer::Error::UnsafeLibraryPath { path, .. } => path.clone(),
+                    other => panic!("expected an unsafe library path, got {other:?}"),
+                })
+                .collect();
+            assert_eq!(paths.len(), 2);
+            assert!(paths.contains(&PathBuf::from("../../other/not.lib")));
+            assert!(paths.contains(&PathBuf::from("/usr/lib/not.lib")));
+        }
+        other => panic!("expected unsafe library paths, got {other:?}"),
+    }
+
+    assert_eq!(
+        index.get_package("logic").unwrap().list_versions(),
+        vec![&Version::new(0, 3, 0)]
+    );
+}
+
+#[test]
+fn misspelled_digest_is_a_parse_error() {
+    let root = TempRoot::new("indexer-misspelled-digest");
+    let provide_path = root
+        .add(
+            "logic",
+            "0.1.0",
+            "[dependencies]\n\n[provides]\nnot = { type = \"gate\", sha265 = \"00\" }\n",
+        )
+        .join("package.toml");
+    let target_path = root
+        .add(
+            "logic",
+            "0.2.0",
+            &format!(
+                "[dependencies]\n\n[provides.not]\ntype = \"gate\"\n\n[provides.not.targets]\n{ARCH}-{OS} = {{ path = \"not.lib\", sha265 = \"00\" }}\n"
+            ),
+        )
+        .join("package.toml");
+    root.add_empty("logic", "0.3.0");
+
+    let (index, res) = PackageIndexBuilder::new()
+        .add_roots(&[root.path().to_path_buf()])
+        .build();
+
+    match res {
+        Err(indexer::Error::NewIndex { errors }) => {
+            let mut paths: Vec<_> = errors
+                .iter()
+                .map(|e| match e {
+                    indexer::Error::ManifestParse { manifest_path, .. } => manifest_path.clone(),
+                    other => panic!("expected a manifest parse error, got {other:?}"),
+                })
+                .collect();
+            paths.sort();
+            assert_eq!(paths, vec![provide_path, target_path]);
+        }
+        other => panic!("expected manifest parse errors, got {other:?}"),
+    }
+    assert_eq!(
+        index.get_package("logic").unwrap().list_versions(),
+        vec![&Version::new(0, 3, 0)]
+    );
+}
+
+#[test]
+fn search_catalog() {
+    let root = TempRoot::new("indexer-search");
+    root.add(
+        "arith",
+        "0.1.0",
+        "description = \"binary arithmetic\"\nkeywords = [\"adder\"]\n\n[dependencies]\n\n[provides]\nfull_adder = { type = \"gate\", description = \"1 bit full adder\" }\nword = \"data\"\n",
+    );
+    root.add(
+        "logic",
+        "0.1.0",
+        "[dependencies]\n\n[provides]\nnot = \"gate\"\nbit = \"data\"\n",
+    );
+
+    let (index, res) = PackageIndexBuilder::new()
+        .add_roots(&[root.path().to_path_buf()])
+        .build();
+    res.unwrap();
+
+    let names = |query: PackageQuery| {
+        index
+            .search(&query)
+            .into_iter()
+            .map(|found| (found.name, found.components))
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        names(PackageQuery::new().keyword("ADDER")),
+        vec![("arith", vec!["full_adder", "word"])]
+    );
+    assert_eq!(
+        names(PackageQuery::new().component_type(PackageComponentType::Data)),
+        vec![("arith", vec!["word"]), ("logic", vec!["bit"])]
+    );
+    assert_eq!(
+        names(
+            PackageQuery::new()
+                .component("no")
+                .component_type(PackageComponentType::Gate)
+        ),
+        vec![("logic", vec!["not"])]
+    );
+    assert!(names(PackageQuery::new().keyword("multiplexer")).is_empty());
+}
+
+#[test]
+fn index_cache() {
+    let root = TempRoot::new("indexer-cache");
+    let cache_root = TempRoot::new("indexer-cache-file");
+    let cache_path = cache_root.path().join("index.toml");
+    let manifest_path = root.add_empty("logic", "0.1.0").join("package.toml");
+    let broken_path = root.add_empty("broken", "0.1.0").join("package.toml");
+    fs::write(&broken_path, "not a manifest").unwrap();
+
+    let build = || {
+        PackageIndexBuilder::new()
+            .with_cache(&cache_path)
+            .add_roots(&[root.path().to_path_buf()])
+            .build()
+    };
+
+    let broken_manifests = |res: Result<(), indexer::Error>| match res {
+        Err(indexer::Error::NewIndex { errors }) => errors
+            .iter()
+            .filter_map(|e| match e {
+                indexer::Error::ManifestParse { manifest_path, .. } => Some(manifest_path.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>(),
+        other => panic!("expected manifest parse errors, got {other:?}"),
+    };
+
+    let reparsed = |res: Result<(), indexer::Error>| {
+        let mut errors = broken_manifests(res);
+        errors.sort();
+        let mut expected = vec![broken_path.clone(), manifest_path.clone()];
+        expected.sort();
+        assert_eq!(errors, expected);
+    };
+
+    let (index, res) = build();
+    assert_eq!(broken_manifests(res), vec![broken_path.clone()]);
+    assert!(index.get_package("logic").is_some());
+    assert!(cache_path.exists());
+
+    // same size and modification time: the cached manifest is used
+    let modified = fs::metadata(&manifest_path).unwrap().modified().unwrap();
+    let content = fs::read(&manifest_path).unwrap();
+    fs::write(&manifest_path, vec![b'#'; content.len()]).unwrap();
+    let file = fs::File::options()
+        .write(true)
+        .open(&manifest_path)
+        .unwrap();
+    file.set_modified(modified).unwrap();
+    drop(file);
+
+    let (index, res) = build();
+    assert_eq!(broken_manifests(res), vec![broken_path.clone()]);
+    assert!(index.get_package("logic").is_some());
+
+    // package.toml edited in place, the package directory did not change:
+    // it is parsed again
+    let file = fs::File::options()
+        .write(true)
+        .open(&manifest_path)
+        .unwrap();
+    file.set_modified(SystemTime::now() + Duration::from_secs(60))
+        .unwrap();
+    drop(file);
+
+    let (index, res) = build();
+    reparsed(res);
+    assert!(index.get_package("logic").is_none());
+
+    // a cache written in another format is discarded
+    let cache = fs::read_to_string(&cache_path).unwrap();
+    let format_line = cache
+        .lines()
+        .find(|line| line.starts_with("format"))
+        .unwrap();
+    fs::write(&cache_path, cache.replace(format_line, "format = 0")).unwrap();
+
+    let (index, res) = build();
+    reparsed(res);
+    assert!(index.get_package("logic").is_none());
+
+    // the package directory changed: the package is listed again,
+    // and package.toml is parsed again as it changed
+    fs::write(&manifest_path, content).unwrap();
+    let (index, res) = build();
+    assert_eq!(broken_manifests(res), vec![broken_path.clone()]);
+    assert!(index.get_package("logic").is_some());
+
+    fs::write(&manifest_path, vec![b'#'; 8]).unwrap();
+    let package_dir = fs::File::open(manifest_path.parent().unwrap().parent().unwrap()).unwrap();
+    package_dir
+        .set_modified(SystemTime::now() + Duration::from_secs(120))
+        .unwrap();
+    drop(package_dir);
+
+    let (index, res) = build();
+    reparsed(res);
+    assert!(index.get_package("logic").is_none());
+}
+
+#[test]
+fn refresh_events() {
+    let root = TempRoot::new("indexer-refresh");
+    let removed = root.add_empty("logic", "0.1.0");
+    let changed = root.add_empty("logic", "0.2.0");
+    root.add_empty("arith", "0.1.0");
+
+    let (mut index, res) = PackageIndexBuilder::new()
+        .add_roots(&[root.path().to_path_buf()])
+        .build();
+    res.unwrap();
+
+    let (events, res) = index.refresh();
+    res.unwrap();
+    assert!(events.is_empty());
+
+    fs::remove_dir_all(removed).unwrap();
+    fs::write(
+        changed.join("package.toml"),
+        "[package]\nname = \"logic\"\nversion = \"0.2.0\"\ndescription = \"changed\"\n\n[dependencies]\n\n[provides]\n",
+    )
+    .unwrap();
+    root.add_empty("logic", "0.3.0");
+
+    let (events, res) = index.refresh();
+    res.unwrap();
+
+    let version = |version: &str| Version::parse(version).unwrap();
+    assert_eq!(
+        events,
+        vec![
+            IndexEvent::Removed {
+                name: "logic".to_string(),
+                version: version("0.1.0")
+            },
+            IndexEvent::Changed {
+                name: "logic".to_string(),
+                version: version("0.2.0")
+            },
+            IndexEvent::Added {
+                name: "logic".to_string(),
+                version: version("0.3.0")
+            },
+        ]
+    );
+    assert_eq!(
+        index.get_package("logic").unwrap().list_versions(),
+        vec![&version("0.2.0"), &version("0.3.0")]
+    );
+}
+
+#[test]
+fn feature_dependencies() {
+    let root = TempRoot::new("indexer-features");
+    root.add(
+        "probes",
+        "0.1.0",
+        "[dependencies]\nlogic = \"^0.1\"\nrom = { version = \"^1\", optional = true, features = [\"large\"] }\n\n[features]\ndebug = [\"trace\", \"dep:rom\", \"probe\"]\ntrace = []\n\n[provides]\nprobe = { type = \"gate\", optional = true }\n",
+    );
+    root.add_empty("logic", "0.1.0");
+    root.add(
+        "rom",
+        "1.0.0",
+        "[dependencies]\n\n[features]\nlarge = []\n\n[provides]\n",
+    );
+    root.add_empty("rom", "1.1.0");
+    root.add(
+        "broken",
+        "0.1.0",
+        "[dependencies]\n\n[features]\nbad = [\"nothing\"]\n\n[provides]\n",
+    );
+
+    let (index, res) = PackageIndexBuilder::new()
+        .add_roots(&[root.path().to_path_buf()])
+        .build();
+    match res {
+        Err(indexer::Error::NewIndex { errors }) => {
+            assert!(matches!(
+                errors.as_slice(),
+                [indexer::Error::InvalidFeature { feature, item, .. }, ..]
+                    if feature == "bad" && item == "nothing"
+            ));
+        }
+        _ => panic!("expected the invalid feature to be reported"),
+    }
+
+    let request = DepsResolveRequest::new("probes".to_string(), VersionReq::parse("^0.1").unwrap());
+
+    let resolved = deps_resolver(&index, std::slice::from_ref(&request)).unwrap();
+    assert!(!resolved.contains_key("rom"));
+
+    let resolved = deps_resolver_with_options(
+        &index,
+        &[request.with_features(vec!["debug".to_string()])],
+        &ResolveOptions::new(),
+    )
+    .unwrap();
+    // 1.1.0 does not have the feature
+    assert_eq!(resolved.packages["rom"], vec![Version::new(1, 0, 0)]);
+    assert_eq!(
+        resolved.features["rom"][&Version::new(1, 0, 0)],
+        ["large".to_string()].into()
+    );
+
+    let manifest = index
+        .get_package("probes")
+        .unwrap()
+        .get_version(&Version::new(0, 1, 0))
+        .unwrap();
+    let provided = |features: &[&str]| {
+        let enabled = manifest.enabled_features(features.iter().copied()).unwrap();
+        manifest
+            .enabled_provides(&enabled)
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>()
+    };
+    assert!(provided(&[]).is_empty());
+    assert_eq!(provided(&["debug"]), vec!["probe"]);
+    assert!(manifest.enabled_features(["missing"]).is_none());
+}
+
+#[test]
+fn host_compatibility() {
+    let root = TempRoot::new("indexer-host");
+    root.add(
+        "logic",
+        "0.1.0",
+        "[host]\nxdsim = \"*\"\nschema = { min = 0, max = 0 }\n\n[dependencies]\n\n[provides]\n",
+    );
+    root.add(
+        "logic",
+        "0.1.1",
+        "[host]\nxdsim = \">=99\"\n\n[dependencies]\n\n[provides]\n",
+    );
+    root.add(
+        "logic",
+        "0.1.2",
+        "[host]\nschema = { min = 5, max = 6 }\n\n[dependencies]\n\n[provides]\n",
+    );
+
+    let (index, res) = PackageIndexBuilder::new()
+        .add_roots(&[root.path().to_path_buf()])
+        .build();
+    res.unwrap();
+
+    let incompatible = index.list_incompatible();
+    assert_eq!(incompatible.len(), 2);
+    assert!(matches!(
+        incompatible[0].2,
+        [HostIncompatibility::XdsimVersion { .. }]
+    ));
+    assert!(matches!(
+        incompatible[1].2,
+        [HostIncompatibility::SchemaVersion { .. }]
+    ));
+
+    let (trace, res) = deps_resolver_traced(
+        &index,
+        &[DepsResolveRequest::new(
+            "logic".to_string(),
+            VersionReq::parse("^0.1").unwrap(),
+        )],
+        &ResolveOptions::new(),
+    );
+    assert_eq!(res.unwrap().packages["logic"], vec![Version::new(0, 1, 0)]);
+    assert!(
+        trace
+            .to_string()
+            .contains("rejected 0.1.2: incompatible with host: built for bindings schema 5..=6")
+    );
 }
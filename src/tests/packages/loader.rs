@@ -1,19 +1,64 @@
+use std::{collections::HashMap, env::consts::DLL_EXTENSION, fs};
+
 use semver::{Version, VersionReq};
+use sha2::{Digest, Sha256};
 
-use crate::packages::{
-    indexer::{
-        component::PackageIndexBuilder,
-        deps_resolver::{DepsResolveRequest, deps_resolver},
+#[cfg(feature = "script")]
+use crate::packages::indexer::deps_resolver::{DepsResolveRequest, deps_resolver};
+use crate::{
+    common::world::{ComponentVersion, ComponentVersionReq},
+    packages::{
+        indexer::{component::PackageIndexBuilder, deps_resolver::EnabledFeatures},
+        loader::{
+            self,
+            indexed::{
+                component::{IndexComponentLoader, LoadOptions},
+                lazy::LazyComponentLoader,
+                validate::{ComponentProblem, validate_package},
+            },
+        },
     },
-    loader::indexed::component::IndexComponentLoader,
+    tests::packages::temp_root::TempRoot,
 };
 
+/// a stateless not gate on std bool
+#[cfg(feature = "script")]
+const NOT_SCRIPT: &str = r#"
+fn definition() {
+    #{
+        consumers: [#{
+            name: "in",
+            data_type_req: #{ "package": "std", version_req: "=0.1.0", component: "bool" },
+            position: [0, 0.5],
+        }],
+        producers: [#{
+            name: "out",
+            data_type: #{ "package": "std", version: "0.1.0", component: "bool" },
+            position: [1, 0.5],
+        }],
+        bounding_box: #{ top: 0, bottom: 1, left: 0, right: 1 },
+    }
+}
+
+fn tick(inputs) {
+    [!inputs[0]]
+}
+"#;
+
+#[cfg(feature = "script")]
 #[test]
 fn load_single_lib() {
+    let root = TempRoot::new("loader-single");
+    let version_root = root.add(
+        "testlib",
+        "0.1.0",
+        "[dependencies]\n\n[provides]\nnot = { type = \"gate\", library = \"rhai\" }\n",
+    );
+    fs::write(version_root.join("not.rhai"), NOT_SCRIPT).unwrap();
+
     let (index, res) = PackageIndexBuilder::new()
-        .add_roots(&[dirs::data_dir().unwrap().join("xdsim/packages/components/")])
+        .add_roots(&[root.path().to_path_buf()])
         .build();
-
     res.unwrap();
 
     let to_load = deps_resolver(
@@ -25,24 +70,254 @@ fn load_single_lib() {
     )
     .unwrap();
 
-    let loaded_libs = IndexComponentLoader::load_all(index, to_load).unwrap();
-
-    dbg!(
-        &loaded_libs
-            .gates
-            .get("testlib")
-            .unwrap()
-            .get(&Version::parse("0.1.0").unwrap())
-            .unwrap()
-            .keys()
-    );
-    dbg!(
-        &loaded_libs
-            .data
-            .get("testlib")
-            .unwrap()
-            .get(&Version::parse("0.1.0").unwrap())
-            .unwrap()
-            .keys()
+    let loaded_libs = IndexComponentLoader::load_all(&index, to_load, &LoadOptions::new()).unwrap();
+
+    let gates = &loaded_libs.gates["testlib"][&Version::new(0, 1, 0)];
+    assert_eq!(gates.keys().collect::<Vec<_>>(), ["not"]);
+    assert_eq!(
+        gates["not"].id(),
+        &ComponentVersion {
+            package: "testlib".to_string(),
+            version: Version::new(0, 1, 0),
+            component: "not".to_string(),
+        }
+    );
+    assert!(loaded_libs.data["testlib"][&Version::new(0, 1, 0)].is_empty());
+}
+
+#[test]
+fn load_rejects_std_package() {
+    let (index, res) = PackageIndexBuilder::new().build();
+    res.unwrap();
+
+    let to_load = HashMap::from([("std".to_string(), vec![Version::new(0, 1, 0)])]);
+
+    match IndexComponentLoader::load_all(&index, to_load, &LoadOptions::new()) {
+        Err(loader::Error::LoadAllComponentPackages { errors }) => match errors.as_slice() {
+            [loader::Error::ReservedPackage { name }] => assert_eq!(name, "std"),
+            other => panic!("expected a reserved package, got {other:?}"),
+        },
+        Err(e) => panic!("expected a reserved package, got {e:?}"),
+        Ok(_) => panic!("expected a reserved package"),
+    }
+}
+
+#[test]
+fn load_checksum_mismatch() {
+    let root = TempRoot::new("loader-checksum");
+    let version_root = root.add(
+        "logic",
+        "0.1.0",
+        "[dependencies]\n\n[provides]\nnot = { type = \"gate\", sha256 = \"0000000000000000000000000000000000000000000000000000000000000000\" }\n",
+    );
+    let lib_path = version_root.join("not").with_extension(DLL_EXTENSION);
+    fs::write(&lib_path, b"stale build").unwrap();
+
+    let (index, res) = PackageIndexBuilder::new()
+        .add_roots(&[root.path().to_path_buf()])
+        .build();
+    res.unwrap();
+
+    let to_load = HashMap::from([("logic".to_string(), vec![Version::parse("0.1.0").unwrap()])]);
+
+    match IndexComponentLoader::load_all(&index, to_load, &LoadOptions::new()) {
+        Err(loader::Error::LoadAllComponentPackages { errors }) => match errors.as_slice() {
+            [loader::Error::ChecksumMismatch { lib_path: got, .. }] => assert_eq!(got, &lib_path),
+            other => panic!("expected a checksum mismatch, got {other:?}"),
+        },
+        Err(e) => panic!("expected a checksum mismatch, got {e:?}"),
+        Ok(_) => panic!("expected a checksum mismatch"),
+    }
+}
+
+#[test]
+fn fresh_copy_checks_digest() {
+    let root = TempRoot::new("loader-fresh-copy");
+    let lib_path = root.path().join("not").with_extension(DLL_EXTENSION);
+    fs::write(&lib_path, b"rebuilt").unwrap();
+
+    match loader::LoadManager::load_fresh_copy(
+        lib_path.clone(),
+        Some("0000000000000000000000000000000000000000000000000000000000000000"),
+    ) {
+        Err(loader::Error::ChecksumMismatch { lib_path: got, .. }) => assert_eq!(got, lib_path),
+        Err(e) => panic!("expected a checksum mismatch, got {e:?}"),
+        Ok(_) => panic!("expected a checksum mismatch"),
+    }
+}
+
+#[test]
+fn checked_load_uses_a_copy() {
+    let root = TempRoot::new("loader-checked-copy");
+    let lib_path = root.path().join("not").with_extension(DLL_EXTENSION);
+    fs::write(&lib_path, b"not a library").unwrap();
+    let digest = Sha256::digest(b"not a library")
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<String>();
+
+    // the digest matches, the copy that is loaded is not a library,
+    // errors still report the original path
+    match loader::LoadManager::load_with_checksum(lib_path.clone(), Some(&digest)) {
+        Err(loader::Error::LoadLib { lib_path: got, .. }) => assert_eq!(got, lib_path),
+        Err(e) => panic!("expected a library that cannot be loaded, got {e:?}"),
+        Ok(_) => panic!("expected a library that cannot be loaded"),
+    }
+}
+
+#[test]
+fn validate_reports_per_component() {
+    let root = TempRoot::new("loader-validate");
+    let version_root = root.add(
+        "logic",
+        "0.1.0",
+        "[dependencies]\n\n[provides]\nnot = \"gate\"\nbit = \"data\"\n",
     );
+    fs::write(
+        version_root.join("bit").with_extension(DLL_EXTENSION),
+        b"not a library",
+    )
+    .unwrap();
+
+    let (index, res) = PackageIndexBuilder::new()
+        .add_roots(&[root.path().to_path_buf()])
+        .build();
+    res.unwrap();
+
+    let report = validate_package(&index, "logic", &Version::parse("0.1.0").unwrap()).unwrap();
+    assert!(!report.is_ok());
+    assert!(matches!(
+        report.components["not"].problems.as_slice(),
+        [ComponentProblem::MissingLibrary]
+    ));
+    assert!(matches!(
+        report.components["bit"].problems.as_slice(),
+        [ComponentProblem::LoadLib { .. }]
+    ));
+
+    assert!(matches!(
+        validate_package(&index, "logic", &Version::parse("0.2.0").unwrap()),
+        Err(loader::Error::MissingPackageVersion { .. })
+    ));
+}
+
+#[test]
+fn lazy_loader_loads_on_first_use() {
+    let root = TempRoot::new("loader-lazy");
+    let version_root = root.add(
+        "logic",
+        "0.1.0",
+        "[dependencies]\n\n[provides]\nnot = \"gate\"\nbit = \"data\"\n",
+    );
+    fs::write(
+        version_root.join("not").with_extension(DLL_EXTENSION),
+        b"not a library",
+    )
+    .unwrap();
+
+    let (index, res) = PackageIndexBuilder::new()
+        .add_roots(&[root.path().to_path_buf()])
+        .build();
+    res.unwrap();
+
+    let to_load = HashMap::from([("logic".to_string(), vec![Version::parse("0.1.0").unwrap()])]);
+
+    // neither library is valid, but nothing is loaded yet
+    let lazy = LazyComponentLoader::new(&index, to_load, &EnabledFeatures::new()).unwrap();
+    assert!(lazy.list_loaded().is_empty());
+
+    let not = ComponentVersion {
+        package: "logic".to_string(),
+        version: Version::parse("0.1.0").unwrap(),
+        component: "not".to_string(),
+    };
+    assert!(lazy.contains(&not));
+    assert_eq!(
+        lazy.find_data_version(&ComponentVersionReq {
+            package: "logic".to_string(),
+            version_req: VersionReq::parse("0.1").unwrap(),
+            component: "bit".to_string(),
+        }),
+        Some(Version::parse("0.1.0").unwrap())
+    );
+
+    assert!(matches!(
+        lazy.load_gate(&not),
+        Err(loader::Error::LoadLib { .. })
+    ));
+    assert!(!lazy.is_loaded(&not));
+
+    assert!(matches!(
+        lazy.load_gate(&ComponentVersion {
+            component: "bit".to_string(),
+            ..not
+        }),
+        Ok(None)
+    ));
+}
+
+#[test]
+fn lazy_register_package() {
+    let root = TempRoot::new("loader-lazy-register");
+    root.add(
+        "logic",
+        "0.1.0",
+        "[dependencies]\n\n[features]\ndebug = [\"probe\"]\n\n[provides]\nnot = \"gate\"\nprobe = { type = \"gate\", optional = true }\n",
+    );
+
+    let (mut index, res) = PackageIndexBuilder::new()
+        .add_roots(&[root.path().to_path_buf()])
+        .build();
+    res.unwrap();
+
+    let v1 = Version::new(0, 1, 0);
+    let v2 = Version::new(0, 2, 0);
+    let component = |version: &Version, name: &str| ComponentVersion {
+        package: "logic".to_string(),
+        version: version.clone(),
+        component: name.to_string(),
+    };
+
+    let features: EnabledFeatures = HashMap::from([(
+        "logic".to_string(),
+        [(v1.clone(), ["debug".to_string()].into())].into(),
+    )]);
+    let lazy = LazyComponentLoader::new(
+        &index,
+        HashMap::from([("logic".to_string(), vec![v1.clone()])]),
+        &features,
+    )
+    .unwrap();
+    assert!(lazy.contains(&component(&v1, "probe")));
+    assert_eq!(
+        lazy.get_features("logic", &v1),
+        Some(["debug".to_string()].into())
+    );
+
+    root.add_empty("logic", "0.2.0");
+    let (mut scanned, res) = index.rescan();
+    res.unwrap();
+    let package = scanned.take_package("logic").unwrap();
+
+    // 0.3.0 does not exist, so 0.2.0 is not registered either
+    assert!(matches!(
+        lazy.register_package(
+            &package,
+            &[v2.clone(), Version::new(0, 3, 0)],
+            &EnabledFeatures::new()
+        ),
+        Err(loader::Error::LoadAllComponentPackages { .. })
+    ));
+    assert!(lazy.get_features("logic", &v2).is_none());
+
+    lazy.register_package(&package, std::slice::from_ref(&v2), &EnabledFeatures::new())
+        .unwrap();
+    assert_eq!(lazy.get_features("logic", &v2), Some(Default::default()));
+    // 0.1.0 is not affected
+    assert!(lazy.contains(&component(&v1, "probe")));
+
+    index.replace_package("logic", Some(package));
+    lazy.unregister_version("logic", &v1);
+    assert!(!lazy.contains(&component(&v1, "not")));
+    assert!(lazy.get_features("logic", &v1).is_none());
 }
@@ -0,0 +1,330 @@
+use std::{env::consts::DLL_EXTENSION, fs, path::PathBuf};
+
+use semver::{Version, VersionReq};
+
+use crate::{
+    packages::{
+        indexer::{
+            self,
+            component::{OVERRIDES_FILE_NAME, PackageIndexBuilder},
+            deps_resolver::{DepsResolveRequest, ResolveOptions, deps_resolver},
+            lockfile::{LOCKFILE_NAME, Lockfile, deps_resolver_locked},
+        },
+        loader::{
+            self,
+            indexed::component::{IndexComponentLoader, LoadOptions},
+        },
+    },
+    tests::packages::temp_root::TempRoot,
+};
+
+#[test]
+fn lockfile_pins_versions() {
+    let root = TempRoot::new("lockfile-pins");
+    root.add_empty("logic", "0.3.0");
+    let lockfile_path = root.path().join(LOCKFILE_NAME);
+    let requests = [DepsResolveRequest::new(
+        "logic".to_string(),
+        VersionReq::parse("^0.3").unwrap(),
+    )];
+
+    let (index, res) = PackageIndexBuilder::new()
+        .add_roots(&[root.path().to_path_buf()])
+        .build();
+    res.unwrap();
+
+    let resolved =
+        deps_resolver_locked(&index, &requests, &ResolveOptions::new(), &lockfile_path).unwrap();
+    assert_eq!(
+        resolved.packages["logic"],
+        vec![Version::parse("0.3.0").unwrap()]
+    );
+    assert_eq!(
+        Lockfile::read(&lockfile_path).unwrap().to_resolved(),
+        resolved.packages
+    );
+
+    // a newer version appears, but the lockfile still pins the old one
+    root.add_empty("logic", "0.3.1");
+    let (index, res) = PackageIndexBuilder::new()
+        .add_roots(&[root.path().to_path_buf()])
+        .build();
+    res.unwrap();
+
+    let resolved =
+        deps_resolver_locked(&index, &requests, &ResolveOptions::new(), &lockfile_path).unwrap();
+    assert_eq!(
+        resolved.packages["logic"],
+        vec![Version::parse("0.3.0").unwrap()]
+    );
+}
+
+#[test]
+fn lockfile_missing_version() {
+    let root = TempRoot::new("lockfile-missing");
+    let old_version = root.add_empty("logic", "0.3.0");
+    root.add_empty("logic", "0.3.1");
+    let lockfile_path = root.path().join(LOCKFILE_NAME);
+    let requests = [DepsResolveRequest::new(
+        "logic".to_string(),
+        VersionReq::parse("=0.3.0").unwrap(),
+    )];
+
+    let (index, res) = PackageIndexBuilder::new()
+        .add_roots(&[root.path().to_path_buf()])
+        .build();
+    res.unwrap();
+    deps_resolver_locked(&index, &requests, &ResolveOptions::new(), &lockfile_path).unwrap();
+
+    fs::remove_dir_all(old_version).unwrap();
+    let (index, res) = PackageIndexBuilder::new()
+        .add_roots(&[root.path().to_path_buf()])
+        .build();
+    res.unwrap();
+
+    match deps_resolver_locked(&index, &requests, &ResolveOptions::new(), &lockfile_path) {
+        Err(indexer::Error::LockedVersionMissing { name, version, .. }) => {
+            assert_eq!(name, "logic");
+            assert_eq!(version, Version::parse("0.3.0").unwrap());
+        }
+        other => panic!("expected locked version missing, got {other:?}"),
+    }
+}
+
+#[test]
+fn yanked_versions_stay_pinned() {
+    let root = TempRoot::new("lockfile-yanked");
+    root.add_empty("logic", "0.3.0");
+    root.add(
+        "logic",
+        "0.3.1",
+        "yanked = true\n\n[dependencies]\n\n[provides]\n",
+    );
+    root.add_empty("logic", "0.3.2");
+    fs::write(
+        root.path().join(OVERRIDES_FILE_NAME),
+        "[logic.\"0.3.2\"]\nyanked = true\n\n[logic.\"0.3.0\"]\ndeprecated = \"use 0.4\"\n",
+    )
+    .unwrap();
+    let lockfile_path = root.path().join(LOCKFILE_NAME);
+    let requests = [DepsResolveRequest::new(
+        "logic".to_string(),
+        VersionReq::parse("^0.3").unwrap(),
+    )];
+
+    let (index, res) = PackageIndexBuilder::new()
+        .add_roots(&[root.path().to_path_buf()])
+        .build();
+    res.unwrap();
+
+    let logic = index.get_package("logic").unwrap();
+    let status = |version| {
+        logic
+            .get_version_entry(&Version::new(0, 3, version))
+            .unwrap()
+            .get_status()
+    };
+    assert!(status(1).yanked);
+    assert!(status(2).yanked);
+    assert_eq!(status(0).deprecated.as_deref(), Some("use 0.4"));
+
+    // fresh resolves skip yanked versions
+    let resolved = deps_resolver(&index, &requests).unwrap();
+    assert_eq!(resolved["logic"], vec![Version::new(0, 3, 0)]);
+
+    // but a lockfile can still pin them
+    fs::write(
+        &lockfile_path,
+        "[[package]]\nname = \"logic\"\nversion = \"0.3.2\"\n",
+    )
+    .unwrap();
+    let resolved =
+        deps_resolver_locked(&index, &requests, &ResolveOptions::new(), &lockfile_path).unwrap();
+    assert_eq!(resolved.packages["logic"], vec![Version::new(0, 3, 2)]);
+
+    let loaded = IndexComponentLoader::load_all(
+        &index,
+        [(
+            "logic".to_string(),
+            vec![Version::new(0, 3, 0), Version::new(0, 3, 2)],
+        )]
+        .into(),
+        &LoadOptions::new(),
+    )
+    .unwrap();
+    assert_eq!(
+        loaded.warnings,
+        vec![
+            loader::Warning::Deprecated {
+                name: "logic".to_string(),
+                version: Version::new(0, 3, 0),
+                message: "use 0.4".to_string(),
+            },
+            loader::Warning::Yanked {
+                name: "logic".to_string(),
+                version: Version::new(0, 3, 2),
+            },
+        ]
+    );
+}
+
+#[test]
+fn lockfile_keeps_features() {
+    let root = TempRoot::new("lockfile-features");
+    root.add(
+        "logic",
+        "0.3.0",
+        "[dependencies]\nrom = { version = \"^1\", optional = true, features = [\"large\"] }\n\n[features]\ndebug = [\"dep:rom\"]\n\n[provides]\n",
+    );
+    root.add(
+        "rom",
+        "1.0.0",
+        "[dependencies]\n\n[features]\nlarge = []\n\n[provides]\n",
+    );
+    let lockfile_path = root.path().join(LOCKFILE_NAME);
+    let requests =
+        [
+            DepsResolveRequest::new("logic".to_string(), VersionReq::parse("^0.3").unwrap())
+                .with_features(vec!["debug".to_string()]),
+        ];
+
+    let (index, res) = PackageIndexBuilder::new()
+        .add_roots(&[root.path().to_path_buf()])
+        .build();
+    res.unwrap();
+
+    let resolved =
+        deps_resolver_locked(&index, &requests, &ResolveOptions::new(), &lockfile_path).unwrap();
+    let lockfile = Lockfile::read(&lockfile_path).unwrap();
+    assert_eq!(lockfile.to_features(), resolved.features);
+    assert!(
+        lockfile
+            .is_valid(&index, &requests, &lockfile_path)
+            .unwrap()
+    );
+
+    // read back from the lockfile, the features are the same
+    let locked =
+        deps_resolver_locked(&index, &requests, &ResolveOptions::new(), &lockfile_path).unwrap();
+    assert_eq!(locked.packages, resolved.packages);
+    assert_eq!(
+        locked.features["logic"][&Version::new(0, 3, 0)],
+        ["debug".to_string()].into()
+    );
+    assert_eq!(
+        locked.features["rom"][&Version::new(1, 0, 0)],
+        ["large".to_string()].into()
+    );
+
+    // the optional dependency enabled by a locked feature is not locked
+    fs::write(
+        &lockfile_path,
+        "[[package]]\nname = \"logic\"\nversion = \"0.3.0\"\nfeatures = [\"debug\"]\n",
+    )
+    .unwrap();
+    let lockfile = Lockfile::read(&lockfile_path).unwrap();
+    assert!(
+        !lockfile
+            .is_valid(&index, &requests, &lockfile_path)
+            .unwrap()
+    );
+
+    // the requested feature is not locked
+    fs::write(
+        &lockfile_path,
+        "[[package]]\nname = \"logic\"\nversion = \"0.3.0\"\n",
+    )
+    .unwrap();
+    let lockfile = Lockfile::read(&lockfile_path).unwrap();
+    assert!(
+        !lockfile
+            .is_valid(&index, &requests, &lockfile_path)
+            .unwrap()
+    );
+
+    // a stale lockfile is resolved again with the features
+    let resolved =
+        deps_resolver_locked(&index, &requests, &ResolveOptions::new(), &lockfile_path).unwrap();
+    assert_eq!(resolved.packages["rom"], vec![Version::new(1, 0, 0)]);
+    assert_eq!(
+        Lockfile::read(&lockfile_path).unwrap().to_features(),
+        resolved.features
+    );
+}
+
+#[test]
+fn host_incompatible_lock_is_stale() {
+    let root = TempRoot::new("lockfile-host");
+    root.add_empty("logic", "0.3.0");
+    root.add(
+        "logic",
+        "0.3.1",
+        "[host]\nxdsim = \">=99\"\n\n[dependencies]\n\n[provides]\n",
+    );
+    let lockfile_path = root.path().join(LOCKFILE_NAME);
+    let requests = [DepsResolveRequest::new(
+        "logic".to_string(),
+        VersionReq::parse("^0.3").unwrap(),
+    )];
+
+    let (index, res) = PackageIndexBuilder::new()
+        .add_roots(&[root.path().to_path_buf()])
+        .build();
+    res.unwrap();
+
+    // e.g. locked on a newer xdsim
+    fs::write(
+        &lockfile_path,
+        "[[package]]\nname = \"logic\"\nversion = \"0.3.1\"\n",
+    )
+    .unwrap();
+    let lockfile = Lockfile::read(&lockfile_path).unwrap();
+    assert!(
+        !lockfile
+            .is_valid(&index, &requests, &lockfile_path)
+            .unwrap()
+    );
+
+    let resolved =
+        deps_resolver_locked(&index, &requests, &ResolveOptions::new(), &lockfile_path).unwrap();
+    assert_eq!(resolved.packages["logic"], vec![Version::new(0, 3, 0)]);
+    assert_eq!(
+        Lockfile::read(&lockfile_path).unwrap().to_resolved(),
+        resolved.packages
+    );
+}
+
+#[test]
+fn lockfile_paths_are_relative() {
+    let root = TempRoot::new("lockfile-relative");
+    root.add(
+        "logic",
+        "0.1.0",
+        "[dependencies]\n\n[provides]\nnot = \"gate\"\n",
+    );
+    let lockfile_path = root.path().join(LOCKFILE_NAME);
+    let requests = [DepsResolveRequest::new(
+        "logic".to_string(),
+        VersionReq::parse("^0.1").unwrap(),
+    )];
+
+    let (index, res) = PackageIndexBuilder::new()
+        .add_roots(&[root.path().to_path_buf()])
+        .build();
+    res.unwrap();
+    deps_resolver_locked(&index, &requests, &ResolveOptions::new(), &lockfile_path).unwrap();
+
+    let lockfile = Lockfile::read(&lockfile_path).unwrap();
+    assert_eq!(
+        lockfile.get_packages()[0].get_libraries()["not"],
+        PathBuf::from("not").with_extension(DLL_EXTENSION)
+    );
+
+    // the lockfile path is a directory, it cannot be written
+    let blocked = root.path().join("blocked.lock");
+    fs::create_dir(&blocked).unwrap();
+    assert!(matches!(
+        lockfile.write(&blocked),
+        Err(indexer::Error::LockfileWrite { .. })
+    ));
+}
@@ -1,2 +1,15 @@
+mod archive;
+mod capabilities;
+mod deps_resolver;
+pub mod fake_library;
+mod graph;
 mod indexer;
 mod loader;
+mod lockfile;
+mod sandbox;
+#[cfg(feature = "script")]
+mod script;
+mod stdlib;
+pub mod temp_root;
+#[cfg(feature = "wasm")]
+mod wasm;
@@ -0,0 +1,415 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use semver::Version;
+use sha2::{Digest, Sha256};
+use xdsim_cbinds::common::BoundingBox;
+
+use crate::{
+    common::world::ComponentVersion,
+    packages::{
+        destructor::{DestructedData, DestructedGate},
+        indexer::component::PackageIndexBuilder,
+        loader::{
+            self,
+            indexed::{
+                component::{IndexComponentLoader, Isolation, LoadOptions},
+                lazy::LazyComponentLoader,
+            },
+        },
+        sandbox::{
+            self, SandboxCommand, SandboxHost,
+            protocol::{ComponentKind, GateDefinition, GateProducerEntry, Request, Response},
+        },
+    },
+    tests::packages::temp_root::TempRoot,
+    world::sim::{self as world_sim, WorldState, requests::*},
+};
+
+fn component() -> ComponentVersion {
+    ComponentVersion {
+        package: "testlib".to_string(),
+        version: Version::parse("0.1.0").unwrap(),
+        component: "not".to_string(),
+    }
+}
+
+#[cfg(unix)]
+fn bit() -> ComponentVersion {
+    ComponentVersion {
+        package: "testlib".to_string(),
+        version: Version::parse("0.1.0").unwrap(),
+        component: "bit".to_string(),
+    }
+}
+
+/// a helper that answers requests without loading any library,
+/// gates have a single bit producer, serialized data is a single 0 byte
+///
+/// the first Tick kills the helper and creates crashed,
+/// once crashed exists every Tick produces a 1
+#[cfg(unix)]
+fn fake_helper(crashed: &Path) -> SandboxCommand {
+    let definition = Response::Definition {
+        definition: GateDefinition {
+            consumers: Vec::new(),
+            producers: vec![GateProducerEntry {
+                name: "out".to_string(),
+                data_type: bit(),
+                position: (1.0, 0.0),
+            }],
+            bounding_box: BoundingBox {
+                top: 1.0,
+                bottom: -1.0,
+                left: -1.0,
+                right: 1.0,
+            }
+            .into(),
+        },
+    };
+    let produced = Response::Producers {
+        producers: vec![vec![1]],
+    };
+
+    let script = r#"
+while read -r request; do
+    case "$request" in
+        '{"Tick"'*)
+            if [ ! -e "$1" ]; then touch "$1"; exit 1; fi
+            echo "$3" ;;
+        '{"Definition"'*) echo "$2" ;;
+        '{"Serialize"'*) echo '{"Bytes":{"bytes":[0]}}' ;;
+        *) echo '"Done"' ;;
+    esac
+done
+"#;
+
+    SandboxCommand::new(
+        "sh",
+        vec![
+            "-c".to_string(),
+            script.to_string(),
+            "sh".to_string(),
+            crashed.to_string_lossy().to_string(),
+            serde_json::to_string(&definition).unwrap(),
+            serde_json::to_string(&produced).unwrap(),
+        ],
+    )
+}
+
+#[test]
+fn helper_keeps_serving_after_failed_requests() {
+    let requests = [
+        serde_json::to_string(&Request::Load {
+            component_id: 1,
+            kind: ComponentKind::Gate,
+            lib_path: PathBuf::from("/nonexistent/not.so"),
+            component: component(),
+            fresh: false,
+            sha256: None,
+        })
+        .unwrap(),
+        "not a request".to_string(),
+        serde_json::to_string(&Request::Default {
+            component_id: 1,
+            instance: 2,
+        })
+        .unwrap(),
+        serde_json::to_string(&Request::Drop { instance: 2 }).unwrap(),
+    ]
+    .join("\n");
+
+    let mut output = Vec::new();
+    sandbox::serve(requests.as_bytes(), &mut output).unwrap();
+
+    let responses: Vec<Response> = String::from_utf8(output)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+
+    assert_eq!(responses.len(), 4);
+    assert!(matches!(responses[0], Response::Failed { .. }));
+    assert!(matches!(responses[1], Response::Failed { .. }));
+    assert!(matches!(responses[2], Response::Failed { .. }));
+    assert!(matches!(responses[3], Response::Done));
+}
+
+#[test]
+fn helper_checks_reloaded_libraries() {
+    let root = TempRoot::new("sandbox-reload-digest");
+    let lib_path = root.path().join("not.so");
+    fs::write(&lib_path, b"not a library").unwrap();
+
+    let load = |sha256: &str| {
+        serde_json::to_string(&Request::Load {
+            component_id: 1,
+            kind: ComponentKind::Gate,
+            lib_path: lib_path.clone(),
+            component: component(),
+            fresh: true,
+            sha256: Some(sha256.to_string()),
+        })
+        .unwrap()
+    };
+    let digest = Sha256::digest(b"not a library")
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<String>();
+    let requests = [load(&"0".repeat(64)), load(&digest)].join("\n");
+
+    let mut output = Vec::new();
+    sandbox::serve(requests.as_bytes(), &mut output).unwrap();
+
+    let reasons: Vec<String> = String::from_utf8(output)
+        .unwrap()
+        .lines()
+        .map(|line| match serde_json::from_str(line).unwrap() {
+            Response::Failed { reason } => reason,
+            other => panic!("expected a failed load, got {other:?}"),
+        })
+        .collect();
+
+    // the digest is checked before the copy is loaded
+    assert_eq!(reasons.len(), 2);
+    assert!(reasons[0].starts_with("ChecksumMismatch"));
+    assert!(reasons[1].starts_with("LoadLib"));
+}
+
+#[cfg(unix)]
+#[test]
+fn crashed_helper_is_restarted() {
+    let sandbox = Arc::new(SandboxHost::new(SandboxCommand::new(
+        "sh",
+        vec!["-c".to_string(), "exit 3".to_string()],
+    )));
+
+    // the helper exits before responding, every request restarts it
+    for _ in 0..2 {
+        let loaded = sandbox.load(
+            ComponentKind::Gate,
+            PathBuf::from("/nonexistent/not.so"),
+            component(),
+            false,
+        );
+
+        assert!(matches!(loaded, Err(sandbox::Error::Crashed { .. })));
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn unresponsive_helper_is_killed() {
+    let sandbox = Arc::new(SandboxHost::new(
+        SandboxCommand::new("sh", vec!["-c".to_string(), "sleep 60".to_string()])
+            .timeout(Duration::from_millis(100)),
+    ));
+
+    // the helper never responds, every request kills it and the next one starts it again
+    for _ in 0..2 {
+        let started = Instant::now();
+        let loaded = sandbox.load(
+            ComponentKind::Gate,
+            PathBuf::from("/nonexistent/not.so"),
+            component(),
+            false,
+        );
+
+        assert!(matches!(loaded, Err(sandbox::Error::Crashed { .. })));
+        assert!(started.elapsed() < Duration::from_secs(30));
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn stuck_helper_is_killed_on_drop() {
+    let sandbox = Arc::new(SandboxHost::new(
+        SandboxCommand::new(
+            "sh",
+            vec![
+                "-c".to_string(),
+                // fails the load, then stops reading stdin as if it were stuck in a tick
+                r#"read -r request; echo '{"Failed":{"reason":"stuck"}}'; exec sleep 60"#
+                    .to_string(),
+            ],
+        )
+        .timeout(Duration::from_millis(100)),
+    ));
+
+    let loaded = sandbox.load(
+        ComponentKind::Gate,
+        PathBuf::from("/nonexistent/not.so"),
+        component(),
+        false,
+    );
+    assert!(matches!(loaded, Err(sandbox::Error::Component { .. })));
+
+    let started = Instant::now();
+    drop(sandbox);
+    assert!(started.elapsed() < Duration::from_secs(30));
+}
+
+#[test]
+fn missing_helper_cannot_spawn() {
+    let sandbox = Arc::new(SandboxHost::new(SandboxCommand::new(
+        "/nonexistent/xdsim",
+        Vec::new(),
+    )));
+
+    let loaded = sandbox.load(
+        ComponentKind::Data,
+        PathBuf::from("/nonexistent/bool.so"),
+        component(),
+        false,
+    );
+
+    assert!(matches!(loaded, Err(sandbox::Error::Spawn { .. })));
+}
+
+#[cfg(unix)]
+#[test]
+fn crashing_tick_restarts_helper() {
+    let root = TempRoot::new("sandbox-crash");
+    let crashed = root.path().join("crashed");
+    let sandbox = Arc::new(SandboxHost::new(fake_helper(&crashed)));
+
+    let mut request = CreateBlankWorld::stdlib();
+    request.data_handles.insert(
+        "testlib".to_string(),
+        [(
+            Version::new(0, 1, 0),
+            HashMap::from([(
+                "bit".to_string(),
+                Arc::new(
+                    DestructedData::new_sandboxed(&sandbox, Path::new("/fake/bit.so"), None, bit())
+                        .unwrap(),
+                ),
+            )]),
+        )]
+        .into(),
+    );
+    request.gate_handles.insert(
+        "testlib".to_string(),
+        [(
+            Version::new(0, 1, 0),
+            HashMap::from([(
+                "not".to_string(),
+                Arc::new(
+                    DestructedGate::new_sandboxed(
+                        &sandbox,
+                        Path::new("/fake/not.so"),
+                        None,
+                        component(),
+                    )
+                    .unwrap(),
+                ),
+            )]),
+        )]
+        .into(),
+    );
+    let mut world = WorldState::new_blank(request);
+
+    let gate_id = world
+        .create_default_gate(CreateDefaultGate { gate: component() })
+        .unwrap();
+
+    let sandbox_error = |world: &mut WorldState| match *world.tick_all().unwrap_err() {
+        world_sim::Error::TickallErrors { errors } => match errors.as_slice() {
+            [entry] => match entry.get_content() {
+                world_sim::Error::TickSingleGate { errors, .. } => match errors.as_slice() {
+                    [
+                        world_sim::Error::Sandbox {
+                            gate_id: got,
+                            reason,
+                        },
+                    ] => {
+                        assert_eq!(*got, gate_id);
+                        reason.clone()
+                    }
+                    e => panic!("expected a sandbox error, got {e:?}"),
+                },
+                e => panic!("expected a gate error, got {e:?}"),
+            },
+            e => panic!("expected a single error, got {e:?}"),
+        },
+        e => panic!("expected tick errors, got {e:?}"),
+    };
+
+    // the helper dies during the tick
+    assert!(sandbox_error(&mut world).contains("Crashed"));
+    assert!(crashed.exists());
+
+    // a new helper is started, and the gate lost with the old one is created again
+    assert!(sandbox_error(&mut world).contains("InstanceReset"));
+
+    world.tick_all().unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn overrides_isolate_a_version() {
+    let root = TempRoot::new("sandbox-overrides");
+    let version_root = root.add(
+        "testlib",
+        "0.1.0",
+        "[dependencies]\n\n[provides]\nnot = { type = \"gate\" }\n",
+    );
+    fs::write(
+        version_root
+            .join("not")
+            .with_extension(std::env::consts::DLL_EXTENSION),
+        b"never loaded in this process",
+    )
+    .unwrap();
+    fs::write(
+        root.path().join("overrides.toml"),
+        "[testlib.\"0.1.0\"]\nisolated = true\n",
+    )
+    .unwrap();
+
+    let (index, res) = PackageIndexBuilder::new()
+        .add_roots(&[root.path().to_path_buf()])
+        .build();
+    res.unwrap();
+
+    let status = index
+        .get_package("testlib")
+        .and_then(|package| package.get_version_entry(&Version::new(0, 1, 0)))
+        .unwrap()
+        .get_status();
+    assert!(status.isolated);
+
+    let to_load = HashMap::from([("testlib".to_string(), vec![Version::new(0, 1, 0)])]);
+
+    // no package is listed in the isolation, the overrides file isolates the version
+    let options = LoadOptions::new().isolation(Isolation {
+        sandbox: Arc::new(SandboxHost::new(fake_helper(&root.path().join("crashed")))),
+        packages: HashSet::new(),
+    });
+    let loaded = IndexComponentLoader::load_all(&index, to_load.clone(), &options).unwrap();
+    assert!(loaded.gates["testlib"][&Version::new(0, 1, 0)]["not"].is_sandboxed());
+
+    // without a helper command the version is not loaded in this process instead
+    match IndexComponentLoader::load_all(&index, to_load.clone(), &LoadOptions::new()) {
+        Err(loader::Error::LoadAllComponentPackages { errors }) => {
+            assert!(matches!(errors.as_slice(), [loader::Error::Sandbox { .. }]))
+        }
+        Err(e) => panic!("expected a sandbox error, got {e:?}"),
+        Ok(_) => panic!("expected a sandbox error"),
+    }
+
+    // the lazy loader would load it in this process
+    match LazyComponentLoader::new(&index, to_load, &Default::default()) {
+        Err(loader::Error::LoadAllComponentPackages { errors }) => assert!(matches!(
+            errors.as_slice(),
+            [loader::Error::IsolatedVersion { .. }]
+        )),
+        Err(e) => panic!("expected an isolated version, got {e:?}"),
+        Ok(_) => panic!("expected an isolated version"),
+    }
+}
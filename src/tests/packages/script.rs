@@ -0,0 +1,311 @@
+use std::{collections::HashMap, fs, sync::Arc};
+
+use semver::Version;
+
+use crate::{
+    common::world::{ComponentVersion, GateConsumerSocket, GateProducerSocket},
+    packages::{
+        chelper::slice,
+        destructor::{self, DestructedGate},
+        indexer::component::PackageIndexBuilder,
+        loader::indexed::{lazy::LazyComponentLoader, validate::validate_package},
+        stdlib,
+    },
+    tests::packages::temp_root::TempRoot,
+    world::sim::{self, WorldState, requests::*},
+};
+
+/// a toggle flip flop on std bool, flips q on the rising edge of clk
+const TOGGLE: &str = r#"
+fn definition() {
+    let bool_req = #{ "package": "std", version_req: "=0.1.0", component: "bool" };
+    let bool_type = #{ "package": "std", version: "0.1.0", component: "bool" };
+
+    #{
+        consumers: [#{ name: "clk", data_type_req: bool_req, position: [0, 0.5] }],
+        producers: [#{ name: "q", data_type: bool_type, position: [1, 0.5] }],
+        bounding_box: #{ top: 0, bottom: 1, left: 0, right: 1 },
+    }
+}
+
+fn init() {
+    #{ q: false, clk: false }
+}
+
+fn tick(inputs) {
+    let clk = inputs[0];
+    if clk && !this.clk {
+        this.q = !this.q;
+    }
+    this.clk = clk;
+    [this.q]
+}
+
+fn serialize() {
+    [if this.q { 1 } else { 0 }, if this.clk { 1 } else { 0 }]
+}
+
+fn deserialize(bytes) {
+    if bytes.len() != 2 {
+        throw "expected 2 bytes";
+    }
+    #{ q: bytes[0] != 0, clk: bytes[1] != 0 }
+}
+"#;
+
+/// a stateless not gate on std bool
+const NOT: &str = r#"
+fn definition() {
+    #{
+        consumers: [#{
+            name: "in",
+            data_type_req: #{ "package": "std", version_req: "=0.1.0", component: "bool" },
+            position: [0, 0.5],
+        }],
+        producers: [#{
+            name: "out",
+            data_type: #{ "package": "std", version: "0.1.0", component: "bool" },
+            position: [1, 0.5],
+        }],
+        bounding_box: #{ top: 0, bottom: 1, left: 0, right: 1 },
+    }
+}
+
+fn tick(inputs) {
+    [!inputs[0]]
+}
+"#;
+
+fn component(name: &str) -> ComponentVersion {
+    ComponentVersion {
+        package: "scripted".to_string(),
+        version: Version::new(0, 1, 0),
+        component: name.to_string(),
+    }
+}
+
+#[test]
+fn script_gate_keeps_state() {
+    let root = TempRoot::new("script-toggle");
+    let lib_path = root.path().join("toggle.rhai");
+    fs::write(&lib_path, TOGGLE).unwrap();
+
+    let gate_type = DestructedGate::new_script(&lib_path, component("toggle")).unwrap();
+    assert!(gate_type.ticks_serialized());
+
+    let gate = gate_type.default_value().unwrap();
+    let definition = gate_type.normalised_definition(gate).unwrap();
+    assert_eq!(definition.consumers[0].name, "clk");
+    assert_eq!(definition.producers[0].data_type, stdlib::component("bool"));
+
+    let data = stdlib::data_handles();
+    let bool_type = &data[stdlib::PACKAGE][&stdlib::component("bool").version]["bool"];
+    let value = |bit: u8| {
+        bool_type
+            .deserialize(&slice::from_vec_rustonly(vec![bit]))
+            .unwrap()
+    };
+    let (low, high) = (value(0), value(1));
+
+    let tick = |clk| {
+        gate_type
+            .tick_serialized(gate, &[(bool_type.as_ref(), clk)], &[bool_type.as_ref()])
+            .unwrap()
+    };
+    assert_eq!(tick(high), vec![vec![1]]);
+    assert_eq!(tick(high), vec![vec![1]]);
+    assert_eq!(tick(low), vec![vec![1]]);
+    assert_eq!(tick(high), vec![vec![0]]);
+    assert_eq!(slice::from_slice::<u8>(&gate_type.serialize(gate)), &[0, 1]);
+
+    let copy = gate_type
+        .deserialize(&slice::from_vec_rustonly(vec![1u8, 0]))
+        .unwrap();
+    assert_eq!(slice::from_slice::<u8>(&gate_type.serialize(copy)), &[1, 0]);
+    assert!(
+        gate_type
+            .deserialize(&slice::from_vec_rustonly(vec![1u8]))
+            .is_none()
+    );
+
+    gate_type.drop_mem(copy);
+    gate_type.drop_mem(gate);
+    bool_type.drop_mem(low);
+    bool_type.drop_mem(high);
+}
+
+#[test]
+fn script_missing_tick() {
+    let root = TempRoot::new("script-missing");
+    let lib_path = root.path().join("broken.rhai");
+    fs::write(&lib_path, "fn definition() { #{} }").unwrap();
+
+    assert!(matches!(
+        DestructedGate::new_script(&lib_path, component("broken")),
+        Err(destructor::Error::Script { .. })
+    ));
+}
+
+#[test]
+fn world_ticks_script_gate() {
+    let root = TempRoot::new("script-world");
+    let version_root = root.add(
+        "scripted",
+        "0.1.0",
+        "[dependencies]\n\n[provides]\nnot = { type = \"gate\", library = \"rhai\" }\n",
+    );
+    fs::write(version_root.join("not.rhai"), NOT).unwrap();
+
+    let (index, res) = PackageIndexBuilder::new()
+        .add_roots(&[root.path().to_path_buf()])
+        .build();
+    res.unwrap();
+
+    let report = validate_package(&index, "scripted", &Version::new(0, 1, 0)).unwrap();
+    assert!(report.is_ok(), "{report:?}");
+
+    let loader = LazyComponentLoader::new(
+        &index,
+        HashMap::from([("scripted".to_string(), vec![Version::new(0, 1, 0)])]),
+        &Default::default(),
+    )
+    .unwrap();
+
+    let mut world = WorldState::new_blank(CreateBlankWorld::stdlib());
+    world.set_lazy_loader(SetLazyLoader {
+        loader: Some(Arc::new(loader)),
+    });
+
+    let not_gate = world
+        .create_default_gate(CreateDefaultGate {
+            gate: component("not"),
+        })
+        .unwrap();
+    world
+        .connect_gates(ConnectIOSockets {
+            producer_socket: GateProducerSocket::new(not_gate, 0),
+            consumer_socket: GateConsumerSocket::new(not_gate, 0),
+        })
+        .unwrap();
+
+    let get_data = |world: &WorldState| unsafe {
+        *(world
+            .get_buffer(&GateProducerSocket::new(not_gate, 0))
+            .unwrap()
+            .get_data_ptr() as *const u8)
+    };
+
+    assert_eq!(get_data(&world), 0);
+    world.tick_all().unwrap();
+    assert_eq!(get_data(&world), 1);
+    world.tick_all().unwrap();
+    assert_eq!(get_data(&world), 0);
+}
+
+#[test]
+fn script_data_is_not_indexed() {
+    let root = TempRoot::new("script-data");
+    root.add(
+        "scripted",
+        "0.1.0",
+        "[dependencies]\n\n[provides]\nbit = { type = \"data\", library = \"rhai\" }\n",
+    );
+
+    let (_, res) = PackageIndexBuilder::new()
+        .add_roots(&[root.path().to_path_buf()])
+        .build();
+    assert!(res.is_err());
+}
+
+#[test]
+fn reload_keeps_gates_the_new_script_rejects() {
+    let root = TempRoot::new("script-reload");
+    let lib_path = root.path().join("toggle.rhai");
+    fs::write(&lib_path, TOGGLE).unwrap();
+
+    let mut request = CreateBlankWorld::stdlib();
+    request.gate_handles.insert(
+        "scripted".to_string(),
+        [(
+            Version::new(0, 1, 0),
+            HashMap::from([(
+                "toggle".to_string(),
+                Arc::new(DestructedGate::new_script(&lib_path, component("toggle")).unwrap()),
+            )]),
+        )]
+        .into(),
+    );
+    let mut world = WorldState::new_blank(request);
+
+    let create = |world: &mut WorldState, gate| {
+        world
+            .create_default_gate(CreateDefaultGate { gate })
+            .unwrap()
+    };
+    let clock = create(&mut world, stdlib::component("not"));
+    let set = create(&mut world, component("toggle"));
+    let unset = create(&mut world, component("toggle"));
+    world
+        .connect_gates(ConnectIOSockets {
+            producer_socket: GateProducerSocket::new(clock, 0),
+            consumer_socket: GateConsumerSocket::new(set, 0),
+        })
+        .unwrap();
+
+    // the clock rises, so q of set is 1 and q of unset is still 0
+    world.tick_all().unwrap();
+    world.tick_all().unwrap();
+
+    // the new script cannot describe gates whose q is set
+    fs::write(
+        &lib_path,
+        TOGGLE.replacen(
+            "fn definition() {",
+            "fn definition() {\n    if this.q { throw \"q is set\"; }",
+            1,
+        ),
+    )
+    .unwrap();
+    let reloaded = world
+        .reload_component(ReloadComponent {
+            component: component("toggle"),
+        })
+        .unwrap();
+    assert_eq!(reloaded.gates, vec![unset]);
+    assert!(matches!(
+        reloaded.kept_gates.as_slice(),
+        [(gate_id, sim::Error::GateDefinition { .. })] if *gate_id == set
+    ));
+    assert!(reloaded.removed_gates.is_empty());
+    assert!(reloaded.disconnected.is_empty());
+    assert_eq!(
+        world.get_gate(&set).unwrap().get_consumer_source(0),
+        Some(&GateProducerSocket::new(clock, 0))
+    );
+
+    // a script that cannot describe any gate is rejected before the gates are touched
+    fs::write(
+        &lib_path,
+        TOGGLE.replacen(
+            "fn definition() {",
+            "fn definition() {\n    throw \"broken\";",
+            1,
+        ),
+    )
+    .unwrap();
+    assert!(matches!(
+        *world
+            .reload_component(ReloadComponent {
+                component: component("toggle"),
+            })
+            .unwrap_err(),
+        sim::Error::GateDefinition { .. }
+    ));
+    for gate_id in [set, unset] {
+        assert_eq!(
+            world.get_gate(&gate_id).unwrap().get_type(),
+            &component("toggle")
+        );
+    }
+    world.tick_all().unwrap();
+}
@@ -0,0 +1,94 @@
+use crate::packages::{chelper::slice, stdlib};
+
+#[test]
+fn bool_round_trips() {
+    let data = stdlib::data_handles();
+    let version = stdlib::component("bool").version;
+    let bool_type = &data[stdlib::PACKAGE][&version]["bool"];
+
+    let value = bool_type.default_value().unwrap();
+    assert_eq!(bool_type.to_bytes(value), vec![0]);
+    bool_type.drop_mem(value);
+
+    let value = bool_type
+        .deserialize(&slice::from_vec_rustonly(vec![1u8]))
+        .unwrap();
+    assert_eq!(bool_type.to_bytes(value), vec![1]);
+    bool_type.drop_mem(value);
+
+    assert!(
+        bool_type
+            .deserialize(&slice::from_vec_rustonly(vec![2u8]))
+            .is_none()
+    );
+}
+
+#[test]
+fn gates_tick_without_libraries() {
+    let data = stdlib::data_handles();
+    let gates = stdlib::gate_handles();
+    let version = stdlib::component("bool").version;
+    let bool_type = &data[stdlib::PACKAGE][&version]["bool"];
+    let gate_type = |name: &str| gates[stdlib::PACKAGE][&version][name].clone();
+
+    let value = |bit: u8| {
+        bool_type
+            .deserialize(&slice::from_vec_rustonly(vec![bit]))
+            .unwrap()
+    };
+    let (low, high) = (value(0), value(1));
+
+    let xor = gate_type("xor");
+    let gate = xor.default_value().unwrap();
+    let definition = xor.normalised_definition(gate).unwrap();
+    assert_eq!(definition.consumers.len(), 2);
+    assert_eq!(definition.producers[0].data_type, stdlib::component("bool"));
+
+    let tick = |a, b| {
+        xor.tick_serialized(
+            gate,
+            &[(bool_type.as_ref(), a), (bool_type.as_ref(), b)],
+            &[bool_type.as_ref()],
+        )
+        .unwrap()
+    };
+    assert_eq!(tick(low, high), vec![vec![1]]);
+    assert_eq!(tick(high, high), vec![vec![0]]);
+    xor.drop_mem(gate);
+
+    // q only follows d on the rising edge of clk
+    let dff = gate_type("dff");
+    let gate = dff.default_value().unwrap();
+    let tick = |d, clk| {
+        dff.tick_serialized(
+            gate,
+            &[(bool_type.as_ref(), d), (bool_type.as_ref(), clk)],
+            &[bool_type.as_ref()],
+        )
+        .unwrap()
+    };
+    assert_eq!(tick(high, low), vec![vec![0]]);
+    assert_eq!(tick(high, high), vec![vec![1]]);
+    assert_eq!(tick(low, high), vec![vec![1]]);
+    assert_eq!(tick(low, low), vec![vec![1]]);
+    assert_eq!(tick(low, high), vec![vec![0]]);
+    assert_eq!(slice::from_slice::<u8>(&dff.serialize(gate)), &[0, 1]);
+    dff.drop_mem(gate);
+
+    bool_type.drop_mem(low);
+    bool_type.drop_mem(high);
+}
+
+#[test]
+fn stateless_gates_have_their_own_pointers() {
+    let gates = stdlib::gate_handles();
+    let version = stdlib::component("bool").version;
+    let not = &gates[stdlib::PACKAGE][&version]["not"];
+
+    let first = not.default_value().unwrap();
+    let second = not.default_value().unwrap();
+    assert_ne!(first, second);
+
+    not.drop_mem(first);
+    not.drop_mem(second);
+}
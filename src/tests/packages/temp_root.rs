@@ -0,0 +1,53 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// a package root in the system temp directory, removed when dropped
+pub struct TempRoot {
+    path: PathBuf,
+}
+
+impl TempRoot {
+    pub fn new(label: &str) -> Self {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let path = std::env::temp_dir().join(format!(
+            "xdsim-test-{label}-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        Self { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// write a package version with the given manifest body
+    /// (everything after the [package] table)
+    pub fn add(&self, name: &str, version: &str, body: &str) -> PathBuf {
+        let version_root = self.path.join(name).join(version);
+        fs::create_dir_all(&version_root).unwrap();
+        fs::write(
+            version_root.join("package.toml"),
+            format!("[package]\nname = \"{name}\"\nversion = \"{version}\"\n\n{body}"),
+        )
+        .unwrap();
+        version_root
+    }
+
+    /// write a package version with no dependencies and no components
+    pub fn add_empty(&self, name: &str, version: &str) -> PathBuf {
+        self.add(name, version, "[dependencies]\n\n[provides]\n")
+    }
+}
+
+impl Drop for TempRoot {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
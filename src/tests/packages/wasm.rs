@@ -0,0 +1,471 @@
+use std::{collections::HashMap, fs, path::Path, sync::Arc};
+
+use semver::Version;
+
+use crate::{
+    common::world::{ComponentVersion, GateConsumerSocket, GateProducerSocket},
+    packages::{
+        chelper::slice,
+        destructor::{self, DestructedData, DestructedGate},
+        indexer::component::PackageIndexBuilder,
+        loader::indexed::{
+            component::{IndexComponentLoader, LoadOptions},
+            validate::validate_package,
+        },
+        stdlib,
+    },
+    tests::packages::temp_root::TempRoot,
+    world::sim::{self, SimData, WorldState, requests::*},
+};
+
+/// a not gate on std bool, its gates have no state
+const NOT_GATE: &str = r#"
+(module
+  (memory (export "memory") 1)
+  (global $next (mut i32) (i32.const 1024))
+  (data (i32.const 0) "DEFINITION")
+  (func (export "schema_version") (result i32) (i32.const SCHEMA_VERSION))
+  (func $alloc (export "alloc") (param $len i32) (result i32)
+    (local $ptr i32)
+    (local.set $ptr (global.get $next))
+    (global.set $next (i32.add (global.get $next) (local.get $len)))
+    (local.get $ptr))
+  (func (export "dealloc") (param i32 i32))
+  (func (export "gate_default") (result i32) (i32.const 1))
+  (func (export "gate_deserialize") (param i32 i32) (result i32) (i32.const 1))
+  (func (export "gate_serialize") (param i32) (result i64) (i64.const 0))
+  (func (export "gate_def") (param i32) (result i64)
+    (i64.const DEFINITION_LEN))
+  (func (export "gate_tick") (param $gate i32) (param $ptr i32) (param $len i32) (result i64)
+    (local $out i32)
+    (local.set $out (call $alloc (i32.const 5)))
+    (i32.store (local.get $out) (i32.const 1))
+    (i32.store8
+      (i32.add (local.get $out) (i32.const 4))
+      (i32.eqz (i32.load8_u (i32.add (local.get $ptr) (i32.const 4)))))
+    (i64.or
+      (i64.shl (i64.extend_i32_u (local.get $out)) (i64.const 32))
+      (i64.const 5)))
+  (func (export "gate_drop") (param i32)))
+"#;
+
+/// a bool whose handles are the value + 1
+const BOOL_DATA: &str = r#"
+(module
+  (memory (export "memory") 1)
+  (global $next (mut i32) (i32.const 1024))
+  (func (export "schema_version") (result i32) (i32.const 0))
+  (func $alloc (export "alloc") (param $len i32) (result i32)
+    (local $ptr i32)
+    (local.set $ptr (global.get $next))
+    (global.set $next (i32.add (global.get $next) (local.get $len)))
+    (local.get $ptr))
+  (func (export "dealloc") (param i32 i32))
+  (func (export "data_default") (result i32) (i32.const 1))
+  (func (export "data_deserialize") (param $ptr i32) (param $len i32) (result i32)
+    (if (result i32)
+      (i32.and
+        (i32.eq (local.get $len) (i32.const 1))
+        (i32.le_u (i32.load8_u (local.get $ptr)) (i32.const 1)))
+      (then (i32.add (i32.load8_u (local.get $ptr)) (i32.const 1)))
+      (else (i32.const 0))))
+  (func (export "data_serialize") (param $data i32) (result i64)
+    (local $out i32)
+    (local.set $out (call $alloc (i32.const 1)))
+    (i32.store8 (local.get $out) (i32.sub (local.get $data) (i32.const 1)))
+    (i64.or
+      (i64.shl (i64.extend_i32_u (local.get $out)) (i64.const 32))
+      (i64.const 1)))
+  (func (export "data_drop") (param i32)))
+"#;
+
+fn not_gate(schema_version: i32) -> Vec<u8> {
+    patched_not_gate(schema_version, "", "")
+}
+
+/// not_gate with the export starting with `(func (export "{export}")` replaced by body
+fn patched_not_gate(schema_version: i32, export: &str, body: &str) -> Vec<u8> {
+    let mut source = NOT_GATE.to_string();
+    if !export.is_empty() {
+        let start = source
+            .find(&format!("(func (export \"{export}\")"))
+            .unwrap();
+        let end = start + source[start..].find("\n  (func").unwrap();
+        source.replace_range(start..end, body);
+    }
+
+    let definition = format!(
+        r#"{{"consumers":[{{"name":"in","data_type_req":{{"package":"std","version_req":"={version}","component":"bool"}},"position":[0.0,0.5]}}],"producers":[{{"name":"out","data_type":{{"package":"std","version":"{version}","component":"bool"}},"position":[1.0,0.5]}}],"bounding_box":{{"top":0.0,"bottom":1.0,"left":0.0,"right":1.0}}}}"#,
+        version = stdlib::VERSION,
+    );
+
+    wat::parse_str(
+        source
+            .replace("DEFINITION_LEN", &definition.len().to_string())
+            .replace("DEFINITION", &definition.replace('"', "\\\""))
+            .replace("SCHEMA_VERSION", &schema_version.to_string()),
+    )
+    .unwrap()
+}
+
+fn write_module(path: &Path, bytes: &[u8]) {
+    fs::write(path, bytes).unwrap();
+}
+
+fn component(name: &str) -> ComponentVersion {
+    ComponentVersion {
+        package: "wasmlogic".to_string(),
+        version: Version::new(0, 1, 0),
+        component: name.to_string(),
+    }
+}
+
+#[test]
+fn gate_ticks_through_memory() {
+    let root = TempRoot::new("wasm-gate");
+    let lib_path = root.path().join("not.wasm");
+    write_module(&lib_path, &not_gate(0));
+
+    let gate_type = DestructedGate::new_wasm(&lib_path, component("not")).unwrap();
+    assert!(gate_type.ticks_serialized());
+    assert!(gate_type.get_library().is_none());
+
+    let gate = gate_type.default_value().unwrap();
+    let definition = gate_type.normalised_definition(gate).unwrap();
+    assert_eq!(definition.consumers[0].name, "in");
+    assert_eq!(definition.producers[0].data_type, stdlib::component("bool"));
+
+    let data = stdlib::data_handles();
+    let bool_type = &data[stdlib::PACKAGE][&stdlib::component("bool").version]["bool"];
+    let high = bool_type
+        .deserialize(&slice::from_vec_rustonly(vec![1u8]))
+        .unwrap();
+
+    let produced = gate_type
+        .tick_serialized(gate, &[(bool_type.as_ref(), high)], &[bool_type.as_ref()])
+        .unwrap();
+    assert_eq!(produced, vec![vec![0]]);
+
+    // gates ticked with data pointers cannot read a wasm bool
+    let wasm_bool = root.path().join("bool.wasm");
+    write_module(&wasm_bool, &wat::parse_str(BOOL_DATA).unwrap());
+    let wasm_bool = DestructedData::new_wasm(&wasm_bool, component("bool")).unwrap();
+    let native_not =
+        &stdlib::gate_handles()[stdlib::PACKAGE][&stdlib::component("not").version]["not"];
+    assert!(!native_not.can_use_data(&wasm_bool));
+    assert!(gate_type.can_use_data(&wasm_bool));
+    assert!(gate_type.can_use_data(bool_type));
+
+    bool_type.drop_mem(high);
+    gate_type.drop_mem(gate);
+}
+
+#[test]
+fn data_round_trips() {
+    let root = TempRoot::new("wasm-data");
+    let lib_path = root.path().join("bool.wasm");
+    write_module(&lib_path, &wat::parse_str(BOOL_DATA).unwrap());
+
+    let bool_type = DestructedData::new_wasm(&lib_path, component("bool")).unwrap();
+    assert!(!bool_type.is_in_process());
+
+    let value = bool_type.default_value().unwrap();
+    assert_eq!(bool_type.to_bytes(value), vec![0]);
+    bool_type.drop_mem(value);
+
+    let value = bool_type
+        .deserialize(&slice::from_vec_rustonly(vec![1u8]))
+        .unwrap();
+    assert_eq!(bool_type.to_bytes(value), vec![1]);
+    bool_type.drop_mem(value);
+
+    assert!(
+        bool_type
+            .deserialize(&slice::from_vec_rustonly(vec![2u8]))
+            .is_none()
+    );
+}
+
+#[test]
+fn unsupported_schema_version() {
+    let root = TempRoot::new("wasm-schema");
+    let lib_path = root.path().join("not.wasm");
+    write_module(&lib_path, &not_gate(7));
+
+    assert!(matches!(
+        DestructedGate::new_wasm(&lib_path, component("not")),
+        Err(destructor::Error::UnsupportedSchemaVersion { version: 7 })
+    ));
+}
+
+#[test]
+fn world_ticks_wasm_gate() {
+    let root = TempRoot::new("wasm-world");
+    let version_root = root.add(
+        "wasmlogic",
+        "0.1.0",
+        "[dependencies]\n\n[provides]\nnot = { type = \"gate\", library = \"wasm\" }\n",
+    );
+    write_module(&version_root.join("not.wasm"), &not_gate(0));
+
+    let (index, res) = PackageIndexBuilder::new()
+        .add_roots(&[root.path().to_path_buf()])
+        .build();
+    res.unwrap();
+
+    let report = validate_package(&index, "wasmlogic", &Version::new(0, 1, 0)).unwrap();
+    assert!(report.is_ok(), "{report:?}");
+
+    let loaded = IndexComponentLoader::load_all(
+        &index,
+        HashMap::from([("wasmlogic".to_string(), vec![Version::new(0, 1, 0)])]),
+        &LoadOptions::new(),
+    )
+    .unwrap();
+
+    let mut request = CreateBlankWorld::stdlib();
+    request.gate_handles.extend(loaded.gates);
+    let mut world = WorldState::new_blank(request);
+
+    let not_gate = world
+        .create_default_gate(CreateDefaultGate {
+            gate: component("not"),
+        })
+        .unwrap();
+    world
+        .connect_gates(ConnectIOSockets {
+            producer_socket: GateProducerSocket::new(not_gate, 0),
+            consumer_socket: GateConsumerSocket::new(not_gate, 0),
+        })
+        .unwrap();
+
+    let get_data = |world: &WorldState| unsafe {
+        *(world
+            .get_buffer(&GateProducerSocket::new(not_gate, 0))
+            .unwrap()
+            .get_data_ptr() as *const u8)
+    };
+
+    assert_eq!(get_data(&world), 0);
+    world.tick_all().unwrap();
+    assert_eq!(get_data(&world), 1);
+    world.tick_all().unwrap();
+    assert_eq!(get_data(&world), 0);
+}
+
+#[test]
+fn wasm_conn_is_not_indexed() {
+    let root = TempRoot::new("wasm-conn");
+    root.add(
+        "wasmlogic",
+        "0.1.0",
+        "[dependencies]\n\n[provides]\nwire = { type = \"conn\", library = \"wasm\" }\n",
+    );
+
+    let (_, res) = PackageIndexBuilder::new()
+        .add_roots(&[root.path().to_path_buf()])
+        .build();
+    assert!(res.is_err());
+}
+
+#[test]
+fn trapping_default_fails_gate_creation() {
+    let root = TempRoot::new("wasm-trap-default");
+    let lib_path = root.path().join("not.wasm");
+    write_module(
+        &lib_path,
+        &patched_not_gate(
+            0,
+            "gate_default",
+            "(func (export \"gate_default\") (result i32) (unreachable))",
+        ),
+    );
+
+    let gate_type = DestructedGate::new_wasm(&lib_path, component("not")).unwrap();
+    assert!(matches!(
+        gate_type.default_value(),
+        Err(destructor::Error::Wasm { .. })
+    ));
+
+    let mut request = CreateBlankWorld::stdlib();
+    request.gate_handles.insert(
+        "wasmlogic".to_string(),
+        [(
+            Version::new(0, 1, 0),
+            HashMap::from([("not".to_string(), Arc::new(gate_type))]),
+        )]
+        .into(),
+    );
+    let mut world = WorldState::new_blank(request);
+
+    match world.create_default_gate(CreateDefaultGate {
+        gate: component("not"),
+    }) {
+        Err(e) => match *e {
+            sim::Error::CreateGate { gate_type, .. } => assert_eq!(gate_type, component("not")),
+            e => panic!("expected a gate that cannot be created, got {e:?}"),
+        },
+        Ok(_) => panic!("expected a gate that cannot be created"),
+    }
+}
+
+#[test]
+fn trapping_default_fails_data_creation() {
+    let root = TempRoot::new("wasm-trap-data-default");
+    let lib_path = root.path().join("bool.wasm");
+    write_module(
+        &lib_path,
+        &wat::parse_str(BOOL_DATA.replace(
+            "(func (export \"data_default\") (result i32) (i32.const 1))",
+            "(func (export \"data_default\") (result i32) (unreachable))",
+        ))
+        .unwrap(),
+    );
+
+    let bool_type = Arc::new(DestructedData::new_wasm(&lib_path, component("bool")).unwrap());
+    assert!(matches!(
+        bool_type.default_value(),
+        Err(destructor::Error::Wasm { .. })
+    ));
+
+    match SimData::new_default(bool_type) {
+        Err(e) => match *e {
+            sim::Error::CreateData { data_type, .. } => assert_eq!(data_type, component("bool")),
+            e => panic!("expected data that cannot be created, got {e:?}"),
+        },
+        Ok(_) => panic!("expected data that cannot be created"),
+    }
+}
+
+#[test]
+fn trapping_tick_is_not_a_sandbox_error() {
+    let root = TempRoot::new("wasm-trap-tick");
+    let lib_path = root.path().join("not.wasm");
+    write_module(
+        &lib_path,
+        &patched_not_gate(
+            0,
+            "gate_tick",
+            "(func (export \"gate_tick\") (param i32 i32 i32) (result i64) (unreachable))",
+        ),
+    );
+
+    let mut request = CreateBlankWorld::stdlib();
+    request.gate_handles.insert(
+        "wasmlogic".to_string(),
+        [(
+            Version::new(0, 1, 0),
+            HashMap::from([(
+                "not".to_string(),
+                Arc::new(DestructedGate::new_wasm(&lib_path, component("not")).unwrap()),
+            )]),
+        )]
+        .into(),
+    );
+    let mut world = WorldState::new_blank(request);
+
+    let gate_id = world
+        .create_default_gate(CreateDefaultGate {
+            gate: component("not"),
+        })
+        .unwrap();
+
+    let errors = match *world.tick_all().unwrap_err() {
+        sim::Error::TickallErrors { errors } => errors,
+        e => panic!("expected tick errors, got {e:?}"),
+    };
+    match errors.as_slice() {
+        [entry] => match entry.get_content() {
+            sim::Error::TickSingleGate { errors, .. } => assert!(matches!(
+                errors.as_slice(),
+                [sim::Error::SerializedTick { gate_id: got, .. }] if *got == gate_id
+            )),
+            e => panic!("expected a gate error, got {e:?}"),
+        },
+        e => panic!("expected a single error, got {e:?}"),
+    }
+}
+
+#[test]
+fn out_of_bounds_output_is_rejected() {
+    let root = TempRoot::new("wasm-out-of-bounds");
+    let lib_path = root.path().join("not.wasm");
+    // 4 GiB at the start of a 64 KiB memory
+    write_module(
+        &lib_path,
+        &patched_not_gate(
+            0,
+            "gate_tick",
+            "(func (export \"gate_tick\") (param i32 i32 i32) (result i64) (i64.const 0xffffffff))",
+        ),
+    );
+
+    let gate_type = DestructedGate::new_wasm(&lib_path, component("not")).unwrap();
+    let gate = gate_type.default_value().unwrap();
+
+    let data = stdlib::data_handles();
+    let bool_type = &data[stdlib::PACKAGE][&stdlib::component("bool").version]["bool"];
+    let low = bool_type.default_value().unwrap();
+
+    assert!(matches!(
+        gate_type.tick_serialized(gate, &[(bool_type.as_ref(), low)], &[bool_type.as_ref()]),
+        Err(destructor::Error::Wasm { .. })
+    ));
+
+    bool_type.drop_mem(low);
+    gate_type.drop_mem(gate);
+}
+
+#[test]
+fn looping_tick_runs_out_of_fuel() {
+    let root = TempRoot::new("wasm-loop");
+    let lib_path = root.path().join("not.wasm");
+    write_module(
+        &lib_path,
+        &patched_not_gate(
+            0,
+            "gate_tick",
+            "(func (export \"gate_tick\") (param i32 i32 i32) (result i64) (loop (br 0)) (i64.const 0))",
+        ),
+    );
+
+    let gate_type = DestructedGate::new_wasm(&lib_path, component("not")).unwrap();
+    let gate = gate_type.default_value().unwrap();
+
+    let data = stdlib::data_handles();
+    let bool_type = &data[stdlib::PACKAGE][&stdlib::component("bool").version]["bool"];
+    let low = bool_type.default_value().unwrap();
+
+    // each tick gets a fresh budget, so it fails every time instead of hanging
+    for _ in 0..2 {
+        assert!(matches!(
+            gate_type.tick_serialized(gate, &[(bool_type.as_ref(), low)], &[bool_type.as_ref()]),
+            Err(destructor::Error::Wasm { .. })
+        ));
+    }
+
+    bool_type.drop_mem(low);
+    gate_type.drop_mem(gate);
+}
+
+#[test]
+fn memory_cannot_grow_past_the_limit() {
+    let root = TempRoot::new("wasm-grow");
+    let lib_path = root.path().join("not.wasm");
+    // 0x8000 pages is 2 GiB, memory.grow returns -1 when it is refused
+    write_module(
+        &lib_path,
+        &patched_not_gate(
+            0,
+            "gate_default",
+            "(func (export \"gate_default\") (result i32) (i32.add (memory.grow (i32.const 0x8000)) (i32.const 1)))",
+        ),
+    );
+
+    // the refused grow makes gate_default return handle 0, which is not a gate
+    let gate_type = DestructedGate::new_wasm(&lib_path, component("not")).unwrap();
+    assert!(matches!(
+        gate_type.default_value(),
+        Err(destructor::Error::Wasm { .. })
+    ));
+}
@@ -0,0 +1,128 @@
+use std::{collections::HashMap, fs, sync::Arc};
+
+use semver::Version;
+
+use crate::{
+    common::world::ComponentVersion,
+    packages::{
+        destructor::{DestructedConn, DestructedData, DestructedGate},
+        indexer::component::{IndexEvent, PackageIndexBuilder},
+        loader::indexed::lazy::LazyComponentLoader,
+    },
+    tests::packages::temp_root::TempRoot,
+    world::{MasterWorld, layout, sim},
+};
+
+fn assert_send<T: Send>() {}
+fn assert_send_sync<T: Send + Sync>() {}
+
+#[test]
+fn world_states_are_send() {
+    assert_send::<sim::WorldState>();
+    assert_send::<layout::WorldState>();
+    assert_send::<MasterWorld>();
+}
+
+#[test]
+fn handles_are_shareable() {
+    assert_send_sync::<DestructedGate>();
+    assert_send_sync::<DestructedData>();
+    assert_send_sync::<DestructedConn>();
+}
+
+#[test]
+fn refresh_packages() {
+    const BODY: &str = "[dependencies]\n\n[provides]\nnot = \"gate\"\n";
+
+    let root = TempRoot::new("master-refresh");
+    let v1_root = root.add("logic", "0.1.0", BODY);
+
+    let (mut index, res) = PackageIndexBuilder::new()
+        .add_roots(&[root.path().to_path_buf()])
+        .build();
+    res.unwrap();
+
+    let v1 = Version::new(0, 1, 0);
+    let v2 = Version::new(0, 2, 0);
+    let not = |version: &Version| ComponentVersion {
+        package: "logic".to_string(),
+        version: version.clone(),
+        component: "not".to_string(),
+    };
+
+    let lazy = Arc::new(
+        LazyComponentLoader::new(
+            &index,
+            HashMap::from([("logic".to_string(), vec![v1.clone()])]),
+            &HashMap::new(),
+        )
+        .unwrap(),
+    );
+    let mut world = MasterWorld::new_blank(layout::CreateBlankWorld::stdlib());
+    world.set_lazy_loader(Some(lazy.clone()));
+
+    // nothing changed on disk
+    let refreshed = world.refresh_packages(&mut index);
+    refreshed.index_res.unwrap();
+    assert!(refreshed.events.is_empty());
+
+    root.add("logic", "0.2.0", BODY);
+    let refreshed = world.refresh_packages(&mut index);
+    refreshed.index_res.unwrap();
+    assert!(refreshed.failed.is_empty());
+    assert_eq!(
+        refreshed.events,
+        [IndexEvent::Added {
+            name: "logic".to_string(),
+            version: v2.clone(),
+        }]
+    );
+    assert!(lazy.contains(&not(&v2)));
+    // registered, but not loaded until a gate uses it
+    assert!(!lazy.is_loaded(&not(&v2)));
+    assert!(
+        index
+            .get_package("logic")
+            .unwrap()
+            .get_version(&v2)
+            .is_some()
+    );
+
+    fs::remove_dir_all(v1_root).unwrap();
+    let refreshed = world.refresh_packages(&mut index);
+    refreshed.index_res.unwrap();
+    assert_eq!(
+        refreshed.events,
+        [IndexEvent::Removed {
+            name: "logic".to_string(),
+            version: v1.clone(),
+        }]
+    );
+    assert!(!lazy.contains(&not(&v1)));
+    assert!(lazy.contains(&not(&v2)));
+    assert!(
+        index
+            .get_package("logic")
+            .unwrap()
+            .get_version(&v1)
+            .is_none()
+    );
+}
+
+#[test]
+fn refresh_packages_sets_a_lazy_loader() {
+    let root = TempRoot::new("master-refresh-no-loader");
+    let (mut index, res) = PackageIndexBuilder::new()
+        .add_roots(&[root.path().to_path_buf()])
+        .build();
+    res.unwrap();
+
+    let mut world = MasterWorld::new_blank(layout::CreateBlankWorld::stdlib());
+
+    root.add_empty("logic", "0.1.0");
+    let refreshed = world.refresh_packages(&mut index);
+    refreshed.index_res.unwrap();
+    assert!(refreshed.failed.is_empty());
+    assert_eq!(refreshed.events.len(), 1);
+    assert!(index.get_package("logic").is_some());
+}
@@ -1,2 +1,3 @@
 mod layout;
+mod master;
 mod sim;
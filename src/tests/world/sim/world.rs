@@ -1,83 +1,41 @@
-use semver::{Version, VersionReq};
+use std::{
+    collections::{BTreeMap, HashMap},
+    ffi::c_void,
+    sync::Arc,
+};
+
+use semver::Version;
 
 use crate::{
-    common::world::{ComponentVersion, GateConsumerSocket, GateProducerSocket},
+    common::world::{ComponentId, ComponentVersion, GateConsumerSocket, GateProducerSocket},
     packages::{
-        indexer::{
-            component::PackageIndexBuilder,
-            deps_resolver::{DepsResolveRequest, deps_resolver},
-        },
-        loader::indexed::component::IndexComponentLoader,
+        chelper::slice,
+        indexer::{component::PackageIndexBuilder, deps_resolver::EnabledFeatures},
+        loader::indexed::lazy::LazyComponentLoader,
+        stdlib,
     },
-    world::sim::{WorldState, requests::*},
+    tests::packages::{fake_library, temp_root::TempRoot},
+    world::sim::{self, WorldState, requests::*},
 };
 
 #[test]
 pub fn create_not_gate() {
-    let (index, res) = PackageIndexBuilder::new()
-        .add_roots(&[dirs::data_dir().unwrap().join("xdsim/packages/components/")])
-        .build();
-
-    res.unwrap();
-
-    let to_load = deps_resolver(
-        &index,
-        &[DepsResolveRequest::new(
-            "testlib".to_string(),
-            VersionReq::parse("0.1.0").unwrap(),
-        )],
-    )
-    .unwrap();
-
-    let loaded_libs = IndexComponentLoader::load_all(index, to_load).unwrap();
-
-    let mut world = WorldState::new_blank(CreateBlankWorld {
-        data_handles: loaded_libs.data,
-        gate_handles: loaded_libs.gates,
-    });
+    let mut world = WorldState::new_blank(CreateBlankWorld::stdlib());
 
     world
         .create_default_gate(CreateDefaultGate {
-            gate: ComponentVersion {
-                package: "testlib".to_string(),
-                version: Version::parse("0.1.0").unwrap(),
-                component: "not".to_string(),
-            },
+            gate: stdlib::component("not"),
         })
         .unwrap();
 }
 
 #[test]
 pub fn tick_not_gate_no_producer() {
-    let (index, res) = PackageIndexBuilder::new()
-        .add_roots(&[dirs::data_dir().unwrap().join("xdsim/packages/components/")])
-        .build();
-
-    res.unwrap();
-
-    let to_load = deps_resolver(
-        &index,
-        &[DepsResolveRequest::new(
-            "testlib".to_string(),
-            VersionReq::parse("0.1.0").unwrap(),
-        )],
-    )
-    .unwrap();
-
-    let loaded_libs = IndexComponentLoader::load_all(index, to_load).unwrap();
-
-    let mut world = WorldState::new_blank(CreateBlankWorld {
-        data_handles: loaded_libs.data,
-        gate_handles: loaded_libs.gates,
-    });
+    let mut world = WorldState::new_blank(CreateBlankWorld::stdlib());
 
     world
         .create_default_gate(CreateDefaultGate {
-            gate: ComponentVersion {
-                package: "testlib".to_string(),
-                version: Version::parse("0.1.0").unwrap(),
-                component: "not".to_string(),
-            },
+            gate: stdlib::component("not"),
         })
         .unwrap();
 
@@ -86,35 +44,11 @@ pub fn tick_not_gate_no_producer() {
 
 #[test]
 pub fn tick_not_gate_multiple() {
-    let (index, res) = PackageIndexBuilder::new()
-        .add_roots(&[dirs::data_dir().unwrap().join("xdsim/packages/components/")])
-        .build();
-
-    res.unwrap();
-
-    let to_load = deps_resolver(
-        &index,
-        &[DepsResolveRequest::new(
-            "testlib".to_string(),
-            VersionReq::parse("0.1.0").unwrap(),
-        )],
-    )
-    .unwrap();
-
-    let loaded_libs = IndexComponentLoader::load_all(index, to_load).unwrap();
-
-    let mut world = WorldState::new_blank(CreateBlankWorld {
-        data_handles: loaded_libs.data,
-        gate_handles: loaded_libs.gates,
-    });
+    let mut world = WorldState::new_blank(CreateBlankWorld::stdlib());
 
     let not_gate = world
         .create_default_gate(CreateDefaultGate {
-            gate: ComponentVersion {
-                package: "testlib".to_string(),
-                version: Version::parse("0.1.0").unwrap(),
-                component: "not".to_string(),
-            },
+            gate: stdlib::component("not"),
         })
         .unwrap();
 
@@ -151,46 +85,65 @@ pub fn tick_not_gate_multiple() {
     assert_eq!(dbg!(get_data!()), 0);
 }
 
+/// gates of one type are ticked in a single batch,
+/// each gate must still read its own consumers and write its own producers
 #[test]
-pub fn tick_not_gate_disconnect_connect() {
-    let (index, res) = PackageIndexBuilder::new()
-        .add_roots(&[dirs::data_dir().unwrap().join("xdsim/packages/components/")])
-        .build();
-
-    res.unwrap();
+pub fn tick_not_gate_chain() {
+    let mut world = WorldState::new_blank(CreateBlankWorld::stdlib());
+
+    let gates: Vec<_> = (0..3)
+        .map(|_| {
+            world
+                .create_default_gate(CreateDefaultGate {
+                    gate: stdlib::component("not"),
+                })
+                .unwrap()
+        })
+        .collect();
+
+    // the first gate is unbound, every other gate consumes the one before it
+    for pair in gates.windows(2) {
+        world
+            .connect_gates(ConnectIOSockets {
+                producer_socket: GateProducerSocket::new(pair[0], 0),
+                consumer_socket: GateConsumerSocket::new(pair[1], 0),
+            })
+            .unwrap();
+    }
 
-    let to_load = deps_resolver(
-        &index,
-        &[DepsResolveRequest::new(
-            "testlib".to_string(),
-            VersionReq::parse("0.1.0").unwrap(),
-        )],
-    )
-    .unwrap();
+    let get_data = |world: &WorldState| -> Vec<u8> {
+        gates
+            .iter()
+            .map(|gate| unsafe {
+                *(world
+                    .get_buffer(&GateProducerSocket::new(*gate, 0))
+                    .unwrap()
+                    .get_data_ptr() as *const u8)
+            })
+            .collect()
+    };
 
-    let loaded_libs = IndexComponentLoader::load_all(index, to_load).unwrap();
+    assert_eq!(get_data(&world), vec![0, 0, 0]);
+    world.tick_all().unwrap();
+    assert_eq!(get_data(&world), vec![1, 1, 1]);
+    world.tick_all().unwrap();
+    assert_eq!(get_data(&world), vec![1, 0, 0]);
+    world.tick_all().unwrap();
+    assert_eq!(get_data(&world), vec![1, 0, 1]);
+}
 
-    let mut world = WorldState::new_blank(CreateBlankWorld {
-        data_handles: loaded_libs.data,
-        gate_handles: loaded_libs.gates,
-    });
+#[test]
+pub fn tick_not_gate_disconnect_connect() {
+    let mut world = WorldState::new_blank(CreateBlankWorld::stdlib());
 
     let not1 = world
         .create_default_gate(CreateDefaultGate {
-            gate: ComponentVersion {
-                package: "testlib".to_string(),
-                version: Version::parse("0.1.0").unwrap(),
-                component: "not".to_string(),
-            },
+            gate: stdlib::component("not"),
         })
         .unwrap();
     let not2 = world
         .create_default_gate(CreateDefaultGate {
-            gate: ComponentVersion {
-                package: "testlib".to_string(),
-                version: Version::parse("0.1.0").unwrap(),
-                component: "not".to_string(),
-            },
+            gate: stdlib::component("not"),
         })
         .unwrap();
 
@@ -325,3 +278,359 @@ pub fn tick_not_gate_disconnect_connect() {
     assert_ne!(dbg!(get_data!(not1)), 0);
     assert_eq!(dbg!(get_data!(not2)), 0);
 }
+
+#[test]
+pub fn reload_not_gate_keeps_connections() {
+    let mut world = WorldState::new_blank(CreateBlankWorld::stdlib());
+
+    let not_gate = stdlib::component("not");
+
+    let not1 = world
+        .create_default_gate(CreateDefaultGate {
+            gate: not_gate.clone(),
+        })
+        .unwrap();
+    let not2 = world
+        .create_default_gate(CreateDefaultGate {
+            gate: not_gate.clone(),
+        })
+        .unwrap();
+
+    world
+        .connect_gates(ConnectIOSockets {
+            producer_socket: GateProducerSocket::new(not1, 0),
+            consumer_socket: GateConsumerSocket::new(not1, 0),
+        })
+        .unwrap();
+    world
+        .connect_gates(ConnectIOSockets {
+            producer_socket: GateProducerSocket::new(not1, 0),
+            consumer_socket: GateConsumerSocket::new(not2, 0),
+        })
+        .unwrap();
+
+    macro_rules! get_data {
+        ($x : expr) => {
+            unsafe {
+                *(world
+                    .get_buffer(&GateProducerSocket::new($x, 0))
+                    .unwrap()
+                    .get_data_ptr() as *const u8)
+            }
+        };
+    }
+
+    world.tick_all().unwrap();
+    assert_ne!(dbg!(get_data!(not1)), 0);
+    assert_ne!(dbg!(get_data!(not2)), 0);
+
+    let reloaded = world
+        .reload_component(ReloadComponent {
+            component: not_gate,
+        })
+        .unwrap();
+
+    assert_eq!(reloaded.gates, vec![not1, not2]);
+    assert!(reloaded.reset_gates.is_empty());
+    assert!(reloaded.removed_gates.is_empty());
+    assert!(reloaded.disconnected.is_empty());
+
+    // buffers survive the reload
+    assert_ne!(dbg!(get_data!(not1)), 0);
+    assert_ne!(dbg!(get_data!(not2)), 0);
+
+    // and so do the connections
+    world.tick_all().unwrap();
+    assert_eq!(dbg!(get_data!(not1)), 0);
+    assert_eq!(dbg!(get_data!(not2)), 0);
+}
+
+#[test]
+fn lazy_gate_fails_on_first_use() {
+    let root = TempRoot::new("world-lazy");
+    root.add(
+        "logic",
+        "0.1.0",
+        "[dependencies]\n\n[provides]\nnot = \"gate\"\n",
+    );
+
+    let (index, res) = PackageIndexBuilder::new()
+        .add_roots(&[root.path().to_path_buf()])
+        .build();
+    res.unwrap();
+
+    let to_load = HashMap::from([("logic".to_string(), vec![Version::parse("0.1.0").unwrap()])]);
+    let lazy = LazyComponentLoader::new(&index, to_load, &EnabledFeatures::new()).unwrap();
+
+    let mut world = WorldState::new_blank(CreateBlankWorld::empty());
+    world.set_lazy_loader(SetLazyLoader {
+        loader: Some(Arc::new(lazy)),
+    });
+
+    let not = ComponentVersion {
+        package: "logic".to_string(),
+        version: Version::parse("0.1.0").unwrap(),
+        component: "not".to_string(),
+    };
+
+    // registered but never loaded, so there is nothing to reload
+    let reloaded = world
+        .reload_component(ReloadComponent {
+            component: not.clone(),
+        })
+        .unwrap();
+    assert!(reloaded.gates.is_empty());
+
+    // the library does not exist, which is only found out when the gate is created
+    let err = world
+        .create_default_gate(CreateDefaultGate { gate: not })
+        .unwrap_err();
+    assert!(matches!(*err, sim::Error::LoadComponent { .. }));
+}
+
+#[test]
+fn register_rejects_stdlib_handles() {
+    let mut world = WorldState::new_blank(CreateBlankWorld::empty());
+
+    match world.register_handles(RegisterHandles {
+        data_handles: stdlib::data_handles(),
+        gate_handles: stdlib::gate_handles(),
+    }) {
+        Err(e) => match *e {
+            sim::Error::ReservedPackage { package } => assert_eq!(package, "std"),
+            other => panic!("expected a reserved package, got {other:?}"),
+        },
+        Ok(()) => panic!("expected a reserved package"),
+    }
+
+    // nothing is registered
+    assert!(
+        world
+            .create_default_gate(CreateDefaultGate {
+                gate: stdlib::component("not"),
+            })
+            .is_err()
+    );
+}
+
+/// a world with the std gates and the not gate of fake_library
+fn fake_library_world(symbols: HashMap<String, *const c_void>) -> WorldState {
+    let component = fake_library::component();
+    let mut request = CreateBlankWorld::stdlib();
+    request.gate_handles.insert(
+        component.package,
+        BTreeMap::from([(
+            component.version,
+            HashMap::from([(
+                component.component,
+                Arc::new(fake_library::destruct(symbols).unwrap()),
+            )]),
+        )]),
+    );
+    WorldState::new_blank(request)
+}
+
+fn fake_library_state(world: &WorldState, gate_id: &ComponentId) -> fake_library::NotState {
+    let gate = world.get_gate(gate_id).unwrap();
+    let bytes = gate.get_handle().serialize(gate.get_gate_ptr());
+    fake_library::NotState::from_bytes(slice::from_slice(&bytes))
+}
+
+#[test]
+fn tick_gates_in_batch() {
+    let mut world = fake_library_world(fake_library::symbols());
+
+    // fake -> fake -> std not -> first fake
+    let gates: Vec<ComponentId> = [
+        fake_library::component(),
+        fake_library::component(),
+        stdlib::component("not"),
+    ]
+    .into_iter()
+    .map(|gate| {
+        world
+            .create_default_gate(CreateDefaultGate { gate })
+            .unwrap()
+    })
+    .collect();
+    for (index, gate) in gates.iter().enumerate() {
+        world
+            .connect_gates(ConnectIOSockets {
+                producer_socket: GateProducerSocket::new(*gate, 0),
+                consumer_socket: GateConsumerSocket::new(gates[(index + 1) % gates.len()], 0),
+            })
+            .unwrap();
+    }
+
+    let get_data = |world: &WorldState, gate: ComponentId| unsafe {
+        *(world
+            .get_buffer(&GateProducerSocket::new(gate, 0))
+            .unwrap()
+            .get_data_ptr() as *const u8)
+    };
+
+    world.tick_all().unwrap();
+    assert_eq!(
+        gates
+            .iter()
+            .map(|gate| get_data(&world, *gate))
+            .collect::<Vec<_>>(),
+        [1, 1, 1]
+    );
+    world.tick_all().unwrap();
+    assert_eq!(
+        gates
+            .iter()
+            .map(|gate| get_data(&world, *gate))
+            .collect::<Vec<_>>(),
+        [0, 0, 0]
+    );
+
+    for gate in &gates[..2] {
+        let state = fake_library_state(&world, gate);
+        assert_eq!((state.ticks, state.batched), (0, 2));
+    }
+}
+
+#[test]
+fn batch_tick_checks_produced_count() {
+    let mut world = fake_library_world(fake_library::extra_batch_symbols());
+
+    let gates: Vec<ComponentId> = (0..2)
+        .map(|_| {
+            world
+                .create_default_gate(CreateDefaultGate {
+                    gate: fake_library::component(),
+                })
+                .unwrap()
+        })
+        .collect();
+
+    let errors = match *world.tick_all().unwrap_err() {
+        sim::Error::TickallErrors { errors } => errors,
+        e => panic!("expected tick errors, got {e:?}"),
+    };
+    assert_eq!(errors.len(), 2);
+    for entry in errors {
+        assert!(gates.contains(&entry.get_emitter()));
+        match entry.get_content() {
+            sim::Error::TickSingleGate { errors, .. } => assert!(matches!(
+                errors.as_slice(),
+                [sim::Error::BatchTick {
+                    gates: 2,
+                    produced: 3,
+                    ..
+                }]
+            )),
+            e => panic!("expected a batch tick error, got {e:?}"),
+        }
+    }
+
+    // the gates have ticked once and keep their old producers
+    for gate in gates {
+        let buffer = world.get_buffer(&GateProducerSocket::new(gate, 0)).unwrap();
+        assert_eq!(unsafe { *(buffer.get_data_ptr() as *const u8) }, 0);
+
+        let state = fake_library_state(&world, &gate);
+        assert_eq!((state.ticks, state.batched), (0, 1));
+    }
+}
+
+#[cfg(feature = "script")]
+#[test]
+fn removing_the_last_gate_unloads_the_library() {
+    let root = TempRoot::new("world-lazy-unload");
+    let version_root = root.add(
+        "scripted",
+        "0.1.0",
+        "[dependencies]\n\n[provides]\nnot = { type = \"gate\", library = \"rhai\" }\n",
+    );
+    std::fs::write(
+        version_root.join("not.rhai"),
+        r#"
+fn definition() {
+    #{
+        consumers: [#{
+            name: "in",
+            data_type_req: #{ "package": "std", version_req: "=0.1.0", component: "bool" },
+            position: [0, 0.5],
+        }],
+        producers: [#{
+            name: "out",
+            data_type: #{ "package": "std", version: "0.1.0", component: "bool" },
+            position: [1, 0.5],
+        }],
+        bounding_box: #{ top: 0, bottom: 1, left: 0, right: 1 },
+    }
+}
+
+fn tick(inputs) {
+    [!inputs[0]]
+}
+"#,
+    )
+    .unwrap();
+
+    let (index, res) = PackageIndexBuilder::new()
+        .add_roots(&[root.path().to_path_buf()])
+        .build();
+    res.unwrap();
+
+    let to_load = HashMap::from([("scripted".to_string(), vec![Version::new(0, 1, 0)])]);
+    let lazy =
+        Arc::new(LazyComponentLoader::new(&index, to_load, &EnabledFeatures::new()).unwrap());
+
+    let mut world = WorldState::new_blank(CreateBlankWorld::stdlib());
+    world.set_lazy_loader(SetLazyLoader {
+        loader: Some(lazy.clone()),
+    });
+
+    let not = ComponentVersion {
+        package: "scripted".to_string(),
+        version: Version::new(0, 1, 0),
+        component: "not".to_string(),
+    };
+    assert!(!lazy.is_loaded(&not));
+
+    let gates: Vec<ComponentId> = (0..2)
+        .map(|_| {
+            world
+                .create_default_gate(CreateDefaultGate { gate: not.clone() })
+                .unwrap()
+        })
+        .collect();
+    world
+        .connect_gates(ConnectIOSockets {
+            producer_socket: GateProducerSocket::new(gates[0], 0),
+            consumer_socket: GateConsumerSocket::new(gates[1], 0),
+        })
+        .unwrap();
+    world.tick_all().unwrap();
+    assert!(lazy.is_loaded(&not));
+
+    world.remove_gate(RemoveGate { gate_id: gates[0] }).unwrap();
+    assert!(lazy.is_loaded(&not));
+    // the connection is removed with the gate
+    assert!(
+        world
+            .get_gate(&gates[1])
+            .unwrap()
+            .get_consumer_source(0)
+            .is_none()
+    );
+
+    world.remove_gate(RemoveGate { gate_id: gates[1] }).unwrap();
+    assert!(!lazy.is_loaded(&not));
+    assert!(matches!(
+        *world
+            .remove_gate(RemoveGate { gate_id: gates[1] })
+            .unwrap_err(),
+        sim::Error::GateNotFound { .. }
+    ));
+
+    // and loaded again on the next use
+    world
+        .create_default_gate(CreateDefaultGate { gate: not.clone() })
+        .unwrap();
+    assert!(lazy.is_loaded(&not));
+}
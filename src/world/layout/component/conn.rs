@@ -1,6 +1,6 @@
 use std::{
     collections::{HashMap, HashSet},
-    rc::Rc,
+    sync::Arc,
 };
 
 use crate::{
@@ -21,7 +21,7 @@ pub struct LayoutConn {
     segments: HashMap<ComponentId, LayoutConnSegment>,
 
     /// data type of the conn
-    data_type: Rc<DestructedData>,
+    data_type: Arc<DestructedData>,
     /// the data producer the conn is connected to
     producer: Option<GateProducerSocket>,
     /// the data consumers the conn is connected to
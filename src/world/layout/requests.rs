@@ -1,21 +1,24 @@
 use std::{
     collections::{BTreeMap, HashMap},
-    rc::Rc,
+    sync::Arc,
 };
 
 use semver::Version;
 
 use crate::{
     common::world::{ComponentId, ComponentVersion, GateConsumerSocket, GateProducerSocket, Vec2},
-    packages::destructor::{DestructedConn, DestructedData, DestructedGate},
+    packages::{
+        destructor::{DestructedConn, DestructedData, DestructedGate},
+        stdlib,
+    },
 };
 
 pub type DestructedConnHandles =
-    HashMap<PackageName, BTreeMap<PackageVersion, HashMap<ComponentName, Rc<DestructedConn>>>>;
+    HashMap<PackageName, BTreeMap<PackageVersion, HashMap<ComponentName, Arc<DestructedConn>>>>;
 pub type DestructedGateHandles =
-    HashMap<PackageName, BTreeMap<PackageVersion, HashMap<ComponentName, Rc<DestructedGate>>>>;
+    HashMap<PackageName, BTreeMap<PackageVersion, HashMap<ComponentName, Arc<DestructedGate>>>>;
 pub type DestructedDataHandles =
-    HashMap<PackageName, BTreeMap<PackageVersion, HashMap<ComponentName, Rc<DestructedData>>>>;
+    HashMap<PackageName, BTreeMap<PackageVersion, HashMap<ComponentName, Arc<DestructedData>>>>;
 
 pub type PackageName = String;
 pub type PackageVersion = Version;
@@ -39,6 +42,18 @@ pub struct CreateBlankWorld {
     pub conn_handles: DestructedConnHandles,
 }
 
+impl CreateBlankWorld {
+    /// Create a world state with no gates,
+    /// where the gates and data types of the built-in stdlib can be used
+    pub fn stdlib() -> Self {
+        Self {
+            data_handles: stdlib::data_handles(),
+            gate_handles: stdlib::gate_handles(),
+            conn_handles: HashMap::new(),
+        }
+    }
+}
+
 /// `WorldState::draw_segment()`
 #[derive(Clone, Copy, Debug)]
 pub struct SegmentDraw {
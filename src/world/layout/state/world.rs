@@ -36,6 +36,27 @@ impl WorldState {
         }
     }
 
+    /// make new gate and data types available in the world
+    pub fn register_handles(
+        &mut self,
+        request: sim::requests::RegisterHandles,
+    ) -> Result<(), Box<layout::Error>> {
+        self.sim_state
+            .register_handles(request)
+            .map_err(layout::Error::Sim)?;
+        Ok(())
+    }
+
+    /// remove gate and data types of a package version from the world
+    pub fn unregister_handles(&mut self, request: sim::requests::UnregisterHandles) {
+        self.sim_state.unregister_handles(request);
+    }
+
+    /// load gate and data types when they are first used
+    pub fn set_lazy_loader(&mut self, request: sim::requests::SetLazyLoader) {
+        self.sim_state.set_lazy_loader(request);
+    }
+
     /// create a new gate in layout world with default state
     pub fn create_default_gate(
         &mut self,
@@ -1,15 +1,55 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
+
 use crate::{
     common::world::ComponentId,
-    world::user::{self, ConnectRequest},
+    packages::{
+        indexer::{
+            self,
+            component::{IndexEvent, PackageIndex},
+        },
+        loader::{self, indexed::lazy::LazyComponentLoader},
+    },
+    world::{
+        layout::CreateBlankWorld,
+        sim::requests::{SetLazyLoader, UnregisterHandles},
+        user::{self, ConnectRequest},
+    },
 };
 
 pub type WorldError = user::Error;
 
 pub struct MasterWorld {
     world: user::WorldState,
+    /// the lazy loader of the world, packages found by refresh_packages are registered in it
+    lazy: Option<Arc<LazyComponentLoader>>,
+}
+
+/// result of MasterWorld::refresh_packages
+#[cfg_attr(feature = "devel", derive(Debug))]
+pub struct RefreshedPackages {
+    /// changes taken into the index and the world
+    pub events: Vec<IndexEvent>,
+    /// errors when rescanning the roots, versions that cannot be indexed are treated as removed
+    pub index_res: Result<(), indexer::Error>,
+    /// e.g. deprecated versions that are registered
+    pub warnings: Vec<loader::Warning>,
+    /// packages whose changes could not be registered,
+    /// they stay as they were in the index and the world, and are tried again on the next refresh
+    pub failed: Vec<(String, loader::Error)>,
 }
 
 impl MasterWorld {
+    /// create a new world with no users, gates are loaded from the handles in the request
+    pub fn new_blank(request: CreateBlankWorld) -> Self {
+        Self {
+            world: user::WorldState::new_blank(request),
+            lazy: None,
+        }
+    }
+
     pub fn connect(&mut self, request: ConnectRequest) -> Result<ComponentId, Box<WorldError>> {
         self.world.connect(request)
     }
@@ -17,4 +57,111 @@ impl MasterWorld {
     pub fn disconnect(&mut self, id: &ComponentId) -> Result<(), Box<WorldError>> {
         self.world.disconnect(id)
     }
+
+    /// load gate and data types registered in the loader when they are first used,
+    /// refresh_packages registers new package versions in it
+    pub fn set_lazy_loader(&mut self, loader: Option<Arc<LazyComponentLoader>>) {
+        self.lazy = loader.clone();
+        self.world.set_lazy_loader(SetLazyLoader { loader });
+    }
+
+    /// rescan the roots of the index and bring the index and the world up to date,
+    /// can be polled to pick up packages installed or removed while the world is running
+    ///
+    /// the changes are applied one package at a time:
+    /// - added and changed versions are registered in the lazy loader (nothing is loaded
+    ///   until a gate uses them), changed versions keep the features they are registered with
+    /// - removed versions can no longer be used for new gates
+    /// - if a version of a package cannot be registered, the package is left as it was
+    ///   in both the index and the world
+    ///
+    /// gates already in the world keep the libraries they are created with.
+    /// if the world has no lazy loader, an empty one is set first
+    pub fn refresh_packages(&mut self, index: &mut PackageIndex) -> RefreshedPackages {
+        let lazy = match &self.lazy {
+            Some(lazy) => lazy.clone(),
+            None => {
+                let lazy = Arc::new(
+                    LazyComponentLoader::new(index, HashMap::new(), &HashMap::new())
+                        .expect("no packages to register"),
+                );
+                self.set_lazy_loader(Some(lazy.clone()));
+                lazy
+            }
+        };
+
+        let (mut scanned, index_res) = index.rescan();
+
+        // events are sorted by package name
+        let mut packages: BTreeMap<String, Vec<IndexEvent>> = BTreeMap::new();
+        for event in index.diff(&scanned) {
+            let (IndexEvent::Added { name, .. }
+            | IndexEvent::Removed { name, .. }
+            | IndexEvent::Changed { name, .. }) = &event;
+            packages.entry(name.clone()).or_default().push(event);
+        }
+
+        let mut refreshed = RefreshedPackages {
+            events: Vec::new(),
+            index_res,
+            warnings: Vec::new(),
+            failed: Vec::new(),
+        };
+
+        for (name, events) in packages {
+            let mut to_register = Vec::new();
+            let mut to_remove = Vec::new();
+            let mut features = BTreeMap::new();
+
+            for event in events.iter() {
+                match event {
+                    IndexEvent::Added { version, .. } => to_register.push(version.clone()),
+                    IndexEvent::Changed { version, .. } => {
+                        if let Some(enabled) = lazy.get_features(&name, version) {
+                            features.insert(version.clone(), enabled);
+                        }
+                        to_register.push(version.clone());
+                        to_remove.push(version.clone());
+                    }
+                    IndexEvent::Removed { version, .. } => to_remove.push(version.clone()),
+                }
+            }
+
+            let package = scanned.take_package(&name);
+
+            if let Some(package) = &package
+                && !to_register.is_empty()
+            {
+                let features = HashMap::from([(name.clone(), features)]);
+                match lazy.register_package(package, &to_register, &features) {
+                    Ok(mut warnings) => refreshed.warnings.append(&mut warnings),
+                    Err(e) => {
+                        refreshed.failed.push((name, e));
+                        continue;
+                    }
+                }
+            }
+
+            for version in to_remove {
+                // handles registered up front take precedence over the lazy loader,
+                // changed versions are loaded from the lazy loader from now on
+                self.world.unregister_handles(UnregisterHandles {
+                    package: name.clone(),
+                    version: version.clone(),
+                });
+
+                if package
+                    .as_ref()
+                    .is_none_or(|package| package.get_version_entry(&version).is_none())
+                {
+                    lazy.unregister_version(&name, &version);
+                }
+            }
+
+            index.replace_package(&name, package);
+            refreshed.events.extend(events);
+        }
+
+        refreshed
+    }
 }
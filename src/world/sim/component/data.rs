@@ -1,35 +1,66 @@
-use std::rc::Rc;
+use std::sync::Arc;
 
 use crate::{
     common::world::{DataPtr, DataPtrMut},
-    packages::destructor::DestructedData,
+    packages::{chelper::slice, destructor::DestructedData},
+    world::sim,
 };
 
 /// A piece of simulation state data
 /// - automatically drops data when self is dropped
 /// - is always valid
+///
+/// # Safety
+///
+/// SimData is Send: the data behind data_ptr is owned by this struct only,
+/// and DestructedData requires component libraries to accept data from any thread.
+/// it is not Sync, the data is only read or dropped through a single owner
 pub struct SimData {
-    handle: Rc<DestructedData>,
+    handle: Arc<DestructedData>,
     data_ptr: DataPtrMut,
 }
 
+unsafe impl Send for SimData {}
+
 impl SimData {
-    /// Create a simulation state data with its default value
-    pub fn new_default(handle: Rc<DestructedData>) -> Self {
-        Self {
-            data_ptr: handle.default_value(),
-            handle,
+    /// Create a simulation state data with its default value,
+    /// fails if the library cannot create one (e.g. its wasm module traps)
+    pub fn new_default(handle: Arc<DestructedData>) -> Result<Self, Box<sim::Error>> {
+        match handle.default_value() {
+            Ok(data_ptr) => Ok(Self { handle, data_ptr }),
+            Err(e) => Err(sim::Error::CreateData {
+                data_type: handle.id().clone(),
+                reason: e.to_string(),
+            }
+            .into()),
         }
     }
 
     /// Create a simulation state data with a given value
-    pub fn new_with_value(handle: Rc<DestructedData>, data: DataPtrMut) -> Self {
+    pub fn new_with_value(handle: Arc<DestructedData>, data: DataPtrMut) -> Self {
         Self {
             handle,
             data_ptr: data,
         }
     }
 
+    /// Recreate a simulation state data from bytes given by to_bytes,
+    /// None if the library cannot deserialize them
+    pub fn from_bytes(handle: Arc<DestructedData>, bytes: Vec<u8>) -> Option<Self> {
+        let data_ptr = handle.deserialize(&slice::from_vec_rustonly(bytes))?;
+        Some(Self { handle, data_ptr })
+    }
+
+    /// Serialize the data, the bytes remain valid after the library is unloaded
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.handle.to_bytes(self.data_ptr)
+    }
+
+    /// get data type handle
+    pub fn get_handle(&self) -> &Arc<DestructedData> {
+        &self.handle
+    }
+
     /// # Safety
     ///
     /// Using the pointer irresponsibly will cause hard to debug memory issues
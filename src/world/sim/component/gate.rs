@@ -1,13 +1,15 @@
-use std::{collections::HashSet, rc::Rc};
+use std::{collections::HashSet, sync::Arc};
+
+use xdsim_cbinds::common::Slice;
 
 use crate::{
     common::world::{
-        ComponentId, ComponentVersion, ComponentVersionReq, DataPtrMut, GateConsumerSocket,
-        GateProducerSocket, GatePtrMut,
+        ComponentId, ComponentVersion, ComponentVersionReq, DataPtr, DataPtrMut,
+        GateConsumerSocket, GateProducerSocket, GatePtrMut,
     },
     packages::{
         chelper::slice,
-        destructor::{DestructedData, DestructedGate, DestructedGateDefinition},
+        destructor::{Capabilities, DestructedData, DestructedGate, DestructedGateDefinition},
     },
     world::sim::{
         self,
@@ -18,8 +20,14 @@ use crate::{
 
 /// A single gate
 /// - calls drop_mem on itself when dropped
+///
+/// # Safety
+///
+/// SimGate is Send: the gate behind gate_ptr is owned by this struct only,
+/// and DestructedGate requires component libraries to accept gates from any thread.
+/// it is not Sync, a gate is ticked and mutated through a single owner
 pub struct SimGate {
-    handle: Rc<DestructedGate>,
+    handle: Arc<DestructedGate>,
     gate_ptr: GatePtrMut,
 
     definition: DestructedGateDefinition,
@@ -28,6 +36,8 @@ pub struct SimGate {
     producers: Vec<SimGateProducerEntry>,
 }
 
+unsafe impl Send for SimGate {}
+
 impl SimGate {
     /// get gate type identifier
     pub fn get_type(&self) -> &ComponentVersion {
@@ -39,7 +49,7 @@ impl SimGate {
 pub struct SimGateConsumerEntry {
     request: ComponentVersionReq,
     /// data type handle to use if unbound
-    default_data_type: Rc<DestructedData>,
+    default_data_type: Arc<DestructedData>,
     status: SimGateConsumerEntryStatus,
 }
 
@@ -47,7 +57,7 @@ pub struct SimGateConsumerEntry {
 pub enum SimGateConsumerEntryStatus {
     Unbound,
     Bound {
-        handle: Rc<DestructedData>,
+        handle: Arc<DestructedData>,
         source: GateProducerSocket,
     },
 }
@@ -58,8 +68,16 @@ impl SimGateConsumerEntryStatus {
     }
 }
 
+/// a gate taken out of the world while its library is reloaded
+pub struct SimGateReloadState {
+    /// the gate serialized by the old library
+    bytes: Vec<u8>,
+    /// producer name -> buffer
+    producers: Vec<(String, SimData)>,
+}
+
 pub struct SimGateProducerEntry {
-    handle: Rc<DestructedData>,
+    handle: Arc<DestructedData>,
 
     read_only: SimData,
     write_only: Option<SimData>,
@@ -82,10 +100,58 @@ impl SimGate {
     /// Create a new gate with its default configuation given a handle
     /// It will fail if one of the data type it references is not in world (data_handles)
     pub fn new_default(
-        handle: Rc<DestructedGate>,
+        handle: Arc<DestructedGate>,
+        world_data: &WorldStateData,
+    ) -> Result<Self, Box<sim::Error>> {
+        let gate_ptr = Self::default_gate_ptr(&handle)?;
+        Self::new_with_value(handle, gate_ptr, world_data)
+    }
+
+    fn default_gate_ptr(handle: &DestructedGate) -> Result<GatePtrMut, Box<sim::Error>> {
+        handle.default_value().map_err(|e| {
+            Box::new(sim::Error::CreateGate {
+                gate_type: handle.id().clone(),
+                reason: e.to_string(),
+            })
+        })
+    }
+
+    /// Create a new gate from a gate pointer created by the handle,
+    /// the pointer is dropped if the gate cannot be created
+    fn new_with_value(
+        handle: Arc<DestructedGate>,
+        gate_ptr: GatePtrMut,
         world_data: &WorldStateData,
     ) -> Result<Self, Box<sim::Error>> {
-        let gate_ptr = handle.default_value();
+        match Self::sockets_from_definition(&handle, gate_ptr, world_data) {
+            Ok((definition, consumers, producers)) => Ok(Self {
+                gate_ptr,
+                handle,
+
+                consumers,
+                producers,
+
+                definition,
+            }),
+            Err(e) => {
+                handle.drop_mem(gate_ptr);
+                Err(e)
+            }
+        }
+    }
+
+    fn sockets_from_definition(
+        handle: &Arc<DestructedGate>,
+        gate_ptr: GatePtrMut,
+        world_data: &WorldStateData,
+    ) -> Result<
+        (
+            DestructedGateDefinition,
+            Vec<SimGateConsumerEntry>,
+            Vec<SimGateProducerEntry>,
+        ),
+        Box<sim::Error>,
+    > {
         let definition = handle.normalised_definition(gate_ptr).map_err(|e| {
             Box::new(sim::Error::GateDefinition {
                 component: handle.id().clone(),
@@ -96,10 +162,17 @@ impl SimGate {
         let mut consumers = Vec::with_capacity(definition.consumers.len());
 
         for entry in definition.consumers.iter() {
-            match world_data.request_handle(&entry.data_type_req) {
+            match world_data.request_handle(&entry.data_type_req)? {
+                Some(data_type) if !handle.can_use_data(&data_type) => {
+                    return Err(sim::Error::SandboxBoundary {
+                        gate_type: handle.id().clone(),
+                        data_type: data_type.id().clone(),
+                    }
+                    .into());
+                }
                 Some(data_type) => consumers.push(SimGateConsumerEntry {
                     request: entry.data_type_req.clone(),
-                    default_data_type: data_type.clone(),
+                    default_data_type: data_type,
                     status: SimGateConsumerEntryStatus::Unbound,
                 }),
                 None => {
@@ -114,10 +187,17 @@ impl SimGate {
         let mut producers = Vec::with_capacity(definition.producers.len());
 
         for entry in definition.producers.iter() {
-            match world_data.get_handle(&entry.data_type) {
+            match world_data.load_handle(&entry.data_type)? {
+                Some(data_type) if !handle.can_use_data(&data_type) => {
+                    return Err(sim::Error::SandboxBoundary {
+                        gate_type: handle.id().clone(),
+                        data_type: data_type.id().clone(),
+                    }
+                    .into());
+                }
                 Some(data_type) => producers.push(SimGateProducerEntry {
                     handle: data_type.clone(),
-                    read_only: SimData::new_default(data_type.clone()),
+                    read_only: SimData::new_default(data_type)?,
                     write_only: None,
                     dependents: HashSet::new(),
                 }),
@@ -130,20 +210,145 @@ impl SimGate {
             }
         }
 
-        Ok(Self {
-            gate_ptr,
-            handle,
+        Ok((definition, consumers, producers))
+    }
 
-            consumers,
-            producers,
+    /// serialize the gate and drop it, keeping its producer buffers by name.
+    /// connections are not kept, disconnect the gate first
+    pub fn into_reload_state(mut self) -> SimGateReloadState {
+        let serialized = self.handle.serialize(self.gate_ptr);
+        let bytes = slice::from_slice::<u8>(&serialized).to_vec();
+        drop(serialized);
 
-            definition,
-        })
+        let producers = std::mem::take(&mut self.producers)
+            .into_iter()
+            .zip(self.definition.producers.iter())
+            .map(|(entry, definition)| (definition.name.clone(), entry.read_only))
+            .collect();
+
+        SimGateReloadState { bytes, producers }
+    }
+
+    /// recreate a gate from its reload state, with the handle of the reloaded library
+    ///
+    /// if the new library cannot deserialize the gate, the gate is reset to its default value
+    /// and the returned bool is true.
+    /// producer buffers are kept if the producer of the same name has the same data type
+    ///
+    /// if the gate cannot be created, the state is returned with the error,
+    /// so the gate can be recreated with the old library instead
+    pub fn from_reload_state(
+        handle: Arc<DestructedGate>,
+        state: SimGateReloadState,
+        world_data: &WorldStateData,
+    ) -> Result<(Self, bool), (Box<sim::Error>, SimGateReloadState)> {
+        let created = match handle.deserialize(&slice::from_vec_rustonly(state.bytes.clone())) {
+            Some(gate_ptr) => {
+                Self::new_with_value(handle, gate_ptr, world_data).map(|gate| (gate, false))
+            }
+            None => Self::new_default(handle, world_data).map(|gate| (gate, true)),
+        };
+
+        let (mut gate, reset) = match created {
+            Ok(created) => created,
+            Err(e) => return Err((e, state)),
+        };
+
+        for (name, buffer) in state.producers {
+            if let Some(index) = gate.producer_index(&name) {
+                let entry = &mut gate.producers[index];
+                if entry.handle.id() == buffer.get_handle().id() {
+                    entry.read_only = buffer;
+                }
+            }
+        }
+
+        Ok((gate, reset))
+    }
+
+    /// switch every socket using the data type to the handle of its reloaded library,
+    /// producer buffers are serialized with the old library and deserialized with the new one
+    ///
+    /// returns the producer indices reset to the default value
+    /// because the new library cannot deserialize them,
+    /// a buffer the new library cannot create a default value for either
+    /// keeps its old value until the gate is next ticked
+    pub fn reload_data_type(&mut self, handle: &Arc<DestructedData>) -> Vec<usize> {
+        let id = handle.id();
+
+        for consumer in self.consumers.iter_mut() {
+            if consumer.default_data_type.id() == id {
+                consumer.default_data_type = handle.clone();
+            }
+
+            if let SimGateConsumerEntryStatus::Bound {
+                handle: bound_handle,
+                ..
+            } = &mut consumer.status
+                && bound_handle.id() == id
+            {
+                *bound_handle = handle.clone();
+            }
+        }
+
+        let mut reset = Vec::new();
+
+        for (index, producer) in self.producers.iter_mut().enumerate() {
+            if producer.handle.id() != id {
+                continue;
+            }
+
+            producer.handle = handle.clone();
+            match SimData::from_bytes(handle.clone(), producer.read_only.to_bytes()) {
+                Some(data) => producer.read_only = data,
+                None => {
+                    if let Ok(data) = SimData::new_default(handle.clone()) {
+                        reset.push(index);
+                        producer.read_only = data;
+                    }
+                }
+            }
+        }
+
+        reset
+    }
+
+    /// index of a consumer by name
+    pub fn consumer_index(&self, name: &str) -> Option<usize> {
+        self.definition
+            .consumers
+            .iter()
+            .position(|entry| entry.name == name)
+    }
+
+    /// index of a producer by name
+    pub fn producer_index(&self, name: &str) -> Option<usize> {
+        self.definition
+            .producers
+            .iter()
+            .position(|entry| entry.name == name)
+    }
+
+    /// producer socket the index-th consumer is bound to
+    pub fn get_consumer_source(&self, index: usize) -> Option<&GateProducerSocket> {
+        match &self.consumers.get(index)?.status {
+            SimGateConsumerEntryStatus::Bound { source, .. } => Some(source),
+            SimGateConsumerEntryStatus::Unbound => None,
+        }
+    }
+
+    /// consumer sockets that depend on the index-th producer
+    pub fn get_producer_dependents(&self, index: usize) -> Option<&HashSet<GateConsumerSocket>> {
+        Some(&self.producers.get(index)?.dependents)
     }
 
     /// if this function returns an error
     /// it is simply reporting a missing SimData that should exist
     /// a default value for that SimData is used and the world can containue as usual
+    ///
+    /// a sandboxed, wasm or script gate also reports its helper, module or script
+    /// failing to tick it, in which case its producers keep their values.
+    /// if a default value cannot be created for a consumer, the gate is not ticked
     pub fn tick(
         &mut self, // doesn't need to be mut, if that is causing issues, will remove
         world_gates: &WorldStateGates,
@@ -154,56 +359,60 @@ impl SimGate {
         // so they can be dropped before the function returns
         let mut temp_datas = Vec::new();
 
-        // creates the array of pointers to consumer data
-        // (is it possible to reduce the amount of cloning here?)
-        let consumer_slice = slice::from_vec_rustonly(
-            self.consumers
+        let consumers =
+            Self::consumer_data(&self.consumers, world_gates, &mut errors, &mut temp_datas);
+
+        // the consumer whose default value cannot be created is in errors,
+        // the producers keep their values
+        let Some(consumers) = consumers else {
+            return Err(sim::Error::TickSingleGate {
+                gate_id: *self_id,
+                errors,
+            }
+            .into());
+        };
+
+        if self.handle.ticks_serialized() {
+            let producer_types: Vec<&DestructedData> = self
+                .producers
                 .iter()
-                .map(|consumer| match &consumer.status {
-                    SimGateConsumerEntryStatus::Bound { handle, source } => {
-                        match world_gates.get_producer(source) {
-                            Some(data) => data.get_data_ptr(),
-                            None => {
-                                errors.push(sim::Error::ProducerSocketNotFound {
-                                    producer_socket: *source,
-                                });
-
-                                // if producer socket not in world, treat as unbound
-                                let temp_data = SimData::new_default(handle.clone());
-                                let ptr = temp_data.get_data_ptr();
-                                temp_datas.push(temp_data);
-                                ptr
-                            }
+                .map(|producer| producer.handle.as_ref())
+                .collect();
+
+            let produced = self
+                .handle
+                .tick_serialized(self.gate_ptr, &consumers, &producer_types);
+
+            match produced {
+                Ok(produced) => {
+                    for (bytes, producer) in produced.into_iter().zip(self.producers.iter_mut()) {
+                        let data = match SimData::from_bytes(producer.handle.clone(), bytes) {
+                            Some(data) => Ok(data),
+                            None => SimData::new_default(producer.handle.clone()),
+                        };
+
+                        // a producer that cannot be created keeps its value
+                        match data {
+                            Ok(data) => producer.write_only = Some(data),
+                            Err(e) => errors.push(*e),
                         }
                     }
-                    SimGateConsumerEntryStatus::Unbound => {
-                        let temp_data = SimData::new_default(consumer.default_data_type.clone());
-                        let ptr = temp_data.get_data_ptr();
-                        temp_datas.push(temp_data);
-                        ptr
-                    }
-                })
-                .collect(),
-        );
-
-        let producer_slice = self.handle.tick(self.gate_ptr, &consumer_slice);
-
-        slice::from_slice::<DataPtrMut>(&producer_slice)
-            .iter()
-            .zip(self.producers.iter_mut())
-            .for_each(
-                |(
-                    &data,
-                    SimGateProducerEntry {
-                        handle,
-                        write_only,
-                        dependents: _,
-                        read_only: _,
-                    },
-                )| {
-                    *write_only = Some(SimData::new_with_value(handle.clone(), data));
-                },
-            );
+                }
+                Err(e) if self.handle.is_sandboxed() => errors.push(sim::Error::Sandbox {
+                    gate_id: *self_id,
+                    reason: e.to_string(),
+                }),
+                Err(e) => errors.push(sim::Error::SerializedTick {
+                    gate_id: *self_id,
+                    reason: e.to_string(),
+                }),
+            }
+        } else {
+            let producer_slice = self
+                .handle
+                .tick(self.gate_ptr, &Self::consumer_slice(&consumers));
+            self.set_produced(slice::from_slice::<DataPtrMut>(&producer_slice));
+        }
 
         if errors.is_empty() {
             Ok(())
@@ -216,6 +425,123 @@ impl SimGate {
         }
     }
 
+    /// if the gate can be ticked together with other gates of its type,
+    /// only gates whose library exports gate_tick_batch are, see WorldStateGates::tick_all
+    pub fn ticks_in_batch(&self) -> bool {
+        !self.handle.ticks_serialized()
+            && self
+                .handle
+                .capabilities()
+                .contains(Capabilities::BATCH_TICK)
+    }
+
+    pub fn get_handle(&self) -> &Arc<DestructedGate> {
+        &self.handle
+    }
+
+    pub fn get_gate_ptr(&self) -> GatePtrMut {
+        self.gate_ptr
+    }
+
+    /// append the pointers to consumer data for DestructedGate::tick_batch to out,
+    /// errors are reported the same way as in tick
+    ///
+    /// returns false if the gate cannot be ticked, some of its consumers may be appended
+    pub fn batch_consumers(
+        &self,
+        world_gates: &WorldStateGates,
+        errors: &mut Vec<sim::Error>,
+        temp_datas: &mut Vec<SimData>,
+        out: &mut Vec<DataPtr>,
+    ) -> bool {
+        for consumer in self.consumers.iter() {
+            match Self::consumer_datum(consumer, world_gates, errors, temp_datas) {
+                Some((_, data)) => out.push(data),
+                None => return false,
+            }
+        }
+
+        true
+    }
+
+    /// write the producers of a tick to the write_only buffers, in definition order
+    pub fn set_produced(&mut self, produced: &[DataPtrMut]) {
+        for (&data, producer) in produced.iter().zip(self.producers.iter_mut()) {
+            producer.write_only = Some(SimData::new_with_value(producer.handle.clone(), data));
+        }
+    }
+
+    /// drop producers of a tick that are not written to the buffers, in definition order
+    pub fn drop_produced(&self, produced: &[DataPtrMut]) {
+        for (&data, producer) in produced.iter().zip(self.producers.iter()) {
+            producer.handle.drop_mem(data);
+        }
+    }
+
+    /// creates the array of pointers to consumer data
+    fn consumer_slice(consumers: &[(&DestructedData, DataPtr)]) -> Slice {
+        slice::from_vec_rustonly(
+            consumers
+                .iter()
+                .map(|(_, data)| *data)
+                .collect::<Vec<DataPtr>>(),
+        )
+    }
+
+    /// data type and data of each consumer, see consumer_datum
+    // (is it possible to reduce the amount of cloning here?)
+    fn consumer_data<'a>(
+        consumers: &'a [SimGateConsumerEntry],
+        world_gates: &WorldStateGates,
+        errors: &mut Vec<sim::Error>,
+        temp_datas: &mut Vec<SimData>,
+    ) -> Option<Vec<(&'a DestructedData, DataPtr)>> {
+        consumers
+            .iter()
+            .map(|consumer| Self::consumer_datum(consumer, world_gates, errors, temp_datas))
+            .collect()
+    }
+
+    /// data type and data of a consumer,
+    /// an unbound (or missing) producer is replaced by a default value kept in temp_datas
+    ///
+    /// None if the default value cannot be created, the error is added to errors
+    fn consumer_datum<'a>(
+        consumer: &'a SimGateConsumerEntry,
+        world_gates: &WorldStateGates,
+        errors: &mut Vec<sim::Error>,
+        temp_datas: &mut Vec<SimData>,
+    ) -> Option<(&'a DestructedData, DataPtr)> {
+        let handle = match &consumer.status {
+            SimGateConsumerEntryStatus::Bound { handle, source } => {
+                match world_gates.get_producer(source) {
+                    Some(data) => return Some((handle.as_ref(), data.get_data_ptr())),
+                    None => {
+                        errors.push(sim::Error::ProducerSocketNotFound {
+                            producer_socket: *source,
+                        });
+
+                        // if producer socket not in world, treat as unbound
+                        handle
+                    }
+                }
+            }
+            SimGateConsumerEntryStatus::Unbound => &consumer.default_data_type,
+        };
+
+        match SimData::new_default(handle.clone()) {
+            Ok(temp_data) => {
+                let ptr = temp_data.get_data_ptr();
+                temp_datas.push(temp_data);
+                Some((handle.as_ref(), ptr))
+            }
+            Err(e) => {
+                errors.push(*e);
+                None
+            }
+        }
+    }
+
     /// replace all read_only buffers with write_only buffers
     /// this is to be ran at the end of a tick
     pub fn flush(&mut self) {
@@ -232,7 +558,7 @@ impl SimGate {
         &mut self,
         consumer_socket: &GateConsumerSocket,
         producer_socket: GateProducerSocket,
-        producer_type: &Rc<DestructedData>,
+        producer_type: &Arc<DestructedData>,
     ) -> Result<(), Box<sim::Error>> {
         let consumer_entry = self
             .consumers
@@ -245,6 +571,14 @@ impl SimGate {
 
         match consumer_entry.status {
             SimGateConsumerEntryStatus::Unbound => {
+                if !self.handle.can_use_data(producer_type) {
+                    return Err(sim::Error::SandboxBoundary {
+                        gate_type: self.handle.id().clone(),
+                        data_type: producer_type.id().clone(),
+                    }
+                    .into());
+                }
+
                 if !consumer_entry.request.matches(producer_type.id()) {
                     return Err(sim::Error::IOTypeMismatch {
                         consumer_socket: *consumer_socket,
@@ -352,7 +686,7 @@ impl SimGate {
     pub fn get_producer_type(
         &self,
         producer_socket: &GateProducerSocket,
-    ) -> Result<&Rc<DestructedData>, Box<sim::Error>> {
+    ) -> Result<&Arc<DestructedData>, Box<sim::Error>> {
         match self.producers.get(producer_socket.get_index()) {
             Some(producer_entry) => Ok(&producer_entry.handle),
             None => Err(sim::Error::ProducerSocketNotFound {
@@ -2,4 +2,4 @@
 mod data;
 pub use data::SimData;
 mod gate;
-pub use gate::SimGate;
+pub use gate::{SimGate, SimGateReloadState};
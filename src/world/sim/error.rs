@@ -14,10 +14,34 @@ pub enum Error {
     RequestedDataTypeNotFound { data_type: ComponentVersionReq },
     /// Missing gate type in world (requested with semver)
     GateTypeNotFound { gate_type: ComponentVersion },
+    /// Neither a gate type nor a data type in world
+    ComponentTypeNotFound { component: ComponentVersion },
+    /// The package name is reserved for the built-in stdlib, handles of it cannot be registered
+    ReservedPackage { package: String },
+    /// The library of a component cannot be loaded again, or the component is not in it
+    ReloadLibrary {
+        component: ComponentVersion,
+        reason: String,
+    },
+    /// The library of a lazily loaded component cannot be loaded, or the component is not in it
+    LoadComponent {
+        component: ComponentVersion,
+        reason: String,
+    },
     /// Single error emitted by tick_all
     /// as of now, tick_all only emits
     /// - MissingData
     TickallErrors { errors: Vec<TickAllErrorEntry> },
+    /// The gate type cannot create a gate, e.g. its wasm module trapped
+    CreateGate {
+        gate_type: ComponentVersion,
+        reason: String,
+    },
+    /// The data type cannot create a default value, e.g. its wasm module trapped
+    CreateData {
+        data_type: ComponentVersion,
+        reason: String,
+    },
     /// Error parsing gate definition
     GateDefinition {
         component: ComponentVersion,
@@ -74,6 +98,32 @@ pub enum Error {
     },
     /// trying to unbind a consumer socket that is not bound to anything
     ConsumerSocketUnbindNothing { consumer_socket: GateConsumerSocket },
+    /// A gate running in this process uses a data type running in a sandbox helper or a wasm module,
+    /// the data cannot be read from this process
+    /// (or a sandboxed gate uses a native or wasm data type, which the helper cannot load)
+    SandboxBoundary {
+        gate_type: ComponentVersion,
+        data_type: ComponentVersion,
+    },
+    /// The sandbox helper failed to tick a gate (e.g. it crashed or did not respond),
+    /// the producers of the gate keep their values
+    Sandbox {
+        gate_id: ComponentId,
+        reason: String,
+    },
+    /// The wasm module or script of a gate failed to tick it (e.g. it trapped or threw),
+    /// the producers of the gate keep their values
+    SerializedTick {
+        gate_id: ComponentId,
+        reason: String,
+    },
+    /// gate_tick_batch returned producers for a different number of gates than it was given,
+    /// the gates of the batch have ticked but keep their old producers
+    BatchTick {
+        gate_type: ComponentVersion,
+        gates: usize,
+        produced: usize,
+    },
     /// An input socket is connected to an output socket but their data_types do not match
     IOTypeMismatch {
         consumer_socket: GateConsumerSocket,
@@ -1,20 +1,25 @@
 //! Requests to poke the world state to do stuff.
 use std::{
     collections::{BTreeMap, HashMap},
-    rc::Rc,
+    sync::Arc,
 };
 
 use semver::Version;
 
 use crate::{
-    common::world::{ComponentVersion, GateConsumerSocket, GateProducerSocket},
-    packages::destructor::{DestructedData, DestructedGate},
+    common::world::{ComponentId, ComponentVersion, GateConsumerSocket, GateProducerSocket},
+    packages::{
+        destructor::{DestructedData, DestructedGate},
+        loader::indexed::lazy::LazyComponentLoader,
+        stdlib,
+    },
+    world::sim,
 };
 
 pub type DestructedGateHandles =
-    HashMap<PackageName, BTreeMap<PackageVersion, HashMap<ComponentName, Rc<DestructedGate>>>>;
+    HashMap<PackageName, BTreeMap<PackageVersion, HashMap<ComponentName, Arc<DestructedGate>>>>;
 pub type DestructedDataHandles =
-    HashMap<PackageName, BTreeMap<PackageVersion, HashMap<ComponentName, Rc<DestructedData>>>>;
+    HashMap<PackageName, BTreeMap<PackageVersion, HashMap<ComponentName, Arc<DestructedData>>>>;
 
 pub type PackageName = String;
 pub type PackageVersion = Version;
@@ -36,6 +41,41 @@ impl CreateBlankWorld {
             gate_handles: HashMap::new(),
         }
     }
+
+    /// Create a world state with no gates,
+    /// where the gates and data types of the built-in stdlib can be used
+    pub fn stdlib() -> Self {
+        Self {
+            data_handles: stdlib::data_handles(),
+            gate_handles: stdlib::gate_handles(),
+        }
+    }
+}
+
+/// `WorldState::register_handles(RegisterHandles)`
+///
+/// handles of the same package version replace the existing ones,
+/// gates already in the world keep using the handles they are created with
+pub struct RegisterHandles {
+    pub data_handles: DestructedDataHandles,
+    pub gate_handles: DestructedGateHandles,
+}
+
+/// `WorldState::unregister_handles(UnregisterHandles)`
+///
+/// new gates of the package version can no longer be created,
+/// gates already in the world keep working
+pub struct UnregisterHandles {
+    pub package: PackageName,
+    pub version: PackageVersion,
+}
+
+/// `WorldState::set_lazy_loader(SetLazyLoader)`
+///
+/// handles registered with register_handles are used before the lazy loader,
+/// None stops loading new components, gates already in the world keep working
+pub struct SetLazyLoader {
+    pub loader: Option<Arc<LazyComponentLoader>>,
 }
 
 /// `WorldState::create_default_gate(CreateDefaultGate) -> Result&lt;ComponentId&gt;`
@@ -44,6 +84,13 @@ pub struct CreateDefaultGate {
     pub gate: ComponentVersion,
 }
 
+/// `WorldState::remove_gate(RemoveGate) -> Result&lt;()&gt;`
+///
+/// every connection to and from the gate is removed with it
+pub struct RemoveGate {
+    pub gate_id: ComponentId,
+}
+
 /// `WorldState::connect_gates(ConnectIOSockets)  -> Result&lt;()&gt;`
 pub struct ConnectIOSockets {
     // self explanatory
@@ -57,3 +104,30 @@ pub struct DisconnectIOSockets {
     pub consumer_socket: GateConsumerSocket,
     pub producer_socket: GateProducerSocket,
 }
+
+/// `WorldState::reload_component(ReloadComponent) -> Result&lt;ReloadedComponent&gt;`
+///
+/// loads the library of a gate or data type again while the world keeps running,
+/// every gate or buffer of the type is serialized with the old library
+/// and deserialized with the new one
+pub struct ReloadComponent {
+    pub component: ComponentVersion,
+}
+
+/// what happened to the world during `WorldState::reload_component`
+#[derive(Debug, Default)]
+pub struct ReloadedComponent {
+    /// gates recreated with the new library
+    pub gates: Vec<ComponentId>,
+    /// gates the new library cannot deserialize, they are reset to their default value
+    pub reset_gates: Vec<ComponentId>,
+    /// gates that cannot be recreated with the new library, they keep the old library
+    pub kept_gates: Vec<(ComponentId, sim::Error)>,
+    /// gates that cannot be recreated with either library, they are removed from the world
+    pub removed_gates: Vec<(ComponentId, sim::Error)>,
+    /// producer buffers the new library cannot deserialize,
+    /// they are reset to the default value
+    pub reset_buffers: Vec<GateProducerSocket>,
+    /// connections to sockets that no longer exist (or changed type) in the new definition
+    pub disconnected: Vec<(GateProducerSocket, GateConsumerSocket)>,
+}
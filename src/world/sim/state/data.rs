@@ -1,42 +1,144 @@
-use std::rc::Rc;
+use std::sync::Arc;
+
+use semver::Version;
 
 use crate::{
     common::world::{ComponentVersion, ComponentVersionReq},
-    packages::destructor::DestructedData,
-    world::sim::requests::DestructedDataHandles,
+    packages::{destructor::DestructedData, loader::indexed::lazy::LazyComponentLoader},
+    world::sim::{self, requests::DestructedDataHandles},
 };
 
 pub struct WorldStateData {
     /// all data types
-    // may one day replace the Rc in SimData with a dumb pointer because it is guaranteed to exist
+    // may one day replace the Arc in SimData with a dumb pointer because it is guaranteed to exist
     // as owned here
     handles: DestructedDataHandles,
+    /// data types that are loaded on first use
+    lazy: Option<Arc<LazyComponentLoader>>,
 }
 
 impl WorldStateData {
     /// create world state data with only handles and no buffers in world
     pub fn new_blank(handles: DestructedDataHandles) -> Self {
-        Self { handles }
+        Self {
+            handles,
+            lazy: None,
+        }
+    }
+
+    /// add or replace data types
+    pub fn register_handles(&mut self, handles: DestructedDataHandles) {
+        for (package, versions) in handles {
+            self.handles.entry(package).or_default().extend(versions);
+        }
+    }
+
+    /// remove all data types of a package version
+    pub fn unregister_handles(&mut self, package: &str, version: &Version) {
+        if let Some(versions) = self.handles.get_mut(package) {
+            versions.remove(version);
+            if versions.is_empty() {
+                self.handles.remove(package);
+            }
+        }
+    }
+
+    /// load data types registered in loader when they are first used
+    pub fn set_lazy_loader(&mut self, loader: Option<Arc<LazyComponentLoader>>) {
+        self.lazy = loader;
+    }
+
+    pub fn get_lazy_loader(&self) -> Option<&Arc<LazyComponentLoader>> {
+        self.lazy.as_ref()
     }
 
-    /// Get handle using a ComponentVersion
-    pub fn get_handle(&self, component: &ComponentVersion) -> Option<&Rc<DestructedData>> {
+    /// Get handle using a ComponentVersion, only if the data type is loaded
+    pub fn get_handle(&self, component: &ComponentVersion) -> Option<Arc<DestructedData>> {
         self.handles
-            .get(&component.package)?
-            .get(&component.version)?
-            .get(&component.component)
+            .get(&component.package)
+            .and_then(|versions| versions.get(&component.version))
+            .and_then(|components| components.get(&component.component))
+            .cloned()
+            .or_else(|| self.lazy.as_ref()?.get_loaded_data(component))
     }
 
-    /// Get handle using a ComponentVersionReq
+    /// Get handle using a ComponentVersion,
+    /// the library of the data type is loaded if it is registered lazily
+    pub fn load_handle(
+        &self,
+        component: &ComponentVersion,
+    ) -> Result<Option<Arc<DestructedData>>, Box<sim::Error>> {
+        if let Some(handle) = self.get_handle(component) {
+            return Ok(Some(handle));
+        }
+
+        match &self.lazy {
+            Some(lazy) => lazy.load_data(component).map_err(|e| {
+                Box::new(sim::Error::LoadComponent {
+                    component: component.clone(),
+                    reason: e.to_string(),
+                })
+            }),
+            None => Ok(None),
+        }
+    }
+
+    /// replace the handle of a data type already in world, e.g. after its library is reloaded
+    pub fn replace_handle(&mut self, handle: Arc<DestructedData>) {
+        if let Some(lazy) = &self.lazy {
+            lazy.replace_data(&handle);
+        }
+
+        let id = handle.id();
+        if let Some(slot) = self
+            .handles
+            .get_mut(&id.package)
+            .and_then(|versions| versions.get_mut(&id.version))
+            .and_then(|components| components.get_mut(&id.component))
+        {
+            *slot = handle;
+        }
+    }
+
+    /// Get handle using a ComponentVersionReq,
+    /// the highest matching version is used whether it is loaded or registered lazily
     pub fn request_handle(
         &self,
         component_req: &ComponentVersionReq,
-    ) -> Option<&Rc<DestructedData>> {
-        self.handles
-            .get(&component_req.package)?
-            .iter()
-            .rfind(|(version, _)| component_req.version_req.matches(version))?
-            .1
-            .get(&component_req.component)
+    ) -> Result<Option<Arc<DestructedData>>, Box<sim::Error>> {
+        let loaded = self
+            .handles
+            .get(&component_req.package)
+            .and_then(|versions| {
+                versions
+                    .iter()
+                    .rfind(|(version, _)| component_req.version_req.matches(version))
+            });
+
+        let lazy_version = self
+            .lazy
+            .as_ref()
+            .and_then(|lazy| lazy.find_data_version(component_req));
+
+        match (loaded, lazy_version) {
+            (Some((loaded_version, _)), Some(version)) if &version > loaded_version => {
+                self.load_lazy_version(component_req, version)
+            }
+            (None, Some(version)) => self.load_lazy_version(component_req, version),
+            (Some((_, components)), _) => Ok(components.get(&component_req.component).cloned()),
+            (None, None) => Ok(None),
+        }
+    }
+
+    fn load_lazy_version(
+        &self,
+        component_req: &ComponentVersionReq,
+        version: Version,
+    ) -> Result<Option<Arc<DestructedData>>, Box<sim::Error>> {
+        self.load_handle(&ComponentVersion {
+            package: component_req.package.clone(),
+            version,
+            component: component_req.component.clone(),
+        })
     }
 }
@@ -1,26 +1,98 @@
-use std::{cell::UnsafeCell, collections::HashMap, rc::Rc};
+use std::{cell::UnsafeCell, collections::HashMap, ops::Range, sync::Arc};
+
+use xdsim_cbinds::common::Slice;
+
+use semver::Version;
 
 use crate::{
     common::world::{
-        ComponentId, ComponentIdIncrementer, ComponentIdType, ComponentVersion, GateConsumerSocket,
-        GateProducerSocket,
+        ComponentId, ComponentIdIncrementer, ComponentIdType, ComponentVersion, DataPtr,
+        DataPtrMut, GateConsumerSocket, GateProducerSocket, GatePtrMut,
+    },
+    packages::{
+        chelper::slice,
+        destructor::{DestructedData, DestructedGate},
+        loader::indexed::lazy::LazyComponentLoader,
     },
-    packages::destructor::DestructedGate,
     world::sim::{
         self,
         component::{SimData, SimGate},
         error::TickAllErrorEntry,
-        requests::DestructedGateHandles,
+        requests::{DestructedGateHandles, ReloadedComponent},
         state::data::WorldStateData,
     },
 };
 
+/// # Safety
+///
+/// gates are in UnsafeCell so tick_all can write the write_only buffers of one gate
+/// while reading the read_only buffers of others, this only happens through &mut self,
+/// so WorldStateGates is Send (as SimGate is) but not Sync
 pub struct WorldStateGates {
     /// all gate types
     handles: DestructedGateHandles,
+    /// gate types that are loaded on first use
+    lazy: Option<Arc<LazyComponentLoader>>,
 
     /// all gates in world
     gates: HashMap<ComponentId, UnsafeCell<SimGate>>,
+    /// buffers of the gate types ticked in batches, kept between ticks, see tick_all
+    batches: HashMap<usize, TickBatch>,
+}
+
+/// buffers to tick the gates of a type with one call, see DestructedGate::tick_batch
+///
+/// # Safety
+///
+/// the pointers are only filled in and used within a call to tick_all
+/// and cleared before it returns, so TickBatch is Send
+struct TickBatch {
+    /// set while ticking, the library is not kept loaded between ticks
+    handle: Option<Arc<DestructedGate>>,
+    gate_ids: Vec<ComponentId>,
+    gate_ptrs: Vec<GatePtrMut>,
+    /// consumer data of every gate, one after another
+    consumer_data: Vec<DataPtr>,
+    /// range of consumer_data of each gate
+    consumer_ranges: Vec<Range<usize>>,
+    /// consumer_ranges borrowed as Slices
+    consumers: Vec<Slice>,
+    consumer_ptrs: Vec<*const Slice>,
+    /// errors of each gate
+    errors: Vec<Vec<sim::Error>>,
+    /// default values of unbound consumers, dropped after the batch is ticked
+    temp_datas: Vec<SimData>,
+}
+
+unsafe impl Send for TickBatch {}
+
+impl TickBatch {
+    fn new() -> Self {
+        Self {
+            handle: None,
+            gate_ids: Vec::new(),
+            gate_ptrs: Vec::new(),
+            consumer_data: Vec::new(),
+            consumer_ranges: Vec::new(),
+            consumers: Vec::new(),
+            consumer_ptrs: Vec::new(),
+            errors: Vec::new(),
+            temp_datas: Vec::new(),
+        }
+    }
+
+    /// keeps the allocations
+    fn clear(&mut self) {
+        self.handle = None;
+        self.gate_ids.clear();
+        self.gate_ptrs.clear();
+        self.consumer_ptrs.clear();
+        self.consumers.clear();
+        self.consumer_ranges.clear();
+        self.consumer_data.clear();
+        self.errors.clear();
+        self.temp_datas.clear();
+    }
 }
 
 impl WorldStateGates {
@@ -28,8 +100,262 @@ impl WorldStateGates {
     pub fn new_blank(handles: DestructedGateHandles) -> Self {
         Self {
             handles,
+            lazy: None,
             gates: HashMap::new(),
+            batches: HashMap::new(),
+        }
+    }
+
+    /// add or replace gate types
+    pub fn register_handles(&mut self, handles: DestructedGateHandles) {
+        for (package, versions) in handles {
+            self.handles.entry(package).or_default().extend(versions);
+        }
+    }
+
+    /// remove all gate types of a package version
+    pub fn unregister_handles(&mut self, package: &str, version: &Version) {
+        if let Some(versions) = self.handles.get_mut(package) {
+            versions.remove(version);
+            if versions.is_empty() {
+                self.handles.remove(package);
+            }
+        }
+    }
+
+    /// load gate types registered in loader when they are first used
+    pub fn set_lazy_loader(&mut self, loader: Option<Arc<LazyComponentLoader>>) {
+        self.lazy = loader;
+    }
+
+    /// Get handle using a ComponentVersion, only if the gate type is loaded
+    pub fn get_handle(&self, gate: &ComponentVersion) -> Option<Arc<DestructedGate>> {
+        self.handles
+            .get(&gate.package)
+            .and_then(|versions| versions.get(&gate.version))
+            .and_then(|components| components.get(&gate.component))
+            .cloned()
+            .or_else(|| self.lazy.as_ref()?.get_loaded_gate(gate))
+    }
+
+    /// Get handle using a ComponentVersion,
+    /// the library of the gate type is loaded if it is registered lazily
+    pub fn load_handle(
+        &self,
+        gate: &ComponentVersion,
+    ) -> Result<Arc<DestructedGate>, Box<sim::Error>> {
+        if let Some(handle) = self.get_handle(gate) {
+            return Ok(handle);
         }
+
+        let loaded = match &self.lazy {
+            Some(lazy) => lazy.load_gate(gate).map_err(|e| {
+                Box::new(sim::Error::LoadComponent {
+                    component: gate.clone(),
+                    reason: e.to_string(),
+                })
+            })?,
+            None => None,
+        };
+
+        loaded.ok_or_else(|| {
+            Box::new(sim::Error::GateTypeNotFound {
+                gate_type: gate.clone(),
+            })
+        })
+    }
+
+    /// replace the handle of a gate type, and recreate every gate of the type with it
+    ///
+    /// each gate is serialized and dropped before it is deserialized with the new handle,
+    /// connections are restored by socket name against the new definition.
+    /// a gate the new handle cannot recreate is recreated with its old handle
+    ///
+    /// nothing is changed if the new handle cannot create a default gate
+    pub fn reload_gate_type(
+        &mut self,
+        handle: Arc<DestructedGate>,
+        world_data: &WorldStateData,
+    ) -> Result<ReloadedComponent, Box<sim::Error>> {
+        // checked before any gate is dropped, e.g. the new definition is invalid
+        drop(SimGate::new_default(handle.clone(), world_data)?);
+
+        let id = handle.id().clone();
+        let mut reloaded = ReloadedComponent::default();
+
+        if let Some(slot) = self
+            .handles
+            .get_mut(&id.package)
+            .and_then(|versions| versions.get_mut(&id.version))
+            .and_then(|components| components.get_mut(&id.component))
+        {
+            *slot = handle.clone();
+        }
+
+        if let Some(lazy) = &self.lazy {
+            lazy.replace_gate(&handle);
+        }
+
+        let mut gate_ids: Vec<ComponentId> = self
+            .gates
+            .iter()
+            .filter(|(_, gate)| unsafe { &*gate.get() }.get_type() == &id)
+            .map(|(gate_id, _)| *gate_id)
+            .collect();
+        gate_ids.sort();
+
+        // gate id -> (consumer names, producer names) in the old definition
+        let mut socket_names = HashMap::new();
+        let mut connections: Vec<(GateProducerSocket, GateConsumerSocket)> = Vec::new();
+
+        for gate_id in gate_ids.iter() {
+            self.gate_connections(gate_id, &mut connections);
+
+            let definition = unsafe { &*self.gates[gate_id].get() }.get_def();
+            socket_names.insert(
+                *gate_id,
+                (
+                    definition
+                        .consumers
+                        .iter()
+                        .map(|entry| entry.name.clone())
+                        .collect::<Vec<_>>(),
+                    definition
+                        .producers
+                        .iter()
+                        .map(|entry| entry.name.clone())
+                        .collect::<Vec<_>>(),
+                ),
+            );
+        }
+
+        for (producer_socket, consumer_socket) in connections.iter() {
+            let _ = self.disconnect(producer_socket, consumer_socket);
+        }
+
+        // every old gate is dropped before any new gate is created
+        let states: Vec<_> = gate_ids
+            .into_iter()
+            .filter_map(|gate_id| {
+                let gate = self.gates.remove(&gate_id)?.into_inner();
+                let old_handle = gate.get_handle().clone();
+                Some((gate_id, old_handle, gate.into_reload_state()))
+            })
+            .collect();
+
+        for (gate_id, old_handle, state) in states {
+            let (e, state) = match SimGate::from_reload_state(handle.clone(), state, world_data) {
+                Ok((gate, reset)) => {
+                    self.gates.insert(gate_id, UnsafeCell::new(gate));
+                    reloaded.gates.push(gate_id);
+                    if reset {
+                        reloaded.reset_gates.push(gate_id);
+                    }
+                    continue;
+                }
+                Err(failed) => failed,
+            };
+
+            match SimGate::from_reload_state(old_handle, state, world_data) {
+                Ok((gate, _)) => {
+                    self.gates.insert(gate_id, UnsafeCell::new(gate));
+                    reloaded.kept_gates.push((gate_id, *e));
+                }
+                Err(_) => reloaded.removed_gates.push((gate_id, *e)),
+            }
+        }
+
+        for (producer_socket, consumer_socket) in connections {
+            let remapped_producer = match socket_names.get(producer_socket.get_id()) {
+                Some((_, producers)) => self
+                    .get_gate(producer_socket.get_id())
+                    .and_then(|gate| gate.producer_index(&producers[producer_socket.get_index()]))
+                    .map(|index| GateProducerSocket::new(*producer_socket.get_id(), index)),
+                None => Some(producer_socket),
+            };
+
+            let remapped_consumer = match socket_names.get(consumer_socket.get_id()) {
+                Some((consumers, _)) => self
+                    .get_gate(consumer_socket.get_id())
+                    .and_then(|gate| gate.consumer_index(&consumers[consumer_socket.get_index()]))
+                    .map(|index| GateConsumerSocket::new(*consumer_socket.get_id(), index)),
+                None => Some(consumer_socket),
+            };
+
+            let restored = match (remapped_producer, remapped_consumer) {
+                (Some(producer), Some(consumer)) => self.connect(producer, consumer).is_ok(),
+                _ => false,
+            };
+
+            if !restored {
+                reloaded
+                    .disconnected
+                    .push((producer_socket, consumer_socket));
+            }
+        }
+
+        Ok(reloaded)
+    }
+
+    /// add the connections to and from a gate to connections, skipping the ones already in it
+    fn gate_connections(
+        &self,
+        gate_id: &ComponentId,
+        connections: &mut Vec<(GateProducerSocket, GateConsumerSocket)>,
+    ) {
+        let Some(gate) = self.get_gate(gate_id) else {
+            return;
+        };
+        let definition = gate.get_def();
+
+        for index in 0..definition.consumers.len() {
+            if let Some(source) = gate.get_consumer_source(index) {
+                let connection = (*source, GateConsumerSocket::new(*gate_id, index));
+                if !connections.contains(&connection) {
+                    connections.push(connection);
+                }
+            }
+        }
+
+        for index in 0..definition.producers.len() {
+            for dependent in gate.get_producer_dependents(index).into_iter().flatten() {
+                let connection = (GateProducerSocket::new(*gate_id, index), *dependent);
+                if !connections.contains(&connection) {
+                    connections.push(connection);
+                }
+            }
+        }
+    }
+
+    /// remove a gate after disconnecting every socket connected to it
+    pub fn remove_gate(&mut self, gate_id: &ComponentId) -> Result<(), Box<sim::Error>> {
+        if !self.gates.contains_key(gate_id) {
+            return Err(sim::Error::GateNotFound { gate_id: *gate_id }.into());
+        }
+
+        let mut connections = Vec::new();
+        self.gate_connections(gate_id, &mut connections);
+        for (producer_socket, consumer_socket) in connections.iter() {
+            self.disconnect(producer_socket, consumer_socket)?;
+        }
+
+        self.gates.remove(gate_id);
+        Ok(())
+    }
+
+    /// switch every gate using a data type to the handle of its reloaded library
+    pub fn reload_data_type(&mut self, handle: &Arc<DestructedData>) -> ReloadedComponent {
+        let mut reloaded = ReloadedComponent::default();
+
+        for (gate_id, gate) in self.gates.iter_mut() {
+            for index in gate.get_mut().reload_data_type(handle) {
+                reloaded
+                    .reset_buffers
+                    .push(GateProducerSocket::new(*gate_id, index));
+            }
+        }
+
+        reloaded
     }
 
     /// create a new gate in world with default state
@@ -39,20 +365,9 @@ impl WorldStateGates {
         world_data: &WorldStateData,
         id_counter: &mut ComponentIdIncrementer,
     ) -> Result<ComponentId, Box<sim::Error>> {
-        fn get_handle<'a>(
-            handles: &'a DestructedGateHandles,
-            gate: &ComponentVersion,
-        ) -> Option<&'a Rc<DestructedGate>> {
-            handles
-                .get(&gate.package)?
-                .get(&gate.version)?
-                .get(&gate.component)
-        }
-
-        let handle = get_handle(&self.handles, &gate)
-            .ok_or_else(|| Box::new(sim::Error::GateTypeNotFound { gate_type: gate }))?;
+        let handle = self.load_handle(&gate)?;
 
-        let created_gate = SimGate::new_default(handle.clone(), world_data)?;
+        let created_gate = SimGate::new_default(handle, world_data)?;
         let new_gate_id = id_counter.get(ComponentIdType::Gate);
 
         self.gates
@@ -65,16 +380,37 @@ impl WorldStateGates {
     // if it is causing trouble, we can remove it
     pub fn tick_all(&mut self) -> Result<(), Box<sim::Error>> {
         let mut tick_errors = Vec::new();
+        // gates whose library exports gate_tick_batch are grouped by type,
+        // each type is ticked with a single call, see DestructedGate::tick_batch
+        let mut batches = std::mem::take(&mut self.batches);
 
         for (gate_id, gate) in self.gates.iter() {
             // the only variable that will be mutated are write_only buffers
             // they will be written once only in a tick, and will not be read from
             // all other variables are to remain unchanged
-            if let Err(e) = unsafe { &mut *gate.get() }.tick(self, gate_id) {
+            let gate = unsafe { &mut *gate.get() };
+
+            if gate.ticks_in_batch() {
+                // gates alive during the tick keep their handle, so its address is unique
+                let batch = batches
+                    .entry(Arc::as_ptr(gate.get_handle()) as usize)
+                    .or_insert_with(TickBatch::new);
+                batch
+                    .handle
+                    .get_or_insert_with(|| gate.get_handle().clone());
+                batch.gate_ids.push(*gate_id);
+            } else if let Err(e) = gate.tick(self, gate_id) {
                 tick_errors.push(TickAllErrorEntry::new(*gate_id, *e));
             }
         }
 
+        // types no gate uses anymore
+        batches.retain(|_, batch| !batch.gate_ids.is_empty());
+        for batch in batches.values_mut() {
+            self.tick_batch(batch, &mut tick_errors);
+        }
+        self.batches = batches;
+
         // flush is in the same funciton as tick_all, because it is ran only after ticking
         for gate in self.gates.values_mut() {
             gate.get_mut().flush();
@@ -90,6 +426,108 @@ impl WorldStateGates {
         }
     }
 
+    /// tick gates of the same type with one call to their handle,
+    /// errors are the same as ticking each gate with SimGate::tick
+    fn tick_batch(&self, batch: &mut TickBatch, tick_errors: &mut Vec<TickAllErrorEntry>) {
+        // only batches with gates are ticked, and each gate sets the handle
+        let Some(handle) = batch.handle.clone() else {
+            return;
+        };
+
+        // gates whose consumers cannot be created are left out of the batch, see SimGate::tick
+        batch.gate_ids.retain(|gate_id| {
+            // unsafe ok because it is treating the gate as immutable
+            let gate = unsafe { &*self.gates[gate_id].get() };
+            let mut errors = Vec::new();
+            let start = batch.consumer_data.len();
+
+            if !gate.batch_consumers(
+                self,
+                &mut errors,
+                &mut batch.temp_datas,
+                &mut batch.consumer_data,
+            ) {
+                batch.consumer_data.truncate(start);
+                tick_errors.push(TickAllErrorEntry::new(
+                    *gate_id,
+                    sim::Error::TickSingleGate {
+                        gate_id: *gate_id,
+                        errors,
+                    },
+                ));
+                return false;
+            }
+
+            batch.gate_ptrs.push(gate.get_gate_ptr());
+            batch.consumer_ranges.push(start..batch.consumer_data.len());
+            batch.errors.push(errors);
+            true
+        });
+
+        if batch.gate_ids.is_empty() {
+            batch.clear();
+            return;
+        }
+
+        // consumer_data is no longer pushed to, so the borrowed slices stay valid
+        batch.consumers.extend(
+            batch
+                .consumer_ranges
+                .iter()
+                .map(|range| slice::from_borrowed(&batch.consumer_data[range.clone()])),
+        );
+        batch.consumer_ptrs.extend(
+            batch
+                .consumers
+                .iter()
+                .map(|consumers| consumers as *const Slice),
+        );
+
+        let produced = handle.tick_batch(&batch.gate_ptrs, &batch.consumer_ptrs);
+        let produced = slice::from_slice::<Slice>(&produced);
+
+        if produced.len() == batch.gate_ids.len() {
+            for (gate_id, producers) in batch.gate_ids.iter().zip(produced) {
+                // the only variable that will be mutated are write_only buffers, see tick_all
+                unsafe { &mut *self.gates[gate_id].get() }.set_produced(slice::from_slice::<
+                    DataPtrMut,
+                >(
+                    producers
+                ));
+            }
+        } else {
+            // the library has already ticked every gate, so the gates are not ticked again
+            // and keep their old buffers, producers that match a gate by index are dropped
+            // and any surplus is leaked, it cannot be matched to a gate that made it
+            for (gate_id, producers) in batch.gate_ids.iter().zip(produced) {
+                unsafe { &*self.gates[gate_id].get() }
+                    .drop_produced(slice::from_slice::<DataPtrMut>(producers));
+            }
+
+            for errors in batch.errors.iter_mut() {
+                errors.push(sim::Error::BatchTick {
+                    gate_type: handle.id().clone(),
+                    gates: batch.gate_ids.len(),
+                    produced: produced.len(),
+                });
+            }
+        }
+
+        for (gate_id, errors) in batch.gate_ids.iter().zip(batch.errors.drain(..)) {
+            if !errors.is_empty() {
+                tick_errors.push(TickAllErrorEntry::new(
+                    *gate_id,
+                    sim::Error::TickSingleGate {
+                        gate_id: *gate_id,
+                        errors,
+                    },
+                ));
+            }
+        }
+
+        batch.clear();
+    }
+
     /// get the producer of a socket
     pub fn get_producer(&self, producer_socket: &GateProducerSocket) -> Option<&SimData> {
         // unsafe ok because it is treating self as immutable
@@ -1,8 +1,11 @@
 //! The world state is a collection of components that connect to each other.
 //!
 //! The world state responds to messages defined in sim::requests
+use std::sync::Arc;
+
 use crate::{
     common::world::{ComponentId, ComponentIdIncrementer, GateProducerSocket},
+    packages::{destructor, stdlib},
     world::sim::{
         self, SimGate,
         component::SimData,
@@ -28,6 +31,41 @@ impl WorldState {
         }
     }
 
+    /// make new gate and data types available in the world,
+    /// gates already in the world are not affected
+    ///
+    /// returns sim::Error::ReservedPackage (and registers nothing)
+    /// if the request contains handles of the stdlib package
+    pub fn register_handles(&mut self, request: RegisterHandles) -> Result<(), Box<sim::Error>> {
+        if request.data_handles.contains_key(stdlib::PACKAGE)
+            || request.gate_handles.contains_key(stdlib::PACKAGE)
+        {
+            return Err(Box::new(sim::Error::ReservedPackage {
+                package: stdlib::PACKAGE.to_string(),
+            }));
+        }
+
+        self.data.register_handles(request.data_handles);
+        self.gates.register_handles(request.gate_handles);
+        Ok(())
+    }
+
+    /// remove gate and data types of a package version from the world,
+    /// gates already in the world keep their handles (and libraries) alive
+    pub fn unregister_handles(&mut self, request: UnregisterHandles) {
+        self.data
+            .unregister_handles(&request.package, &request.version);
+        self.gates
+            .unregister_handles(&request.package, &request.version);
+    }
+
+    /// load gate and data types registered in the lazy loader when they are first used,
+    /// their libraries are unloaded again once nothing in the world uses them
+    pub fn set_lazy_loader(&mut self, request: SetLazyLoader) {
+        self.data.set_lazy_loader(request.loader.clone());
+        self.gates.set_lazy_loader(request.loader);
+    }
+
     /// Create a new gate in world with default state
     pub fn create_default_gate(
         &mut self,
@@ -37,6 +75,12 @@ impl WorldState {
             .create_default_gate(request.gate, &self.data, &mut self.id_counter)
     }
 
+    /// remove a gate and its connections from the world,
+    /// a lazily loaded library is unloaded once no gate or buffer uses it
+    pub fn remove_gate(&mut self, request: RemoveGate) -> Result<(), Box<sim::Error>> {
+        self.gates.remove_gate(&request.gate_id)
+    }
+
     /// tick the current world
     /// if this function returns error, its not end of the world
     /// it just means a buffer is used as consumer to a gate, but is not present
@@ -85,6 +129,48 @@ impl WorldState {
             .disconnect(&request.producer_socket, &request.consumer_socket)
     }
 
+    /// load the library of a gate or data type again, and move everything in the world
+    /// that uses the type over to the new library
+    ///
+    /// the world is unchanged if the library cannot be reloaded,
+    /// or a gate type from it cannot create a default gate
+    pub fn reload_component(
+        &mut self,
+        request: ReloadComponent,
+    ) -> Result<ReloadedComponent, Box<sim::Error>> {
+        let component = request.component;
+        let reload_error = |e: destructor::Error| {
+            Box::new(sim::Error::ReloadLibrary {
+                component: component.clone(),
+                reason: e.to_string(),
+            })
+        };
+
+        if let Some(handle) = self.gates.get_handle(&component) {
+            let handle = handle.reload().map_err(reload_error)?;
+            return self.gates.reload_gate_type(Arc::new(handle), &self.data);
+        }
+
+        if let Some(handle) = self.data.get_handle(&component) {
+            let handle = Arc::new(handle.reload().map_err(reload_error)?);
+
+            let reloaded = self.gates.reload_data_type(&handle);
+            self.data.replace_handle(handle);
+            return Ok(reloaded);
+        }
+
+        // a lazily registered component that is not loaded picks up the new library when it is
+        if self
+            .data
+            .get_lazy_loader()
+            .is_some_and(|lazy| lazy.contains(&component))
+        {
+            return Ok(ReloadedComponent::default());
+        }
+
+        Err(sim::Error::ComponentTypeNotFound { component }.into())
+    }
+
     /// get the component id counter
     pub fn counter_mut(&mut self) -> &mut ComponentIdIncrementer {
         &mut self.id_counter
@@ -1,10 +1,19 @@
 use crate::{
     common::{self, world::ComponentId},
-    world::user::ident::{UserIdent, UserIdentNormalised},
+    world::{
+        layout,
+        user::ident::{UserIdent, UserIdentNormalised},
+    },
 };
 
 pub enum Error {
     Common(Box<common::Error>),
-    PlayerAlreadyOnline { ident: UserIdentNormalised },
-    InvalidPlayerId { id: ComponentId },
+    /// error originating from the layout state
+    Layout(Box<layout::Error>),
+    PlayerAlreadyOnline {
+        ident: UserIdentNormalised,
+    },
+    InvalidPlayerId {
+        id: ComponentId,
+    },
 }
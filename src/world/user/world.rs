@@ -3,7 +3,7 @@ use std::collections::{HashMap, HashSet};
 use crate::{
     common::world::{ComponentId, ComponentIdType},
     world::{
-        layout,
+        layout, sim,
         user::{
             self,
             ident::{UserDisplay, UserIdent, UserIdentNormalised},
@@ -22,6 +22,15 @@ pub struct WorldState {
 }
 
 impl WorldState {
+    /// create a new world with no users
+    pub fn new_blank(request: layout::CreateBlankWorld) -> Self {
+        Self {
+            layout_state: layout::WorldState::new_blank(request),
+            online_user_displays: HashMap::new(),
+            all_user_ids: HashMap::new(),
+        }
+    }
+
     /// send a sanitised user connect request to the world
     pub fn connect(&mut self, request: ConnectRequest) -> Result<ComponentId, Box<user::Error>> {
         let (ident, display) = match request {
@@ -58,6 +67,26 @@ impl WorldState {
         }
     }
 
+    /// make new gate and data types available in the world
+    pub fn register_handles(
+        &mut self,
+        request: sim::requests::RegisterHandles,
+    ) -> Result<(), Box<user::Error>> {
+        self.layout_state
+            .register_handles(request)
+            .map_err(|e| Box::new(user::Error::Layout(e)))
+    }
+
+    /// remove gate and data types of a package version from the world
+    pub fn unregister_handles(&mut self, request: sim::requests::UnregisterHandles) {
+        self.layout_state.unregister_handles(request);
+    }
+
+    /// load gate and data types when they are first used
+    pub fn set_lazy_loader(&mut self, request: sim::requests::SetLazyLoader) {
+        self.layout_state.set_lazy_loader(request);
+    }
+
     /// disconnect a user from world
     pub fn disconnect(&mut self, id: &ComponentId) -> Result<(), Box<user::Error>> {
         if self.online_user_displays.remove(id).is_some() {